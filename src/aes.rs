@@ -0,0 +1,213 @@
+//! AES-256 (FIPS 197) and XTS mode (IEEE 1619), used for encrypted NVS partitions
+
+const SBOX: [u8; 256] = [
+    0x63, 0x7c, 0x77, 0x7b, 0xf2, 0x6b, 0x6f, 0xc5, 0x30, 0x01, 0x67, 0x2b, 0xfe, 0xd7, 0xab, 0x76,
+    0xca, 0x82, 0xc9, 0x7d, 0xfa, 0x59, 0x47, 0xf0, 0xad, 0xd4, 0xa2, 0xaf, 0x9c, 0xa4, 0x72, 0xc0,
+    0xb7, 0xfd, 0x93, 0x26, 0x36, 0x3f, 0xf7, 0xcc, 0x34, 0xa5, 0xe5, 0xf1, 0x71, 0xd8, 0x31, 0x15,
+    0x04, 0xc7, 0x23, 0xc3, 0x18, 0x96, 0x05, 0x9a, 0x07, 0x12, 0x80, 0xe2, 0xeb, 0x27, 0xb2, 0x75,
+    0x09, 0x83, 0x2c, 0x1a, 0x1b, 0x6e, 0x5a, 0xa0, 0x52, 0x3b, 0xd6, 0xb3, 0x29, 0xe3, 0x2f, 0x84,
+    0x53, 0xd1, 0x00, 0xed, 0x20, 0xfc, 0xb1, 0x5b, 0x6a, 0xcb, 0xbe, 0x39, 0x4a, 0x4c, 0x58, 0xcf,
+    0xd0, 0xef, 0xaa, 0xfb, 0x43, 0x4d, 0x33, 0x85, 0x45, 0xf9, 0x02, 0x7f, 0x50, 0x3c, 0x9f, 0xa8,
+    0x51, 0xa3, 0x40, 0x8f, 0x92, 0x9d, 0x38, 0xf5, 0xbc, 0xb6, 0xda, 0x21, 0x10, 0xff, 0xf3, 0xd2,
+    0xcd, 0x0c, 0x13, 0xec, 0x5f, 0x97, 0x44, 0x17, 0xc4, 0xa7, 0x7e, 0x3d, 0x64, 0x5d, 0x19, 0x73,
+    0x60, 0x81, 0x4f, 0xdc, 0x22, 0x2a, 0x90, 0x88, 0x46, 0xee, 0xb8, 0x14, 0xde, 0x5e, 0x0b, 0xdb,
+    0xe0, 0x32, 0x3a, 0x0a, 0x49, 0x06, 0x24, 0x5c, 0xc2, 0xd3, 0xac, 0x62, 0x91, 0x95, 0xe4, 0x79,
+    0xe7, 0xc8, 0x37, 0x6d, 0x8d, 0xd5, 0x4e, 0xa9, 0x6c, 0x56, 0xf4, 0xea, 0x65, 0x7a, 0xae, 0x08,
+    0xba, 0x78, 0x25, 0x2e, 0x1c, 0xa6, 0xb4, 0xc6, 0xe8, 0xdd, 0x74, 0x1f, 0x4b, 0xbd, 0x8b, 0x8a,
+    0x70, 0x3e, 0xb5, 0x66, 0x48, 0x03, 0xf6, 0x0e, 0x61, 0x35, 0x57, 0xb9, 0x86, 0xc1, 0x1d, 0x9e,
+    0xe1, 0xf8, 0x98, 0x11, 0x69, 0xd9, 0x8e, 0x94, 0x9b, 0x1e, 0x87, 0xe9, 0xce, 0x55, 0x28, 0xdf,
+    0x8c, 0xa1, 0x89, 0x0d, 0xbf, 0xe6, 0x42, 0x68, 0x41, 0x99, 0x2d, 0x0f, 0xb0, 0x54, 0xbb, 0x16,
+];
+
+const ROUNDS: usize = 14;
+
+fn xtime(byte: u8) -> u8 {
+    (byte << 1) ^ if byte & 0x80 != 0 { 0x1b } else { 0 }
+}
+
+fn multiply(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        a = xtime(a);
+        b >>= 1;
+    }
+    product
+}
+
+/// Mix each column of the state with the rows of a circulant matrix
+fn mix_columns(state: &mut [u8; 16], matrix: [u8; 4]) {
+    for column in state.chunks_mut(4) {
+        let a = [column[0], column[1], column[2], column[3]];
+        for (row, value) in column.iter_mut().enumerate() {
+            *value = (0..4).fold(0, |acc, i| acc ^ multiply(matrix[(i + 4 - row) % 4], a[i]));
+        }
+    }
+}
+
+/// AES-256 with its expanded key
+pub struct Aes256 {
+    round_keys: [[u8; 16]; ROUNDS + 1],
+    inverse_sbox: [u8; 256],
+}
+
+impl Aes256 {
+    pub fn new(key: &[u8; 32]) -> Self {
+        let mut words = [[0u8; 4]; 4 * (ROUNDS + 1)];
+        for (i, word) in key.chunks(4).enumerate() {
+            words[i].copy_from_slice(word);
+        }
+        let mut rcon = 1u8;
+        for i in 8..words.len() {
+            let mut temp = words[i - 1];
+            if i % 8 == 0 {
+                temp = [
+                    SBOX[temp[1] as usize] ^ rcon,
+                    SBOX[temp[2] as usize],
+                    SBOX[temp[3] as usize],
+                    SBOX[temp[0] as usize],
+                ];
+                rcon = xtime(rcon);
+            } else if i % 8 == 4 {
+                temp = temp.map(|byte| SBOX[byte as usize]);
+            }
+            for (j, byte) in temp.iter().enumerate() {
+                words[i][j] = words[i - 8][j] ^ byte;
+            }
+        }
+
+        let mut round_keys = [[0u8; 16]; ROUNDS + 1];
+        for (round, round_key) in round_keys.iter_mut().enumerate() {
+            for (column, word) in words[4 * round..4 * round + 4].iter().enumerate() {
+                round_key[4 * column..4 * column + 4].copy_from_slice(word);
+            }
+        }
+        let mut inverse_sbox = [0u8; 256];
+        for (index, value) in SBOX.iter().enumerate() {
+            inverse_sbox[*value as usize] = index as u8;
+        }
+        Self {
+            round_keys,
+            inverse_sbox,
+        }
+    }
+
+    fn add_round_key(&self, state: &mut [u8; 16], round: usize) {
+        for (byte, key) in state.iter_mut().zip(self.round_keys[round]) {
+            *byte ^= key;
+        }
+    }
+
+    pub fn encrypt_block(&self, block: &mut [u8; 16]) {
+        self.add_round_key(block, 0);
+        for round in 1..=ROUNDS {
+            let state = *block;
+            // SubBytes and ShiftRows: row r moves r columns to the left
+            for (index, byte) in block.iter_mut().enumerate() {
+                let (row, column) = (index % 4, index / 4);
+                *byte = SBOX[state[row + 4 * ((column + row) % 4)] as usize];
+            }
+            if round != ROUNDS {
+                mix_columns(block, [2, 3, 1, 1]);
+            }
+            self.add_round_key(block, round);
+        }
+    }
+
+    pub fn decrypt_block(&self, block: &mut [u8; 16]) {
+        for round in (1..=ROUNDS).rev() {
+            self.add_round_key(block, round);
+            if round != ROUNDS {
+                mix_columns(block, [14, 11, 13, 9]);
+            }
+            let state = *block;
+            for (index, byte) in block.iter_mut().enumerate() {
+                let (row, column) = (index % 4, index / 4);
+                *byte = self.inverse_sbox[state[row + 4 * ((column + 4 - row) % 4)] as usize];
+            }
+        }
+        self.add_round_key(block, 0);
+    }
+}
+
+/// XTS-AES-256 with a data key and a tweak key, for data of whole 16 byte blocks
+pub struct Xts {
+    data: Aes256,
+    tweak: Aes256,
+}
+
+impl Xts {
+    /// Key of 64 bytes: the data key followed by the tweak key
+    pub fn new(key: &[u8; 64]) -> Self {
+        let (data, tweak) = key.split_at(32);
+        Self {
+            data: Aes256::new(data.try_into().unwrap()),
+            tweak: Aes256::new(tweak.try_into().unwrap()),
+        }
+    }
+
+    fn process(&self, tweak: [u8; 16], data: &mut [u8], encrypt: bool) {
+        let mut mask = tweak;
+        self.tweak.encrypt_block(&mut mask);
+        for chunk in data.chunks_mut(16) {
+            let mut block = [0u8; 16];
+            block.copy_from_slice(chunk);
+            block.iter_mut().zip(mask).for_each(|(b, m)| *b ^= m);
+            if encrypt {
+                self.data.encrypt_block(&mut block);
+            } else {
+                self.data.decrypt_block(&mut block);
+            }
+            block.iter_mut().zip(mask).for_each(|(b, m)| *b ^= m);
+            chunk.copy_from_slice(&block);
+
+            // Multiply the mask by x in GF(2^128), little endian
+            let carry = mask[15] >> 7;
+            for i in (1..16).rev() {
+                mask[i] = (mask[i] << 1) | (mask[i - 1] >> 7);
+            }
+            mask[0] = (mask[0] << 1) ^ if carry != 0 { 0x87 } else { 0 };
+        }
+    }
+
+    pub fn encrypt(&self, tweak: [u8; 16], data: &mut [u8]) {
+        self.process(tweak, data, true);
+    }
+
+    pub fn decrypt(&self, tweak: [u8; 16], data: &mut [u8]) {
+        self.process(tweak, data, false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sha256::to_hex;
+
+    #[test]
+    fn test_aes256_xts() {
+        // FIPS 197, appendix C.3
+        let key: Vec<u8> = (0..32).collect();
+        let aes = Aes256::new(key.as_slice().try_into().unwrap());
+        let mut block: [u8; 16] = core::array::from_fn(|i| (i as u8) * 0x11);
+        aes.encrypt_block(&mut block);
+        assert_eq!(to_hex(&block), "8ea2b7ca516745bfeafc49904b496089");
+        aes.decrypt_block(&mut block);
+        assert_eq!(to_hex(&block), "00112233445566778899aabbccddeeff");
+
+        let key: Vec<u8> = (0..64).collect();
+        let xts = Xts::new(key.as_slice().try_into().unwrap());
+        let mut tweak = [0u8; 16];
+        tweak[..2].copy_from_slice(&0x1040u16.to_le_bytes());
+        let mut data = [0xAB; 32];
+        xts.encrypt(tweak, &mut data);
+        assert_eq!(
+            to_hex(&data),
+            "9b84154aebc57b023c87a1a7319afbf7933b30f897d7f93fc9ce1da9271ef922"
+        );
+        xts.decrypt(tweak, &mut data);
+        assert_eq!(data, [0xAB; 32]);
+    }
+}
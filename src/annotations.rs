@@ -0,0 +1,160 @@
+//! CI annotations for compiler diagnostics: GCC and Clang errors and warnings in the
+//! tool output are re-emitted as workflow commands, which GitHub Actions shows on the
+//! lines of the pull request diff.
+
+use regex::Regex;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Annotations {
+    /// `::error file=...,line=...::message` workflow commands
+    Github,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line: u32,
+    pub column: Option<u32>,
+    /// "error" or "warning"
+    pub severity: String,
+    pub message: String,
+}
+
+/// `file:line[:column]: [fatal ]error|warning: message`, notes are left out
+const DIAGNOSTIC_PATTERN: &str = r"^(.+?):(\d+):(?:(\d+):)? (?:fatal )?(error|warning): (.+)$";
+
+static FORMAT: OnceLock<Option<Annotations>> = OnceLock::new();
+
+/// Diagnostics already annotated, the same header is often compiled many times
+static SEEN: Mutex<Vec<Diagnostic>> = Mutex::new(Vec::new());
+
+/// Select the annotations once the command line is parsed, GitHub Actions gets them
+/// without asking
+pub fn init(format: Option<Annotations>) {
+    FORMAT.get_or_init(|| {
+        format.or_else(|| {
+            (std::env::var("GITHUB_ACTIONS").as_deref() == Ok("true"))
+                .then_some(Annotations::Github)
+        })
+    });
+}
+
+/// Drop the color escape sequences ESP-IDF's -fdiagnostics-color=always adds
+fn strip_ansi(line: &str) -> String {
+    let mut text = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            text.push(c);
+        }
+    }
+    text
+}
+
+pub fn parse_diagnostic(line: &str) -> Option<Diagnostic> {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    let pattern = PATTERN.get_or_init(|| Regex::new(DIAGNOSTIC_PATTERN).unwrap());
+    let line = strip_ansi(line);
+    let captures = pattern.captures(line.trim_end())?;
+    let group = |index: usize| {
+        captures
+            .get(index)
+            .map(|group| group.as_str().to_string())
+            .unwrap_or_default()
+    };
+    Some(Diagnostic {
+        file: group(1),
+        line: group(2).parse().ok()?,
+        column: group(3).parse().ok(),
+        severity: group(4),
+        message: group(5),
+    })
+}
+
+fn escape_data(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_property(text: &str) -> String {
+    escape_data(text).replace(':', "%3A").replace(',', "%2C")
+}
+
+/// The workflow command of a diagnostic, with the file relative to the workspace so
+/// that GitHub can place it in the diff
+pub fn format_github(diagnostic: &Diagnostic, workspace: Option<&Path>) -> String {
+    let file = workspace
+        .and_then(|workspace| Path::new(&diagnostic.file).strip_prefix(workspace).ok())
+        .map(|file| file.to_string_lossy().to_string())
+        .unwrap_or_else(|| diagnostic.file.clone());
+    let mut properties = format!("file={},line={}", escape_property(&file), diagnostic.line);
+    if let Some(column) = diagnostic.column {
+        properties.push_str(&format!(",col={}", column));
+    }
+    format!(
+        "::{} {}::{}",
+        diagnostic.severity,
+        properties,
+        escape_data(&diagnostic.message)
+    )
+}
+
+/// The annotation to print for a line of tool output, if annotations are enabled and
+/// the line is a diagnostic not annotated before
+pub fn annotate(line: &str) -> Option<String> {
+    let Some(Some(Annotations::Github)) = FORMAT.get() else {
+        return None;
+    };
+    let diagnostic = parse_diagnostic(line)?;
+    let mut seen = SEEN.lock().unwrap();
+    if seen.contains(&diagnostic) {
+        return None;
+    }
+    let workspace = std::env::var_os("GITHUB_WORKSPACE");
+    let annotation = format_github(&diagnostic, workspace.as_deref().map(Path::new));
+    seen.push(diagnostic);
+    Some(annotation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_github_annotations() {
+        let diagnostic = parse_diagnostic(
+            "/work/proj/main/main.c:12:5: \x1b[01;31merror: \x1b[m'foo' undeclared, 50% sure",
+        )
+        .unwrap();
+        assert_eq!(diagnostic.file, "/work/proj/main/main.c");
+        assert_eq!(diagnostic.line, 12);
+        assert_eq!(diagnostic.column, Some(5));
+        assert_eq!(diagnostic.severity, "error");
+        assert_eq!(
+            format_github(&diagnostic, Some(Path::new("/work/proj"))),
+            "::error file=main/main.c,line=12,col=5::'foo' undeclared, 50%25 sure"
+        );
+
+        let warning =
+            parse_diagnostic(r"C:\proj\main\app.c:7: warning: unused variable 'x'").unwrap();
+        assert_eq!(warning.file, r"C:\proj\main\app.c");
+        assert_eq!(warning.column, None);
+        assert_eq!(
+            format_github(&warning, None),
+            r"::warning file=C%3A\proj\main\app.c,line=7::unused variable 'x'"
+        );
+
+        assert!(parse_diagnostic("main.c:3:1: fatal error: foo.h: No such file").is_some());
+        assert!(parse_diagnostic("main.c:12:5: note: declared here").is_none());
+        assert!(parse_diagnostic("[12/100] Building C object main.c.obj").is_none());
+    }
+}
@@ -0,0 +1,118 @@
+use anyhow::Result;
+
+/// First byte of every ESP app image
+const IMAGE_MAGIC: u8 = 0xE9;
+/// Magic word of esp_app_desc_t
+const APP_DESC_MAGIC: u32 = 0xABCD_5432;
+/// esp_app_desc_t follows the image header (24 bytes) and the first segment header (8 bytes)
+const APP_DESC_OFFSET: usize = 32;
+const APP_DESC_SIZE: usize = 176;
+
+/// Secure boot v2 appends a 4 KB sector of signature blocks to the 4 KB aligned image
+const SIGNATURE_SECTOR_SIZE: usize = 4096;
+const SIGNATURE_BLOCK_MAGIC: u8 = 0xE7;
+/// Signature block versions: RSA-PSS and ECDSA
+const SIGNATURE_BLOCK_VERSIONS: [u8; 2] = [0x02, 0x03];
+
+/// The esp_app_desc_t embedded in an app image, as reported by esp_app_get_description()
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppDescription {
+    pub secure_version: u32,
+    pub version: String,
+    pub project_name: String,
+    pub time: String,
+    pub date: String,
+    pub idf_version: String,
+    /// SHA-256 of the app ELF
+    pub elf_sha256: [u8; 32],
+}
+
+fn c_string(bytes: &[u8]) -> String {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..len]).to_string()
+}
+
+/// Read the app description from an app binary
+pub fn parse_app_description(image: &[u8]) -> Result<AppDescription> {
+    if image.first() != Some(&IMAGE_MAGIC) {
+        anyhow::bail!("Not an ESP app image (invalid magic byte)");
+    }
+    let desc = image
+        .get(APP_DESC_OFFSET..APP_DESC_OFFSET + APP_DESC_SIZE)
+        .ok_or_else(|| anyhow::anyhow!("App image is too short"))?;
+
+    let read_u32 = |start: usize| {
+        u32::from_le_bytes([
+            desc[start],
+            desc[start + 1],
+            desc[start + 2],
+            desc[start + 3],
+        ])
+    };
+    if read_u32(0) != APP_DESC_MAGIC {
+        anyhow::bail!("App image has no app description");
+    }
+
+    let mut elf_sha256 = [0u8; 32];
+    elf_sha256.copy_from_slice(&desc[144..176]);
+
+    Ok(AppDescription {
+        secure_version: read_u32(4),
+        version: c_string(&desc[16..48]),
+        project_name: c_string(&desc[48..80]),
+        time: c_string(&desc[80..96]),
+        date: c_string(&desc[96..112]),
+        idf_version: c_string(&desc[112..144]),
+        elf_sha256,
+    })
+}
+
+/// Whether a bootloader or app image carries a secure boot v2 signature block
+pub fn has_signature_block(image: &[u8]) -> bool {
+    if image.len() < 2 * SIGNATURE_SECTOR_SIZE || !image.len().is_multiple_of(SIGNATURE_SECTOR_SIZE)
+    {
+        return false;
+    }
+    let block = &image[image.len() - SIGNATURE_SECTOR_SIZE..];
+    block[0] == SIGNATURE_BLOCK_MAGIC && SIGNATURE_BLOCK_VERSIONS.contains(&block[1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_app_description() {
+        let mut image = vec![0u8; APP_DESC_OFFSET + APP_DESC_SIZE];
+        image[0] = IMAGE_MAGIC;
+        let desc = &mut image[APP_DESC_OFFSET..];
+        desc[0..4].copy_from_slice(&APP_DESC_MAGIC.to_le_bytes());
+        desc[4..8].copy_from_slice(&2u32.to_le_bytes());
+        desc[16..21].copy_from_slice(b"1.2.3");
+        desc[48..55].copy_from_slice(b"blinker");
+        desc[112..118].copy_from_slice(b"v5.3.1");
+        desc[144] = 0xab;
+
+        let description = parse_app_description(&image).unwrap();
+        assert_eq!(description.secure_version, 2);
+        assert_eq!(description.version, "1.2.3");
+        assert_eq!(description.project_name, "blinker");
+        assert_eq!(description.idf_version, "v5.3.1");
+        assert_eq!(description.elf_sha256[0], 0xab);
+
+        image[0] = 0;
+        assert!(parse_app_description(&image).is_err());
+    }
+
+    #[test]
+    fn test_has_signature_block() {
+        let mut image = vec![0u8; 3 * SIGNATURE_SECTOR_SIZE];
+        image[0] = IMAGE_MAGIC;
+        assert!(!has_signature_block(&image));
+
+        image[2 * SIGNATURE_SECTOR_SIZE] = SIGNATURE_BLOCK_MAGIC;
+        image[2 * SIGNATURE_SECTOR_SIZE + 1] = 0x02;
+        assert!(has_signature_block(&image));
+        assert!(!has_signature_block(&image[..image.len() - 1]));
+    }
+}
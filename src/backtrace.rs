@@ -0,0 +1,216 @@
+//! Decoding of the code addresses in panic output with the toolchain's addr2line
+
+use crate::{build_systems, utils};
+use std::path::{Path, PathBuf};
+
+/// Panic register dump entries holding code addresses (Xtensa and RISC-V)
+const CODE_REGISTERS: [&str; 3] = ["PC", "MEPC", "RA"];
+
+fn parse_hex(text: &str) -> Option<u32> {
+    let digits = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))?;
+    u32::from_str_radix(digits, 16).ok()
+}
+
+/// Code addresses in a console line: the PCs of a "Backtrace:" line (PC:SP pairs),
+/// the code registers of a panic register dump and "abort() was called at PC 0x..."
+pub fn code_addresses(line: &str) -> Vec<u32> {
+    if let Some((_, frames)) = line.split_once("Backtrace:") {
+        return frames
+            .split_whitespace()
+            .filter_map(|frame| parse_hex(frame.split(':').next()?))
+            .filter(|&address| address != 0)
+            .collect();
+    }
+
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    let mut addresses = Vec::new();
+    for (index, token) in tokens.iter().enumerate() {
+        if !CODE_REGISTERS.contains(token) {
+            continue;
+        }
+        // "PC      : 0x400d1234" in register dumps, "at PC 0x400d1234" in abort messages
+        let value = match tokens.get(index + 1) {
+            Some(&":") => tokens.get(index + 2),
+            value => value,
+        };
+        if let Some(address) = value.and_then(|value| parse_hex(value)) {
+            addresses.push(address);
+        }
+    }
+    addresses.retain(|&address| address != 0);
+    addresses
+}
+
+/// Split `addr2line -pfiaC` output into one block per address, keeping the
+/// "(inlined by)" lines with their address
+pub fn parse_frames(output: &str) -> Vec<(u32, String)> {
+    let mut frames: Vec<(u32, String)> = Vec::new();
+    for line in output.lines() {
+        let address = line
+            .split_once(": ")
+            .and_then(|(address, _)| parse_hex(address));
+        match (address, frames.last_mut()) {
+            (Some(address), _) => frames.push((address, line.to_string())),
+            (None, Some((_, frame))) if !line.trim().is_empty() => {
+                frame.push('\n');
+                frame.push_str(line);
+            }
+            _ => {}
+        }
+    }
+    frames
+}
+
+fn is_resolved(frame: &str) -> bool {
+    !frame.contains(": ?? ")
+}
+
+/// ROM ELF for the chip revision (major * 100 + minor) from ESP_ROM_ELF_DIR: the
+/// highest revision not newer than the chip, or the oldest one if it isn't known
+fn select_rom_elf(dir: &Path, target: &str, revision: Option<u32>) -> Option<PathBuf> {
+    let prefix = format!("{}_rev", target);
+    let mut candidates: Vec<(u32, PathBuf)> = std::fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let rev = name.strip_prefix(&prefix)?.strip_suffix("_rom.elf")?;
+            Some((rev.parse().ok()?, entry.path()))
+        })
+        .collect();
+    candidates.sort();
+
+    match revision {
+        Some(revision) => candidates
+            .iter()
+            .rev()
+            .find(|(rev, _)| *rev <= revision)
+            .or(candidates.first())
+            .map(|(_, path)| path.clone()),
+        None => candidates.first().map(|(_, path)| path.clone()),
+    }
+}
+
+/// Resolves code addresses against the app ELF, and the ROM ELF if ESP-IDF provides one
+pub struct Addr2Line {
+    tool: String,
+    elf: PathBuf,
+    target: Option<String>,
+    /// Chip revision from the boot log, selecting the ROM ELF
+    revision: Option<u32>,
+}
+
+impl Addr2Line {
+    /// Use the addr2line of the toolchain the project was configured with
+    pub fn find(build_dir: &Path, target: Option<&str>) -> Option<Self> {
+        let tool = build_systems::get_cmake_cache_entry(build_dir, "CMAKE_ADDR2LINE")
+            .filter(|tool| Path::new(tool).exists())?;
+        let elf = utils::get_app_elf_path(build_dir)
+            .ok()
+            .filter(|elf| elf.exists())?;
+        Some(Self {
+            tool,
+            elf,
+            target: target.map(str::to_string),
+            revision: None,
+        })
+    }
+
+    pub fn tool(&self) -> &str {
+        &self.tool
+    }
+
+    /// Track the chip revision printed by the bootloader ("chip revision: v3.0")
+    pub fn observe(&mut self, line: &str) {
+        if let Some((_, version)) = line.split_once("chip revision: v") {
+            let version = version.split_whitespace().next().unwrap_or("");
+            let (major, minor) = version.split_once('.').unwrap_or((version, "0"));
+            if let (Ok(major), Ok(minor)) = (major.parse::<u32>(), minor.parse::<u32>()) {
+                self.revision = Some(major * 100 + minor);
+            }
+        }
+    }
+
+    async fn run(&self, elf: &Path, addresses: &[u32]) -> Vec<(u32, String)> {
+        let addresses: Vec<String> = addresses.iter().map(|a| format!("0x{:08x}", a)).collect();
+        let mut args = vec!["-pfiaC", "-e", elf.to_str().unwrap_or_default()];
+        args.extend(addresses.iter().map(String::as_str));
+        match utils::run_command_with_output(&self.tool, &args, None).await {
+            Ok(output) => parse_frames(&output),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Decoded frames ("0x400d1234: app_main at main.c:12") of the addresses that
+    /// could be resolved, in order
+    pub async fn decode(&self, addresses: &[u32]) -> Vec<String> {
+        let mut frames = self.run(&self.elf, addresses).await;
+
+        let unresolved: Vec<u32> = frames
+            .iter()
+            .filter(|(_, frame)| !is_resolved(frame))
+            .map(|(address, _)| *address)
+            .collect();
+        let rom_elf = std::env::var("ESP_ROM_ELF_DIR")
+            .ok()
+            .zip(self.target.as_deref())
+            .and_then(|(dir, target)| select_rom_elf(Path::new(&dir), target, self.revision));
+        if let (false, Some(rom_elf)) = (unresolved.is_empty(), rom_elf) {
+            for (address, rom_frame) in self.run(&rom_elf, &unresolved).await {
+                if let Some((_, frame)) = frames.iter_mut().find(|(a, _)| *a == address) {
+                    *frame = rom_frame;
+                }
+            }
+        }
+
+        frames
+            .into_iter()
+            .map(|(_, frame)| frame)
+            .filter(|frame| is_resolved(frame))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_addresses() {
+        assert_eq!(
+            code_addresses(
+                "Backtrace: 0x400d1234:0x3ffb1230 0x400d5678:0x3ffb1250 0x00000000:0x00000000 |<-CORRUPTED"
+            ),
+            vec![0x400d1234, 0x400d5678]
+        );
+        assert_eq!(
+            code_addresses("PC      : 0x400d1234  PS      : 0x00060030  A0      : 0x800d5678"),
+            vec![0x400d1234]
+        );
+        assert_eq!(
+            code_addresses("MEPC    : 0x42008a4e  RA      : 0x42008a46  SP      : 0x3fc8f2a0"),
+            vec![0x42008a4e, 0x42008a46]
+        );
+        assert_eq!(
+            code_addresses("abort() was called at PC 0x400d7e1b on core 0"),
+            vec![0x400d7e1b]
+        );
+        assert!(code_addresses("I (312) cpu_start: Pro cpu up.").is_empty());
+
+        let frames = parse_frames(
+            "0x400d1234: helper at /p/main/main.c:10\n (inlined by) app_main at /p/main/main.c:20\n0x40001234: ?? ??:0\n",
+        );
+        assert_eq!(frames.len(), 2);
+        assert_eq!(
+            frames[0],
+            (
+                0x400d1234,
+                "0x400d1234: helper at /p/main/main.c:10\n (inlined by) app_main at /p/main/main.c:20"
+                    .to_string()
+            )
+        );
+        assert!(!is_resolved(&frames[1].1));
+    }
+}
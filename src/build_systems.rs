@@ -13,6 +13,43 @@ pub struct Generator {
     pub force_progression: bool,
 }
 
+impl Generator {
+    /// The command line running the build tool in `build_dir` like idf.py does, e.g.
+    /// `ninja -C build -v app`. `jobs` replaces the default job count, a `-j` in `args`
+    /// replaces both.
+    pub fn build_command(
+        &self,
+        build_dir: &Path,
+        verbose: bool,
+        jobs: Option<u32>,
+        args: &[String],
+    ) -> Vec<String> {
+        let has_jobs = args
+            .iter()
+            .any(|arg| arg.starts_with("-j") || arg.starts_with("--jobs"));
+        let mut command = Vec::new();
+        let mut parts = self.command.iter();
+        while let Some(part) = parts.next() {
+            if (has_jobs || jobs.is_some()) && part == "-j" {
+                parts.next();
+                continue;
+            }
+            command.push(part.clone());
+        }
+        command.push("-C".to_string());
+        command.push(build_dir.to_string_lossy().to_string());
+        if let Some(jobs) = jobs.filter(|_| !has_jobs) {
+            command.push("-j".to_string());
+            command.push(jobs.to_string());
+        }
+        if verbose {
+            command.push(self.verbose_flag.clone());
+        }
+        command.extend(args.iter().cloned());
+        command
+    }
+}
+
 /// Get the ordered list of available generators, similar to ESP-IDF's GENERATORS
 pub fn get_generators() -> BTreeMap<String, Generator> {
     let mut generators = BTreeMap::new();
@@ -58,6 +95,49 @@ pub fn get_generators() -> BTreeMap<String, Generator> {
     generators
 }
 
+/// The definition of a generator by its CMake name, None for generators idf-rs leaves
+/// to `cmake --build`
+pub fn get_generator(name: &str) -> Option<Generator> {
+    get_generators().remove(name)
+}
+
+/// Progress reported by a status line of the build tool
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildStatus<'a> {
+    pub percent: u32,
+    /// Finished and total targets, only known with Ninja
+    pub targets: Option<(u32, u32)>,
+    pub description: &'a str,
+}
+
+/// Split Ninja's status line "[finished/total] description"
+pub fn parse_ninja_status(line: &str) -> Option<(u32, u32, &str)> {
+    let (status, description) = line.strip_prefix('[')?.split_once("] ")?;
+    let (finished, total) = status.split_once('/')?;
+    Some((
+        finished.parse().ok()?,
+        total.parse().ok()?,
+        description.trim_end(),
+    ))
+}
+
+/// Parse a status line of Ninja or of the Makefiles CMake generates ("[ 45%] description")
+pub fn parse_build_status(line: &str) -> Option<BuildStatus<'_>> {
+    if let Some((finished, total, description)) = parse_ninja_status(line) {
+        return Some(BuildStatus {
+            percent: (finished * 100).checked_div(total).unwrap_or(0).min(100),
+            targets: Some((finished, total)),
+            description,
+        });
+    }
+    let (percent, description) = line.strip_prefix('[')?.split_once("%] ")?;
+    Some(BuildStatus {
+        percent: percent.trim_start().parse::<u32>().ok()?.min(100),
+        targets: None,
+        description: description.trim_end(),
+    })
+}
+
 /// Check if an executable exists by running its version command
 pub fn executable_exists(args: &[String]) -> bool {
     if args.is_empty() {
@@ -91,8 +171,8 @@ pub fn detect_cmake_generator() -> Result<String> {
     );
 }
 
-/// Parse CMakeCache.txt to extract the generator used
-pub fn get_generator_from_cache(build_dir: &Path) -> Option<String> {
+/// Read an entry from CMakeCache.txt in the build directory
+pub fn get_cmake_cache_entry(build_dir: &Path, name: &str) -> Option<String> {
     let cache_path = build_dir.join("CMakeCache.txt");
     if !cache_path.exists() {
         return None;
@@ -101,9 +181,10 @@ pub fn get_generator_from_cache(build_dir: &Path) -> Option<String> {
     match std::fs::read_to_string(&cache_path) {
         Ok(content) => {
             for line in content.lines() {
-                if line.starts_with("CMAKE_GENERATOR:INTERNAL=") {
-                    if let Some(generator) = line.split('=').nth(1) {
-                        return Some(generator.to_string());
+                // Entries have the form NAME:TYPE=VALUE
+                if let Some((key, value)) = line.split_once('=') {
+                    if key.split(':').next() == Some(name) {
+                        return Some(value.to_string());
                     }
                 }
             }
@@ -113,6 +194,11 @@ pub fn get_generator_from_cache(build_dir: &Path) -> Option<String> {
     }
 }
 
+/// Parse CMakeCache.txt to extract the generator used
+pub fn get_generator_from_cache(build_dir: &Path) -> Option<String> {
+    get_cmake_cache_entry(build_dir, "CMAKE_GENERATOR")
+}
+
 /// Get the appropriate generator for the build
 /// This follows ESP-IDF's logic:
 /// 1. Use explicit generator if provided
@@ -152,10 +238,63 @@ mod tests {
         #[cfg(not(target_os = "windows"))]
         {
             assert!(keys.len() >= 2);
-            assert!(keys.contains(&"Unix Makefiles"));
+            assert!(keys.contains(&&"Unix Makefiles".to_string()));
         }
     }
 
+    #[test]
+    fn test_build_command() {
+        let ninja = get_generator("Ninja").unwrap();
+        assert_eq!(
+            ninja.build_command(Path::new("build"), true, None, &["app".to_string()]),
+            vec!["ninja", "-C", "build", "-v", "app"]
+        );
+        assert_eq!(
+            ninja.build_command(Path::new("build"), false, Some(2), &[]),
+            vec!["ninja", "-C", "build", "-j", "2"]
+        );
+
+        let make = Generator {
+            command: vec!["make".to_string(), "-j".to_string(), "10".to_string()],
+            version: Vec::new(),
+            dry_run: Vec::new(),
+            verbose_flag: "VERBOSE=1".to_string(),
+            force_progression: false,
+        };
+        assert_eq!(
+            make.build_command(Path::new("build"), false, Some(2), &["-j4".to_string()]),
+            vec!["make", "-C", "build", "-j4"]
+        );
+        assert_eq!(
+            make.build_command(Path::new("build"), false, None, &[]),
+            vec!["make", "-j", "10", "-C", "build"]
+        );
+        assert_eq!(
+            make.build_command(Path::new("build"), false, Some(2), &[]),
+            vec!["make", "-C", "build", "-j", "2"]
+        );
+        assert!(get_generator("Ninja Multi-Config").is_none());
+
+        assert_eq!(
+            parse_ninja_status("[12/345] Building C object esp-idf/main/main.c.obj\n"),
+            Some((12, 345, "Building C object esp-idf/main/main.c.obj"))
+        );
+        assert_eq!(parse_ninja_status("[Warning] foo"), None);
+        assert_eq!(parse_ninja_status("main.c:1:2: warning: x"), None);
+
+        let status = parse_build_status("[1/4] Linking app.elf").unwrap();
+        assert_eq!((status.percent, status.targets), (25, Some((1, 4))));
+        assert_eq!(
+            parse_build_status("[  5%] Built target partition_table\n"),
+            Some(BuildStatus {
+                percent: 5,
+                targets: None,
+                description: "Built target partition_table",
+            })
+        );
+        assert_eq!(parse_build_status("[Warning] foo"), None);
+    }
+
     #[test]
     fn test_executable_exists() {
         // This should exist on most systems
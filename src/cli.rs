@@ -0,0 +1,1975 @@
+//! The command line: the global options, the commands and their dispatch
+
+use anyhow::Result;
+use clap::{Args, Parser, Subcommand};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::{annotations, commands, config, hints, output, serial_ports, state, utils};
+
+#[derive(Parser, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+#[command(name = "idf-rs")]
+#[command(about = "ESP-IDF CLI build management tool (Rust implementation)")]
+pub struct Cli {
+    /// Show IDF version and exit
+    #[arg(long = "idf-version")]
+    pub idf_version: bool,
+
+    /// Print list of supported targets and exit
+    #[arg(long, alias = "list-targets")]
+    pub list_targets: bool,
+
+    /// Project directory
+    #[arg(short = 'C', long = "project-dir")]
+    pub project_dir: Option<PathBuf>,
+
+    /// Build directory
+    #[arg(short = 'B', long = "build-dir")]
+    pub build_dir: Option<PathBuf>,
+
+    /// Use the port, baud rate and OpenOCD configuration of a profile from config.toml
+    #[arg(long, env = "IDF_RS_PROFILE")]
+    pub profile: Option<String>,
+
+    /// Default build directory pattern, e.g. "build.{target}" for one build directory per target
+    #[arg(long = "build-dir-pattern", env = "IDF_RS_BUILD_DIR_PATTERN")]
+    pub build_dir_pattern: Option<String>,
+
+    /// Verbose build output
+    #[arg(short, long)]
+    pub verbose: bool,
+
+    /// Enable IDF features that are still in preview
+    #[arg(long)]
+    pub preview: bool,
+
+    /// Use ccache in build
+    #[arg(long)]
+    pub ccache: bool,
+
+    /// Disable ccache in build
+    #[arg(long = "no-ccache")]
+    pub no_ccache: bool,
+
+    /// Compiler launcher replacing ccache, e.g. sccache, icecc or the path of a wrapper
+    #[arg(
+        long = "compiler-launcher",
+        value_name = "LAUNCHER",
+        conflicts_with = "ccache"
+    )]
+    pub compiler_launcher: Option<String>,
+
+    /// CMake generator
+    #[arg(short = 'G', long = "generator")]
+    pub generator: Option<String>,
+
+    /// Number of parallel build jobs, instead of the build tool's default
+    #[arg(short = 'j', long = "jobs", value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub jobs: Option<u32>,
+
+    /// Disable hints on how to resolve errors and logging
+    #[arg(long = "no-hints")]
+    pub no_hints: bool,
+
+    /// Create a cmake cache entry (can be repeated), kept for later builds of the build directory
+    #[arg(short = 'D', long = "define-cache-entry", action = clap::ArgAction::Append)]
+    pub define_cache_entry: Vec<String>,
+
+    /// Serial port
+    #[arg(short = 'p', long = "port", env = "ESPPORT")]
+    pub port: Option<String>,
+
+    /// Select the serial port by USB serial number or /dev/serial/by-id path
+    #[arg(long = "device-id", conflicts_with = "port")]
+    pub device_id: Option<String>,
+
+    /// Never prompt: probe several serial ports without asking and fail when chips answer on
+    /// more than one, don't ask before erasing
+    #[arg(long = "non-interactive")]
+    pub non_interactive: bool,
+
+    /// Don't ask before destructive commands such as erase-flash
+    #[arg(short = 'y', long = "yes")]
+    pub yes: bool,
+
+    /// Global baud rate
+    #[arg(short = 'b', long = "baud", env = "ESPBAUD")]
+    pub baud: Option<u32>,
+
+    /// Set an environment variable for the external commands of this invocation (can be repeated)
+    #[arg(long = "env", value_name = "KEY=VALUE", value_parser = utils::parse_env_assignment)]
+    pub env: Vec<(String, String)>,
+
+    /// Output format, json prints machine-readable events to stdout for CI
+    #[arg(long = "output-format", value_enum, default_value_t = output::OutputFormat::Text)]
+    pub output_format: output::OutputFormat,
+
+    /// Annotate compiler errors and warnings for CI, default github under GitHub Actions
+    #[arg(long = "annotations", value_enum)]
+    pub annotations: Option<annotations::Annotations>,
+
+    /// Fail when the app uses more than this percentage of its partition
+    #[arg(long = "app-size-threshold", default_value_t = 100)]
+    pub app_size_threshold: u8,
+
+    #[command(subcommand)]
+    pub command: Option<Commands>,
+}
+
+impl Cli {
+    /// The default options for a project, as `idf-rs -C <project_dir>` without a command.
+    /// Fails when an environment variable behind an option (e.g. ESPBAUD) is invalid.
+    pub fn for_project(project_dir: impl Into<PathBuf>) -> Result<Self> {
+        let mut cli = Cli::try_parse_from(["idf-rs"])?;
+        cli.project_dir = Some(project_dir.into());
+        Ok(cli)
+    }
+
+    /// Whether the user can be asked: stdin is a terminal, no CI system runs us and
+    /// neither --non-interactive nor JSON output was chosen
+    pub fn can_prompt(&self) -> bool {
+        use std::io::IsTerminal;
+
+        !self.non_interactive
+            && !output::is_json()
+            && std::env::var_os("CI").is_none()
+            && std::io::stdin().is_terminal()
+    }
+
+    /// Ask before an action that destroys data on the device, unless --yes was given or
+    /// nobody can be asked (CI and scripts keep working unattended)
+    pub fn confirm_destructive(&self, action: &str) -> Result<()> {
+        if self.yes || !self.can_prompt() {
+            return Ok(());
+        }
+        if utils::confirm(&format!("{} Continue?", action), false)? {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("Aborted, nothing was erased"))
+        }
+    }
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Commands {
+    /// Build the project
+    #[command(alias = "all")]
+    Build {
+        /// Don't print the memory usage summary after a successful build
+        #[arg(long = "no-size-summary")]
+        no_size_summary: bool,
+        /// Run a size analysis after a successful build
+        #[arg(
+            long,
+            value_enum,
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "summary"
+        )]
+        size: Option<commands::size::SizeReport>,
+        /// Additional build arguments
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Build only the app
+    App,
+    /// Build only bootloader
+    Bootloader,
+    /// Delete build output files from the build directory
+    Clean {
+        /// Only remove the object files and library of this component (repeatable)
+        #[arg(long = "component", value_name = "NAME")]
+        components: Vec<String>,
+    },
+    /// Delete the entire build directory contents
+    Fullclean,
+    /// Flash the project
+    Flash {
+        /// Extra arguments to pass to esptool
+        #[arg(long = "extra-args")]
+        extra_args: Option<String>,
+        /// Force write, skip security and compatibility checks
+        #[arg(long)]
+        force: bool,
+        /// Enable trace-level output of flasher tool interactions
+        #[arg(long)]
+        trace: bool,
+        /// Continue an interrupted flash instead of rewriting everything
+        #[arg(long)]
+        resume: bool,
+        /// Program over USB-JTAG with probe-rs instead of esptool
+        #[arg(long, conflicts_with = "resume")]
+        jtag: bool,
+        /// Flash every connected ESP device (USB serial ports with an ESP VID:PID) in parallel
+        #[arg(long = "all-ports", conflicts_with_all = ["resume", "jtag"])]
+        all_ports: bool,
+        /// Ports of a repeated -p/--port, flashed in parallel
+        #[arg(skip)]
+        ports: Vec<String>,
+        /// Flash arguments
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Flash the app only
+    AppFlash {
+        /// Extra arguments to pass to esptool
+        #[arg(long = "extra-args")]
+        extra_args: Option<String>,
+        /// Force write, skip security and compatibility checks
+        #[arg(long)]
+        force: bool,
+        /// Enable trace-level output of flasher tool interactions
+        #[arg(long)]
+        trace: bool,
+    },
+    /// Flash bootloader only
+    BootloaderFlash,
+    /// Flash the project with encryption by the device (flash encryption in development mode)
+    EncryptedFlash {
+        /// Force write, skip security and compatibility checks
+        #[arg(long)]
+        force: bool,
+    },
+    /// Flash the app only, encrypted by the device
+    EncryptedAppFlash {
+        /// Force write, skip security and compatibility checks
+        #[arg(long)]
+        force: bool,
+    },
+    /// Display serial output
+    Monitor {
+        #[command(flatten)]
+        options: commands::monitor::MonitorOptions,
+    },
+    /// Run "menuconfig" project configuration tool
+    Menuconfig {
+        /// Print the pending sdkconfig changes and discard them
+        #[arg(long = "dry-run")]
+        dry_run: bool,
+        /// Use the built-in menu instead of the Python/curses menuconfig
+        #[arg(long)]
+        native: bool,
+    },
+    /// Set sdkconfig options, e.g. set-config CONFIG_FOO=y CONFIG_BAR="baz"
+    SetConfig {
+        /// Options to set
+        #[arg(required = true, value_name = "CONFIG_NAME=VALUE")]
+        assignments: Vec<String>,
+        /// Don't reconfigure the project afterwards
+        #[arg(long = "no-reconfigure")]
+        no_reconfigure: bool,
+    },
+    /// Print the value of sdkconfig options
+    GetConfig {
+        /// Options to print
+        #[arg(required = true, value_name = "CONFIG_NAME")]
+        names: Vec<String>,
+    },
+    /// Set the chip target to build
+    SetTarget {
+        /// Target chip (e.g., esp32, esp32s3, etc.)
+        target: String,
+    },
+    /// Erase entire flash chip
+    EraseFlash,
+    /// List connected USB serial ports with their stable identifiers
+    ListPorts {
+        /// Print only the port names, one per line (e.g. for shell completion)
+        #[arg(long)]
+        names: bool,
+    },
+    /// Check the flashed regions of the device against the build's images
+    VerifyFlash,
+    /// Read a range of the device's flash into a file
+    ReadFlash {
+        /// Flash address, e.g. 0x9000
+        address: String,
+        /// Number of bytes, e.g. 0x6000 or 24K, or ALL for the whole flash
+        size: String,
+        /// File to write the flash contents to
+        output: PathBuf,
+    },
+    /// Back up a partition of the device, or its whole flash, to a file
+    DumpFlash {
+        /// Partition label, e.g. nvs (default: the whole flash)
+        #[arg(long)]
+        partition: Option<String>,
+        /// Output file (default: build/<partition>-dump.bin or build/flash-dump.bin)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Read the chip type, revision, features, MAC address and flash size of the device
+    ChipInfo {
+        /// Print the information as JSON, e.g. for provisioning scripts
+        #[arg(long)]
+        json: bool,
+    },
+    /// Combine the bootloader, partition table, app and other flashed binaries into one image
+    MergeBin {
+        /// Output file (default: build/merged-binary.<format>)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Output format
+        #[arg(long, value_enum, default_value = "bin")]
+        format: commands::flash::MergeFormat,
+        /// Pad the bin image to this flash size, e.g. 4MB
+        #[arg(long)]
+        fill_flash_size: Option<String>,
+    },
+    /// Convert the build's binaries into a UF2 image (build/uf2.bin) for the target's UF2 family
+    Uf2 {
+        /// Only include the app (build/uf2-app.bin), like idf.py uf2-app
+        #[arg(long)]
+        app_only: bool,
+    },
+    /// Copy the UF2 image to a mounted UF2 bootloader drive
+    Uf2Flash {
+        /// Only use drives whose volume name contains this text
+        #[arg(long)]
+        volume: Option<String>,
+        /// Only flash the app
+        #[arg(long)]
+        app_only: bool,
+    },
+    /// Pack the build's binaries into a USB DFU image (build/dfu.bin) for ESP32-S2/S3
+    Dfu,
+    /// Download the DFU image to a device in USB DFU mode with dfu-util
+    DfuFlash {
+        /// USB path of the device when several are connected, as shown by dfu-list
+        #[arg(long)]
+        path: Option<String>,
+    },
+    /// List the connected USB DFU devices
+    DfuList,
+    /// Print basic size information about the app
+    Size,
+    /// Print per-component size information
+    SizeComponents,
+    /// Print per-source-file size information
+    SizeFiles,
+    /// Re-run CMake
+    Reconfigure {
+        /// Also delete these build artifacts (comma separated)
+        #[arg(long = "clean-cache", value_enum, value_delimiter = ',')]
+        clean_cache: Vec<commands::build::CacheArtifact>,
+    },
+    /// Create a new project
+    CreateProject {
+        /// Project name
+        name: String,
+        /// Project path
+        #[arg(short, long)]
+        path: Option<PathBuf>,
+    },
+    /// Adopt an existing project: detect target and IDF version, check the layout, write idf.toml
+    Init {
+        /// Overwrite an existing idf.toml
+        #[arg(long)]
+        force: bool,
+    },
+    /// Print list of build system targets
+    BuildSystemTargets,
+    /// Convert a legacy component (component.mk or pre-IDF 4 CMake) to idf_component_register
+    MigrateComponent {
+        /// Component directory
+        dir: PathBuf,
+        /// Print the converted CMakeLists.txt instead of writing it
+        #[arg(long = "dry-run")]
+        dry_run: bool,
+    },
+    /// Compile a single source file
+    Compile {
+        /// Source file to compile
+        file: PathBuf,
+    },
+    /// Print the preprocessed output of a source file
+    Preprocess {
+        /// Source file to preprocess
+        file: PathBuf,
+        /// Write the output to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Print an annotated assembly listing of a source file or function
+    Asm {
+        /// Source file to generate assembly for
+        #[arg(required_unless_present = "function")]
+        file: Option<PathBuf>,
+        /// Disassemble only this function (from the file's object or the app ELF)
+        #[arg(long)]
+        function: Option<String>,
+        /// Write the listing to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Format the project sources with clang-format
+    Format {
+        /// Only check formatting, fail if any file would change
+        #[arg(long)]
+        check: bool,
+    },
+    /// Run static analysis (cppcheck) on the project sources
+    Analyze {
+        /// Fail when there are findings not recorded in the baseline
+        #[arg(long = "fail-on-new")]
+        fail_on_new: bool,
+        /// Record the current findings as the accepted baseline
+        #[arg(long = "update-baseline")]
+        update_baseline: bool,
+    },
+    /// Project configuration (sdkconfig) tools
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Component manifest (idf_component.yml) tools
+    Manifest {
+        #[command(subcommand)]
+        action: ManifestAction,
+    },
+    /// Summarize heap_caps_print_heap_info() reports from a device log
+    HeapAnalyze {
+        /// Log file with the captured output ("-" reads stdin)
+        file: PathBuf,
+        /// Export all snapshots to a CSV file
+        #[arg(long)]
+        csv: Option<PathBuf>,
+    },
+    /// Run OpenOCD for the target with FreeRTOS thread awareness
+    Openocd {
+        /// Don't enable FreeRTOS thread awareness
+        #[arg(long)]
+        no_rtos: bool,
+    },
+    /// Run GDB connected to OpenOCD, with FreeRTOS task commands
+    Gdb {
+        /// Don't load the FreeRTOS convenience commands
+        #[arg(long)]
+        no_rtos: bool,
+    },
+    /// Run GDB in its text UI, connected to OpenOCD
+    Gdbtui {
+        /// Don't load the FreeRTOS convenience commands
+        #[arg(long)]
+        no_rtos: bool,
+    },
+    /// Decode a core dump from the coredump partition or a file and print a crash summary
+    CoredumpInfo {
+        /// Core dump file (partition image, ELF core file or captured console log) instead of reading the device
+        #[arg(long)]
+        core: Option<PathBuf>,
+    },
+    /// Open a core dump from the coredump partition or a file in GDB
+    CoredumpDebug {
+        /// Core dump file (partition image, ELF core file or captured console log) instead of reading the device
+        #[arg(long)]
+        core: Option<PathBuf>,
+    },
+    /// Start OpenOCD in the background and run GDB against it, stopping OpenOCD when GDB exits
+    Debug {
+        /// Don't enable FreeRTOS thread awareness
+        #[arg(long)]
+        no_rtos: bool,
+        /// Use GDB's text UI
+        #[arg(long)]
+        tui: bool,
+        /// Debug over USB-JTAG with the probe-rs GDB server instead of OpenOCD
+        #[arg(long = "probe-rs")]
+        probe_rs: bool,
+    },
+    /// Package the app binary with version, target and SHA-256 metadata for OTA servers
+    OtaPackage {
+        /// Output directory (default: [ota] output_dir in idf.toml, or <build>/ota)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// PEM private key to sign the app binary with (default: [ota] signing_key)
+        #[arg(long = "sign-key")]
+        sign_key: Option<PathBuf>,
+    },
+    /// Serve the app image over HTTP(S) for esp_https_ota tests, logging device requests
+    OtaServe {
+        /// Port to listen on
+        #[arg(long, default_value_t = 8070)]
+        port: u16,
+        /// Serve over HTTPS with a self-signed certificate (generated in <build>/ota-serve)
+        #[arg(long)]
+        https: bool,
+        /// Host name or address the devices use, written into the certificate (default: this machine's address)
+        #[arg(long)]
+        host: Option<String>,
+        /// Serve this image instead of the built app binary
+        #[arg(long)]
+        file: Option<PathBuf>,
+    },
+    /// Print the project's partition table (generated without gen_esp32part.py), or edit its CSV
+    PartitionTable {
+        #[command(subcommand)]
+        action: Option<PartitionTableAction>,
+    },
+    /// Flash the partition table only
+    PartitionTableFlash,
+    /// Show the otadata entries and the boot and next update OTA partitions of the device
+    OtaInfo,
+    /// Select the OTA partition the bootloader starts
+    SwitchOtaPartition {
+        /// OTA partition label or slot number, e.g. ota_1 or 1
+        slot: String,
+    },
+    /// Erase otadata so the device boots the factory app
+    EraseOtadata,
+    /// Generate an NVS partition image from a CSV (like nvs_partition_gen.py)
+    NvsGen {
+        /// CSV with key,type,encoding,value rows
+        csv: PathBuf,
+        /// Image to write
+        output: PathBuf,
+        /// Partition size, e.g. 0x6000 or 24K
+        size: String,
+        /// Encrypt with the XTS keys of this key file
+        #[arg(long)]
+        keyfile: Option<PathBuf>,
+    },
+    /// Decode an NVS partition image into key/value pairs, printed as CSV
+    NvsDump {
+        /// NVS image, e.g. from read-partition nvs
+        input: PathBuf,
+        /// Decrypt with the XTS keys of this key file
+        #[arg(long)]
+        keyfile: Option<PathBuf>,
+    },
+    /// Build a SPIFFS image of a directory for a partition (with ESP-IDF's spiffsgen.py)
+    SpiffsGen {
+        /// Directory with the files to put in the image
+        dir: PathBuf,
+        /// Partition label, e.g. storage
+        partition: String,
+        /// Write the image to the partition after building it
+        #[arg(long)]
+        flash: bool,
+    },
+    /// Build a LittleFS image of a directory for a partition (with mklittlefs)
+    LittlefsGen {
+        /// Directory with the files to put in the image
+        dir: PathBuf,
+        /// Partition label, e.g. storage
+        partition: String,
+        /// Write the image to the partition after building it
+        #[arg(long)]
+        flash: bool,
+    },
+    /// Build a wear-levelled FATFS image of a directory for a partition (with ESP-IDF's fatfsgen)
+    FatfsGen {
+        /// Directory with the files to put in the image
+        dir: PathBuf,
+        /// Partition label, e.g. storage
+        partition: String,
+        /// Build a raw image without the wear levelling layer
+        #[arg(long)]
+        no_wear_levelling: bool,
+        /// Keep the modification times of the files instead of a fixed date
+        #[arg(long)]
+        preserve_time: bool,
+        /// Write the image to the partition after building it
+        #[arg(long)]
+        flash: bool,
+    },
+    /// Unpack a FATFS partition read from the device, or an image file, into a directory
+    FatfsExtract {
+        /// Directory to unpack the files into
+        output: PathBuf,
+        /// Partition label to read from the device, e.g. storage
+        #[arg(long, required_unless_present = "image", conflicts_with = "image")]
+        partition: Option<String>,
+        /// Unpack this image instead of reading the device
+        #[arg(long)]
+        image: Option<PathBuf>,
+    },
+    /// Erase a partition of the device by name
+    ErasePartition {
+        /// Partition label, e.g. nvs
+        name: String,
+    },
+    /// Read a partition of the device by name into a file
+    ReadPartition {
+        /// Partition label, e.g. storage
+        name: String,
+        /// File to write the partition contents to
+        output: PathBuf,
+    },
+    /// Write a file to a partition of the device by name, erasing the rest of it
+    WritePartition {
+        /// Partition label, e.g. storage
+        name: String,
+        /// Image to write
+        input: PathBuf,
+    },
+    /// Report secure boot, flash encryption, JTAG and efuse key state of the device
+    SecurityInfo,
+    /// Show the efuses of the device grouped by category
+    EfuseSummary {
+        /// Print the efuses as JSON
+        #[arg(long)]
+        json: bool,
+        /// Use virtual efuses of the project's target instead of a device
+        #[arg(long)]
+        virt: bool,
+    },
+    /// Burn an efuse field of the device (irreversible)
+    EfuseBurn {
+        /// Efuse name, e.g. DIS_USB_JTAG
+        field: String,
+        /// New value
+        value: String,
+        /// Use virtual efuses of the project's target instead of a device
+        #[arg(long)]
+        virt: bool,
+    },
+    /// Disable reading of an efuse key block by software (irreversible)
+    EfuseReadProtect {
+        /// Efuse block name, e.g. BLOCK_KEY0
+        name: String,
+        /// Use virtual efuses of the project's target instead of a device
+        #[arg(long)]
+        virt: bool,
+    },
+    /// Disable writing of an efuse (irreversible)
+    EfuseWriteProtect {
+        /// Efuse name, e.g. DIS_USB_JTAG
+        name: String,
+        /// Use virtual efuses of the project's target instead of a device
+        #[arg(long)]
+        virt: bool,
+    },
+    /// Run esptool of the ESP-IDF environment with the project's port and baud rate
+    Esptool {
+        /// esptool arguments, e.g. -- read_mac
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Run espefuse of the ESP-IDF environment with the project's port and baud rate
+    Espefuse {
+        /// espefuse arguments, e.g. -- summary
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Run espsecure of the ESP-IDF environment
+    Espsecure {
+        /// espsecure arguments, e.g. -- digest_sbv2_public_key --keyfile key.pem
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Sign a bootloader or app image for secure boot (espsecure.py sign_data)
+    SecureSignData {
+        /// Image to sign
+        datafile: PathBuf,
+        /// Private signing key (default: CONFIG_SECURE_BOOT_SIGNING_KEY)
+        #[arg(long)]
+        keyfile: Option<PathBuf>,
+        /// Write the signed image here instead of signing in place
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Generate a secure boot signing key (espsecure.py generate_signing_key)
+    SecureGenerateSigningKey {
+        /// File to write the private key to
+        keyfile: PathBuf,
+        /// Signing scheme, e.g. rsa3072 or ecdsa256 (default: the one of sdkconfig)
+        #[arg(long)]
+        scheme: Option<String>,
+    },
+    /// Summarize locally recorded build and flash metrics (opt in with [stats] enabled = true)
+    Stats {
+        /// Number of past days to summarize
+        #[arg(long, default_value_t = 28)]
+        days: u64,
+        /// Include every project instead of only the current one
+        #[arg(long)]
+        all: bool,
+    },
+    /// Capture the TRAX instruction trace memory via OpenOCD
+    Trace {
+        #[command(subcommand)]
+        action: TraceAction,
+    },
+    /// Build, flash and monitor the devices listed in devices.toml
+    Fleet {
+        #[command(subcommand)]
+        action: FleetAction,
+    },
+    /// Compiler cache (ccache/sccache) tools
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+    /// Print the compiler cache hit/miss statistics, e.g. after a build
+    CcacheStats,
+    /// Managed component dependency tools
+    Deps {
+        #[command(subcommand)]
+        action: DepsAction,
+    },
+    /// Serve build, flash, monitor, config and size requests over JSON-RPC, for IDEs
+    Daemon {
+        /// Local address to listen on
+        #[arg(long, value_name = "ADDR:PORT", default_value = "127.0.0.1:7878")]
+        listen: String,
+    },
+    /// Print the shell completion script, e.g. `source <(idf-rs completions bash)`
+    Completions { shell: commands::completions::Shell },
+    /// Install idf-rs as idf.py replacement (creates symlink)
+    InstallAlias {
+        /// Force installation even if backup exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Uninstall idf-rs alias and restore original idf.py
+    UninstallAlias,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum PartitionTableAction {
+    /// Change the size of a partition and move the partitions after it
+    Resize {
+        label: String,
+        /// New size, e.g. 0x180000, 1536K or 2M
+        size: String,
+        /// Partition CSV to edit instead of the one configured in sdkconfig
+        #[arg(long)]
+        file: Option<PathBuf>,
+    },
+    /// Add a partition ("label,type,subtype,size[,flags]" or a full CSV row)
+    Add {
+        spec: String,
+        /// Insert after this partition instead of at the end
+        #[arg(long)]
+        after: Option<String>,
+        /// Partition CSV to edit instead of the one configured in sdkconfig
+        #[arg(long)]
+        file: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum TraceAction {
+    /// Start tracing on the device
+    Start {
+        /// Stop tracing when this symbol or address is executed (e.g. esp_panic_handler)
+        #[arg(long = "stop-at")]
+        stop_at: Option<String>,
+    },
+    /// Stop tracing, save the trace memory in the build directory and decode it
+    Dump {
+        /// Decoder command, {dump} and {elf} are replaced with the dump and app ELF paths
+        #[arg(long, env = "IDF_RS_TRAX_DECODER")]
+        decoder: Option<String>,
+    },
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct FleetSelection {
+    /// Only devices in this group
+    #[arg(long)]
+    pub group: Option<String>,
+    /// Only the named device (can be repeated)
+    #[arg(long = "device")]
+    pub devices: Vec<String>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum FleetAction {
+    /// List the devices in devices.toml
+    List {
+        /// Only devices in this group
+        #[arg(long)]
+        group: Option<String>,
+    },
+    /// Build the variant of each selected device
+    Build {
+        #[command(flatten)]
+        selection: FleetSelection,
+    },
+    /// Build and flash the selected devices in parallel
+    Flash {
+        #[command(flatten)]
+        selection: FleetSelection,
+        /// Start monitoring each device after flashing
+        #[arg(long)]
+        monitor: bool,
+    },
+    /// Monitor the selected devices in parallel
+    Monitor {
+        #[command(flatten)]
+        selection: FleetSelection,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum CacheAction {
+    /// Show the effective cache configuration and verify the remote storage
+    Doctor,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum DepsAction {
+    /// Check dependencies.lock against the dependency policy and the registry
+    Audit {
+        /// Policy file (default: idf-deps-policy.yml in the project directory)
+        #[arg(long)]
+        policy: Option<PathBuf>,
+        /// Skip querying the component registry for yanked versions
+        #[arg(long)]
+        offline: bool,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ConfigAction {
+    /// Named copies of sdkconfig, kept in .sdkconfig-snapshots
+    Snapshot {
+        #[command(subcommand)]
+        action: SnapshotAction,
+    },
+    /// Find config options by name, prompt or help text (-v shows their help)
+    Search { pattern: String },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum SnapshotAction {
+    /// Save the current sdkconfig under a name
+    Save {
+        name: String,
+        /// Note stored with the snapshot
+        #[arg(short, long)]
+        message: Option<String>,
+        /// Overwrite an existing snapshot
+        #[arg(long)]
+        force: bool,
+    },
+    /// Replace sdkconfig with a saved snapshot
+    Restore { name: String },
+    /// List the saved snapshots
+    List,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ManifestAction {
+    /// Validate idf_component.yml files in the project
+    Lint,
+}
+
+#[cfg_attr(not(windows), allow(dead_code))]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct EimIdfConfig {
+    #[serde(rename = "gitPath")]
+    git_path: String,
+    #[serde(rename = "idfInstalled")]
+    idf_installed: Vec<EimIdfInstallation>,
+    #[serde(rename = "idfSelectedId")]
+    idf_selected_id: String,
+    #[serde(rename = "eimPath")]
+    eim_path: String,
+    version: String,
+}
+
+#[cfg_attr(not(windows), allow(dead_code))]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct EimIdfInstallation {
+    #[serde(rename = "activationScript")]
+    activation_script: String,
+    id: String,
+    #[serde(rename = "idfToolsPath")]
+    idf_tools_path: String,
+    name: String,
+    path: String,
+    python: String,
+}
+
+/// Derive the build directory from --build-dir-pattern and the active target
+fn apply_build_dir_pattern(cli: &mut Cli) -> Result<()> {
+    if cli.build_dir.is_some() {
+        return Ok(());
+    }
+
+    if let Some(pattern) = &cli.build_dir_pattern {
+        let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+        let sdk_config = config::load_project_config(&project_dir)?;
+        let env_target = utils::env_var("IDF_TARGET");
+        let target = sdk_config
+            .get_target()
+            .or(env_target.as_ref())
+            .map(|target| target.as_str())
+            .unwrap_or(utils::DEFAULT_TARGET);
+
+        let build_dir = utils::expand_build_dir_pattern(pattern, &project_dir, target);
+        if cli.verbose {
+            println!(
+                "Using build directory {} for target {}",
+                build_dir.display(),
+                target
+            );
+        }
+        cli.build_dir = Some(build_dir);
+    }
+
+    Ok(())
+}
+
+/// Resolve --device-id to the serial port it currently refers to
+fn apply_device_id(cli: &mut Cli) -> Result<()> {
+    if let Some(device_id) = &cli.device_id {
+        let port = serial_ports::resolve_device_id(device_id)?;
+        if cli.verbose {
+            println!("Device {} is at {}", device_id, port);
+        }
+        cli.port = Some(port);
+    }
+    Ok(())
+}
+
+/// Fill in the options the command line leaves unset from the selected profile
+fn apply_profile(cli: &mut Cli) -> Result<()> {
+    let Some(name) = &cli.profile else {
+        return Ok(());
+    };
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let profile = config::load_profile(&project_dir, name)?;
+    if cli.verbose {
+        println!("Using profile {}", name);
+    }
+
+    if cli.port.is_none() {
+        cli.port = profile.port;
+    }
+    if cli.baud.is_none() {
+        cli.baud = profile.baud;
+    }
+    if let Some(commands) = profile.openocd_commands {
+        if !cli.env.iter().any(|(name, _)| name == "OPENOCD_COMMANDS") {
+            cli.env.push(("OPENOCD_COMMANDS".to_string(), commands));
+        }
+    }
+    Ok(())
+}
+
+/// Fill in the options the command line leaves unset from the idf.toml defaults
+fn apply_project_settings(cli: &mut Cli) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let settings = config::load_project_settings(&project_dir)?;
+
+    if cli.port.is_none() {
+        cli.port = settings.port;
+    }
+    if cli.baud.is_none() {
+        cli.baud = settings.baud;
+    }
+    if cli.generator.is_none() {
+        cli.generator = settings.generator;
+    }
+
+    // -D entries of the command line come later and win over those of the same name
+    let mut defines = settings.defines;
+    if !settings.sdkconfig_defaults.is_empty() && utils::env_var("SDKCONFIG_DEFAULTS").is_none() {
+        defines.push(format!(
+            "SDKCONFIG_DEFAULTS={}",
+            settings.sdkconfig_defaults.join(";")
+        ));
+    }
+    defines.append(&mut cli.define_cache_entry);
+    cli.define_cache_entry = defines;
+
+    // The ESP-IDF build takes the target from the environment until sdkconfig has one
+    if let Some(target) = settings.target {
+        let configured = config::get_sdkconfig_path(&project_dir).exists()
+            || utils::env_var("IDF_TARGET").is_some();
+        if !configured {
+            cli.env.push(("IDF_TARGET".to_string(), target));
+        }
+    }
+    Ok(())
+}
+
+/// Use the serial settings and generator remembered for this project when none were given
+fn apply_project_state(cli: &mut Cli) {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+    let project_state = state::ProjectState::load(&build_dir);
+
+    if cli.port.is_none() {
+        if let Some(port) = project_state.port {
+            // A board that came back under another device node is detected again
+            let gone = serial_ports::transport(&port)
+                .is_ok_and(|port| port.can_reconnect() && !port.is_available());
+            if gone {
+                if cli.verbose {
+                    println!("Port {} from previous session is not available", port);
+                }
+            } else {
+                if cli.verbose {
+                    println!("Using port {} from previous session", port);
+                }
+                cli.port = Some(port);
+            }
+        }
+    }
+
+    // The flash baud rate is for the esptool commands, the monitor remembers its own
+    if cli.baud.is_none() && !matches!(cli.command, Some(Commands::Monitor { .. })) {
+        cli.baud = project_state.flash_baud;
+    }
+
+    if cli.generator.is_none() {
+        cli.generator = project_state.generator;
+    }
+}
+
+/// Fill in settings that depend on the project state: the build directory,
+/// the port behind --device-id and the remembered serial settings, and record
+/// the --env variables for the external commands
+pub fn resolve_defaults(cli: &mut Cli) -> Result<()> {
+    output::init(cli.output_format)?;
+    annotations::init(cli.annotations);
+    // The settings below read IDF_TARGET and SDKCONFIG with the --env values applied
+    utils::set_user_env(&cli.env);
+    apply_build_dir_pattern(cli)?;
+    apply_device_id(cli)?;
+    apply_profile(cli)?;
+    apply_project_settings(cli)?;
+    apply_project_state(cli);
+    utils::set_user_env(&cli.env);
+    Ok(())
+}
+
+/// Name under which a command's metrics are recorded, for the commands worth tracking
+fn stats_command_name(command: &Commands) -> Option<&'static str> {
+    match command {
+        Commands::Build { .. } => Some("build"),
+        Commands::App => Some("app"),
+        Commands::Bootloader => Some("bootloader"),
+        Commands::Flash { .. } => Some("flash"),
+        Commands::AppFlash { .. } => Some("app-flash"),
+        Commands::BootloaderFlash => Some("bootloader-flash"),
+        _ => None,
+    }
+}
+
+/// Name of a command as typed, e.g. "app-flash"
+fn command_name(command: &Commands) -> String {
+    let variant = format!("{:?}", command);
+    let variant = variant
+        .split(|c: char| !c.is_alphanumeric())
+        .next()
+        .unwrap_or_default();
+    let mut name = String::new();
+    for (i, c) in variant.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            name.push('-');
+        }
+        name.push(c.to_ascii_lowercase());
+    }
+    name
+}
+
+/// Commands running tools that need the terminal, whose output isn't logged
+fn is_interactive(command: &Commands) -> bool {
+    matches!(
+        command,
+        Commands::Monitor { .. }
+            | Commands::Menuconfig { .. }
+            | Commands::Openocd { .. }
+            | Commands::Gdb { .. }
+            | Commands::Gdbtui { .. }
+            | Commands::Debug { .. }
+            | Commands::CoredumpDebug { .. }
+    )
+}
+
+/// Commands talking to the device over the serial port
+fn uses_serial_port(command: &Commands) -> bool {
+    match command {
+        Commands::Flash {
+            jtag,
+            all_ports,
+            ports,
+            ..
+        } => !jtag && !all_ports && ports.is_empty(),
+        Commands::Monitor { options } => !options.probe_rs,
+        Commands::Esptool { args } | Commands::Espefuse { args } => {
+            !commands::tools::has_port_option(args)
+        }
+        Commands::EfuseSummary { virt, .. }
+        | Commands::EfuseBurn { virt, .. }
+        | Commands::EfuseReadProtect { virt, .. }
+        | Commands::EfuseWriteProtect { virt, .. } => !virt,
+        Commands::AppFlash { .. }
+        | Commands::BootloaderFlash
+        | Commands::EncryptedFlash { .. }
+        | Commands::EncryptedAppFlash { .. }
+        | Commands::EraseFlash
+        | Commands::ChipInfo { .. }
+        | Commands::VerifyFlash
+        | Commands::ReadFlash { .. }
+        | Commands::DumpFlash { .. }
+        | Commands::PartitionTableFlash
+        | Commands::OtaInfo
+        | Commands::SwitchOtaPartition { .. }
+        | Commands::EraseOtadata
+        | Commands::ErasePartition { .. }
+        | Commands::ReadPartition { .. }
+        | Commands::WritePartition { .. }
+        | Commands::SecurityInfo => true,
+        _ => false,
+    }
+}
+
+/// Run a command, recording its metrics when the user opted in
+pub async fn execute_command(cli: &Cli, command: &Commands) -> Result<()> {
+    // Without a port, look for the ESP device instead of leaving it to each tool
+    let detected;
+    let cli = if cli.port.is_none() && uses_serial_port(command) {
+        match commands::ports::detect_port(cli).await? {
+            Some(port) => {
+                detected = Cli {
+                    port: Some(port),
+                    ..cli.clone()
+                };
+                &detected
+            }
+            None => cli,
+        }
+    } else {
+        cli
+    };
+
+    // A missing port fails here rather than in each tool's connection retries, and a
+    // port another idf-rs uses before clobbering its session
+    let port_lock = match cli.port.as_deref().filter(|_| uses_serial_port(command)) {
+        Some(port) => {
+            if !serial_ports::transport(port)?.is_available() {
+                return Err(anyhow::anyhow!(
+                    "Serial port {} is not available: the device isn't connected, or the serial server (e.g. ser2net) of a remote port isn't running",
+                    port
+                ));
+            }
+            Some(serial_ports::PortLock::acquire(
+                port,
+                &command_name(command),
+            )?)
+        }
+        None => None,
+    };
+
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+    let log_dir = build_dir.join("log");
+    utils::set_output_log((!is_interactive(command)).then_some(log_dir.as_path()));
+
+    let recorder = match stats_command_name(command) {
+        Some(name) => commands::stats::start(cli, name).await,
+        None => None,
+    };
+
+    let name = command_name(command);
+    output::emit(&output::Event::CommandStarted {
+        command: name.clone(),
+    });
+    let started = std::time::Instant::now();
+
+    let result = dispatch_command(cli, command).await;
+
+    output::emit(&output::Event::CommandFinished {
+        command: name,
+        duration_ms: started.elapsed().as_millis() as u64,
+        success: result.is_ok(),
+        error: result.as_ref().err().map(|e| format!("{:#}", e)),
+    });
+
+    if let Some(recorder) = recorder {
+        recorder.finish(cli, &result).await;
+    }
+    if let (Err(e), false) = (&result, cli.no_hints) {
+        hints::print_for_failure(&build_dir, e);
+    }
+    if let Some(port_lock) = port_lock {
+        port_lock.keep_for_chain();
+    }
+    result
+}
+
+/// Dispatch a command to its implementation
+async fn dispatch_command(cli: &Cli, command: &Commands) -> Result<()> {
+    match command {
+        Commands::Build {
+            no_size_summary,
+            size,
+            args,
+        } => commands::build::execute(cli, args, !no_size_summary, *size).await,
+        Commands::App => commands::build::execute_app(cli).await,
+        Commands::Bootloader => commands::build::execute_bootloader(cli).await,
+        Commands::Clean { components } => commands::build::execute_clean(cli, components).await,
+        Commands::Fullclean => commands::build::execute_fullclean(cli).await,
+        Commands::Flash {
+            extra_args,
+            force,
+            trace,
+            resume,
+            jtag,
+            all_ports,
+            ports,
+            args,
+        } => {
+            if *jtag {
+                return commands::flash::execute_jtag(cli).await;
+            }
+            if *all_ports || !ports.is_empty() {
+                let options = commands::flash::MultiFlashOptions {
+                    extra_args: extra_args.clone(),
+                    force: *force,
+                    trace: *trace,
+                };
+                return commands::flash::execute_multi(cli, ports, *all_ports, &options).await;
+            }
+            commands::flash::execute(cli, args, extra_args.as_deref(), *force, *trace, *resume)
+                .await
+        }
+        Commands::AppFlash {
+            extra_args,
+            force,
+            trace,
+        } => commands::flash::execute_app(cli, extra_args.as_deref(), *force, *trace).await,
+        Commands::BootloaderFlash => commands::flash::execute_bootloader(cli).await,
+        Commands::EncryptedFlash { force } => {
+            commands::flash::execute_encrypted(cli, false, *force).await
+        }
+        Commands::EncryptedAppFlash { force } => {
+            commands::flash::execute_encrypted(cli, true, *force).await
+        }
+        Commands::Monitor { options } => commands::monitor::execute(cli, options).await,
+        Commands::Menuconfig { dry_run, native } => {
+            commands::config::execute_menuconfig(cli, *dry_run, *native).await
+        }
+        Commands::SetConfig {
+            assignments,
+            no_reconfigure,
+        } => commands::config::execute_set_config(cli, assignments, !no_reconfigure).await,
+        Commands::GetConfig { names } => commands::config::execute_get_config(cli, names).await,
+        Commands::SetTarget { target } => commands::config::execute_set_target(cli, target).await,
+        Commands::EraseFlash => commands::flash::execute_erase(cli).await,
+        Commands::ListPorts { names } => commands::ports::execute_list(*names).await,
+        Commands::VerifyFlash => commands::flash::execute_verify(cli).await,
+        Commands::ReadFlash {
+            address,
+            size,
+            output,
+        } => commands::flash::execute_read_flash(cli, address, size, output).await,
+        Commands::DumpFlash { partition, output } => {
+            commands::flash::execute_dump_flash(cli, partition.as_deref(), output.as_deref()).await
+        }
+        Commands::ChipInfo { json } => commands::chip::execute_info(cli, *json).await,
+        Commands::MergeBin {
+            output,
+            format,
+            fill_flash_size,
+        } => {
+            commands::flash::execute_merge_bin(
+                cli,
+                output.as_deref(),
+                *format,
+                fill_flash_size.as_deref(),
+            )
+            .await
+        }
+        Commands::Uf2 { app_only } => commands::uf2::execute(cli, *app_only).await,
+        Commands::Uf2Flash { volume, app_only } => {
+            commands::uf2::execute_flash(cli, volume.as_deref(), *app_only).await
+        }
+        Commands::Dfu => commands::dfu::execute(cli).await,
+        Commands::DfuFlash { path } => commands::dfu::execute_flash(cli, path.as_deref()).await,
+        Commands::DfuList => commands::dfu::execute_list(cli).await,
+        Commands::Size => commands::size::execute(cli).await,
+        Commands::SizeComponents => commands::size::execute_components(cli).await,
+        Commands::SizeFiles => commands::size::execute_files(cli).await,
+        Commands::Reconfigure { clean_cache } => {
+            commands::build::execute_reconfigure(cli, clean_cache).await
+        }
+        Commands::CreateProject { name, path } => {
+            commands::project::create_project(cli, name, path.as_deref()).await
+        }
+        Commands::Init { force } => commands::project::execute_init(cli, *force).await,
+        Commands::BuildSystemTargets => commands::build::list_build_targets(cli).await,
+        Commands::MigrateComponent { dir, dry_run } => {
+            commands::migrate::execute(cli, dir, *dry_run).await
+        }
+        Commands::Compile { file } => commands::compile::execute(cli, file).await,
+        Commands::Preprocess { file, output } => {
+            commands::compile::execute_preprocess(cli, file, output.as_deref()).await
+        }
+        Commands::Asm {
+            file,
+            function,
+            output,
+        } => {
+            commands::compile::execute_asm(
+                cli,
+                file.as_deref(),
+                function.as_deref(),
+                output.as_deref(),
+            )
+            .await
+        }
+        Commands::Format { check } => commands::format::execute(cli, *check).await,
+        Commands::Analyze {
+            fail_on_new,
+            update_baseline,
+        } => commands::analyze::execute(cli, *fail_on_new, *update_baseline).await,
+        Commands::Config { action } => match action {
+            ConfigAction::Snapshot { action } => match action {
+                SnapshotAction::Save {
+                    name,
+                    message,
+                    force,
+                } => {
+                    commands::config::execute_snapshot_save(cli, name, message.as_deref(), *force)
+                        .await
+                }
+                SnapshotAction::Restore { name } => {
+                    commands::config::execute_snapshot_restore(cli, name).await
+                }
+                SnapshotAction::List => commands::config::execute_snapshot_list(cli).await,
+            },
+            ConfigAction::Search { pattern } => {
+                commands::config::execute_search(cli, pattern).await
+            }
+        },
+        Commands::Manifest { action } => match action {
+            ManifestAction::Lint => commands::manifest::execute_lint(cli).await,
+        },
+        Commands::HeapAnalyze { file, csv } => {
+            commands::heap::execute(cli, file, csv.as_deref()).await
+        }
+        Commands::Openocd { no_rtos } => commands::debug::execute_openocd(cli, *no_rtos).await,
+        Commands::Gdb { no_rtos } => commands::debug::execute_gdb(cli, *no_rtos, false).await,
+        Commands::Gdbtui { no_rtos } => commands::debug::execute_gdb(cli, *no_rtos, true).await,
+        Commands::CoredumpInfo { core } => {
+            commands::coredump::execute_info(cli, core.as_deref()).await
+        }
+        Commands::CoredumpDebug { core } => {
+            commands::coredump::execute_debug(cli, core.as_deref()).await
+        }
+        Commands::Debug {
+            no_rtos,
+            tui,
+            probe_rs,
+        } => commands::debug::execute_debug(cli, *no_rtos, *tui, *probe_rs).await,
+        Commands::OtaPackage { output, sign_key } => {
+            commands::ota::execute_package(cli, output.as_deref(), sign_key.as_deref()).await
+        }
+        Commands::OtaServe {
+            port,
+            https,
+            host,
+            file,
+        } => {
+            commands::ota::execute_serve(cli, *port, *https, host.as_deref(), file.as_deref()).await
+        }
+        Commands::PartitionTable { action } => match action {
+            None => commands::partition_table::execute_print(cli).await,
+            Some(PartitionTableAction::Resize { label, size, file }) => {
+                commands::partition_table::execute_resize(cli, label, size, file.as_deref()).await
+            }
+            Some(PartitionTableAction::Add { spec, after, file }) => {
+                commands::partition_table::execute_add(cli, spec, after.as_deref(), file.as_deref())
+                    .await
+            }
+        },
+        Commands::PartitionTableFlash => commands::partition_table::execute_flash(cli).await,
+        Commands::OtaInfo => commands::ota::execute_info(cli).await,
+        Commands::SwitchOtaPartition { slot } => commands::ota::execute_switch(cli, slot).await,
+        Commands::EraseOtadata => commands::ota::execute_erase_otadata(cli).await,
+        Commands::NvsGen {
+            csv,
+            output,
+            size,
+            keyfile,
+        } => commands::nvs::execute_gen(cli, csv, output, size, keyfile.as_deref()).await,
+        Commands::NvsDump { input, keyfile } => {
+            commands::nvs::execute_dump(cli, input, keyfile.as_deref()).await
+        }
+        Commands::SpiffsGen {
+            dir,
+            partition,
+            flash,
+        } => commands::filesystem::execute_spiffs_gen(cli, dir, partition, *flash).await,
+        Commands::LittlefsGen {
+            dir,
+            partition,
+            flash,
+        } => commands::filesystem::execute_littlefs_gen(cli, dir, partition, *flash).await,
+        Commands::FatfsGen {
+            dir,
+            partition,
+            no_wear_levelling,
+            preserve_time,
+            flash,
+        } => {
+            commands::filesystem::execute_fatfs_gen(
+                cli,
+                dir,
+                partition,
+                *no_wear_levelling,
+                *preserve_time,
+                *flash,
+            )
+            .await
+        }
+        Commands::FatfsExtract {
+            output,
+            partition,
+            image,
+        } => {
+            commands::filesystem::execute_fatfs_extract(
+                cli,
+                output,
+                partition.as_deref(),
+                image.as_deref(),
+            )
+            .await
+        }
+        Commands::ErasePartition { name } => {
+            commands::partition_table::execute_erase_partition(cli, name).await
+        }
+        Commands::ReadPartition { name, output } => {
+            commands::partition_table::execute_read_partition(cli, name, output).await
+        }
+        Commands::WritePartition { name, input } => {
+            commands::partition_table::execute_write_partition(cli, name, input).await
+        }
+        Commands::SecurityInfo => commands::security::execute(cli).await,
+        Commands::EfuseSummary { json, virt } => {
+            commands::efuse::execute_summary(cli, *json, *virt).await
+        }
+        Commands::EfuseBurn { field, value, virt } => {
+            commands::efuse::execute_burn(cli, field, value, *virt).await
+        }
+        Commands::EfuseReadProtect { name, virt } => {
+            commands::efuse::execute_read_protect(cli, name, *virt).await
+        }
+        Commands::EfuseWriteProtect { name, virt } => {
+            commands::efuse::execute_write_protect(cli, name, *virt).await
+        }
+        Commands::Esptool { args } => {
+            commands::tools::execute(cli, commands::tools::Tool::Esptool, args).await
+        }
+        Commands::Espefuse { args } => {
+            commands::tools::execute(cli, commands::tools::Tool::Espefuse, args).await
+        }
+        Commands::Espsecure { args } => {
+            commands::tools::execute(cli, commands::tools::Tool::Espsecure, args).await
+        }
+        Commands::SecureSignData {
+            datafile,
+            keyfile,
+            output,
+        } => {
+            commands::security::execute_sign_data(
+                cli,
+                datafile,
+                keyfile.as_deref(),
+                output.as_deref(),
+            )
+            .await
+        }
+        Commands::SecureGenerateSigningKey { keyfile, scheme } => {
+            commands::security::execute_generate_signing_key(cli, keyfile, scheme.as_deref()).await
+        }
+        Commands::Stats { days, all } => commands::stats::execute(cli, *days, *all).await,
+        Commands::Trace { action } => match action {
+            TraceAction::Start { stop_at } => {
+                commands::trace::execute_start(cli, stop_at.as_deref()).await
+            }
+            TraceAction::Dump { decoder } => {
+                commands::trace::execute_dump(cli, decoder.as_deref()).await
+            }
+        },
+        Commands::Fleet { action } => {
+            use commands::fleet::FleetOperation;
+            let (operation, selection) = match action {
+                FleetAction::List { group } => {
+                    return commands::fleet::execute_list(cli, group.as_deref()).await
+                }
+                FleetAction::Build { selection } => (FleetOperation::Build, selection),
+                FleetAction::Flash { selection, monitor } => {
+                    (FleetOperation::Flash { monitor: *monitor }, selection)
+                }
+                FleetAction::Monitor { selection } => (FleetOperation::Monitor, selection),
+            };
+            commands::fleet::execute(
+                cli,
+                operation,
+                selection.group.as_deref(),
+                &selection.devices,
+            )
+            .await
+        }
+        Commands::Cache { action } => match action {
+            CacheAction::Doctor => commands::cache::execute_doctor(cli).await,
+        },
+        Commands::CcacheStats => commands::cache::execute_stats(cli).await,
+        Commands::Deps { action } => match action {
+            DepsAction::Audit { policy, offline } => {
+                commands::deps::execute_audit(cli, policy.as_deref(), *offline).await
+            }
+        },
+        Commands::Completions { shell } => commands::completions::execute(*shell).await,
+        Commands::Daemon { listen } => commands::daemon::execute(cli, listen).await,
+        Commands::InstallAlias { force } => execute_install_alias(*force).await,
+        Commands::UninstallAlias => execute_uninstall_alias().await,
+    }
+}
+
+/// Install idf-rs as idf.py replacement
+async fn execute_install_alias(force: bool) -> Result<()> {
+    println!("Installing idf-rs as idf.py replacement...");
+
+    #[cfg(windows)]
+    {
+        execute_install_alias_windows(force).await
+    }
+
+    #[cfg(not(windows))]
+    {
+        execute_install_alias_unix(force).await
+    }
+}
+
+/// Windows-specific install-alias implementation using EIM
+#[cfg(windows)]
+async fn execute_install_alias_windows(force: bool) -> Result<()> {
+    use std::path::Path;
+
+    // Read EIM configuration
+    let eim_config_path = Path::new("C:\\Espressif\\tools\\eim_idf.json");
+    if !eim_config_path.exists() {
+        return Err(anyhow::anyhow!(
+            "EIM configuration not found at {}. Please ensure ESP-IDF is installed via EIM (Espressif Installation Manager).",
+            eim_config_path.display()
+        ));
+    }
+
+    let config_content = std::fs::read_to_string(eim_config_path)
+        .map_err(|e| anyhow::anyhow!("Failed to read EIM configuration: {}", e))?;
+
+    let config: EimIdfConfig = serde_json::from_str(&config_content)
+        .map_err(|e| anyhow::anyhow!("Failed to parse EIM configuration: {}", e))?;
+
+    // Find the current ESP-IDF installation's tools path
+    let current_installation = config
+        .idf_installed
+        .iter()
+        .find(|install| install.id == config.idf_selected_id)
+        .ok_or_else(|| anyhow::anyhow!("Current ESP-IDF installation not found in EIM config"))?;
+
+    println!(
+        "Found ESP-IDF installation: {} at {}",
+        current_installation.name, current_installation.path
+    );
+    println!("Tools path: {}", current_installation.idf_tools_path);
+
+    // The idf-exe directory structure
+    let idf_exe_dir = Path::new(&current_installation.idf_tools_path).join("idf-exe");
+    if !idf_exe_dir.exists() {
+        return Err(anyhow::anyhow!(
+            "idf-exe directory not found at {}. This might not be a complete EIM installation.",
+            idf_exe_dir.display()
+        ));
+    }
+
+    // Find the idf.py.exe version directory (should be something like "1.0.3")
+    let version_dirs: Vec<_> = std::fs::read_dir(&idf_exe_dir)
+        .map_err(|e| anyhow::anyhow!("Failed to read idf-exe directory: {}", e))?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let path = entry.path();
+            if path.is_dir() {
+                Some(path)
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    if version_dirs.is_empty() {
+        return Err(anyhow::anyhow!(
+            "No version directories found in {}",
+            idf_exe_dir.display()
+        ));
+    }
+
+    // Use the first (and typically only) version directory
+    let version_dir = &version_dirs[0];
+    let original_idf_exe = version_dir.join("idf.py.exe");
+
+    if !original_idf_exe.exists() {
+        return Err(anyhow::anyhow!(
+            "idf.py.exe not found at {}",
+            original_idf_exe.display()
+        ));
+    }
+
+    println!(
+        "Found original idf.py.exe at: {}",
+        original_idf_exe.display()
+    );
+
+    // Create backup
+    let backup_path = version_dir.join("idf.py.exe.backup");
+    if backup_path.exists() {
+        if !force {
+            return Err(anyhow::anyhow!(
+                "Backup already exists at {}. Use --force to overwrite.",
+                backup_path.display()
+            ));
+        } else {
+            println!("Removing existing backup: {}", backup_path.display());
+            std::fs::remove_file(&backup_path)
+                .map_err(|e| anyhow::anyhow!("Failed to remove existing backup: {}", e))?;
+        }
+    }
+
+    // Check if we're already installed
+    if original_idf_exe.metadata().map(|m| m.len()).unwrap_or(0)
+        == std::env::current_exe().unwrap().metadata().unwrap().len()
+    {
+        println!("idf-rs appears to already be installed as idf.py.exe");
+        return Ok(());
+    }
+
+    // Create backup of original
+    println!(
+        "Creating backup: {} -> {}",
+        original_idf_exe.display(),
+        backup_path.display()
+    );
+    std::fs::copy(&original_idf_exe, &backup_path)
+        .map_err(|e| anyhow::anyhow!("Failed to create backup: {}", e))?;
+
+    // Get current executable path
+    let current_exe = std::env::current_exe()
+        .map_err(|e| anyhow::anyhow!("Failed to get current executable path: {}", e))?;
+
+    // Replace the original idf.py.exe with our binary
+    println!(
+        "Replacing idf.py.exe: {} -> {}",
+        current_exe.display(),
+        original_idf_exe.display()
+    );
+    std::fs::copy(&current_exe, &original_idf_exe).map_err(|e| {
+        // Try to restore backup if copy fails
+        let _ = std::fs::copy(&backup_path, &original_idf_exe);
+        let _ = std::fs::remove_file(&backup_path);
+        anyhow::anyhow!("Failed to replace idf.py.exe: {}", e)
+    })?;
+
+    println!("✅ Successfully installed idf-rs as idf.py replacement!");
+    println!(
+        "   Original idf.py.exe backed up to: {}",
+        backup_path.display()
+    );
+    println!("   idf.py.exe now points to idf-rs");
+    println!();
+    println!("You can now use 'idf.py' commands and they will use the fast Rust implementation.");
+    println!("To restore the original, run: idf-rs uninstall-alias");
+
+    Ok(())
+}
+
+/// Unix-specific install-alias implementation using symlinks
+#[cfg(not(windows))]
+async fn execute_install_alias_unix(force: bool) -> Result<()> {
+    use std::path::Path;
+    use std::process::Command;
+
+    // Find the current idf.py location
+    let idf_py_output = Command::new("which")
+        .arg("idf.py")
+        .output()
+        .map_err(|e| anyhow::anyhow!("Failed to locate idf.py: {}", e))?;
+
+    if !idf_py_output.status.success() {
+        return Err(anyhow::anyhow!(
+            "idf.py not found in PATH. Please ensure ESP-IDF is properly installed."
+        ));
+    }
+
+    let idf_py_path = String::from_utf8(idf_py_output.stdout)
+        .map_err(|e| anyhow::anyhow!("Invalid UTF-8 in idf.py path: {}", e))?
+        .trim()
+        .to_string();
+
+    let idf_py_path = Path::new(&idf_py_path);
+
+    // Find the current idf-rs location
+    let idf_rs_output = Command::new("which")
+        .arg("idf-rs")
+        .output()
+        .map_err(|e| anyhow::anyhow!("Failed to locate idf-rs: {}", e))?;
+
+    if !idf_rs_output.status.success() {
+        return Err(anyhow::anyhow!(
+            "idf-rs not found in PATH. Please install idf-rs first."
+        ));
+    }
+
+    let idf_rs_path = String::from_utf8(idf_rs_output.stdout)
+        .map_err(|e| anyhow::anyhow!("Invalid UTF-8 in idf-rs path: {}", e))?
+        .trim()
+        .to_string();
+
+    println!("Found idf.py at: {}", idf_py_path.display());
+    println!("Found idf-rs at: {}", idf_rs_path);
+
+    // Create backup path
+    let backup_path = idf_py_path
+        .parent()
+        .ok_or_else(|| anyhow::anyhow!("Cannot determine parent directory of idf.py"))?
+        .join("idf-old.py");
+
+    // Check if backup already exists
+    if backup_path.exists() {
+        if !force {
+            return Err(anyhow::anyhow!(
+                "Backup already exists at {}. Use --force to overwrite.",
+                backup_path.display()
+            ));
+        } else {
+            println!("Removing existing backup: {}", backup_path.display());
+            std::fs::remove_file(&backup_path)
+                .map_err(|e| anyhow::anyhow!("Failed to remove existing backup: {}", e))?;
+        }
+    }
+
+    // Check if idf.py is already a symlink to idf-rs
+    if idf_py_path.is_symlink() {
+        let target = std::fs::read_link(idf_py_path)
+            .map_err(|e| anyhow::anyhow!("Failed to read symlink target: {}", e))?;
+
+        if target.to_string_lossy().contains("idf-rs") {
+            println!("idf.py is already linked to idf-rs: {}", target.display());
+            return Ok(());
+        }
+    }
+
+    // Step 1: Rename idf.py to idf-old.py
+    println!(
+        "Creating backup: {} -> {}",
+        idf_py_path.display(),
+        backup_path.display()
+    );
+    std::fs::rename(idf_py_path, &backup_path)
+        .map_err(|e| anyhow::anyhow!("Failed to create backup: {}", e))?;
+
+    // Step 2: Create symlink from idf.py to idf-rs
+    println!(
+        "Creating symlink: {} -> {}",
+        idf_py_path.display(),
+        idf_rs_path
+    );
+
+    std::os::unix::fs::symlink(&idf_rs_path, idf_py_path).map_err(|e| {
+        // Try to restore backup if symlink creation fails
+        let _ = std::fs::rename(&backup_path, idf_py_path);
+        anyhow::anyhow!("Failed to create symlink: {}", e)
+    })?;
+
+    println!("✅ Successfully installed idf-rs as idf.py replacement!");
+    println!("   Original idf.py backed up to: {}", backup_path.display());
+    println!("   idf.py now points to: {}", idf_rs_path);
+    println!();
+    println!("You can now use 'idf.py' commands and they will use the fast Rust implementation.");
+    println!("To restore the original, run: idf-rs uninstall-alias");
+
+    Ok(())
+}
+
+/// Uninstall idf-rs alias and restore original idf.py
+async fn execute_uninstall_alias() -> Result<()> {
+    println!("Uninstalling idf-rs alias and restoring original idf.py...");
+
+    #[cfg(windows)]
+    {
+        execute_uninstall_alias_windows().await
+    }
+
+    #[cfg(not(windows))]
+    {
+        execute_uninstall_alias_unix().await
+    }
+}
+
+/// Windows-specific uninstall-alias implementation
+#[cfg(windows)]
+async fn execute_uninstall_alias_windows() -> Result<()> {
+    use std::path::Path;
+
+    // Read EIM configuration
+    let eim_config_path = Path::new("C:\\Espressif\\tools\\eim_idf.json");
+    if !eim_config_path.exists() {
+        return Err(anyhow::anyhow!(
+            "EIM configuration not found at {}. Please ensure ESP-IDF is installed via EIM.",
+            eim_config_path.display()
+        ));
+    }
+
+    let config_content = std::fs::read_to_string(eim_config_path)
+        .map_err(|e| anyhow::anyhow!("Failed to read EIM configuration: {}", e))?;
+
+    let config: EimIdfConfig = serde_json::from_str(&config_content)
+        .map_err(|e| anyhow::anyhow!("Failed to parse EIM configuration: {}", e))?;
+
+    // Find the current ESP-IDF installation's tools path
+    let current_installation = config
+        .idf_installed
+        .iter()
+        .find(|install| install.id == config.idf_selected_id)
+        .ok_or_else(|| anyhow::anyhow!("Current ESP-IDF installation not found in EIM config"))?;
+
+    // The idf-exe directory structure
+    let idf_exe_dir = Path::new(&current_installation.idf_tools_path).join("idf-exe");
+    if !idf_exe_dir.exists() {
+        return Err(anyhow::anyhow!(
+            "idf-exe directory not found at {}.",
+            idf_exe_dir.display()
+        ));
+    }
+
+    // Find the version directory
+    let version_dirs: Vec<_> = std::fs::read_dir(&idf_exe_dir)
+        .map_err(|e| anyhow::anyhow!("Failed to read idf-exe directory: {}", e))?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let path = entry.path();
+            if path.is_dir() {
+                Some(path)
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    if version_dirs.is_empty() {
+        return Err(anyhow::anyhow!(
+            "No version directories found in {}",
+            idf_exe_dir.display()
+        ));
+    }
+
+    let version_dir = &version_dirs[0];
+    let current_idf_exe = version_dir.join("idf.py.exe");
+    let backup_path = version_dir.join("idf.py.exe.backup");
+
+    // Check if backup exists
+    if !backup_path.exists() {
+        return Err(anyhow::anyhow!(
+            "No backup found at {}. Cannot restore original idf.py.exe.",
+            backup_path.display()
+        ));
+    }
+
+    // Check if current idf.py.exe exists
+    if !current_idf_exe.exists() {
+        return Err(anyhow::anyhow!(
+            "Current idf.py.exe not found at {}.",
+            current_idf_exe.display()
+        ));
+    }
+
+    println!("Found backup at: {}", backup_path.display());
+    println!("Restoring to: {}", current_idf_exe.display());
+
+    // Remove current idf.py.exe
+    std::fs::remove_file(&current_idf_exe)
+        .map_err(|e| anyhow::anyhow!("Failed to remove current idf.py.exe: {}", e))?;
+
+    // Restore from backup
+    println!(
+        "Restoring backup: {} -> {}",
+        backup_path.display(),
+        current_idf_exe.display()
+    );
+    std::fs::copy(&backup_path, &current_idf_exe)
+        .map_err(|e| anyhow::anyhow!("Failed to restore backup: {}", e))?;
+
+    // Remove backup file
+    std::fs::remove_file(&backup_path)
+        .map_err(|e| anyhow::anyhow!("Failed to remove backup file: {}", e))?;
+
+    println!("✅ Successfully restored original idf.py.exe!");
+    println!("   idf.py.exe now points to the original ESP-IDF Python implementation.");
+
+    Ok(())
+}
+
+/// Unix-specific uninstall-alias implementation
+#[cfg(not(windows))]
+async fn execute_uninstall_alias_unix() -> Result<()> {
+    use std::path::Path;
+    use std::process::Command;
+
+    // Find the current idf.py location
+    let idf_py_output = Command::new("which")
+        .arg("idf.py")
+        .output()
+        .map_err(|e| anyhow::anyhow!("Failed to locate idf.py: {}", e))?;
+
+    if !idf_py_output.status.success() {
+        return Err(anyhow::anyhow!("idf.py not found in PATH."));
+    }
+
+    let idf_py_path = String::from_utf8(idf_py_output.stdout)
+        .map_err(|e| anyhow::anyhow!("Invalid UTF-8 in idf.py path: {}", e))?
+        .trim()
+        .to_string();
+
+    let idf_py_path = Path::new(&idf_py_path);
+
+    // Create backup path
+    let backup_path = idf_py_path
+        .parent()
+        .ok_or_else(|| anyhow::anyhow!("Cannot determine parent directory of idf.py"))?
+        .join("idf-old.py");
+
+    // Check if backup exists
+    if !backup_path.exists() {
+        return Err(anyhow::anyhow!(
+            "No backup found at {}. Cannot restore original idf.py.",
+            backup_path.display()
+        ));
+    }
+
+    // Check if current idf.py is our symlink
+    if !idf_py_path.is_symlink() {
+        return Err(anyhow::anyhow!(
+            "Current idf.py at {} is not a symlink. Manual intervention required.",
+            idf_py_path.display()
+        ));
+    }
+
+    // Remove the symlink
+    println!("Removing symlink: {}", idf_py_path.display());
+    std::fs::remove_file(idf_py_path)
+        .map_err(|e| anyhow::anyhow!("Failed to remove symlink: {}", e))?;
+
+    // Restore the backup
+    println!(
+        "Restoring backup: {} -> {}",
+        backup_path.display(),
+        idf_py_path.display()
+    );
+    std::fs::rename(&backup_path, idf_py_path)
+        .map_err(|e| anyhow::anyhow!("Failed to restore backup: {}", e))?;
+
+    println!("✅ Successfully restored original idf.py!");
+    println!("   idf.py now points to the original ESP-IDF Python implementation.");
+
+    Ok(())
+}
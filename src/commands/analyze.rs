@@ -0,0 +1,227 @@
+use crate::{build_systems, compile_commands, utils, Cli};
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+/// Output template that is easy to parse back
+const CPPCHECK_TEMPLATE: &str = "{file}:{line}:{severity}:{id}:{message}";
+
+/// Baseline of accepted findings, stored in the project root so it can be committed
+const BASELINE_FILE: &str = ".cppcheck-baseline";
+
+/// Checks that produce noise on ESP-IDF projects
+const IDF_SUPPRESSIONS: &[&str] = &[
+    // System headers come from the toolchain sysroot
+    "missingIncludeSystem",
+    // app_main and ISR handlers are only referenced by the framework
+    "unusedFunction",
+    "unmatchedSuppression",
+    // FreeRTOS and driver macros trip cppcheck's preprocessor
+    "unknownMacro",
+];
+
+/// A single cppcheck finding
+#[derive(Debug, Clone, PartialEq)]
+struct Finding {
+    file: String,
+    line: u32,
+    severity: String,
+    id: String,
+    message: String,
+}
+
+impl Finding {
+    /// Key used to match findings against the baseline. Line numbers are left
+    /// out so unrelated edits don't turn existing findings into new ones.
+    fn baseline_key(&self) -> String {
+        format!("{}:{}:{}", self.file, self.id, self.message)
+    }
+}
+
+fn parse_findings(output: &str, project_dir: &Path) -> Vec<Finding> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(5, ':');
+            let file = parts.next()?;
+            let line = parts.next()?.parse().ok()?;
+            let severity = parts.next()?;
+            let id = parts.next()?;
+            let message = parts.next()?;
+
+            let file = Path::new(file)
+                .strip_prefix(project_dir)
+                .unwrap_or(Path::new(file));
+
+            Some(Finding {
+                file: file.to_string_lossy().to_string(),
+                line,
+                severity: severity.to_string(),
+                id: id.to_string(),
+                message: message.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Name of the component a project-relative source file belongs to
+fn component_name(file: &str) -> String {
+    let mut parts = file.split('/');
+    match parts.next() {
+        Some("components") => parts.next().unwrap_or("components").to_string(),
+        Some(first) => first.to_string(),
+        None => file.to_string(),
+    }
+}
+
+fn load_baseline(project_dir: &Path) -> Result<BTreeSet<String>> {
+    let path = project_dir.join(BASELINE_FILE);
+    if !path.exists() {
+        return Ok(BTreeSet::new());
+    }
+
+    Ok(std::fs::read_to_string(path)?
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.to_string())
+        .collect())
+}
+
+pub async fn execute(cli: &Cli, fail_on_new: bool, update_baseline: bool) -> Result<()> {
+    utils::setup_idf_environment()?;
+
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+
+    if !build_systems::executable_exists(&["cppcheck".to_string(), "--version".to_string()]) {
+        return Err(anyhow::anyhow!(
+            "cppcheck not found in PATH. Install it to use the 'analyze' command."
+        ));
+    }
+
+    let compile_commands_path = compile_commands::get_compile_commands_path(&build_dir);
+    if !compile_commands_path.exists() {
+        println!("Compilation database doesn't exist. Configuring project first...");
+        crate::commands::build::execute_reconfigure(cli, &[]).await?;
+    }
+
+    println!("Running static analysis...");
+
+    let idf_path = utils::get_idf_path()?;
+    let report_path = build_dir.join("cppcheck.txt");
+
+    let mut cppcheck_args = vec![
+        format!("--project={}", compile_commands_path.display()),
+        "--enable=warning,style,performance,portability".to_string(),
+        "--inline-suppr".to_string(),
+        "--quiet".to_string(),
+        format!("--template={}", CPPCHECK_TEMPLATE),
+        format!("--output-file={}", report_path.display()),
+        format!("-j{}", num_cpus::get()),
+        // Only analyze the project's own sources, not ESP-IDF or managed components
+        format!("--file-filter={}/*", project_dir.display()),
+        format!("--suppress=*:{}/*", idf_path.display()),
+        format!("--suppress=*:{}/*", build_dir.display()),
+        format!(
+            "--suppress=*:{}/managed_components/*",
+            project_dir.display()
+        ),
+    ];
+    cppcheck_args.extend(
+        IDF_SUPPRESSIONS
+            .iter()
+            .map(|id| format!("--suppress={}", id)),
+    );
+
+    let cppcheck_args: Vec<&str> = cppcheck_args.iter().map(|s| s.as_str()).collect();
+    utils::run_command("cppcheck", &cppcheck_args, Some(&project_dir), cli.verbose).await?;
+
+    let report = std::fs::read_to_string(&report_path).unwrap_or_default();
+    let findings = parse_findings(&report, &project_dir);
+
+    // Summarize findings per component and severity
+    let mut summary: BTreeMap<String, BTreeMap<String, usize>> = BTreeMap::new();
+    for finding in &findings {
+        *summary
+            .entry(component_name(&finding.file))
+            .or_default()
+            .entry(finding.severity.clone())
+            .or_default() += 1;
+    }
+
+    if findings.is_empty() {
+        println!("No findings.");
+    } else {
+        for finding in &findings {
+            println!(
+                "{}:{}: {}: {} [{}]",
+                finding.file, finding.line, finding.severity, finding.message, finding.id
+            );
+        }
+
+        println!();
+        println!("Findings per component:");
+        for (component, severities) in &summary {
+            let counts: Vec<String> = severities
+                .iter()
+                .map(|(severity, count)| format!("{} {}", count, severity))
+                .collect();
+            println!("  {:<24} {}", component, counts.join(", "));
+        }
+    }
+    println!("Full report: {}", report_path.display());
+
+    if update_baseline {
+        let mut content = String::from(
+            "# Accepted cppcheck findings, generated by 'idf-rs analyze --update-baseline'\n",
+        );
+        let keys: BTreeSet<String> = findings.iter().map(|f| f.baseline_key()).collect();
+        for key in keys {
+            content.push_str(&key);
+            content.push('\n');
+        }
+        std::fs::write(project_dir.join(BASELINE_FILE), content)?;
+        println!(
+            "Baseline updated: {}",
+            project_dir.join(BASELINE_FILE).display()
+        );
+        return Ok(());
+    }
+
+    if fail_on_new {
+        let baseline = load_baseline(&project_dir)?;
+        let new_findings = findings
+            .iter()
+            .filter(|finding| !baseline.contains(&finding.baseline_key()))
+            .count();
+
+        if new_findings > 0 {
+            return Err(anyhow::anyhow!(
+                "{} new finding(s) not present in {}",
+                new_findings,
+                BASELINE_FILE
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_findings() {
+        let output = "/project/components/sensor/sensor.c:42:warning:uninitvar:Uninitialized variable: x\n\
+                      /project/main/main.c:7:style:variableScope:The scope of the variable 'i' can be reduced.\n";
+        let findings = parse_findings(output, Path::new("/project"));
+
+        assert_eq!(findings.len(), 2);
+        assert_eq!(findings[0].file, "components/sensor/sensor.c");
+        assert_eq!(findings[0].line, 42);
+        assert_eq!(findings[0].message, "Uninitialized variable: x");
+        assert_eq!(component_name(&findings[0].file), "sensor");
+        assert_eq!(component_name(&findings[1].file), "main");
+    }
+}
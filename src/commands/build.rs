@@ -1,19 +1,275 @@
-use crate::{build_systems, utils, Cli};
+use crate::commands::cache;
+use crate::commands::size::{self, SizeReport};
+use crate::state::ProjectState;
+use crate::{build_systems, config, output, sha256, utils, Cli};
 use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// Build artifacts that `reconfigure --clean-cache` can delete in addition to CMakeCache.txt
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheArtifact {
+    /// The CMakeFiles directory with CMake's internal state
+    CmakeFiles,
+    /// Kconfig-generated files (config.env, config/sdkconfig.h and friends)
+    Kconfig,
+    /// Downloaded components (managed_components) and the component manager's files in
+    /// the build directory. dependencies.lock is kept, it pins the component versions.
+    Components,
+    /// All of the above
+    All,
+}
 
-pub async fn execute(cli: &Cli, args: &[String]) -> Result<()> {
-    utils::setup_idf_environment()?;
+impl CacheArtifact {
+    /// Paths to delete for this artifact
+    fn paths(self, project_dir: &Path, build_dir: &Path) -> Vec<std::path::PathBuf> {
+        match self {
+            CacheArtifact::CmakeFiles => vec![build_dir.join("CMakeFiles")],
+            CacheArtifact::Kconfig => vec![build_dir.join("config.env"), build_dir.join("config")],
+            CacheArtifact::Components => {
+                let mut paths = vec![project_dir.join("managed_components")];
+                paths.extend(
+                    [
+                        "managed_components_list.temp.cmake",
+                        "local_components_list.temp.yml",
+                        "components_with_manifests_list.temp",
+                        "component_requires.temp.cmake",
+                    ]
+                    .iter()
+                    .map(|name| build_dir.join(name)),
+                );
+                paths
+            }
+            CacheArtifact::All => [
+                CacheArtifact::CmakeFiles,
+                CacheArtifact::Kconfig,
+                CacheArtifact::Components,
+            ]
+            .iter()
+            .flat_map(|artifact| artifact.paths(project_dir, build_dir))
+            .collect(),
+        }
+    }
+}
 
-    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
-    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+/// Target the build directory was configured for, from CMakeCache.txt
+fn get_cached_target(build_dir: &Path) -> Option<String> {
+    build_systems::get_cmake_cache_entry(build_dir, "IDF_TARGET").or_else(|| {
+        // Older caches only record the toolchain file, e.g. toolchain-esp32s3.cmake
+        let toolchain = build_systems::get_cmake_cache_entry(build_dir, "CMAKE_TOOLCHAIN_FILE")?;
+        let file_name = Path::new(&toolchain)
+            .file_stem()?
+            .to_string_lossy()
+            .to_string();
+        file_name.strip_prefix("toolchain-").map(|t| t.to_string())
+    })
+}
 
-    println!("Building project in: {}", project_dir.display());
-    println!("Build directory: {}", build_dir.display());
+/// Make sure the build directory was configured for the target in sdkconfig.
+/// A mismatch makes CMake fail with cryptic errors, so offer a fullclean instead.
+/// `$IDF_TARGET`, when set, has to agree with both like idf.py requires.
+async fn check_target_consistency(cli: &Cli, project_dir: &Path, build_dir: &Path) -> Result<()> {
+    let sdk_config = config::load_project_config(project_dir)?;
+
+    if let Some(env_target) = utils::env_var("IDF_TARGET").filter(|target| !target.is_empty()) {
+        let configured = sdk_config
+            .get_target()
+            .map(|target| ("sdkconfig", target.clone()))
+            .or_else(|| get_cached_target(build_dir).map(|target| ("CMakeCache.txt", target)));
+        if let Some((source, target)) = configured.filter(|(_, target)| *target != env_target) {
+            return Err(anyhow::anyhow!(
+                "Target settings are not consistent: IDF_TARGET is '{}' in the environment, {} has '{}'. Run 'idf-rs set-target {}' or unset IDF_TARGET.",
+                env_target,
+                source,
+                target,
+                env_target
+            ));
+        }
+    }
 
-    // Get the appropriate generator (explicit, cached, or auto-detected)
-    let generator = build_systems::get_build_generator(cli.generator.as_ref(), &build_dir)?;
+    let (Some(target), Some(cached_target)) =
+        (sdk_config.get_target(), get_cached_target(build_dir))
+    else {
+        return Ok(());
+    };
 
-    println!("Using generator: {}", generator);
+    if *target == cached_target {
+        return Ok(());
+    }
+
+    println!(
+        "The build directory was configured for target '{}', but sdkconfig sets target '{}'.",
+        cached_target, target
+    );
+
+    if cli.can_prompt()
+        && utils::confirm(
+            "Run fullclean and reconfigure for the new target now?",
+            true,
+        )?
+    {
+        execute_fullclean(cli).await?;
+        return Ok(());
+    }
+
+    Err(anyhow::anyhow!(
+        "Target mismatch between {} and sdkconfig. Run 'idf-rs fullclean' and build again.",
+        build_dir.join("CMakeCache.txt").display()
+    ))
+}
+
+/// Name of a `NAME[:TYPE]=VALUE` cache entry
+fn cache_entry_name(entry: &str) -> &str {
+    entry.split([':', '=']).next().unwrap_or(entry)
+}
+
+/// Add `-D` entries to the ones stored for the build directory, replacing stored
+/// entries of the same name
+fn merge_cache_entries(mut stored: Vec<String>, entries: &[String]) -> Vec<String> {
+    for entry in entries {
+        stored.retain(|stored| cache_entry_name(stored) != cache_entry_name(entry));
+        stored.push(entry.clone());
+    }
+    stored
+}
+
+/// The `-D` entries of this invocation on top of those of earlier configure steps.
+/// They are stored with the project state once the configure step succeeds, so that
+/// builds after a reconfigure keep them, until `fullclean`.
+fn cache_entries(cli: &Cli, build_dir: &Path) -> Vec<String> {
+    merge_cache_entries(
+        ProjectState::load(build_dir).cache_entries,
+        &cli.define_cache_entry,
+    )
+}
+
+/// Check the sdkconfig defaults fragments exist, returning the `SDKCONFIG_DEFAULTS`
+/// cache entry for fragments given in the environment (the ESP-IDF build only reads
+/// the cache entry). Fragments from a `-D` entry are passed on as they are.
+fn sdkconfig_defaults_define(
+    cli: &Cli,
+    project_dir: &Path,
+    cache_entries: &[String],
+) -> Result<Option<String>> {
+    let defaults = config::get_sdkconfig_defaults(project_dir, cache_entries);
+    for path in &defaults {
+        if !path.exists() {
+            return Err(anyhow::anyhow!(
+                "sdkconfig defaults file {} does not exist",
+                path.display()
+            ));
+        }
+    }
+    if cli.verbose && !defaults.is_empty() {
+        println!(
+            "Applying sdkconfig defaults: {}",
+            defaults
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    if config::defines_sdkconfig_defaults(cache_entries)
+        || utils::env_var("SDKCONFIG_DEFAULTS").is_none()
+    {
+        return Ok(None);
+    }
+    Ok(Some(format!(
+        "SDKCONFIG_DEFAULTS={}",
+        defaults
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join(";")
+    )))
+}
+
+/// The `SDKCONFIG` cache entry for an sdkconfig moved with `$SDKCONFIG`, unless one of
+/// `defines` (a variant's or -D) already places it
+fn sdkconfig_env_define(project_dir: &Path, defines: &[String]) -> Option<String> {
+    // An empty SDKCONFIG means the default sdkconfig, like get_sdkconfig_path has it
+    if utils::env_var("SDKCONFIG").is_none_or(|path| path.is_empty())
+        || defines
+            .iter()
+            .any(|define| cache_entry_name(define) == "SDKCONFIG")
+    {
+        return None;
+    }
+    Some(format!(
+        "SDKCONFIG={}",
+        config::get_sdkconfig_path(project_dir).display()
+    ))
+}
+
+/// Project files the configure step reads, besides the sdkconfig files
+const CONFIGURE_INPUTS: &[&str] = &["CMakeLists.txt", "idf_component.yml", "dependencies.lock"];
+
+fn is_configure_input(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    CONFIGURE_INPUTS.contains(&name.as_str()) || name.starts_with("sdkconfig")
+}
+
+/// The configure inputs directly in `dir`
+fn find_configure_inputs(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    files.extend(
+        entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file() && is_configure_input(path)),
+    );
+}
+
+/// Hash of what the configure step depends on: its arguments (generator and `-D`
+/// entries), the ESP-IDF in use and the CMakeLists.txt, component manifests and
+/// sdkconfig files of the project, its main component and the components in
+/// `components/`. Only these directories are looked at, not the whole tree.
+fn configure_fingerprint(project_dir: &Path, cmake_args: &[&str]) -> Result<String> {
+    let mut files = Vec::new();
+    find_configure_inputs(project_dir, &mut files);
+    find_configure_inputs(&project_dir.join("main"), &mut files);
+    if let Ok(components) = std::fs::read_dir(project_dir.join("components")) {
+        for component in components.flatten() {
+            find_configure_inputs(&component.path(), &mut files);
+        }
+    }
+    files.sort();
+
+    let mut data = cmake_args.join("\0").into_bytes();
+    data.push(0);
+    data.extend(std::env::var("IDF_PATH").unwrap_or_default().as_bytes());
+    for file in &files {
+        data.push(0);
+        data.extend(file.to_string_lossy().as_bytes());
+        data.push(0);
+        data.extend(std::fs::read(file)?);
+    }
+    Ok(sha256::hex_digest(&data))
+}
+
+/// Whether the build directory holds a configured build for the generator to run
+fn is_configured(build_dir: &Path) -> bool {
+    build_dir.join("CMakeCache.txt").exists()
+        && (build_dir.join("build.ninja").exists() || build_dir.join("Makefile").exists())
+}
+
+/// Run the CMake configure step with the compiler cache and `-D` cache entries applied,
+/// returning the environment the build step needs. The step is skipped when none of its
+/// inputs changed since the last successful run.
+async fn configure(
+    cli: &Cli,
+    project_dir: &Path,
+    build_dir: &Path,
+    generator: &str,
+    extra_defines: &[String],
+) -> Result<utils::CommandEnv> {
+    let (cache_defines, env) = cache::prepare(cli, project_dir, build_dir)?;
 
     let mut cmake_args = vec![
         "-B",
@@ -21,33 +277,154 @@ pub async fn execute(cli: &Cli, args: &[String]) -> Result<()> {
         "-S",
         project_dir.to_str().unwrap(),
         "-G",
-        &generator,
+        generator,
     ];
 
-    // Add cache entry if specified
-    if let Some(cache_entry) = &cli.define_cache_entry {
-        cmake_args.extend_from_slice(&["-D", cache_entry]);
+    for define in cache_defines.iter().chain(extra_defines) {
+        cmake_args.extend_from_slice(&["-D", define]);
     }
 
-    // Configure step
-    utils::run_command("cmake", &cmake_args, Some(&project_dir), cli.verbose).await?;
+    let entries = cache_entries(cli, build_dir);
 
-    // Build step
-    let mut build_args = vec!["--build", build_dir.to_str().unwrap()];
+    let defines: Vec<String> = extra_defines.iter().chain(&entries).cloned().collect();
+    let sdkconfig_define = sdkconfig_env_define(project_dir, &defines);
+    if let Some(define) = &sdkconfig_define {
+        cmake_args.extend_from_slice(&["-D", define]);
+    }
+
+    // Variants choose their own defaults
+    let defaults_define = if extra_defines
+        .iter()
+        .any(|define| define.starts_with("SDKCONFIG_DEFAULTS"))
+    {
+        None
+    } else {
+        sdkconfig_defaults_define(cli, project_dir, &entries)?
+    };
+    if let Some(define) = &defaults_define {
+        cmake_args.extend_from_slice(&["-D", define]);
+    }
+
+    for entry in &entries {
+        cmake_args.extend_from_slice(&["-D", entry]);
+    }
+
+    let mut state = ProjectState::load(build_dir);
+    if is_configured(build_dir)
+        && state.configure_fingerprint.is_some()
+        && state.configure_fingerprint == configure_fingerprint(project_dir, &cmake_args).ok()
+    {
+        println!("CMake configuration is up to date");
+        return Ok(env);
+    }
 
+    if state.configure_fingerprint.take().is_some() {
+        state.save(build_dir)?;
+    }
+    utils::run_command_with_env("cmake", &cmake_args, Some(project_dir), cli.verbose, &env).await?;
+
+    // Hashed after the run, which rewrites sdkconfig
+    state.configure_fingerprint = configure_fingerprint(project_dir, &cmake_args).ok();
+    state.cache_entries = entries;
+    state.generator = Some(generator.to_string());
+    cache::remember_launcher(&mut state, &cache_defines);
+    if let Err(e) = state.save(build_dir) {
+        output::warning(format!("failed to save project state: {}", e));
+    }
+    Ok(env)
+}
+
+/// Run the build step, calling the generator's build tool directly (e.g. `ninja -C build`)
+/// for its lower startup overhead and progress bar, and `cmake --build` for generators
+/// idf-rs has no definition of
+async fn run_build_tool(
+    cli: &Cli,
+    project_dir: &Path,
+    build_dir: &Path,
+    generator: &str,
+    args: &[String],
+    env: &utils::CommandEnv,
+) -> Result<()> {
+    if let Some(definition) = build_systems::get_generator(generator) {
+        let command = definition.build_command(build_dir, cli.verbose, cli.jobs, args);
+        let command_args: Vec<&str> = command[1..].iter().map(String::as_str).collect();
+        return utils::run_build_command(
+            &command[0],
+            &command_args,
+            Some(project_dir),
+            cli.verbose,
+            env,
+        )
+        .await;
+    }
+
+    let jobs = cli.jobs.map(|jobs| jobs.to_string());
+    let mut build_args = vec!["--build", build_dir.to_str().unwrap()];
+    if let Some(jobs) = &jobs {
+        build_args.extend_from_slice(&["--parallel", jobs]);
+    }
     if cli.verbose {
         build_args.push("--verbose");
     }
-
-    // Add additional arguments
     if !args.is_empty() {
         build_args.push("--");
-        for arg in args {
-            build_args.push(arg);
-        }
+        build_args.extend(args.iter().map(String::as_str));
+    }
+    utils::run_command_with_env("cmake", &build_args, Some(project_dir), cli.verbose, env).await
+}
+
+/// Build a variant of the project whose target and sdkconfig are given as CMake
+/// cache entries, skipping the check against the project's sdkconfig
+pub async fn execute_variant(cli: &Cli, defines: &[String]) -> Result<()> {
+    utils::setup_idf_environment()?;
+
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+
+    println!("Build directory: {}", build_dir.display());
+
+    let generator = build_systems::get_build_generator(cli.generator.as_ref(), &build_dir)?;
+    let env = configure(cli, &project_dir, &build_dir, &generator, defines).await?;
+
+    run_build_tool(cli, &project_dir, &build_dir, &generator, &[], &env).await?;
+
+    size::check_app_partition_size(&build_dir, cli.app_size_threshold)
+}
+
+pub async fn execute(
+    cli: &Cli,
+    args: &[String],
+    size_summary: bool,
+    size_report: Option<SizeReport>,
+) -> Result<()> {
+    utils::setup_idf_environment()?;
+
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+
+    println!("Building project in: {}", project_dir.display());
+    println!("Build directory: {}", build_dir.display());
+
+    // Get the appropriate generator (explicit, cached, or auto-detected)
+    let generator = build_systems::get_build_generator(cli.generator.as_ref(), &build_dir)?;
+
+    println!("Using generator: {}", generator);
+
+    check_target_consistency(cli, &project_dir, &build_dir).await?;
+
+    let env = configure(cli, &project_dir, &build_dir, &generator, &[]).await?;
+
+    // Build step, additional arguments go to the build tool
+    run_build_tool(cli, &project_dir, &build_dir, &generator, args, &env).await?;
+
+    // A requested size report supersedes the default memory usage summary
+    match size_report {
+        Some(report) => size::execute_report(cli, report).await?,
+        None if size_summary => size::print_memory_summary(cli).await,
+        None => {}
     }
 
-    utils::run_command("cmake", &build_args, Some(&project_dir), cli.verbose).await?;
+    size::check_app_partition_size(&build_dir, cli.app_size_threshold)?;
 
     println!("Build completed successfully!");
     Ok(())
@@ -61,9 +438,14 @@ pub async fn execute_app(cli: &Cli) -> Result<()> {
 
     println!("Building app only...");
 
-    let build_args = vec!["--build", build_dir.to_str().unwrap(), "--target", "app"];
+    // The compiler launcher needs the remote cache environment, the cache entries are already configured
+    let (_, env) = cache::prepare(cli, &project_dir, &build_dir)?;
 
-    utils::run_command("cmake", &build_args, Some(&project_dir), cli.verbose).await?;
+    let generator = build_systems::get_build_generator(cli.generator.as_ref(), &build_dir)?;
+    let target = ["app".to_string()];
+    run_build_tool(cli, &project_dir, &build_dir, &generator, &target, &env).await?;
+
+    size::check_app_partition_size(&build_dir, cli.app_size_threshold)?;
 
     println!("App build completed successfully!");
     Ok(())
@@ -77,23 +459,109 @@ pub async fn execute_bootloader(cli: &Cli) -> Result<()> {
 
     println!("Building bootloader only...");
 
-    let build_args = vec![
-        "--build",
-        build_dir.to_str().unwrap(),
-        "--target",
-        "bootloader",
-    ];
+    // The compiler launcher needs the remote cache environment, the cache entries are already configured
+    let (_, env) = cache::prepare(cli, &project_dir, &build_dir)?;
 
-    utils::run_command("cmake", &build_args, Some(&project_dir), cli.verbose).await?;
+    let generator = build_systems::get_build_generator(cli.generator.as_ref(), &build_dir)?;
+    let target = ["bootloader".to_string()];
+    run_build_tool(cli, &project_dir, &build_dir, &generator, &target, &env).await?;
 
     println!("Bootloader build completed successfully!");
     Ok(())
 }
 
-pub async fn execute_clean(cli: &Cli) -> Result<()> {
+/// Find a component of the build by name, also accepting managed components
+/// without their namespace (e.g. "led_strip" for "espressif__led_strip")
+fn find_component<'a>(
+    info: &'a serde_json::Map<String, serde_json::Value>,
+    name: &str,
+) -> Result<(&'a str, &'a serde_json::Value)> {
+    if let Some((key, value)) = info.get_key_value(name) {
+        return Ok((key, value));
+    }
+
+    let suffix = format!("__{}", name);
+    let matches: Vec<_> = info
+        .iter()
+        .filter(|(key, _)| key.ends_with(&suffix))
+        .collect();
+    match matches.as_slice() {
+        [(key, value)] => Ok((key.as_str(), value)),
+        [] => {
+            let names: Vec<&str> = info.keys().map(String::as_str).collect();
+            Err(anyhow::anyhow!(
+                "Component '{}' is not part of this build. Components: {}",
+                name,
+                names.join(", ")
+            ))
+        }
+        _ => {
+            let names: Vec<&str> = matches.iter().map(|(key, _)| key.as_str()).collect();
+            Err(anyhow::anyhow!(
+                "Component '{}' is ambiguous: {}",
+                name,
+                names.join(", ")
+            ))
+        }
+    }
+}
+
+/// Remove the object files and library of single components, so only they are rebuilt
+async fn clean_components(cli: &Cli, build_dir: &Path, components: &[String]) -> Result<()> {
+    if !build_dir.join("build.ninja").exists() {
+        return Err(anyhow::anyhow!(
+            "Cleaning single components requires a Ninja build in {}",
+            build_dir.display()
+        ));
+    }
+
+    let description = utils::load_project_description(build_dir)?;
+    let info = description["build_component_info"]
+        .as_object()
+        .ok_or_else(|| anyhow::anyhow!("project_description.json has no component info"))?;
+    let canonical_build_dir = build_dir.canonicalize()?;
+
+    let mut libraries = Vec::new();
+    for name in components {
+        let (component, entry) = find_component(info, name)?;
+        let file = entry["file"].as_str().unwrap_or_default();
+        if file.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Component '{}' has no library to clean",
+                component
+            ));
+        }
+
+        // Ninja knows the library by its path relative to the build directory
+        let library = Path::new(file);
+        let library = library
+            .strip_prefix(&canonical_build_dir)
+            .or_else(|_| library.strip_prefix(build_dir))
+            .unwrap_or(library);
+        println!("Cleaning component {} ({})", component, library.display());
+        libraries.push(library.to_string_lossy().to_string());
+    }
+
+    let mut args = vec!["-C", build_dir.to_str().unwrap(), "-t", "clean"];
+    args.extend(libraries.iter().map(String::as_str));
+    utils::run_command("ninja", &args, None, cli.verbose).await?;
+
+    println!("Components will be rebuilt by the next build");
+    Ok(())
+}
+
+pub async fn execute_clean(cli: &Cli, components: &[String]) -> Result<()> {
     let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
     let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
 
+    if !components.is_empty() {
+        if !build_dir.exists() {
+            println!("Build directory doesn't exist, nothing to clean.");
+            return Ok(());
+        }
+        return clean_components(cli, &build_dir, components).await;
+    }
+
     println!("Cleaning build directory: {}", build_dir.display());
 
     if build_dir.exists() {
@@ -124,7 +592,7 @@ pub async fn execute_fullclean(cli: &Cli) -> Result<()> {
     Ok(())
 }
 
-pub async fn execute_reconfigure(cli: &Cli) -> Result<()> {
+pub async fn execute_reconfigure(cli: &Cli, clean_cache: &[CacheArtifact]) -> Result<()> {
     utils::setup_idf_environment()?;
 
     let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
@@ -138,21 +606,26 @@ pub async fn execute_reconfigure(cli: &Cli) -> Result<()> {
         std::fs::remove_file(&cmake_cache)?
     }
 
+    for artifact in clean_cache {
+        for path in artifact.paths(&project_dir, &build_dir) {
+            if path.is_dir() {
+                println!("Removing {}", path.display());
+                std::fs::remove_dir_all(&path)?;
+            } else if path.exists() {
+                println!("Removing {}", path.display());
+                std::fs::remove_file(&path)?;
+            }
+        }
+    }
+
     // Get the appropriate generator (explicit or auto-detected, since cache was removed)
     let generator = build_systems::get_build_generator(cli.generator.as_ref(), &build_dir)?;
 
     println!("Using generator: {}", generator);
 
-    let cmake_args = vec![
-        "-B",
-        build_dir.to_str().unwrap(),
-        "-S",
-        project_dir.to_str().unwrap(),
-        "-G",
-        &generator,
-    ];
-
-    utils::run_command("cmake", &cmake_args, Some(&project_dir), cli.verbose).await?;
+    // The same configure step as a build's, with the compiler cache environment, and
+    // recording its fingerprint, so the next build knows it's up to date
+    configure(cli, &project_dir, &build_dir, &generator, &[]).await?;
 
     println!("Reconfigure completed successfully!");
     Ok(())
@@ -182,3 +655,54 @@ pub async fn list_build_targets(cli: &Cli) -> Result<()> {
     println!("{}", output);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_cache_entries() {
+        let stored = vec!["FOO=1".to_string(), "BAR:BOOL=ON".to_string()];
+        assert_eq!(
+            merge_cache_entries(stored, &["BAR=OFF".to_string(), "BAZ=2".to_string()]),
+            vec!["FOO=1", "BAR=OFF", "BAZ=2"]
+        );
+    }
+
+    #[test]
+    fn test_configure_fingerprint() {
+        let dir = std::env::temp_dir().join(format!("idf-rs-configure-{}", std::process::id()));
+        let build_dir = dir.join("build");
+        std::fs::create_dir_all(dir.join("main")).unwrap();
+        std::fs::create_dir_all(&build_dir).unwrap();
+        std::fs::write(dir.join("CMakeLists.txt"), "project(app)").unwrap();
+        std::fs::write(dir.join("main/CMakeLists.txt"), "idf_component_register()").unwrap();
+        std::fs::write(dir.join("sdkconfig"), "CONFIG_FOO=y").unwrap();
+        std::fs::write(dir.join("main/main.c"), "").unwrap();
+        std::fs::write(build_dir.join("CMakeLists.txt"), "generated").unwrap();
+
+        let args = ["-G", "Ninja"];
+        let fingerprint = configure_fingerprint(&dir, &args).unwrap();
+        // Sources and the build directory don't matter
+        std::fs::write(dir.join("main/main.c"), "int x;").unwrap();
+        std::fs::write(build_dir.join("CMakeLists.txt"), "changed").unwrap();
+        assert_eq!(configure_fingerprint(&dir, &args).unwrap(), fingerprint);
+
+        std::fs::write(
+            dir.join("main/CMakeLists.txt"),
+            "idf_component_register(SRCS main.c)",
+        )
+        .unwrap();
+        let changed = configure_fingerprint(&dir, &args).unwrap();
+        assert_ne!(changed, fingerprint);
+        std::fs::write(dir.join("sdkconfig"), "CONFIG_FOO=n").unwrap();
+        assert_ne!(configure_fingerprint(&dir, &args).unwrap(), changed);
+        assert_ne!(
+            configure_fingerprint(&dir, &["-G", "Ninja", "-D", "FOO=1"]).unwrap(),
+            configure_fingerprint(&dir, &args).unwrap()
+        );
+
+        assert!(!is_configured(&build_dir));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,564 @@
+use crate::config::{self, CacheBackend, CacheSettings};
+use crate::state::ProjectState;
+use crate::{build_systems, utils, Cli};
+use anyhow::Result;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+
+/// ccache gained remote storage support in 4.4
+const MIN_CCACHE_REMOTE_VERSION: (u32, u32) = (4, 4);
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Split `scheme://[user@]host[:port]/path` into scheme, authority and path
+fn split_url(url: &str) -> Option<(&str, &str, &str)> {
+    let (scheme, rest) = url.split_once("://")?;
+    let (authority, path) = match rest.find('/') {
+        Some(index) => (&rest[..index], &rest[index + 1..]),
+        None => (rest, ""),
+    };
+    Some((scheme, authority, path))
+}
+
+/// Environment variables pointing the cache backend at the remote storage
+pub fn backend_environment(settings: &CacheSettings) -> Result<Vec<(String, String)>> {
+    let url = match &settings.remote_url {
+        Some(url) => url,
+        None => return Ok(Vec::new()),
+    };
+    let (scheme, authority, path) =
+        split_url(url).ok_or_else(|| anyhow::anyhow!("Invalid cache.remote_url '{}'", url))?;
+    let token = settings.auth_token();
+    let mut env = Vec::new();
+
+    match settings.backend {
+        CacheBackend::Ccache => {
+            let storage = match (scheme, &token) {
+                ("http" | "https", Some(token)) => format!("{}|bearer-token={}", url, token),
+                ("redis" | "rediss", Some(token)) if !authority.contains('@') => {
+                    format!("{}://:{}@{}/{}", scheme, token, authority, path)
+                }
+                ("http" | "https" | "redis" | "rediss" | "file", _) => url.clone(),
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "ccache does not support '{}' remote storage (use http, redis or file)",
+                        scheme
+                    ))
+                }
+            };
+            env.push(("CCACHE_REMOTE_STORAGE".to_string(), storage));
+            if settings.remote_only {
+                env.push(("CCACHE_REMOTE_ONLY".to_string(), "true".to_string()));
+            }
+        }
+        CacheBackend::Sccache => match scheme {
+            "s3" => {
+                env.push(("SCCACHE_BUCKET".to_string(), authority.to_string()));
+                if !path.is_empty() {
+                    env.push(("SCCACHE_S3_KEY_PREFIX".to_string(), path.to_string()));
+                }
+            }
+            "gs" => {
+                env.push(("SCCACHE_GCS_BUCKET".to_string(), authority.to_string()));
+                if !path.is_empty() {
+                    env.push(("SCCACHE_GCS_KEY_PREFIX".to_string(), path.to_string()));
+                }
+                env.push(("SCCACHE_GCS_RW_MODE".to_string(), "READ_WRITE".to_string()));
+            }
+            "redis" | "rediss" => {
+                env.push(("SCCACHE_REDIS_ENDPOINT".to_string(), url.clone()));
+                if let Some(token) = token {
+                    env.push(("SCCACHE_REDIS_PASSWORD".to_string(), token));
+                }
+            }
+            "http" | "https" => {
+                env.push(("SCCACHE_WEBDAV_ENDPOINT".to_string(), url.clone()));
+                if let Some(token) = token {
+                    env.push(("SCCACHE_WEBDAV_TOKEN".to_string(), token));
+                }
+            }
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "sccache does not support '{}' remote storage (use s3, gs, redis or http)",
+                    scheme
+                ))
+            }
+        },
+    }
+
+    Ok(env)
+}
+
+/// CMake cache entries selecting the compiler cache. The compiler launcher is only
+/// cleared when idf-rs set one in an earlier configure step (`launcher_set`), a launcher
+/// set up in the project's CMake is left alone.
+pub fn cmake_defines(settings: &CacheSettings, launcher_set: bool) -> Vec<String> {
+    match (settings.enabled, settings.backend) {
+        (None, _) => Vec::new(),
+        (Some(true), CacheBackend::Sccache) => vec![
+            "CCACHE_ENABLE=0".to_string(),
+            "CMAKE_C_COMPILER_LAUNCHER=sccache".to_string(),
+            "CMAKE_CXX_COMPILER_LAUNCHER=sccache".to_string(),
+        ],
+        (Some(enabled), _) => {
+            let mut defines = vec![format!("CCACHE_ENABLE={}", if enabled { 1 } else { 0 })];
+            if launcher_set {
+                defines.push("CMAKE_C_COMPILER_LAUNCHER=".to_string());
+                defines.push("CMAKE_CXX_COMPILER_LAUNCHER=".to_string());
+            }
+            defines
+        }
+    }
+}
+
+/// Remember in the project state whether the cache entries of a configure step set a
+/// compiler launcher, so a later step knows it may clear it
+pub fn remember_launcher(state: &mut ProjectState, defines: &[String]) {
+    if let Some(launcher) = defines
+        .iter()
+        .find_map(|define| define.strip_prefix("CMAKE_C_COMPILER_LAUNCHER="))
+    {
+        state.compiler_launcher = Some(launcher.to_string()).filter(|l| !l.is_empty());
+    }
+}
+
+/// CMake cache entries running the compilers through `launcher` instead of ccache
+pub fn launcher_defines(launcher: &str) -> Vec<String> {
+    vec![
+        "CCACHE_ENABLE=0".to_string(),
+        format!("CMAKE_C_COMPILER_LAUNCHER={}", launcher),
+        format!("CMAKE_CXX_COMPILER_LAUNCHER={}", launcher),
+    ]
+}
+
+/// Whether a launcher given by name is in PATH, or given by path exists
+fn launcher_exists(launcher: &str) -> bool {
+    let path = Path::new(launcher);
+    if path.components().count() > 1 {
+        return path.is_file();
+    }
+    std::env::var_os("PATH")
+        .map(|paths| {
+            std::env::split_paths(&paths).any(|dir| {
+                dir.join(launcher).is_file() || dir.join(format!("{}.exe", launcher)).is_file()
+            })
+        })
+        .unwrap_or(false)
+}
+
+/// Resolve the cache settings with the --ccache/--no-ccache flags applied
+fn resolve_settings(cli: &Cli, project_dir: &Path) -> Result<CacheSettings> {
+    let mut settings = config::load_cache_settings(project_dir)?;
+    if cli.no_ccache {
+        settings.enabled = Some(false);
+    } else if cli.ccache {
+        settings.enabled = Some(true);
+    }
+    Ok(settings)
+}
+
+/// Prepare the compiler cache for a build: return the CMake cache entries selecting it
+/// and the remote storage environment the compiler launcher needs during the build
+pub fn prepare(
+    cli: &Cli,
+    project_dir: &Path,
+    build_dir: &Path,
+) -> Result<(Vec<String>, utils::CommandEnv)> {
+    let settings = resolve_settings(cli, project_dir)?;
+    let mut env = utils::CommandEnv::new();
+
+    if let Some(launcher) = &cli.compiler_launcher {
+        if !launcher_exists(launcher) {
+            return Err(anyhow::anyhow!(
+                "Compiler launcher '{}' was not found",
+                launcher
+            ));
+        }
+        // The remote storage of idf.toml still applies when it names the configured backend
+        let name = Path::new(launcher).file_stem().unwrap_or_default();
+        if name.to_string_lossy() == settings.backend.to_string() {
+            for (name, value) in backend_environment(&settings)? {
+                env.set(name, value);
+            }
+        }
+        return Ok((launcher_defines(launcher), env));
+    }
+
+    if settings.enabled == Some(true) {
+        let backend = settings.backend.to_string();
+        if !build_systems::executable_exists(&[backend.clone(), "--version".to_string()]) {
+            return Err(anyhow::anyhow!(
+                "{} is enabled but was not found in PATH. Run 'idf-rs cache doctor' for details.",
+                backend
+            ));
+        }
+
+        for (name, value) in backend_environment(&settings)? {
+            env.set(name, value);
+        }
+    }
+
+    let launcher_set = ProjectState::load(build_dir).compiler_launcher.is_some();
+    Ok((cmake_defines(&settings, launcher_set), env))
+}
+
+/// Cache hits and misses from `ccache --print-stats`
+fn parse_ccache_counters(output: &str) -> Option<(u64, u64)> {
+    let mut hits = None;
+    let mut misses = None;
+    for line in output.lines() {
+        let (name, value) = match line.split_once('\t') {
+            Some((name, value)) => (name, value.trim().parse::<u64>().ok()),
+            None => continue,
+        };
+        match name {
+            "direct_cache_hit" | "preprocessed_cache_hit" => {
+                hits = Some(hits.unwrap_or(0) + value?);
+            }
+            "cache_miss" => misses = value,
+            _ => {}
+        }
+    }
+    Some((hits?, misses?))
+}
+
+/// The environment of the backend's commands, with the remote storage configured
+fn backend_command_env(settings: &CacheSettings) -> Result<utils::CommandEnv> {
+    let mut env = utils::CommandEnv::new();
+    for (name, value) in backend_environment(settings)? {
+        env.set(name, value);
+    }
+    Ok(env)
+}
+
+/// ccache's cumulative hit and miss counters, when builds use ccache. The difference
+/// between two readings gives the hits and misses of a build.
+pub async fn hit_counters(cli: &Cli, project_dir: &Path) -> Option<(u64, u64)> {
+    let settings = resolve_settings(cli, project_dir).ok()?;
+    if settings.enabled != Some(true) || settings.backend != CacheBackend::Ccache {
+        return None;
+    }
+
+    let env = backend_command_env(&settings).ok()?;
+    let output = utils::run_command_with_output_env("ccache", &["--print-stats"], None, &env).await;
+    parse_ccache_counters(&output.ok()?)
+}
+
+/// Print the hit/miss statistics of the configured backend. ESP-IDF may enable ccache on
+/// its own (IDF_CCACHE_ENABLE), so the statistics are shown whether or not idf-rs enabled it.
+pub async fn execute_stats(cli: &Cli) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let settings = resolve_settings(cli, &project_dir)?;
+    let env = backend_command_env(&settings)?;
+
+    if settings.backend == CacheBackend::Sccache {
+        return utils::run_command_with_env("sccache", &["--show-stats"], None, cli.verbose, &env)
+            .await;
+    }
+
+    let output = utils::run_command_with_output_env("ccache", &["--print-stats"], None, &env)
+        .await
+        .map_err(|_| anyhow::anyhow!("ccache was not found in PATH"))?;
+    let (hits, misses) = parse_ccache_counters(&output).ok_or_else(|| {
+        anyhow::anyhow!("Could not read the ccache statistics (ccache 4.0 or newer is required)")
+    })?;
+    let total = hits + misses;
+    println!("Cache hits:   {}", hits);
+    println!("Cache misses: {}", misses);
+    if total > 0 {
+        println!("Hit rate:     {:.1}%", hits as f64 * 100.0 / total as f64);
+    }
+    if cli.verbose {
+        println!();
+        utils::run_command_with_env("ccache", &["--show-stats"], None, false, &env).await?;
+    }
+    Ok(())
+}
+
+fn parse_ccache_version(output: &str) -> Option<(u32, u32)> {
+    let version = output
+        .lines()
+        .next()?
+        .split_whitespace()
+        .find(|word| word.starts_with(|c: char| c.is_ascii_digit()))?;
+    let mut parts = version.split('.');
+    Some((parts.next()?.parse().ok()?, parts.next()?.parse().ok()?))
+}
+
+async fn check_http(url: &str, token: Option<&str>) -> Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    let null_device = if cfg!(windows) { "NUL" } else { "/dev/null" };
+    let mut command = tokio::process::Command::new("curl");
+    command
+        .args(["-sS", "-o", null_device, "-w", "%{http_code}", "-m", "10"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    // The token goes to curl in a config on stdin, the command line is visible to `ps`
+    if token.is_some() {
+        command.args(["-K", "-"]).stdin(Stdio::piped());
+    }
+    command.arg(url);
+
+    let mut child = command
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Failed to run curl: {}", e))?;
+    if let (Some(token), Some(mut stdin)) = (token, child.stdin.take()) {
+        let token = token.replace('\\', "\\\\").replace('"', "\\\"");
+        stdin
+            .write_all(format!("header = \"Authorization: Bearer {}\"\n", token).as_bytes())
+            .await?;
+    }
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "Command failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    let status = String::from_utf8_lossy(&output.stdout);
+    match status.trim().parse::<u16>() {
+        Ok(401) | Ok(403) => Err(anyhow::anyhow!(
+            "server rejected the credentials (HTTP {})",
+            status.trim()
+        )),
+        Ok(code) if code < 500 => Ok(()),
+        _ => Err(anyhow::anyhow!("server returned HTTP {}", status.trim())),
+    }
+}
+
+fn check_tcp(authority: &str, default_port: u16) -> Result<()> {
+    let host = authority
+        .rsplit_once('@')
+        .map(|(_, host)| host)
+        .unwrap_or(authority);
+    let address = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{}:{}", host, default_port)
+    };
+
+    let socket = address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("could not resolve {}", address))?;
+    TcpStream::connect_timeout(&socket, CONNECT_TIMEOUT)?;
+    Ok(())
+}
+
+async fn check_remote(settings: &CacheSettings, env: &utils::CommandEnv) -> Result<()> {
+    let url = settings.remote_url.as_deref().unwrap_or_default();
+    let (scheme, authority, path) =
+        split_url(url).ok_or_else(|| anyhow::anyhow!("invalid URL '{}'", url))?;
+    let token = settings.auth_token();
+
+    match scheme {
+        "http" | "https" => check_http(url, token.as_deref()).await,
+        "redis" | "rediss" => check_tcp(authority, 6379),
+        "file" => {
+            let dir = Path::new("/").join(path);
+            if dir.is_dir() {
+                Ok(())
+            } else {
+                Err(anyhow::anyhow!(
+                    "directory {} does not exist",
+                    dir.display()
+                ))
+            }
+        }
+        // Cloud storage is verified by starting the sccache server with the bucket configured
+        _ => {
+            let _ =
+                utils::run_command_with_output_env("sccache", &["--stop-server"], None, env).await;
+            utils::run_command_with_output_env("sccache", &["--start-server"], None, env).await?;
+            Ok(())
+        }
+    }
+}
+
+pub async fn execute_doctor(cli: &Cli) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let mut problems = 0;
+
+    println!("Configuration files:");
+    let global_path = config::get_global_settings_path();
+    let project_path = config::get_project_settings_path(&project_dir);
+    for path in global_path.iter().chain(std::iter::once(&project_path)) {
+        let status = if path.exists() {
+            "found"
+        } else {
+            "not present"
+        };
+        println!("  {} ({})", path.display(), status);
+    }
+
+    let settings = resolve_settings(cli, &project_dir)?;
+    println!();
+    println!("Backend:      {}", settings.backend);
+    println!(
+        "Enabled:      {}",
+        match settings.enabled {
+            Some(true) => "yes",
+            Some(false) => "no",
+            None => "ESP-IDF default (IDF_CCACHE_ENABLE)",
+        }
+    );
+    println!(
+        "Remote:       {}",
+        settings.remote_url.as_deref().unwrap_or("none")
+    );
+    if settings.remote_only {
+        println!("Remote only:  yes");
+    }
+    println!();
+
+    let backend = settings.backend.to_string();
+    match utils::run_command_with_output(&backend, &["--version"], None).await {
+        Ok(output) => {
+            println!("✓ {}", output.lines().next().unwrap_or(&backend));
+            if settings.backend == CacheBackend::Ccache && settings.remote_url.is_some() {
+                match parse_ccache_version(&output) {
+                    Some(version) if version < MIN_CCACHE_REMOTE_VERSION => {
+                        println!(
+                            "✗ ccache {}.{} does not support remote storage, 4.4 or newer is required",
+                            version.0, version.1
+                        );
+                        problems += 1;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Err(_) => {
+            println!("✗ {} not found in PATH", backend);
+            problems += 1;
+        }
+    }
+
+    if let Some(name) = &settings.auth_token_env {
+        if settings.auth_token().is_some() {
+            println!("✓ Auth token found in ${}", name);
+        } else {
+            println!("✗ Auth token variable ${} is not set", name);
+            problems += 1;
+        }
+    }
+
+    if settings.remote_only && settings.backend == CacheBackend::Sccache {
+        println!("! remote_only has no effect with sccache");
+    }
+
+    if settings.remote_url.is_some() {
+        match backend_environment(&settings) {
+            Ok(vars) => {
+                let mut env = utils::CommandEnv::new();
+                for (name, value) in vars {
+                    if cli.verbose {
+                        println!("  {}={}", name, utils::display_env_value(&name, &value));
+                    }
+                    env.set(name, value);
+                }
+                match check_remote(&settings, &env).await {
+                    Ok(()) => println!("✓ Remote storage is reachable"),
+                    Err(e) => {
+                        println!("✗ Remote storage check failed: {}", e);
+                        problems += 1;
+                    }
+                }
+            }
+            Err(e) => {
+                println!("✗ {}", e);
+                problems += 1;
+            }
+        }
+    }
+
+    if problems > 0 {
+        return Err(anyhow::anyhow!(
+            "Found {} problem(s) with the compiler cache setup",
+            problems
+        ));
+    }
+
+    println!("Compiler cache setup looks good!");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backend_environment() {
+        let mut settings = CacheSettings {
+            backend: CacheBackend::Sccache,
+            enabled: Some(true),
+            remote_url: Some("s3://ci-cache/esp-idf".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            backend_environment(&settings).unwrap(),
+            vec![
+                ("SCCACHE_BUCKET".to_string(), "ci-cache".to_string()),
+                ("SCCACHE_S3_KEY_PREFIX".to_string(), "esp-idf".to_string()),
+            ]
+        );
+
+        settings.backend = CacheBackend::Ccache;
+        assert!(backend_environment(&settings).is_err());
+
+        settings.remote_url = Some("http://cache.local/ccache".to_string());
+        settings.remote_only = true;
+        assert_eq!(
+            backend_environment(&settings).unwrap(),
+            vec![
+                (
+                    "CCACHE_REMOTE_STORAGE".to_string(),
+                    "http://cache.local/ccache".to_string()
+                ),
+                ("CCACHE_REMOTE_ONLY".to_string(), "true".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_launcher_defines() {
+        assert_eq!(
+            launcher_defines("icecc"),
+            vec![
+                "CCACHE_ENABLE=0",
+                "CMAKE_C_COMPILER_LAUNCHER=icecc",
+                "CMAKE_CXX_COMPILER_LAUNCHER=icecc",
+            ]
+        );
+        assert!(!launcher_exists("/nonexistent/icecc"));
+    }
+
+    #[test]
+    fn test_launcher_cleared_only_when_set_by_idf_rs() {
+        let settings = CacheSettings {
+            enabled: Some(false),
+            ..Default::default()
+        };
+        assert_eq!(cmake_defines(&settings, false), vec!["CCACHE_ENABLE=0"]);
+        assert_eq!(cmake_defines(&settings, true).len(), 3);
+
+        let mut state = ProjectState::default();
+        remember_launcher(&mut state, &launcher_defines("sccache"));
+        assert_eq!(state.compiler_launcher.as_deref(), Some("sccache"));
+        remember_launcher(&mut state, &["CCACHE_ENABLE=1".to_string()]);
+        assert_eq!(state.compiler_launcher.as_deref(), Some("sccache"));
+        remember_launcher(&mut state, &cmake_defines(&settings, true));
+        assert_eq!(state.compiler_launcher, None);
+    }
+
+    #[test]
+    fn test_parse_ccache_counters() {
+        let output = "stats_updated_timestamp\t1700000000\ndirect_cache_hit\t12\npreprocessed_cache_hit\t3\ncache_miss\t5\n";
+        assert_eq!(parse_ccache_counters(output), Some((15, 5)));
+        assert_eq!(parse_ccache_counters("cache_miss\t5\n"), None);
+    }
+}
@@ -0,0 +1,185 @@
+use crate::commands::flash;
+use crate::{utils, Cli};
+use anyhow::Result;
+use serde::Serialize;
+
+/// What esptool reports about the connected chip and its flash
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ChipInfo {
+    /// Chip model as esptool names it, e.g. "ESP32-S3 (QFN56)"
+    pub model: String,
+    /// ESP-IDF target of the chip, e.g. "esp32s3"
+    pub target: Option<String>,
+    /// Silicon revision, e.g. "v0.2"
+    pub revision: Option<String>,
+    pub features: Vec<String>,
+    /// Crystal frequency, e.g. "40MHz"
+    pub crystal: Option<String>,
+    pub mac: Option<String>,
+    /// Flash size detected from the flash chip ID, e.g. "8MB"
+    pub flash_size: Option<String>,
+    /// JEDEC manufacturer and device ID of the flash chip, as hex
+    pub flash_manufacturer: Option<String>,
+    pub flash_device: Option<String>,
+}
+
+/// ESP-IDF target of an esptool chip model: "ESP32-C3 (QFN32)" is esp32c3, while
+/// "ESP32-D0WD-V3" and "ESP32-PICO-D4" are esp32 variants
+pub fn target_of_model(model: &str) -> Option<String> {
+    let name = model.split_whitespace().next()?.to_lowercase();
+    let mut parts = name.split('-');
+    let family = parts.next()?;
+    if !family.starts_with("esp") {
+        return None;
+    }
+
+    let series = parts.next().filter(|series| {
+        let mut chars = series.chars();
+        matches!(chars.next(), Some('s' | 'c' | 'h' | 'p'))
+            && !chars.as_str().is_empty()
+            && chars.all(|c| c.is_ascii_digit())
+    });
+    Some(format!("{}{}", family, series.unwrap_or_default()))
+}
+
+/// Parse the output of `esptool flash_id`, of esptool v4 ("Chip is ...") and v5
+/// ("Chip type: ...")
+pub fn parse_esptool_output(output: &str) -> Option<ChipInfo> {
+    let mut info = ChipInfo::default();
+
+    for line in output.lines().map(str::trim) {
+        if let Some(chip) = line
+            .strip_prefix("Chip is ")
+            .or_else(|| line.strip_prefix("Chip type:"))
+        {
+            let chip = chip.trim();
+            match chip.rfind("(revision ") {
+                Some(start) => {
+                    info.model = chip[..start].trim().to_string();
+                    info.revision = Some(
+                        chip[start + "(revision ".len()..]
+                            .trim_end_matches(')')
+                            .to_string(),
+                    );
+                }
+                None => info.model = chip.to_string(),
+            }
+        } else if let Some(features) = line.strip_prefix("Features:") {
+            info.features = features
+                .split(',')
+                .map(|feature| feature.trim().to_string())
+                .filter(|feature| !feature.is_empty())
+                .collect();
+        } else if let Some(crystal) = line
+            .strip_prefix("Crystal is ")
+            .or_else(|| line.strip_prefix("Crystal frequency:"))
+        {
+            info.crystal = Some(crystal.trim().to_string());
+        } else if let Some(mac) = line.strip_prefix("MAC:") {
+            info.mac = Some(mac.trim().to_string());
+        } else if let Some(size) = line.strip_prefix("Detected flash size:") {
+            info.flash_size = Some(size.trim().to_string());
+        } else if let Some(manufacturer) = line.strip_prefix("Manufacturer:") {
+            info.flash_manufacturer = Some(manufacturer.trim().to_string());
+        } else if let Some(device) = line.strip_prefix("Device:") {
+            info.flash_device = Some(device.trim().to_string());
+        }
+    }
+
+    if info.model.is_empty() {
+        return None;
+    }
+    info.target = target_of_model(&info.model);
+    Some(info)
+}
+
+/// Connect to the device and read its chip and flash information with esptool
+pub async fn read_chip_info(cli: &Cli) -> Result<ChipInfo> {
+    let esptool = flash::esptool_command(cli, "auto")?;
+    let mut args: Vec<&str> = esptool[1..].iter().map(String::as_str).collect();
+    args.push("flash_id");
+
+    let output = utils::run_command_with_output(&esptool[0], &args, None)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to connect to the chip: {}", e))?;
+    parse_esptool_output(&output)
+        .ok_or_else(|| anyhow::anyhow!("esptool didn't report the chip type"))
+}
+
+pub async fn execute_info(cli: &Cli, json: bool) -> Result<()> {
+    utils::setup_idf_environment()?;
+
+    let info = read_chip_info(cli).await?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&info)?);
+        return Ok(());
+    }
+
+    let unknown = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+    println!("Chip:       {}", info.model);
+    println!("Target:     {}", unknown(&info.target));
+    println!("Revision:   {}", unknown(&info.revision));
+    println!("Features:   {}", info.features.join(", "));
+    println!("Crystal:    {}", unknown(&info.crystal));
+    println!("MAC:        {}", unknown(&info.mac));
+    println!("Flash size: {}", unknown(&info.flash_size));
+    if let (Some(manufacturer), Some(device)) = (&info.flash_manufacturer, &info.flash_device) {
+        println!(
+            "Flash ID:   manufacturer {}, device {}",
+            manufacturer, device
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_of_model() {
+        assert_eq!(
+            target_of_model("ESP32-S3 (QFN56)").as_deref(),
+            Some("esp32s3")
+        );
+        assert_eq!(target_of_model("ESP32-C61").as_deref(), Some("esp32c61"));
+        assert_eq!(target_of_model("ESP32-D0WD-V3").as_deref(), Some("esp32"));
+        assert_eq!(target_of_model("ESP32-PICO-D4").as_deref(), Some("esp32"));
+        assert_eq!(target_of_model("unknown"), None);
+    }
+
+    #[test]
+    fn test_parse_esptool_output() {
+        let output = "esptool.py v4.7.0\n\
+            Serial port /dev/ttyUSB0\n\
+            Connecting....\n\
+            Detecting chip type... ESP32-S3\n\
+            Chip is ESP32-S3 (QFN56) (revision v0.2)\n\
+            Features: WiFi, BLE, Embedded PSRAM 8MB (AP_3v3)\n\
+            Crystal is 40MHz\n\
+            MAC: 7c:df:a1:e0:12:34\n\
+            Uploading stub...\n\
+            Manufacturer: c8\n\
+            Device: 4018\n\
+            Detected flash size: 16MB\n";
+        let info = parse_esptool_output(output).unwrap();
+        assert_eq!(info.model, "ESP32-S3 (QFN56)");
+        assert_eq!(info.target.as_deref(), Some("esp32s3"));
+        assert_eq!(info.revision.as_deref(), Some("v0.2"));
+        assert_eq!(
+            info.features,
+            vec!["WiFi", "BLE", "Embedded PSRAM 8MB (AP_3v3)"]
+        );
+        assert_eq!(info.crystal.as_deref(), Some("40MHz"));
+        assert_eq!(info.mac.as_deref(), Some("7c:df:a1:e0:12:34"));
+        assert_eq!(info.flash_size.as_deref(), Some("16MB"));
+
+        let v5 = "Chip type:          ESP32-C3 (QFN32) (revision v0.4)\n\
+            Crystal frequency:  40MHz\n";
+        let info = parse_esptool_output(v5).unwrap();
+        assert_eq!(info.target.as_deref(), Some("esp32c3"));
+        assert_eq!(info.crystal.as_deref(), Some("40MHz"));
+
+        assert_eq!(parse_esptool_output("A fatal error occurred"), None);
+    }
+}
@@ -0,0 +1,164 @@
+use crate::compile_commands::{self, CompileCommand};
+use crate::{build_systems, utils, Cli};
+use anyhow::Result;
+use std::path::Path;
+
+/// Find the compile command for a source file, configuring the project first if needed
+async fn find_compile_command(cli: &Cli, build_dir: &Path, file: &Path) -> Result<CompileCommand> {
+    if !compile_commands::get_compile_commands_path(build_dir).exists() {
+        println!("Compilation database doesn't exist. Configuring project first...");
+        crate::commands::build::execute_reconfigure(cli, &[]).await?;
+    }
+
+    let commands = compile_commands::load(build_dir)?;
+    compile_commands::find_for_file(&commands, file)
+        .cloned()
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "{} is not part of the build. Is it listed in the SRCS of a component?",
+                file.display()
+            )
+        })
+}
+
+/// Run a compile command's arguments from the directory it was generated for
+async fn run_compiler(command: &CompileCommand, args: &[String], verbose: bool) -> Result<()> {
+    let (program, program_args) = args
+        .split_first()
+        .ok_or_else(|| anyhow::anyhow!("Empty compile command for {}", command.file.display()))?;
+    let program_args: Vec<&str> = program_args.iter().map(|s| s.as_str()).collect();
+
+    utils::run_command(program, &program_args, Some(&command.directory), verbose).await
+}
+
+pub async fn execute(cli: &Cli, file: &Path) -> Result<()> {
+    utils::setup_idf_environment()?;
+
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+
+    let command = find_compile_command(cli, &build_dir, file).await?;
+    let object = command
+        .object_path()
+        .ok_or_else(|| anyhow::anyhow!("Cannot determine object file for {}", file.display()))?;
+
+    println!("Compiling {} -> {}", file.display(), object.display());
+
+    if build_systems::get_generator_from_cache(&build_dir).as_deref() == Some("Ninja") {
+        // Ninja exposes every object file as a target
+        let build_args = vec![
+            "--build",
+            build_dir.to_str().unwrap(),
+            "--target",
+            object.to_str().unwrap(),
+        ];
+
+        utils::run_command("cmake", &build_args, Some(&project_dir), cli.verbose).await?;
+    } else {
+        // Other generators don't, so run the exact compiler invocation instead
+        run_compiler(&command, &command.args(), cli.verbose).await?;
+    }
+
+    println!("Compile completed successfully!");
+    Ok(())
+}
+
+pub async fn execute_preprocess(cli: &Cli, file: &Path, output: Option<&Path>) -> Result<()> {
+    utils::setup_idf_environment()?;
+
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+
+    let command = find_compile_command(cli, &build_dir, file).await?;
+
+    // Resolve the output against the current directory, the compiler runs in the build directory
+    let output = output.map(std::path::absolute).transpose()?;
+    let args = command.args_for_mode("-E", output.as_deref());
+
+    run_compiler(&command, &args, cli.verbose).await?;
+
+    if let Some(output) = output {
+        println!("Preprocessed output written to: {}", output.display());
+    }
+
+    Ok(())
+}
+
+pub async fn execute_asm(
+    cli: &Cli,
+    file: Option<&Path>,
+    function: Option<&str>,
+    output: Option<&Path>,
+) -> Result<()> {
+    utils::setup_idf_environment()?;
+
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+
+    let output = output.map(std::path::absolute).transpose()?;
+
+    let function = match function {
+        Some(function) => function,
+        None => {
+            // Annotated compiler output for the whole file
+            let file = file.ok_or_else(|| anyhow::anyhow!("asm requires a file or --function"))?;
+            let command = find_compile_command(cli, &build_dir, file).await?;
+
+            let stdout = Path::new("-");
+            let mut args = command.args_for_mode("-S", Some(output.as_deref().unwrap_or(stdout)));
+            args.push("-fverbose-asm".to_string());
+
+            run_compiler(&command, &args, cli.verbose).await?;
+
+            if let Some(output) = output {
+                println!("Assembly listing written to: {}", output.display());
+            }
+            return Ok(());
+        }
+    };
+
+    // Disassemble a single function with interleaved source, either from the
+    // file's object or from the linked app
+    let binary = match file {
+        Some(file) => {
+            let command = find_compile_command(cli, &build_dir, file).await?;
+            let object = command.object_path().ok_or_else(|| {
+                anyhow::anyhow!("Cannot determine object file for {}", file.display())
+            })?;
+            if !build_dir.join(&object).exists() {
+                execute(cli, file).await?;
+            }
+            build_dir.join(object)
+        }
+        None => {
+            let elf = utils::get_app_elf_path(&build_dir)?;
+            if !elf.exists() {
+                return Err(anyhow::anyhow!(
+                    "App ELF {} doesn't exist. Run 'build' command first.",
+                    elf.display()
+                ));
+            }
+            elf
+        }
+    };
+
+    let objdump = build_systems::get_cmake_cache_entry(&build_dir, "CMAKE_OBJDUMP")
+        .ok_or_else(|| anyhow::anyhow!("CMAKE_OBJDUMP not found in CMakeCache.txt"))?;
+
+    let disassemble_arg = format!("--disassemble={}", function);
+    let objdump_args = vec!["-d", "-S", "-l", &disassemble_arg, binary.to_str().unwrap()];
+
+    match output {
+        Some(output) => {
+            let listing =
+                utils::run_command_with_output(&objdump, &objdump_args, Some(&project_dir)).await?;
+            std::fs::write(&output, listing)?;
+            println!("Assembly listing written to: {}", output.display());
+        }
+        None => {
+            utils::run_command(&objdump, &objdump_args, Some(&project_dir), cli.verbose).await?;
+        }
+    }
+
+    Ok(())
+}
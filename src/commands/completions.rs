@@ -0,0 +1,692 @@
+//! Shell completion scripts, generated from the command line definition. Targets are
+//! written into the scripts, serial ports are asked from `idf-rs list-ports --names`
+//! while completing.
+
+use crate::cli::Cli;
+use crate::utils;
+use anyhow::Result;
+use clap::CommandFactory;
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+/// The values completed for an option or positional argument
+#[derive(Debug, Clone, PartialEq)]
+enum Values {
+    /// Anything, left to the shell's file completion
+    Any,
+    List(Vec<String>),
+    /// The serial ports attached when completing
+    Ports,
+}
+
+#[derive(Debug)]
+struct CompletionOption {
+    /// e.g. ["-p", "--port"]
+    names: Vec<String>,
+    help: String,
+    /// None for flags
+    values: Option<Values>,
+}
+
+/// A command or subcommand with what can follow it
+#[derive(Debug)]
+struct Node {
+    /// The subcommand names leading to it, empty for idf-rs itself
+    path: Vec<String>,
+    options: Vec<CompletionOption>,
+    /// Names and help of the subcommands
+    subcommands: Vec<(String, String)>,
+    /// Values of the positional arguments, when they are known
+    positional: Option<Values>,
+}
+
+impl Node {
+    fn key(&self) -> String {
+        self.path.join(" ")
+    }
+
+    /// Commands can be chained after a top-level command without subcommands, e.g.
+    /// `build flash monitor`
+    fn chains(&self) -> bool {
+        self.path.len() == 1 && self.subcommands.is_empty()
+    }
+}
+
+fn first_line(help: Option<&clap::builder::StyledStr>) -> String {
+    help.map(|help| help.to_string())
+        .and_then(|help| help.lines().next().map(str::to_string))
+        .unwrap_or_default()
+}
+
+fn values_of(arg: &clap::Arg, path: &[String]) -> Values {
+    match arg.get_id().as_str() {
+        "port" if path.is_empty() => Values::Ports,
+        "target" => Values::List(
+            utils::SUPPORTED_TARGETS
+                .iter()
+                .map(|t| t.to_string())
+                .collect(),
+        ),
+        _ => {
+            let values: Vec<String> = arg
+                .get_possible_values()
+                .iter()
+                .filter(|value| !value.is_hide_set())
+                .map(|value| value.get_name().to_string())
+                .collect();
+            if values.is_empty() {
+                Values::Any
+            } else {
+                Values::List(values)
+            }
+        }
+    }
+}
+
+fn collect(command: &clap::Command, path: Vec<String>, nodes: &mut Vec<Node>) {
+    let mut options = Vec::new();
+    let mut positional = None;
+    for arg in command.get_arguments().filter(|arg| !arg.is_hide_set()) {
+        let values = arg
+            .get_action()
+            .takes_values()
+            .then(|| values_of(arg, &path));
+        if arg.is_positional() {
+            if positional.is_none() {
+                positional = values.filter(|values| *values != Values::Any);
+            }
+            continue;
+        }
+        let mut names = Vec::new();
+        if let Some(short) = arg.get_short() {
+            names.push(format!("-{}", short));
+        }
+        if let Some(long) = arg.get_long() {
+            names.push(format!("--{}", long));
+        }
+        options.push(CompletionOption {
+            names,
+            help: first_line(arg.get_help()),
+            values,
+        });
+    }
+
+    let subcommands: Vec<&clap::Command> = command
+        .get_subcommands()
+        .filter(|subcommand| !subcommand.is_hide_set())
+        .collect();
+    nodes.push(Node {
+        path: path.clone(),
+        options,
+        subcommands: subcommands
+            .iter()
+            .map(|subcommand| {
+                (
+                    subcommand.get_name().to_string(),
+                    first_line(subcommand.get_about()),
+                )
+            })
+            .collect(),
+        positional,
+    });
+    for subcommand in subcommands {
+        // clap's help subcommand would repeat the whole tree
+        if subcommand.get_name() == "help" {
+            continue;
+        }
+        let mut path = path.clone();
+        path.push(subcommand.get_name().to_string());
+        collect(subcommand, path, nodes);
+    }
+}
+
+fn nodes() -> Vec<Node> {
+    let mut command = Cli::command();
+    command.build();
+    let mut nodes = Vec::new();
+    collect(&command, Vec::new(), &mut nodes);
+    nodes
+}
+
+/// Case patterns of an option's names at a path, the top-level options (which main
+/// also accepts after a command) at any
+fn option_patterns(node: &Node, option: &CompletionOption) -> Vec<String> {
+    let path = if node.path.is_empty() {
+        "*".to_string()
+    } else {
+        node.key()
+    };
+    option
+        .names
+        .iter()
+        .map(|name| format!("{}:{}", path, name))
+        .collect()
+}
+
+/// Flags of subcommands named like a top-level option taking a value, which must not
+/// complete that value
+fn shadowing_flags(nodes: &[Node]) -> Vec<(&Node, &CompletionOption)> {
+    let top_level: Vec<&String> = nodes[0]
+        .options
+        .iter()
+        .filter(|option| option.values.is_some())
+        .flat_map(|option| &option.names)
+        .collect();
+    nodes[1..]
+        .iter()
+        .flat_map(|node| node.options.iter().map(move |option| (node, option)))
+        .filter(|(_, option)| {
+            option.values.is_none() && option.names.iter().any(|name| top_level.contains(&name))
+        })
+        .collect()
+}
+
+/// The "path:word" patterns moving from a command to one of its subcommands, below
+/// the top level
+fn transitions(nodes: &[Node]) -> Vec<String> {
+    nodes[1..]
+        .iter()
+        .flat_map(|node| {
+            node.subcommands
+                .iter()
+                .map(move |(name, _)| format!("{}:{}", node.key(), name))
+        })
+        .collect()
+}
+
+fn top_level_commands(nodes: &[Node]) -> Vec<&str> {
+    nodes[0]
+        .subcommands
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect()
+}
+
+fn quote_single(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+pub fn bash(bin: &str) -> String {
+    let nodes = nodes();
+    let mut script = format!(
+        "# bash completion for {bin}, generated by `{bin} completions bash`\n\
+         _idf_rs() {{\n    \
+         local cur=\"${{COMP_WORDS[COMP_CWORD]}}\" prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"\n    \
+         local cmd_path=\"\" word i\n    \
+         local top_commands=\"{commands}\"\n    \
+         for ((i = 1; i < COMP_CWORD; i++)); do\n        \
+         word=\"${{COMP_WORDS[i]}}\"\n        \
+         case \"$cmd_path:$word\" in\n",
+        commands = top_level_commands(&nodes).join(" ")
+    );
+    let transitions = transitions(&nodes);
+    if !transitions.is_empty() {
+        script.push_str(&format!(
+            "            {}) cmd_path=\"$cmd_path $word\" ;;\n",
+            quote_patterns(&transitions)
+        ));
+    }
+    let commands: Vec<String> = top_level_commands(&nodes)
+        .iter()
+        .map(|name| format!("*:{}", name))
+        .collect();
+    script.push_str(&format!(
+        "            {}) cmd_path=\"$word\" ;;\n        esac\n    done\n\n    \
+         case \"$cmd_path:$prev\" in\n",
+        quote_patterns(&commands)
+    ));
+
+    let ports = "$(\"${COMP_WORDS[0]}\" list-ports --names 2>/dev/null)";
+    let compgen = |words: &str| format!("COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))", words);
+    for (node, option) in shadowing_flags(&nodes) {
+        script.push_str(&format!(
+            "        {}) ;;\n",
+            quote_patterns(&option_patterns(node, option))
+        ));
+    }
+    for node in nodes.iter().rev() {
+        for option in &node.options {
+            let action = match &option.values {
+                None => continue,
+                Some(Values::Any) => "return".to_string(),
+                Some(Values::List(values)) => format!("{}; return", compgen(&values.join(" "))),
+                Some(Values::Ports) => format!("{}; return", compgen(ports)),
+            };
+            script.push_str(&format!(
+                "        {}) {} ;;\n",
+                quote_patterns(&option_patterns(node, option)),
+                action
+            ));
+        }
+    }
+    script.push_str("    esac\n\n    case \"$cmd_path\" in\n");
+
+    for node in &nodes {
+        let mut words: Vec<&str> = node
+            .options
+            .iter()
+            .flat_map(|option| option.names.iter().map(String::as_str))
+            .collect();
+        if node.path.is_empty() || node.chains() {
+            words.push("$top_commands");
+        } else {
+            words.extend(node.subcommands.iter().map(|(name, _)| name.as_str()));
+        }
+        match &node.positional {
+            Some(Values::List(values)) => words.extend(values.iter().map(String::as_str)),
+            Some(Values::Ports) => words.push(ports),
+            _ => {}
+        }
+        script.push_str(&format!(
+            "        \"{}\") {} ;;\n",
+            node.key(),
+            compgen(&words.join(" "))
+        ));
+    }
+    script.push_str(&format!(
+        "    esac\n}}\ncomplete -o default -F _idf_rs {} idf.py\n",
+        bin
+    ));
+    script
+}
+
+fn quote_patterns(patterns: &[String]) -> String {
+    patterns
+        .iter()
+        .map(|pattern| match pattern.split_once(':') {
+            // The wildcard path stays unquoted
+            Some(("*", rest)) => format!("*:{}", quote_single(rest)),
+            _ => quote_single(pattern),
+        })
+        .collect::<Vec<_>>()
+        .join("|")
+}
+
+pub fn zsh(bin: &str) -> String {
+    let nodes = nodes();
+    let describe =
+        |name: &str, help: &str| quote_single(&format!("{}:{}", name.replace(':', r"\:"), help));
+    let top_commands: Vec<String> = nodes[0]
+        .subcommands
+        .iter()
+        .map(|(name, help)| describe(name, help))
+        .collect();
+    let mut script = format!(
+        "#compdef {bin} idf.py\n\
+         # zsh completion for {bin}, generated by `{bin} completions zsh`\n\
+         _idf_rs() {{\n    \
+         local cmd_path=\"\" word i\n    \
+         local -a candidates top_commands\n    \
+         top_commands=({top_commands})\n    \
+         for ((i = 2; i < CURRENT; i++)); do\n        \
+         word=\"${{words[i]}}\"\n        \
+         case \"$cmd_path:$word\" in\n",
+        top_commands = top_commands.join(" ")
+    );
+    let transitions = transitions(&nodes);
+    if !transitions.is_empty() {
+        script.push_str(&format!(
+            "            {}) cmd_path=\"$cmd_path $word\" ;;\n",
+            quote_patterns(&transitions)
+        ));
+    }
+    let commands: Vec<String> = top_level_commands(&nodes)
+        .iter()
+        .map(|name| format!("*:{}", name))
+        .collect();
+    script.push_str(&format!(
+        "            {}) cmd_path=\"$word\" ;;\n        esac\n    done\n\n    \
+         case \"$cmd_path:${{words[CURRENT-1]}}\" in\n",
+        quote_patterns(&commands)
+    ));
+
+    let ports = "compadd -- ${(f)\"$(${words[1]} list-ports --names 2>/dev/null)\"}";
+    for (node, option) in shadowing_flags(&nodes) {
+        script.push_str(&format!(
+            "        {}) ;;\n",
+            quote_patterns(&option_patterns(node, option))
+        ));
+    }
+    for node in nodes.iter().rev() {
+        for option in &node.options {
+            let action = match &option.values {
+                None => continue,
+                Some(Values::Any) => "_files; return".to_string(),
+                Some(Values::List(values)) => format!("compadd -- {}; return", values.join(" ")),
+                Some(Values::Ports) => format!("{}; return", ports),
+            };
+            script.push_str(&format!(
+                "        {}) {} ;;\n",
+                quote_patterns(&option_patterns(node, option)),
+                action
+            ));
+        }
+    }
+    script.push_str("    esac\n\n    case \"$cmd_path\" in\n");
+
+    for node in &nodes {
+        let mut candidates: Vec<String> = node
+            .options
+            .iter()
+            .flat_map(|option| option.names.iter().map(|name| describe(name, &option.help)))
+            .collect();
+        if node.path.is_empty() || node.chains() {
+            candidates.push("$top_commands".to_string());
+        } else {
+            candidates.extend(
+                node.subcommands
+                    .iter()
+                    .map(|(name, help)| describe(name, help)),
+            );
+        }
+        let positional = match &node.positional {
+            Some(Values::List(values)) => format!("; compadd -- {}", values.join(" ")),
+            Some(Values::Ports) => format!("; {}", ports),
+            _ => String::new(),
+        };
+        script.push_str(&format!(
+            "        \"{}\") candidates=({}){} ;;\n",
+            node.key(),
+            candidates.join(" "),
+            positional
+        ));
+    }
+    script.push_str(&format!(
+        "    esac\n    _describe -t commands {} candidates\n}}\n\n_idf_rs \"$@\"\n",
+        bin
+    ));
+    script
+}
+
+fn fish_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\\', r"\\").replace('\'', r"\'"))
+}
+
+pub fn fish(bin: &str) -> String {
+    let nodes = nodes();
+    let mut script = format!(
+        "# fish completion for {bin}, generated by `{bin} completions fish`\n\
+         function __idf_rs_path\n    \
+         set -l cmd_path ''\n    \
+         for word in (commandline -opc)[2..-1]\n        \
+         switch \"$cmd_path:$word\"\n"
+    );
+    let transitions = transitions(&nodes);
+    if !transitions.is_empty() {
+        let patterns: Vec<String> = transitions.iter().map(|t| fish_quote(t)).collect();
+        script.push_str(&format!(
+            "            case {}\n                set cmd_path \"$cmd_path $word\"\n",
+            patterns.join(" ")
+        ));
+    }
+    let commands: Vec<String> = top_level_commands(&nodes)
+        .iter()
+        .map(|name| format!("'*:{}'", name))
+        .collect();
+    let chaining: Vec<String> = std::iter::once(String::new())
+        .chain(nodes.iter().filter(|node| node.chains()).map(Node::key))
+        .map(|key| fish_quote(&key))
+        .collect();
+    script.push_str(&format!(
+        "            case {}\n                set cmd_path $word\n        end\n    end\n    \
+         echo $cmd_path\n\
+         end\n\n\
+         function __idf_rs_at\n    \
+         set -l cmd_path (__idf_rs_path)\n    \
+         test \"$cmd_path\" = \"$argv[1]\"\n\
+         end\n\n\
+         function __idf_rs_chains\n    \
+         set -l cmd_path (__idf_rs_path)\n    \
+         contains -- \"$cmd_path\" {}\n\
+         end\n\n\
+         function __idf_rs_ports\n    \
+         set -l bin (commandline -opc)[1]\n    \
+         $bin list-ports --names 2>/dev/null\n\
+         end\n\n\
+         complete -c {bin} -f\n\
+         complete -c idf.py -w {bin}\n",
+        commands.join(" "),
+        chaining.join(" ")
+    ));
+
+    for node in &nodes {
+        let condition = fish_quote(&format!("__idf_rs_at {}", fish_quote(&node.key())));
+        for option in &node.options {
+            let mut line = format!("complete -c {} -n {}", bin, condition);
+            for name in &option.names {
+                match name.strip_prefix("--") {
+                    Some(long) => line.push_str(&format!(" -l {}", long)),
+                    None => line.push_str(&format!(" -s {}", &name[1..])),
+                }
+            }
+            match &option.values {
+                None => {}
+                Some(Values::Any) => line.push_str(" -r -F"),
+                Some(Values::List(values)) => {
+                    line.push_str(&format!(" -x -a {}", fish_quote(&values.join(" "))))
+                }
+                Some(Values::Ports) => line.push_str(" -x -a '(__idf_rs_ports)'"),
+            }
+            if !option.help.is_empty() {
+                line.push_str(&format!(" -d {}", fish_quote(&option.help)));
+            }
+            script.push_str(&line);
+            script.push('\n');
+        }
+        let condition = if node.path.is_empty() {
+            "__idf_rs_chains".to_string()
+        } else {
+            condition
+        };
+        for (name, help) in &node.subcommands {
+            script.push_str(&format!(
+                "complete -c {} -n {} -a {} -d {}\n",
+                bin,
+                condition,
+                name,
+                fish_quote(help)
+            ));
+        }
+        match &node.positional {
+            Some(Values::List(values)) => script.push_str(&format!(
+                "complete -c {} -n {} -a {}\n",
+                bin,
+                condition,
+                fish_quote(&values.join(" "))
+            )),
+            Some(Values::Ports) => script.push_str(&format!(
+                "complete -c {} -n {} -a '(__idf_rs_ports)'\n",
+                bin, condition
+            )),
+            _ => {}
+        }
+    }
+    script
+}
+
+fn powershell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+pub fn powershell(bin: &str) -> String {
+    let nodes = nodes();
+    let describe = |name: &str, help: &str| {
+        powershell_quote(&format!(
+            "{}={}",
+            name,
+            if help.is_empty() { name } else { help }
+        ))
+    };
+    let list = |items: Vec<String>| {
+        items
+            .iter()
+            .map(|item| powershell_quote(item))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut script = format!(
+        "# PowerShell completion for {bin}, generated by `{bin} completions powershell`\n\
+         Register-ArgumentCompleter -Native -CommandName '{bin}', 'idf.py' -ScriptBlock {{\n    \
+         param($wordToComplete, $commandAst, $cursorPosition)\n    \
+         $words = @($commandAst.CommandElements |\n        \
+         Where-Object {{ $_.Extent.EndOffset -lt $cursorPosition }} |\n        \
+         ForEach-Object {{ $_.ToString() }})\n    \
+         $transitions = @({})\n    \
+         # name=help\n    \
+         $topCommands = @({})\n    \
+         $commands = $topCommands | ForEach-Object {{ ($_ -split '=', 2)[0] }}\n    \
+         $path = ''\n    \
+         foreach ($word in $words | Select-Object -Skip 1) {{\n        \
+         if ($transitions -contains \"${{path}}:$word\") {{ $path = \"$path $word\" }}\n        \
+         elseif ($commands -contains $word) {{ $path = $word }}\n    \
+         }}\n    \
+         $prev = $words[-1]\n    \
+         $values = $null\n    \
+         switch -Wildcard (\"${{path}}:$prev\") {{\n",
+        list(transitions(&nodes)),
+        nodes[0]
+            .subcommands
+            .iter()
+            .map(|(name, help)| describe(name, help))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    let ports = "@(& $words[0] list-ports --names 2>$null)";
+    for (node, option) in shadowing_flags(&nodes) {
+        for pattern in option_patterns(node, option) {
+            script.push_str(&format!(
+                "        {} {{ break }}\n",
+                powershell_quote(&pattern)
+            ));
+        }
+    }
+    for node in nodes.iter().rev() {
+        for option in &node.options {
+            let action = match &option.values {
+                None => continue,
+                // Nothing leaves it to PowerShell's path completion
+                Some(Values::Any) => "return".to_string(),
+                Some(Values::List(values)) => {
+                    format!("$values = @({}); break", list(values.clone()))
+                }
+                Some(Values::Ports) => format!("$values = {}; break", ports),
+            };
+            for pattern in option_patterns(node, option) {
+                script.push_str(&format!(
+                    "        {} {{ {} }}\n",
+                    powershell_quote(&pattern),
+                    action
+                ));
+            }
+        }
+    }
+    script.push_str(
+        "    }\n    \
+         if ($null -ne $values) {\n        \
+         $values | Where-Object { $_ -like \"$wordToComplete*\" } | ForEach-Object {\n            \
+         [System.Management.Automation.CompletionResult]::new($_, $_, 'ParameterValue', $_)\n        \
+         }\n        \
+         return\n    \
+         }\n\n    \
+         $candidates = switch ($path) {\n",
+    );
+
+    for node in &nodes {
+        let mut candidates: Vec<String> = node
+            .options
+            .iter()
+            .flat_map(|option| option.names.iter().map(|name| describe(name, &option.help)))
+            .collect();
+        let mut extra = String::new();
+        if node.path.is_empty() || node.chains() {
+            extra.push_str(" + $topCommands");
+        } else {
+            candidates.extend(
+                node.subcommands
+                    .iter()
+                    .map(|(name, help)| describe(name, help)),
+            );
+        }
+        if let Some(Values::List(values)) = &node.positional {
+            candidates.extend(values.iter().map(|value| describe(value, "")));
+        }
+        if let Some(Values::Ports) = node.positional {
+            extra.push_str(&format!(" + @({} | ForEach-Object {{ \"$_=$_\" }})", ports));
+        }
+        script.push_str(&format!(
+            "        {} {{ @({}){} }}\n",
+            powershell_quote(&node.key()),
+            candidates.join(", "),
+            extra
+        ));
+    }
+    script.push_str(
+        "    }\n    \
+         $candidates | ForEach-Object {\n        \
+         $name, $help = $_ -split '=', 2\n        \
+         if ($name -like \"$wordToComplete*\") {\n            \
+         $type = if ($name.StartsWith('-')) { 'ParameterName' } else { 'ParameterValue' }\n            \
+         [System.Management.Automation.CompletionResult]::new($name, $name, $type, $help)\n        \
+         }\n    \
+         }\n\
+         }\n",
+    );
+    script
+}
+
+/// Print the completion script of a shell
+pub async fn execute(shell: Shell) -> Result<()> {
+    let bin = Cli::command().get_name().to_string();
+    let script = match shell {
+        Shell::Bash => bash(&bin),
+        Shell::Zsh => zsh(&bin),
+        Shell::Fish => fish(&bin),
+        Shell::Powershell => powershell(&bin),
+    };
+    print!("{}", script);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_completion_model() {
+        let nodes = nodes();
+        let top = &nodes[0];
+        assert!(top.path.is_empty());
+        assert!(top.subcommands.iter().any(|(name, _)| name == "build"));
+        let port = top
+            .options
+            .iter()
+            .find(|option| option.names.contains(&"--port".to_string()))
+            .unwrap();
+        assert_eq!(port.values, Some(Values::Ports));
+
+        let set_target = nodes
+            .iter()
+            .find(|node| node.key() == "set-target")
+            .unwrap();
+        assert!(
+            matches!(&set_target.positional, Some(Values::List(targets)) if targets.contains(&"esp32s3".to_string()))
+        );
+        assert!(set_target.chains());
+        assert!(nodes.iter().any(|node| node.key() == "fleet flash"));
+        assert!(transitions(&nodes).contains(&"fleet:flash".to_string()));
+
+        let script = bash("idf-rs");
+        assert!(script.contains(
+            "*:'--port') COMPREPLY=($(compgen -W \"$(\"${COMP_WORDS[0]}\" list-ports --names"
+        ));
+        assert!(script.contains("complete -o default -F _idf_rs idf-rs idf.py"));
+        assert!(fish("idf-rs").contains("-s p -l port -x -a '(__idf_rs_ports)'"));
+    }
+}
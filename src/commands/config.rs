@@ -1,7 +1,27 @@
-use crate::{config, utils, Cli};
+use crate::{commands, config, kconfig, output, stats, utils, Cli};
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
-pub async fn execute_menuconfig(cli: &Cli) -> Result<()> {
+/// Project-local directory holding the named sdkconfig snapshots
+const SNAPSHOT_DIR: &str = ".sdkconfig-snapshots";
+
+/// Metadata stored next to each sdkconfig snapshot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SnapshotInfo {
+    /// Unix time the snapshot was saved
+    created: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    idf_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    note: Option<String>,
+}
+
+pub async fn execute_menuconfig(cli: &Cli, dry_run: bool, native: bool) -> Result<()> {
     utils::setup_idf_environment()?;
 
     let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
@@ -9,26 +29,231 @@ pub async fn execute_menuconfig(cli: &Cli) -> Result<()> {
 
     println!("Starting menuconfig...");
 
-    // Ensure build directory exists and is configured
-    if !build_dir.exists() {
-        println!("Build directory doesn't exist. Configuring project first...");
-        crate::commands::build::execute_reconfigure(cli).await?;
+    // Ensure build directory exists and is configured, menuconfig needs its config.env
+    if !build_dir.join("config.env").exists() {
+        println!("Project isn't configured yet. Configuring project first...");
+        crate::commands::build::execute_reconfigure(cli, &[]).await?;
     }
 
-    // Run menuconfig using cmake
-    let menuconfig_args = vec![
-        "--build",
-        build_dir.to_str().unwrap(),
-        "--target",
-        "menuconfig",
-    ];
+    // The edited configuration is only written to sdkconfig once the changes are confirmed
+    let sdkconfig_path = config::get_sdkconfig_path(&project_dir);
+    let original_content = std::fs::read_to_string(&sdkconfig_path).unwrap_or_default();
+
+    let updated_content = if native {
+        let changes = commands::menuconfig::run(&build_dir, &sdkconfig_path)?;
+        config::update_sdkconfig_content(&original_content, &changes)
+    } else {
+        run_python_menuconfig(&build_dir, &sdkconfig_path)?
+    };
+
+    let original = config::SdkConfig::parse_sdkconfig(&original_content)?;
+    let updated = config::SdkConfig::parse_sdkconfig(&updated_content)?;
+    let changes = config::diff(&original, &updated);
+
+    if changes.is_empty() {
+        println!("No configuration changes.");
+    } else {
+        println!("Pending sdkconfig changes:");
+        for change in &changes {
+            println!("  {}", change);
+        }
 
-    utils::run_command("cmake", &menuconfig_args, Some(&project_dir), cli.verbose).await?;
+        let keep = if dry_run {
+            false
+        } else if std::io::stdin().is_terminal() {
+            utils::confirm("Save these changes to sdkconfig?", true)?
+        } else {
+            true
+        };
+
+        if keep {
+            // The next build regenerates sdkconfig.h and config.env from it
+            std::fs::write(&sdkconfig_path, updated_content)?;
+        } else if dry_run {
+            println!("Dry run: sdkconfig left unchanged.");
+        } else {
+            println!("Changes discarded, sdkconfig left unchanged.");
+        }
+    }
 
     println!("Menuconfig completed!");
     Ok(())
 }
 
+/// Run the Python/curses menuconfig of ESP-IDF on a copy of sdkconfig, with the Kconfig
+/// environment of the configured build, and return the edited content. Unlike the
+/// menuconfig build target this leaves sdkconfig and the generated config files alone.
+fn run_python_menuconfig(build_dir: &Path, sdkconfig_path: &Path) -> Result<String> {
+    let mut env = kconfig::load_config_env(build_dir)?;
+    let idf_path = match env.get("IDF_PATH") {
+        Some(path) if !path.is_empty() => PathBuf::from(path),
+        _ => utils::get_idf_path()?,
+    };
+    env.insert(
+        "IDF_PATH".to_string(),
+        idf_path.to_string_lossy().to_string(),
+    );
+
+    let edited_path = build_dir.join("sdkconfig.menuconfig");
+    match std::fs::read_to_string(sdkconfig_path) {
+        Ok(content) => std::fs::write(&edited_path, content)?,
+        Err(_) => {
+            let _ = std::fs::remove_file(&edited_path);
+        }
+    }
+
+    let status = std::process::Command::new(utils::get_python_executable()?)
+        .args(["-m", "menuconfig"])
+        .arg(idf_path.join("Kconfig"))
+        .envs(&env)
+        .env("KCONFIG_CONFIG", &edited_path)
+        .env("MENUCONFIG_STYLE", "aquatic")
+        .status()
+        .map_err(|e| anyhow::anyhow!("Failed to start menuconfig: {}", e))?;
+    let edited = std::fs::read_to_string(&edited_path).unwrap_or_default();
+    let _ = std::fs::remove_file(&edited_path);
+
+    if !status.success() {
+        return Err(anyhow::anyhow!("menuconfig failed with {}", status));
+    }
+    Ok(edited)
+}
+
+/// Option name with the CONFIG_ prefix
+fn option_name(name: &str) -> String {
+    match name.strip_prefix("CONFIG_") {
+        Some(_) => name.to_string(),
+        None => format!("CONFIG_{}", name),
+    }
+}
+
+pub async fn execute_set_config(
+    cli: &Cli,
+    assignments: &[String],
+    reconfigure: bool,
+) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+
+    let mut requested = Vec::new();
+    for assignment in assignments {
+        let (name, value) =
+            utils::parse_env_assignment(assignment).map_err(|e| anyhow::anyhow!(e))?;
+        // A value still quoted after the shell is a string value
+        let value = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(&value)
+            .to_string();
+        requested.push((option_name(&name), value));
+    }
+
+    // Options are validated against the Kconfig tree of the configured project
+    if !build_dir.join("config.env").exists() {
+        println!("Project isn't configured yet. Configuring project first...");
+        commands::build::execute_reconfigure(cli, &[]).await?;
+    }
+    let (tree, _) = kconfig::load(&build_dir)?;
+
+    let sdkconfig_path = config::get_sdkconfig_path(&project_dir);
+    let content = std::fs::read_to_string(&sdkconfig_path).unwrap_or_default();
+    let mut values = config::parse_option_values(&content);
+
+    let mut changes: Vec<(String, String)> = Vec::new();
+    for (name, input) in requested {
+        let symbol = tree.get(&name).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Unknown config option {}. Search with 'idf-rs config search'.",
+                name
+            )
+        })?;
+        let lookup = |symbol: &str| values.get(&format!("CONFIG_{}", symbol)).cloned();
+        let value = symbol.parse_value(&input, &lookup)?;
+
+        let unmet: Vec<&String> = symbol
+            .depends_on
+            .iter()
+            .filter(|condition| !kconfig::evaluate(condition, &lookup))
+            .collect();
+        if !unmet.is_empty() {
+            output::warning(format!(
+                "{} depends on {}, which is not met. Reconfiguring will drop the value.",
+                name,
+                unmet
+                    .iter()
+                    .map(|c| c.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" && ")
+            ));
+        }
+
+        println!("{}={}", name, symbol.sdkconfig_value(&value));
+        changes.retain(|(option, _)| *option != name);
+        changes.push((name.clone(), symbol.sdkconfig_value(&value)));
+        values.insert(name, value);
+    }
+
+    std::fs::write(
+        &sdkconfig_path,
+        config::update_sdkconfig_content(&content, &changes),
+    )?;
+
+    // Reconfiguring applies the dependencies of the new values to the rest of sdkconfig
+    if reconfigure {
+        commands::build::execute_reconfigure(cli, &[]).await?;
+    }
+
+    Ok(())
+}
+
+/// The values of config options, by their names with the CONFIG_ prefix
+pub fn get_config_values(cli: &Cli, names: &[String]) -> Result<Vec<(String, String)>> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+
+    let content = std::fs::read_to_string(config::get_sdkconfig_path(&project_dir))
+        .map_err(|_| anyhow::anyhow!("sdkconfig not found. Run 'idf-rs reconfigure' first."))?;
+    let values = config::parse_option_values(&content);
+    // Without a configured build only the options in sdkconfig are known
+    let tree = kconfig::load(&build_dir).ok().map(|(tree, _)| tree);
+
+    let mut result = Vec::new();
+    for name in names {
+        let name = option_name(name);
+        let symbol = tree.as_ref().and_then(|tree| tree.get(&name));
+        let value = match (values.get(&name), symbol) {
+            (Some(value), _) => value.clone(),
+            // Bools that aren't written to sdkconfig are disabled
+            (None, Some(symbol))
+                if matches!(
+                    symbol.kind,
+                    Some(kconfig::SymbolType::Bool | kconfig::SymbolType::Tristate)
+                ) =>
+            {
+                "n".to_string()
+            }
+            (None, Some(_)) => anyhow::bail!("{} is not set", name),
+            (None, None) => anyhow::bail!("Unknown config option {}", name),
+        };
+        result.push((name, value));
+    }
+
+    Ok(result)
+}
+
+pub async fn execute_get_config(cli: &Cli, names: &[String]) -> Result<()> {
+    for (name, value) in get_config_values(cli, names)? {
+        // A single option prints just its value, for use in scripts
+        if names.len() == 1 {
+            println!("{}", value);
+        } else {
+            println!("{}={}", name, value);
+        }
+    }
+
+    Ok(())
+}
+
 pub async fn execute_set_target(cli: &Cli, target: &str) -> Result<()> {
     let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
 
@@ -61,3 +286,224 @@ pub async fn execute_set_target(cli: &Cli, target: &str) -> Result<()> {
 
     Ok(())
 }
+
+fn snapshot_paths(project_dir: &Path, name: &str) -> Result<(PathBuf, PathBuf)> {
+    if name.is_empty()
+        || name.starts_with('.')
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        return Err(anyhow::anyhow!(
+            "Invalid snapshot name '{}' (use letters, digits, '-', '_' and '.')",
+            name
+        ));
+    }
+
+    let dir = project_dir.join(SNAPSHOT_DIR);
+    Ok((
+        dir.join(format!("{}.sdkconfig", name)),
+        dir.join(format!("{}.json", name)),
+    ))
+}
+
+fn format_time(time: u64) -> String {
+    let (year, month, day) = stats::civil_date(time / stats::SECONDS_PER_DAY);
+    let seconds = time % stats::SECONDS_PER_DAY;
+    format!(
+        "{}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60
+    )
+}
+
+/// Save a copy of sdkconfig as a named snapshot
+pub async fn execute_snapshot_save(
+    cli: &Cli,
+    name: &str,
+    note: Option<&str>,
+    force: bool,
+) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let sdkconfig_path = config::get_sdkconfig_path(&project_dir);
+    let (snapshot_path, info_path) = snapshot_paths(&project_dir, name)?;
+
+    let content = std::fs::read_to_string(&sdkconfig_path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", sdkconfig_path.display(), e))?;
+    if snapshot_path.exists() && !force {
+        return Err(anyhow::anyhow!(
+            "Snapshot '{}' already exists, use --force to overwrite it",
+            name
+        ));
+    }
+
+    let sdkconfig = config::SdkConfig::parse_sdkconfig(&content)?;
+    let info = SnapshotInfo {
+        created: stats::now(),
+        target: sdkconfig.get_target().cloned(),
+        idf_version: utils::get_idf_version(),
+        note: note.map(str::to_string),
+    };
+
+    std::fs::create_dir_all(project_dir.join(SNAPSHOT_DIR))?;
+    std::fs::write(&snapshot_path, content)?;
+    std::fs::write(&info_path, serde_json::to_string_pretty(&info)?)?;
+
+    println!(
+        "Saved sdkconfig as snapshot '{}' ({} options)",
+        name,
+        sdkconfig.settings.len()
+    );
+    Ok(())
+}
+
+/// Replace sdkconfig with a named snapshot, showing what changes
+pub async fn execute_snapshot_restore(cli: &Cli, name: &str) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let sdkconfig_path = config::get_sdkconfig_path(&project_dir);
+    let (snapshot_path, _) = snapshot_paths(&project_dir, name)?;
+
+    let content = std::fs::read_to_string(&snapshot_path).map_err(|_| {
+        anyhow::anyhow!(
+            "Snapshot '{}' does not exist, see 'config snapshot list'",
+            name
+        )
+    })?;
+
+    let current = std::fs::read_to_string(&sdkconfig_path).unwrap_or_default();
+    let changes = config::diff(
+        &config::SdkConfig::parse_sdkconfig(&current)?,
+        &config::SdkConfig::parse_sdkconfig(&content)?,
+    );
+
+    std::fs::write(&sdkconfig_path, content)?;
+
+    if changes.is_empty() {
+        println!("Restored snapshot '{}', no options changed", name);
+    } else {
+        println!("Restored snapshot '{}':", name);
+        for change in &changes {
+            println!("  {}", change);
+        }
+        println!("The next build reconfigures the project with the restored options.");
+    }
+    Ok(())
+}
+
+/// List the snapshots with their metadata and how far they are from the current sdkconfig
+pub async fn execute_snapshot_list(cli: &Cli) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let dir = project_dir.join(SNAPSHOT_DIR);
+
+    let mut names: Vec<String> = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                entry
+                    .file_name()
+                    .to_str()?
+                    .strip_suffix(".sdkconfig")
+                    .map(str::to_string)
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    if names.is_empty() {
+        println!("No sdkconfig snapshots, create one with 'config snapshot save <name>'");
+        return Ok(());
+    }
+    names.sort();
+
+    let current = config::load_project_config(&project_dir).ok();
+    println!(
+        "{:<20} {:<17} {:<10} {:>8}  Note",
+        "Name", "Created", "Target", "Changes"
+    );
+    for name in &names {
+        let (snapshot_path, info_path) = snapshot_paths(&project_dir, name)?;
+        let info: Option<SnapshotInfo> = std::fs::read_to_string(&info_path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok());
+        let changes = match (&current, config::SdkConfig::load_from_file(&snapshot_path)) {
+            (Some(current), Ok(snapshot)) => config::diff(current, &snapshot).len().to_string(),
+            _ => "-".to_string(),
+        };
+
+        let row = format!(
+            "{:<20} {:<17} {:<10} {:>8}  {}",
+            name,
+            info.as_ref()
+                .map(|info| format_time(info.created))
+                .unwrap_or_else(|| "-".to_string()),
+            info.as_ref()
+                .and_then(|info| info.target.clone())
+                .unwrap_or_else(|| "-".to_string()),
+            changes,
+            info.as_ref()
+                .and_then(|info| info.note.clone())
+                .unwrap_or_default()
+        );
+        println!("{}", row.trim_end());
+    }
+    println!();
+    println!("Changes are counted against the current sdkconfig.");
+    Ok(())
+}
+
+/// Find config options by name, prompt or help text
+pub async fn execute_search(cli: &Cli, pattern: &str) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+
+    let started = Instant::now();
+    let (tree, cached) = kconfig::load(&build_dir)?;
+    if cli.verbose {
+        println!(
+            "{} {} options from {} Kconfig files in {} ms",
+            if cached { "Loaded" } else { "Parsed" },
+            tree.symbols.len(),
+            tree.files.len(),
+            started.elapsed().as_millis()
+        );
+    }
+
+    let sdkconfig = config::load_project_config(&project_dir).ok();
+    // An exact option name shows just that option, with its help
+    let matches = match tree.get(pattern) {
+        Some(symbol) => vec![symbol],
+        None => tree.search(pattern),
+    };
+    if matches.is_empty() {
+        println!("No config options match '{}'", pattern);
+        return Ok(());
+    }
+
+    for symbol in &matches {
+        let name = format!("CONFIG_{}", symbol.name);
+        let value = sdkconfig
+            .as_ref()
+            .and_then(|sdkconfig| sdkconfig.settings.get(&name))
+            .map(String::as_str)
+            .unwrap_or("(not set)");
+        let kind = symbol.kind.map(|kind| kind.to_string()).unwrap_or_default();
+        println!("{} = {} [{}]", name, value, kind);
+        if let Some(prompt) = &symbol.prompt {
+            let mut location = symbol.menu.clone();
+            location.push(prompt.clone());
+            println!("    {}", location.join(" → "));
+        }
+        if cli.verbose || matches.len() == 1 {
+            if let Some(help) = &symbol.help {
+                for line in help.lines() {
+                    println!("    | {}", line);
+                }
+            }
+        }
+    }
+    println!();
+    println!("{} option(s) match '{}'", matches.len(), pattern);
+    Ok(())
+}
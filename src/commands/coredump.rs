@@ -0,0 +1,253 @@
+use crate::commands::{flash, partition_table as partition_commands};
+use crate::coredump::{self, CoreDump};
+use crate::partition_table::{self, TYPE_DATA};
+use crate::{openocd, output, sha256, stats, utils, Cli};
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// Subtype of the partition the panic handler writes core dumps to
+const SUBTYPE_COREDUMP: u8 = 0x03;
+
+/// Directory of the build the monitor saves captured crashes to
+const CAPTURE_DIR: &str = "coredumps";
+
+/// GDB commands printing the crash summary, like espcoredump's info_corefile
+const INFO_COMMANDS: [&str; 4] = [
+    "info threads",
+    "thread apply all bt full",
+    "info registers",
+    "info frame",
+];
+
+/// A core dump file: the partition image, the ELF core file itself or a captured log
+/// with the base64 encoded UART output
+fn read_core_file(path: &Path) -> Result<Vec<u8>> {
+    let data = std::fs::read(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+    match coredump::from_uart_text(&String::from_utf8_lossy(&data))? {
+        Some(decoded) => Ok(decoded),
+        None => Ok(data),
+    }
+}
+
+/// Read the core dump partition of the device
+async fn read_partition(cli: &Cli, project_dir: &Path, build_dir: &Path) -> Result<Vec<u8>> {
+    utils::setup_idf_environment()?;
+    let (_, partitions) = partition_commands::load_partitions(cli)?;
+    let partition = partitions
+        .iter()
+        .find(|p| p.ptype == TYPE_DATA && p.subtype == SUBTYPE_COREDUMP)
+        .ok_or_else(|| {
+            anyhow::anyhow!("The partition table has no coredump partition, enable CONFIG_ESP_COREDUMP_ENABLE_TO_FLASH")
+        })?;
+
+    println!(
+        "Reading core dump partition '{}' (0x{:x}, {})...",
+        partition.label,
+        partition.offset,
+        partition_table::format_size(partition.size)
+    );
+    std::fs::create_dir_all(build_dir)?;
+    let image = build_dir.join("coredump-read.bin");
+    let offset = format!("0x{:x}", partition.offset);
+    let size = format!("0x{:x}", partition.size);
+    let image_arg = image.to_string_lossy();
+    flash::run_esptool(
+        cli,
+        project_dir,
+        &["read_flash", &offset, &size, &image_arg],
+    )
+    .await?;
+    Ok(std::fs::read(&image)?)
+}
+
+/// Extract the core dump and save its ELF core file at `path`
+fn save_core(path: &Path, data: &[u8]) -> Result<CoreDump> {
+    let dump = coredump::extract(data)?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, &dump.elf)?;
+    Ok(dump)
+}
+
+/// `<build>/coredumps/<date>-<time>.<extension>` for crashes captured by the monitor
+pub fn capture_path(build_dir: &Path, extension: &str) -> Result<PathBuf> {
+    let time = stats::now();
+    let (year, month, day) = stats::civil_date(time / stats::SECONDS_PER_DAY);
+    let seconds = time % stats::SECONDS_PER_DAY;
+    let dir = build_dir.join(CAPTURE_DIR);
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir.join(format!(
+        "{}{:02}{:02}-{:02}{:02}{:02}.{}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        extension
+    )))
+}
+
+/// The core dump with the project and build directory it is decoded against
+struct LoadedCore {
+    project_dir: PathBuf,
+    build_dir: PathBuf,
+    dump: CoreDump,
+    path: PathBuf,
+}
+
+async fn load_core(cli: &Cli, core: Option<&Path>) -> Result<LoadedCore> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+    let data = match core {
+        Some(path) => read_core_file(path)?,
+        None => read_partition(cli, &project_dir, &build_dir).await?,
+    };
+    let path = build_dir.join("coredump.elf");
+    let dump = save_core(&path, &data)?;
+    Ok(LoadedCore {
+        project_dir,
+        build_dir,
+        dump,
+        path,
+    })
+}
+
+/// Print what the core dump's notes say, warning when it belongs to another build
+fn print_summary(dump: &CoreDump, app_elf: &Path) -> Result<()> {
+    let summary = coredump::summarize(&dump.elf)?;
+    println!("==================== CORE DUMP SUMMARY ====================");
+    if let Some(checksum) = dump.checksum {
+        println!("Checksum:        {} (valid)", checksum);
+    }
+    if let Some(reason) = &summary.panic_reason {
+        println!("Panic reason:    {}", reason);
+    }
+    if let Some(task) = summary.crashed_task {
+        println!("Crashed task:    0x{:08x}", task);
+    }
+    println!("Tasks:           {}", summary.tasks);
+    println!("Memory segments: {}", summary.memory_segments);
+    if let Some(app_sha256) = &summary.app_sha256 {
+        println!("App ELF SHA256:  {}", app_sha256);
+        if let Ok(elf) = std::fs::read(app_elf) {
+            if !sha256::to_hex(&sha256::digest(&elf)).starts_with(app_sha256.as_str()) {
+                output::warning(format!(
+                    "The core dump was taken with a different app than {}, the backtraces may be wrong",
+                    app_elf.display()
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Print the summary and the backtraces of all tasks with GDB in batch mode
+async fn report(cli: &Cli, core: &LoadedCore) -> Result<()> {
+    let app_elf = utils::get_app_elf_path(&core.build_dir)?;
+    print_summary(&core.dump, &app_elf)?;
+
+    let target = openocd::resolve_target(&core.project_dir, &core.build_dir);
+    let gdb = openocd::gdb_executable(&target);
+    let core_arg = format!("--core={}", core.path.display());
+    let mut args = vec!["--nw", "--batch", "-q", &core_arg];
+    for command in INFO_COMMANDS {
+        args.extend(["-ex", command]);
+    }
+    let app_elf_arg = app_elf.to_string_lossy();
+    args.push(&app_elf_arg);
+    if let Err(e) = utils::run_command(&gdb, &args, Some(&core.project_dir), cli.verbose).await {
+        output::warning(format!(
+            "Could not print the backtraces with {} ({})",
+            gdb, e
+        ));
+    }
+    println!("===========================================================");
+    Ok(())
+}
+
+/// Decode a core dump from the device's coredump partition or a file
+pub async fn execute_info(cli: &Cli, core: Option<&Path>) -> Result<()> {
+    let core = load_core(cli, core).await?;
+    println!("Saved the ELF core file as {}", core.path.display());
+    report(cli, &core).await
+}
+
+/// Open the core dump in GDB to inspect the crashed program's tasks and memory
+pub async fn execute_debug(cli: &Cli, core: Option<&Path>) -> Result<()> {
+    let core = load_core(cli, core).await?;
+    let app_elf = utils::get_app_elf_path(&core.build_dir)?;
+    print_summary(&core.dump, &app_elf)?;
+
+    let target = openocd::resolve_target(&core.project_dir, &core.build_dir);
+    let gdb = openocd::gdb_executable(&target);
+    let core_arg = format!("--core={}", core.path.display());
+    let app_elf_arg = app_elf.to_string_lossy();
+    utils::run_command(
+        &gdb,
+        &["--nw", &core_arg, &app_elf_arg],
+        Some(&core.project_dir),
+        cli.verbose,
+    )
+    .await
+}
+
+/// Save a core dump captured from the console as it was printed and as an ELF core
+/// file, and print its report with a hint where to find it, as the monitor does when
+/// the panic handler prints one
+pub async fn report_captured(
+    cli: &Cli,
+    project_dir: &Path,
+    build_dir: &Path,
+    data: &[u8],
+) -> Option<PathBuf> {
+    let result = async {
+        let raw_path = capture_path(build_dir, "bin")?;
+        std::fs::write(&raw_path, data)?;
+        let path = raw_path.with_extension("elf");
+        let dump = save_core(&path, data)?;
+        let core = LoadedCore {
+            project_dir: project_dir.to_path_buf(),
+            build_dir: build_dir.to_path_buf(),
+            dump,
+            path,
+        };
+        report(cli, &core).await?;
+        Ok::<_, anyhow::Error>(raw_path)
+    }
+    .await;
+    match result {
+        Ok(path) => {
+            println!(
+                "--- Core dump saved to {}, inspect it with: idf-rs coredump-debug --core {} ---",
+                path.display(),
+                path.display()
+            );
+            Some(path)
+        }
+        Err(e) => {
+            output::warning(format!("Could not decode the core dump ({})", e));
+            None
+        }
+    }
+}
+
+/// Save the panic handler output captured by the monitor
+pub fn save_panic_log(build_dir: &Path, lines: &[String]) -> Option<PathBuf> {
+    let result = capture_path(build_dir, "txt").and_then(|path| {
+        std::fs::write(&path, lines.join("\n") + "\n")?;
+        Ok(path)
+    });
+    match result {
+        Ok(path) => {
+            println!("--- Panic output saved to {} ---", path.display());
+            Some(path)
+        }
+        Err(e) => {
+            output::warning(format!("Could not save the panic output ({})", e));
+            None
+        }
+    }
+}
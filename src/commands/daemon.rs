@@ -0,0 +1,519 @@
+//! `idf-rs daemon`: a long-running idf-rs for IDEs and other tools, driven with
+//! JSON-RPC 2.0 over a local TCP socket, one message per line. Commands stream their
+//! output and events as notifications while they run. A connection starts with an
+//! "authenticate" request carrying the token the daemon wrote to a file only its user
+//! can read, so other local users and web pages can't drive it.
+
+use crate::cli::{self, Commands};
+use crate::{commands, output, utils, Cli};
+use anyhow::Result;
+use clap::Parser;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio::task::JoinHandle;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// The command of the request failed, the message is its error
+const COMMAND_FAILED: i64 = -32000;
+/// The connection didn't start with a valid "authenticate" request
+const UNAUTHORIZED: i64 = -32001;
+
+/// Address the monitor streams its log lines to, unless monitor-start gives one
+const MONITOR_ADDRESS: &str = "127.0.0.1:7879";
+
+/// The commands of the requests, parsed like the command line
+#[derive(Parser)]
+#[command(no_binary_name = true)]
+struct Request {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// Commands share the output log and the event sink, so one runs at a time
+static RUNNING: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// The monitor started with monitor-start
+static MONITOR: std::sync::Mutex<Option<JoinHandle<()>>> = std::sync::Mutex::new(None);
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({"jsonrpc": "2.0", "method": method, "params": params})
+}
+
+/// Tool output becomes an "output" notification, the other events "progress"
+fn event_notification(event: &output::Event) -> Value {
+    match event {
+        output::Event::Output { stream, line } => {
+            notification("output", json!({"stream": stream, "line": line}))
+        }
+        event => notification("progress", serde_json::to_value(event).unwrap_or_default()),
+    }
+}
+
+/// The options of a request: the daemon's, with the project, build directory and
+/// serial settings of the params, not resolved yet
+fn request_cli(base: &Cli, params: &Value) -> Result<Cli, RpcError> {
+    let string = |name: &str| match &params[name] {
+        Value::Null => Ok(None),
+        Value::String(value) => Ok(Some(value.clone())),
+        _ => Err(RpcError::new(
+            INVALID_PARAMS,
+            format!("{} must be a string", name),
+        )),
+    };
+
+    let mut cli = base.clone();
+    // Nobody is at the daemon's terminal to answer a prompt
+    cli.non_interactive = true;
+    if let Some(project_dir) = string("project_dir")? {
+        cli.project_dir = Some(project_dir.into());
+        // The build directory of another project is resolved again
+        cli.build_dir = None;
+    }
+    if let Some(build_dir) = string("build_dir")? {
+        cli.build_dir = Some(build_dir.into());
+    }
+    if let Some(port) = string("port")? {
+        cli.port = Some(port);
+    }
+    match &params["baud"] {
+        Value::Null => {}
+        baud => {
+            let baud = baud
+                .as_u64()
+                .and_then(|baud| u32::try_from(baud).ok())
+                .ok_or_else(|| RpcError::new(INVALID_PARAMS, "baud must be a number"))?;
+            cli.baud = Some(baud);
+        }
+    }
+    Ok(cli)
+}
+
+/// Complete the options of a request from the project. Resolving sets the --env
+/// variables and the output of the whole process, so it happens while holding
+/// RUNNING, not under a command that is already running.
+fn resolve(mut cli: Cli) -> Result<Cli, RpcError> {
+    cli::resolve_defaults(&mut cli).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))?;
+    Ok(cli)
+}
+
+/// A list of strings in the params, empty when missing
+fn strings(params: &Value, name: &str) -> Result<Vec<String>, RpcError> {
+    match &params[name] {
+        Value::Null => Ok(Vec::new()),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| item.as_str().map(str::to_string))
+            .collect::<Option<_>>()
+            .ok_or_else(|| {
+                RpcError::new(
+                    INVALID_PARAMS,
+                    format!("{} must be a list of strings", name),
+                )
+            }),
+        _ => Err(RpcError::new(
+            INVALID_PARAMS,
+            format!("{} must be a list of strings", name),
+        )),
+    }
+}
+
+/// Run a command line, forwarding its events as notifications
+async fn run_command(
+    cli: Cli,
+    args: Vec<String>,
+    notifications: &UnboundedSender<Value>,
+) -> Result<Value, RpcError> {
+    let request =
+        Request::try_parse_from(args).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))?;
+
+    let _running = RUNNING.lock().await;
+    let cli = resolve(Cli {
+        command: Some(request.command.clone()),
+        ..cli
+    })?;
+    let (sink, mut events) = mpsc::unbounded_channel();
+    output::set_sink(Some(sink));
+    // Boxed, the daemon is one of the commands it runs
+    let mut command = Box::pin(cli::execute_command(&cli, &request.command));
+    let result = loop {
+        tokio::select! {
+            result = &mut command => break result,
+            Some(event) = events.recv() => {
+                let _ = notifications.send(event_notification(&event));
+            }
+        }
+    };
+    output::set_sink(None);
+    while let Ok(event) = events.try_recv() {
+        let _ = notifications.send(event_notification(&event));
+    }
+
+    result
+        .map(|()| Value::Null)
+        .map_err(|e| RpcError::new(COMMAND_FAILED, format!("{:#}", e)))
+}
+
+fn config_get(cli: &Cli, params: &Value) -> Result<Value, RpcError> {
+    let names = strings(params, "names")?;
+    let values = commands::config::get_config_values(cli, &names)
+        .map_err(|e| RpcError::new(COMMAND_FAILED, e.to_string()))?;
+    Ok(Value::Object(
+        values
+            .into_iter()
+            .map(|(name, value)| (name, Value::String(value)))
+            .collect(),
+    ))
+}
+
+fn size(cli: &Cli) -> Result<Value, RpcError> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+    let app = commands::size::app_size(&build_dir)
+        .map_err(|e| RpcError::new(COMMAND_FAILED, e.to_string()))?;
+    Ok(match app {
+        Some(app) => json!({
+            "binary": app.binary,
+            "size": app.size,
+            "partition": app.partition,
+            "partition_size": app.partition_size,
+            "percent_used": (app.percent_used() * 10.0).round() / 10.0,
+        }),
+        None => Value::Null,
+    })
+}
+
+/// Start the monitor in the background, its log lines go to a WebSocket. The client
+/// that started it is notified when it exits.
+fn monitor_start(
+    cli: Cli,
+    params: &Value,
+    notifications: &UnboundedSender<Value>,
+) -> Result<Value, RpcError> {
+    let args = strings(params, "args")?;
+    let address = params["address"]
+        .as_str()
+        .unwrap_or(MONITOR_ADDRESS)
+        .to_string();
+
+    let mut monitor = MONITOR.lock().unwrap();
+    if monitor.as_ref().is_some_and(|task| !task.is_finished()) {
+        return Err(RpcError::new(
+            COMMAND_FAILED,
+            "The monitor is already running",
+        ));
+    }
+    let notifications = notifications.clone();
+    let websocket = format!("ws://{}/", address);
+    *monitor = Some(tokio::task::spawn_local(async move {
+        let options = commands::monitor::MonitorOptions {
+            serve: Some(address),
+            args,
+            ..Default::default()
+        };
+        let result = commands::monitor::execute(&cli, &options).await;
+        let params = match result {
+            Ok(()) => json!({}),
+            Err(e) => json!({"error": format!("{:#}", e)}),
+        };
+        let _ = notifications.send(notification("monitor-exited", params));
+    }));
+    Ok(json!({"websocket": websocket}))
+}
+
+fn monitor_stop() -> Value {
+    let task = MONITOR.lock().unwrap().take();
+    let running = task.as_ref().is_some_and(|task| !task.is_finished());
+    if let Some(task) = task {
+        task.abort();
+    }
+    json!({"stopped": running})
+}
+
+async fn call(
+    base: &Cli,
+    method: &str,
+    params: &Value,
+    notifications: &UnboundedSender<Value>,
+) -> Result<Value, RpcError> {
+    let cli = request_cli(base, params)?;
+    let command_line = |command: &str, args: Vec<String>| {
+        let mut line = vec![command.to_string()];
+        line.extend(args);
+        line
+    };
+
+    match method {
+        "build" => {
+            let args = command_line("build", strings(params, "args")?);
+            run_command(cli, args, notifications).await
+        }
+        "flash" => {
+            let args = command_line("flash", strings(params, "args")?);
+            run_command(cli, args, notifications).await
+        }
+        "config-set" => {
+            let mut args = command_line("set-config", strings(params, "assignments")?);
+            if params["reconfigure"] == Value::Bool(false) {
+                args.push("--no-reconfigure".to_string());
+            }
+            run_command(cli, args, notifications).await
+        }
+        "config-get" => {
+            let _running = RUNNING.lock().await;
+            config_get(&resolve(cli)?, params)
+        }
+        "size" => {
+            let _running = RUNNING.lock().await;
+            size(&resolve(cli)?)
+        }
+        "monitor-start" => {
+            let cli = {
+                let _running = RUNNING.lock().await;
+                resolve(cli)?
+            };
+            monitor_start(cli, params, notifications)
+        }
+        "monitor-stop" => Ok(monitor_stop()),
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("Unknown method {}", method),
+        )),
+    }
+}
+
+/// Answer one message; notifications (messages without an id) get no response
+async fn handle_message(cli: &Cli, message: &str, responses: &UnboundedSender<Value>) {
+    let request: Value = match serde_json::from_str(message) {
+        Ok(request) => request,
+        Err(e) => {
+            let error = json!({"code": PARSE_ERROR, "message": e.to_string()});
+            let _ = responses.send(json!({"jsonrpc": "2.0", "id": null, "error": error}));
+            return;
+        }
+    };
+    let id = request.get("id").cloned();
+    let params = request.get("params").cloned().unwrap_or_else(|| json!({}));
+
+    let result = match request["method"].as_str() {
+        Some(method) if params.is_object() => call(cli, method, &params, responses).await,
+        Some(_) => Err(RpcError::new(INVALID_PARAMS, "params must be an object")),
+        None => Err(RpcError::new(INVALID_REQUEST, "method is missing")),
+    };
+
+    let Some(id) = id else {
+        return;
+    };
+    let response = match result {
+        Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        Err(error) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": {"code": error.code, "message": error.message},
+        }),
+    };
+    let _ = responses.send(response);
+}
+
+/// Whether a message is the "authenticate" request with the daemon's token, answering it
+fn authenticate(message: &str, token: &str, responses: &UnboundedSender<Value>) -> bool {
+    let request: Value = serde_json::from_str(message).unwrap_or_default();
+    let given = request["params"]["token"].as_str().unwrap_or_default();
+    // Compared in full, the time taken doesn't tell how much of the token matched
+    let valid = request["method"] == "authenticate"
+        && given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0;
+
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let _ = responses.send(if valid {
+        json!({"jsonrpc": "2.0", "id": id, "result": true})
+    } else {
+        json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": {"code": UNAUTHORIZED, "message": "authenticate with the token of the daemon's token file first"},
+        })
+    });
+    valid
+}
+
+async fn handle_connection(stream: TcpStream, cli: Cli, token: &str) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+
+    // Responses and notifications of all the requests go through one writer
+    let (sender, mut messages) = mpsc::unbounded_channel::<Value>();
+    tokio::task::spawn_local(async move {
+        while let Some(message) = messages.recv().await {
+            let line = format!("{}\n", message);
+            if writer.write_all(line.as_bytes()).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut lines = BufReader::new(reader).lines();
+    // Anything but the token first, e.g. the header of an HTTP request a web page
+    // makes to the port, ends the connection
+    match lines.next_line().await? {
+        Some(line) if authenticate(&line, token, &sender) => {}
+        _ => return Ok(()),
+    }
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        // Requests run concurrently so that e.g. monitor-stop isn't held up by a build
+        let cli = cli.clone();
+        let sender = sender.clone();
+        tokio::task::spawn_local(async move {
+            handle_message(&cli, &line, &sender).await;
+        });
+    }
+    Ok(())
+}
+
+/// A random token: 128 bits from the system's random source, as hex
+fn random_token() -> Result<String> {
+    let mut bytes = [0u8; 16];
+    #[cfg(unix)]
+    {
+        use std::io::Read;
+        std::fs::File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+    }
+    #[cfg(not(unix))]
+    {
+        use std::hash::{BuildHasher, Hasher};
+        // The keys of RandomState come from the system's random source
+        for half in bytes.chunks_mut(8) {
+            let hasher = std::collections::hash_map::RandomState::new().build_hasher();
+            half.copy_from_slice(&hasher.finish().to_le_bytes());
+        }
+    }
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// File holding the token of the daemon listening on `listen`
+fn token_path(listen: &str) -> Result<std::path::PathBuf> {
+    let name: String = listen
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    Ok(utils::user_runtime_dir()?.join(format!("daemon-{}.token", name)))
+}
+
+/// Serve JSON-RPC requests on `listen` until interrupted
+pub async fn execute(cli: &Cli, listen: &str) -> Result<()> {
+    let listener = TcpListener::bind(listen)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to listen on {}: {}", listen, e))?;
+    let token = random_token()?;
+    let token_file = token_path(listen)?;
+    utils::write_private_file(&token_file, &token)?;
+    println!(
+        "idf-rs daemon listening on {} (JSON-RPC 2.0, one message per line), token in {}",
+        listen,
+        token_file.display()
+    );
+
+    // Commands aren't Send, they run on this thread
+    let local = tokio::task::LocalSet::new();
+    local
+        .run_until(async {
+            loop {
+                let (stream, peer) = listener.accept().await?;
+                println!("--- Client {} connected ---", peer);
+                let cli = cli.clone();
+                let token = token.clone();
+                tokio::task::spawn_local(async move {
+                    let _ = handle_connection(stream, cli, &token).await;
+                    println!("--- Client {} disconnected ---", peer);
+                });
+            }
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_parsing() {
+        let request = Request::try_parse_from(["set-config", "FOO=y", "--no-reconfigure"]).unwrap();
+        assert!(matches!(
+            request.command,
+            Commands::SetConfig {
+                no_reconfigure: true,
+                ..
+            }
+        ));
+
+        let params = json!({"args": ["-j", "4"], "names": [1]});
+        assert_eq!(strings(&params, "args").unwrap(), vec!["-j", "4"]);
+        assert!(strings(&params, "missing").unwrap().is_empty());
+        assert_eq!(
+            strings(&params, "names").err().unwrap().code,
+            INVALID_PARAMS
+        );
+
+        let line = output::Event::Output {
+            stream: "stderr".to_string(),
+            line: "warning: x".to_string(),
+        };
+        assert_eq!(
+            event_notification(&line),
+            json!({"jsonrpc": "2.0", "method": "output", "params": {"stream": "stderr", "line": "warning: x"}})
+        );
+        let (responses, mut sent) = mpsc::unbounded_channel();
+        let token = "0123456789abcdef";
+        assert!(authenticate(
+            r#"{"jsonrpc": "2.0", "id": 1, "method": "authenticate", "params": {"token": "0123456789abcdef"}}"#,
+            token,
+            &responses
+        ));
+        assert_eq!(sent.try_recv().unwrap()["result"], true);
+        assert!(!authenticate(
+            r#"{"jsonrpc": "2.0", "id": 1, "method": "authenticate", "params": {"token": "0123456789abcdee"}}"#,
+            token,
+            &responses
+        ));
+        assert_eq!(sent.try_recv().unwrap()["error"]["code"], UNAUTHORIZED);
+        assert!(!authenticate("POST / HTTP/1.1", token, &responses));
+        assert!(!authenticate(
+            r#"{"jsonrpc": "2.0", "id": 1, "method": "build", "params": {}}"#,
+            token,
+            &responses
+        ));
+
+        let started = output::Event::CommandStarted {
+            command: "build".to_string(),
+        };
+        assert_eq!(
+            event_notification(&started)["params"],
+            json!({"event": "command_started", "command": "build"})
+        );
+    }
+}
@@ -0,0 +1,270 @@
+use crate::{openocd, output, probe_rs, utils, Cli};
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+
+const GDB_PORT: u16 = 3333;
+
+/// How long `debug` waits for the GDB server to accept connections
+const GDB_SERVER_STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// GDB convenience commands for FreeRTOS tasks. Stacks are filled with 0xa5 when a
+/// task is created, so the untouched bytes above pxStack are its high-water mark.
+const FREERTOS_GDB_COMMANDS: &str = r#"
+define freertos-tasks
+  info threads
+end
+document freertos-tasks
+List the FreeRTOS tasks with their names and state.
+end
+
+define freertos-stack-hwm
+  set $tcb = (TCB_t *)($arg0)
+  set $byte = (unsigned char *)$tcb->pxStack
+  set $unused = 0
+  while *$byte == 0xa5
+    set $byte = $byte + 1
+    set $unused = $unused + 1
+  end
+  printf "%-16s %10u bytes\n", $tcb->pcTaskName, $unused
+end
+document freertos-stack-hwm
+Print the stack high-water mark (bytes never used) of a task: freertos-stack-hwm <TaskHandle_t>
+end
+
+define freertos-stack-hwm-list
+  set $item = $arg0.xListEnd.pxNext
+  set $count = $arg0.uxNumberOfItems
+  while $count > 0
+    freertos-stack-hwm $item->pvOwner
+    set $item = $item->pxNext
+    set $count = $count - 1
+  end
+end
+
+define freertos-stacks
+  printf "%-16s %16s\n", "TASK", "STACK HWM"
+  set $priority = 0
+  while $priority < sizeof(pxReadyTasksLists) / sizeof(pxReadyTasksLists[0])
+    freertos-stack-hwm-list pxReadyTasksLists[$priority]
+    set $priority = $priority + 1
+  end
+  freertos-stack-hwm-list xDelayedTaskList1
+  freertos-stack-hwm-list xDelayedTaskList2
+  freertos-stack-hwm-list xSuspendedTaskList
+end
+document freertos-stacks
+Print the stack high-water mark of every FreeRTOS task.
+end
+"#;
+
+/// Check that the app keeps the symbols OpenOCD needs for FreeRTOS thread awareness
+async fn check_freertos_symbols(build_dir: &Path) -> bool {
+    let symbols = match utils::read_elf_symbols(build_dir).await {
+        Ok(symbols) => symbols,
+        Err(e) => {
+            output::warning(format!(
+                "Could not check FreeRTOS symbols ({}), build the project first",
+                e
+            ));
+            return true;
+        }
+    };
+
+    let missing = openocd::missing_freertos_symbols(&symbols);
+    if missing.is_empty() {
+        return true;
+    }
+
+    output::warning(format!(
+        "FreeRTOS thread awareness disabled, the app ELF lacks {}. Enable CONFIG_FREERTOS_DEBUG_OCDAWARE and rebuild.",
+        missing.join(", ")
+    ));
+    false
+}
+
+/// Project, build directory and target the debug commands work on
+struct DebugContext {
+    project_dir: PathBuf,
+    build_dir: PathBuf,
+    target: String,
+}
+
+impl DebugContext {
+    fn load(cli: &Cli) -> Self {
+        let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+        let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+        let target = openocd::resolve_target(&project_dir, &build_dir);
+        Self {
+            project_dir,
+            build_dir,
+            target,
+        }
+    }
+}
+
+fn openocd_args(target: &str, freertos: bool) -> Result<Vec<String>> {
+    let mut args = openocd::rtos_args(freertos);
+    args.extend(openocd::config_args(target)?);
+    Ok(args)
+}
+
+/// Run OpenOCD for the project's target with FreeRTOS thread awareness
+pub async fn execute_openocd(cli: &Cli, no_rtos: bool) -> Result<()> {
+    let DebugContext {
+        project_dir,
+        build_dir,
+        target,
+    } = DebugContext::load(cli);
+
+    let freertos = !no_rtos && check_freertos_symbols(&build_dir).await;
+    if freertos {
+        println!("FreeRTOS thread awareness enabled");
+    }
+
+    let args = openocd_args(&target, freertos)?;
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    utils::run_command("openocd", &args, Some(&project_dir), cli.verbose).await
+}
+
+/// GDB script connecting to the GDB server. OpenOCD is asked to reset and halt the
+/// chip, probe-rs halts it when GDB attaches.
+fn write_gdbinit(build_dir: &Path, elf: &Path, freertos: bool, openocd: bool) -> Result<PathBuf> {
+    let gdbinit_dir = build_dir.join("gdbinit");
+    std::fs::create_dir_all(&gdbinit_dir)?;
+
+    let mut script = format!(
+        "set pagination off\nfile \"{}\"\ntarget extended-remote :{}\n",
+        elf.display(),
+        GDB_PORT
+    );
+    if openocd {
+        script.push_str("monitor reset halt\n");
+    }
+    script.push_str("maintenance flush register-cache\n");
+    if freertos {
+        script.push_str(FREERTOS_GDB_COMMANDS);
+    }
+    script.push_str("thbreak app_main\ncontinue\n");
+
+    let path = gdbinit_dir.join("gdbinit");
+    std::fs::write(&path, script)?;
+    Ok(path)
+}
+
+/// Run the target's GDB with the generated gdbinit, in its text UI with `tui`
+async fn run_gdb(
+    cli: &Cli,
+    context: &DebugContext,
+    elf: &Path,
+    freertos: bool,
+    openocd: bool,
+    tui: bool,
+) -> Result<()> {
+    let gdbinit = write_gdbinit(&context.build_dir, elf, freertos, openocd)?;
+    if freertos {
+        println!("FreeRTOS commands: freertos-tasks, freertos-stacks, freertos-stack-hwm <task>");
+    }
+
+    let gdb = openocd::gdb_executable(&context.target);
+    let mut args = vec!["-x", gdbinit.to_str().unwrap()];
+    if tui {
+        args.push("-tui");
+    }
+    utils::run_command(&gdb, &args, Some(&context.project_dir), cli.verbose).await
+}
+
+/// Run GDB connected to a running OpenOCD, with FreeRTOS convenience commands
+pub async fn execute_gdb(cli: &Cli, no_rtos: bool, tui: bool) -> Result<()> {
+    let context = DebugContext::load(cli);
+    let elf = utils::get_app_elf_path(&context.build_dir)?;
+    let freertos = !no_rtos && check_freertos_symbols(&context.build_dir).await;
+    run_gdb(cli, &context, &elf, freertos, true, tui).await
+}
+
+/// Wait until the GDB server accepts connections, failing if it exits first
+async fn wait_for_gdb_server(
+    server: &mut tokio::process::Child,
+    name: &str,
+    log: &Path,
+) -> Result<()> {
+    let deadline = Instant::now() + GDB_SERVER_STARTUP_TIMEOUT;
+    while Instant::now() < deadline {
+        if let Some(status) = server.try_wait()? {
+            return Err(anyhow::anyhow!(
+                "{} exited ({}) before GDB could connect, see {}",
+                name,
+                status,
+                log.display()
+            ));
+        }
+        if tokio::net::TcpStream::connect(("127.0.0.1", GDB_PORT))
+            .await
+            .is_ok()
+        {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+    Err(anyhow::anyhow!(
+        "{} is not listening on port {} after {} seconds, see {}",
+        name,
+        GDB_PORT,
+        GDB_SERVER_STARTUP_TIMEOUT.as_secs(),
+        log.display()
+    ))
+}
+
+/// Start OpenOCD (or the probe-rs GDB server with `use_probe_rs`) in the background,
+/// logging to <build>/<server>.log, run GDB against it and stop the server when GDB exits
+pub async fn execute_debug(cli: &Cli, no_rtos: bool, tui: bool, use_probe_rs: bool) -> Result<()> {
+    let context = DebugContext::load(cli);
+    let DebugContext {
+        project_dir,
+        build_dir,
+        target,
+    } = &context;
+    let elf = utils::get_app_elf_path(build_dir)?;
+    let freertos = !no_rtos && check_freertos_symbols(build_dir).await;
+
+    let (name, program, args) = if use_probe_rs {
+        let args = probe_rs::gdb_server_args(target, GDB_PORT)?;
+        ("probe-rs", probe_rs::program(), args)
+    } else {
+        (
+            "OpenOCD",
+            "openocd".to_string(),
+            openocd_args(target, freertos)?,
+        )
+    };
+    if cli.verbose {
+        println!("Running: {} {}", program, args.join(" "));
+    }
+    std::fs::create_dir_all(build_dir)?;
+    let log_path = build_dir.join(format!("{}.log", name.to_lowercase()));
+    let log = std::fs::File::create(&log_path)?;
+    let mut server = tokio::process::Command::new(&program)
+        .args(&args)
+        .current_dir(project_dir)
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log)
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Failed to execute {}: {}", program, e))?;
+
+    println!(
+        "Started {} for {}, log in {}",
+        name,
+        target,
+        log_path.display()
+    );
+    let result = match wait_for_gdb_server(&mut server, name, &log_path).await {
+        Ok(()) => run_gdb(cli, &context, &elf, freertos, !use_probe_rs, tui).await,
+        Err(e) => Err(e),
+    };
+
+    server.kill().await.ok();
+    result
+}
@@ -0,0 +1,371 @@
+use crate::{output, utils, version_spec, yaml, Cli};
+use anyhow::Result;
+use serde_json::Value;
+use std::path::Path;
+
+const LOCK_FILE: &str = "dependencies.lock";
+const DEFAULT_POLICY_FILE: &str = "idf-deps-policy.yml";
+const DEFAULT_REGISTRY_URL: &str = "https://components.espressif.com";
+
+/// Dependency policy loaded from `idf-deps-policy.yml`:
+///
+/// ```yaml
+/// allowed_sources: [service, idf, local]
+/// allowed_registries: [https://components.espressif.com]
+/// require_pinned: true
+/// denied:
+///   espressif/some_component: ["<1.2.0", "1.3.0"]
+/// ```
+#[derive(Debug, Default)]
+pub struct Policy {
+    pub allowed_sources: Option<Vec<String>>,
+    pub allowed_registries: Option<Vec<String>>,
+    pub require_pinned: bool,
+    /// Known vulnerable versions per component, as version constraints
+    pub denied: Vec<(String, Vec<String>)>,
+}
+
+/// Read a list of strings, also accepting a single string
+fn string_list(value: Option<&Value>, key: &str) -> Result<Option<Vec<String>>> {
+    match value {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::Array(items)) => Ok(Some(
+            items
+                .iter()
+                .map(|item| {
+                    item.as_str()
+                        .map(str::to_string)
+                        .ok_or_else(|| anyhow::anyhow!("'{}' entries must be strings", key))
+                })
+                .collect::<Result<_>>()?,
+        )),
+        Some(Value::String(item)) => Ok(Some(vec![item.clone()])),
+        Some(_) => Err(anyhow::anyhow!("'{}' must be a list", key)),
+    }
+}
+
+impl Policy {
+    pub fn parse(content: &str) -> Result<Policy> {
+        let value = yaml::parse(content)?;
+
+        let mut denied = Vec::new();
+        if let Some(entries) = value.get("denied").and_then(Value::as_object) {
+            for (name, specs) in entries {
+                let specs =
+                    string_list(Some(specs), &format!("denied.{}", name))?.unwrap_or_default();
+                denied.push((name.clone(), specs));
+            }
+        }
+
+        Ok(Policy {
+            allowed_sources: string_list(value.get("allowed_sources"), "allowed_sources")?,
+            allowed_registries: string_list(value.get("allowed_registries"), "allowed_registries")?,
+            require_pinned: value
+                .get("require_pinned")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+            denied,
+        })
+    }
+}
+
+/// A component entry of dependencies.lock
+#[derive(Debug)]
+pub struct LockedComponent {
+    pub name: String,
+    pub version: String,
+    pub source_type: String,
+    pub registry_url: Option<String>,
+}
+
+pub fn parse_lock_file(content: &str) -> Result<Vec<LockedComponent>> {
+    let value = yaml::parse(content)?;
+    let dependencies = value
+        .get("dependencies")
+        .and_then(Value::as_object)
+        .ok_or_else(|| anyhow::anyhow!("{} has no 'dependencies' section", LOCK_FILE))?;
+
+    Ok(dependencies
+        .iter()
+        .map(|(name, entry)| {
+            let source = entry.get("source");
+            LockedComponent {
+                name: name.clone(),
+                version: entry
+                    .get("version")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string(),
+                source_type: source
+                    .and_then(|source| source.get("type"))
+                    .and_then(Value::as_str)
+                    .unwrap_or("unknown")
+                    .to_string(),
+                registry_url: source
+                    .and_then(|source| source.get("registry_url"))
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+            }
+        })
+        .collect())
+}
+
+fn normalize_url(url: &str) -> &str {
+    url.trim_end_matches('/')
+}
+
+/// Check the locked components against the policy
+pub fn check_policy(components: &[LockedComponent], policy: &Policy) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    for component in components {
+        if let Some(allowed) = &policy.allowed_sources {
+            if !allowed.contains(&component.source_type) {
+                violations.push(format!(
+                    "{}: source '{}' is not allowed (allowed: {})",
+                    component.name,
+                    component.source_type,
+                    allowed.join(", ")
+                ));
+            }
+        }
+
+        if let (Some(allowed), Some(registry)) =
+            (&policy.allowed_registries, &component.registry_url)
+        {
+            if !allowed
+                .iter()
+                .any(|url| normalize_url(url) == normalize_url(registry))
+            {
+                violations.push(format!(
+                    "{}: registry {} is not allowed",
+                    component.name, registry
+                ));
+            }
+        }
+
+        for (name, specs) in &policy.denied {
+            if *name != component.name {
+                continue;
+            }
+            for spec in specs {
+                if version_spec::matches(&component.version, spec) == Some(true) {
+                    violations.push(format!(
+                        "{}: version {} is denied by policy ({})",
+                        component.name, component.version, spec
+                    ));
+                }
+            }
+        }
+    }
+
+    violations
+}
+
+/// Collect the dependency constraints declared in the project's manifests
+fn find_unpinned_dependencies(project_dir: &Path) -> Result<Vec<String>> {
+    let mut manifests = Vec::new();
+    utils::find_files(
+        project_dir,
+        &["build", "managed_components"],
+        &|path: &Path| {
+            path.file_name()
+                .map(|name| name == "idf_component.yml")
+                .unwrap_or(false)
+        },
+        &mut manifests,
+    )?;
+    manifests.sort();
+
+    let mut unpinned = Vec::new();
+    for manifest in manifests {
+        let value = yaml::parse(&std::fs::read_to_string(&manifest)?)
+            .map_err(|e| anyhow::anyhow!("{}: {}", manifest.display(), e))?;
+        let dependencies = match value.get("dependencies").and_then(Value::as_object) {
+            Some(dependencies) => dependencies,
+            None => continue,
+        };
+
+        for (name, entry) in dependencies {
+            // The IDF version and local/git components are not resolved from the registry
+            if name == "idf"
+                || entry.get("path").is_some()
+                || entry.get("override_path").is_some()
+                || entry.get("git").is_some()
+            {
+                continue;
+            }
+
+            let spec = entry
+                .as_str()
+                .or_else(|| entry.get("version").and_then(Value::as_str))
+                .unwrap_or("*");
+            if !version_spec::is_exact(spec) {
+                let display_path = manifest.strip_prefix(project_dir).unwrap_or(&manifest);
+                unpinned.push(format!(
+                    "{}: '{}' in {} is not pinned to an exact version",
+                    name,
+                    spec,
+                    display_path.display()
+                ));
+            }
+        }
+    }
+
+    Ok(unpinned)
+}
+
+/// Query the component registry for yanked versions
+async fn check_registry(component: &LockedComponent) -> Result<Option<String>> {
+    let registry = component
+        .registry_url
+        .as_deref()
+        .map(normalize_url)
+        .unwrap_or(DEFAULT_REGISTRY_URL);
+    let url = format!("{}/api/components/{}", registry, component.name);
+
+    let output = utils::run_command_with_output("curl", &["-fsSL", &url], None).await?;
+    let info: Value = serde_json::from_str(&output)?;
+
+    let entry = info
+        .get("versions")
+        .and_then(Value::as_array)
+        .and_then(|versions| {
+            versions.iter().find(|entry| {
+                entry.get("version").and_then(Value::as_str) == Some(&component.version)
+            })
+        });
+
+    Ok(entry.and_then(|entry| {
+        entry.get("yanked_at").filter(|yanked| !yanked.is_null())?;
+        let message = entry
+            .get("yanked_message")
+            .and_then(Value::as_str)
+            .unwrap_or("no reason given");
+        Some(format!(
+            "{}: version {} was yanked from the registry ({})",
+            component.name, component.version, message
+        ))
+    }))
+}
+
+pub async fn execute_audit(cli: &Cli, policy_path: Option<&Path>, offline: bool) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+
+    let lock_path = project_dir.join(LOCK_FILE);
+    let content = std::fs::read_to_string(&lock_path).map_err(|_| {
+        anyhow::anyhow!(
+            "{} not found. Run 'idf-rs reconfigure' to resolve the dependencies first.",
+            lock_path.display()
+        )
+    })?;
+    let components = parse_lock_file(&content)?;
+
+    let policy_path = policy_path
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| project_dir.join(DEFAULT_POLICY_FILE));
+    let policy = if policy_path.exists() {
+        println!("Using policy {}", policy_path.display());
+        Policy::parse(&std::fs::read_to_string(&policy_path)?)
+            .map_err(|e| anyhow::anyhow!("{}: {}", policy_path.display(), e))?
+    } else {
+        println!(
+            "No policy file found at {}, only checking for yanked versions",
+            policy_path.display()
+        );
+        Policy::default()
+    };
+
+    println!("Auditing {} locked component(s)...", components.len());
+
+    let mut violations = check_policy(&components, &policy);
+
+    if policy.require_pinned {
+        violations.extend(find_unpinned_dependencies(&project_dir)?);
+    }
+
+    if offline {
+        println!("Skipping registry checks (--offline)");
+    } else {
+        for component in components
+            .iter()
+            .filter(|component| component.source_type == "service")
+        {
+            match check_registry(component).await {
+                Ok(Some(violation)) => violations.push(violation),
+                Ok(None) => {}
+                Err(e) => output::warning(format!(
+                    "could not query the registry for {}: {}",
+                    component.name, e
+                )),
+            }
+        }
+    }
+
+    if violations.is_empty() {
+        println!("No policy violations found.");
+        return Ok(());
+    }
+
+    for violation in &violations {
+        println!("  {}", violation);
+    }
+
+    Err(anyhow::anyhow!(
+        "Dependency audit found {} violation(s)",
+        violations.len()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCK: &str = r#"
+dependencies:
+  espressif/button:
+    component_hash: abc
+    source:
+      registry_url: https://components.espressif.com/
+      type: service
+    version: 3.2.0
+  espressif/led_strip:
+    source:
+      registry_url: https://example.com/
+      type: service
+    version: 2.5.3
+  idf:
+    source:
+      type: idf
+    version: 5.1.2
+  my_lib:
+    source:
+      git: https://github.com/example/my_lib.git
+      type: git
+    version: 0123abcd
+direct_dependencies:
+- espressif/button
+manifest_hash: def
+target: esp32
+version: 2.0.0
+"#;
+
+    #[test]
+    fn test_check_policy() {
+        let components = parse_lock_file(LOCK).unwrap();
+        let policy = Policy::parse(
+            "allowed_sources: [service, idf]\nallowed_registries:\n  - https://components.espressif.com\ndenied:\n  espressif/button: [\"<3.2.1\"]\n",
+        )
+        .unwrap();
+
+        let violations = check_policy(&components, &policy);
+        assert_eq!(
+            violations,
+            vec![
+                "espressif/button: version 3.2.0 is denied by policy (<3.2.1)",
+                "espressif/led_strip: registry https://example.com/ is not allowed",
+                "my_lib: source 'git' is not allowed (allowed: service, idf)",
+            ]
+        );
+    }
+}
@@ -0,0 +1,82 @@
+use crate::commands::flash;
+use crate::{flash_image, utils, Cli};
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// Size of the parts the binaries are split into, as mkdfu.py does by default
+const DEFAULT_PART_SIZE: usize = 512 * 1024;
+
+fn dfu_pid(chip: &str) -> Result<u16> {
+    flash_image::dfu_pid(chip).ok_or_else(|| {
+        anyhow::anyhow!(
+            "{} has no USB DFU bootloader. DFU is supported on esp32s2 and esp32s3.",
+            chip
+        )
+    })
+}
+
+/// Write build/dfu.bin from the binaries in flasher_args.json, like the dfu target
+fn generate(build_dir: &Path) -> Result<(PathBuf, u16)> {
+    let (chip, files) = flash::load_flash_files(build_dir)?;
+    let pid = dfu_pid(&chip)?;
+    let part_size = std::env::var("ESP_DFU_PART_SIZE")
+        .ok()
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(DEFAULT_PART_SIZE);
+
+    let path = build_dir.join("dfu.bin");
+    std::fs::write(&path, flash_image::dfu(&files, pid, part_size)?)?;
+    Ok((path, pid))
+}
+
+async fn run_dfu_util(args: &[&str], project_dir: &Path, verbose: bool) -> Result<()> {
+    utils::run_command("dfu-util", args, Some(project_dir), verbose)
+        .await
+        .map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to run dfu-util ({}). Install it from https://dfu-util.sourceforge.net and put it on PATH.",
+                e
+            )
+        })
+}
+
+pub async fn execute(cli: &Cli) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+
+    let (path, _) = generate(&build_dir)?;
+    println!("Generated DFU image {}", path.display());
+    Ok(())
+}
+
+/// Generate the DFU image and download it to the device with dfu-util
+pub async fn execute_flash(cli: &Cli, path: Option<&str>) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+
+    let (image, pid) = generate(&build_dir)?;
+    let device = format!("{:04x}:{:04x}", flash_image::ESPRESSIF_VID, pid);
+    let image = image.to_string_lossy();
+    let mut args = vec!["-d", &device];
+    if let Some(path) = path {
+        args.extend(["-p", path]);
+    }
+    args.extend(["-D", &image]);
+
+    println!("Flashing {} via USB DFU ({})...", image, device);
+    run_dfu_util(&args, &project_dir, cli.verbose).await?;
+    println!("DFU flash completed successfully!");
+    Ok(())
+}
+
+/// List the connected DFU devices, only those of the target when the project is built
+pub async fn execute_list(cli: &Cli) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+
+    let device = match flash::load_flash_files(&build_dir) {
+        Ok((chip, _)) => format!("{:04x}:{:04x}", flash_image::ESPRESSIF_VID, dfu_pid(&chip)?),
+        Err(_) => format!("{:04x}:", flash_image::ESPRESSIF_VID),
+    };
+    run_dfu_util(&["-d", &device, "-l"], &project_dir, cli.verbose).await
+}
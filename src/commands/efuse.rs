@@ -0,0 +1,228 @@
+use crate::{config, utils, Cli};
+use anyhow::Result;
+use serde_json::Value;
+use std::path::PathBuf;
+
+/// Virtual efuses of `--virt` runs, kept in the build directory so that a burn shows
+/// up in the next summary
+fn virtual_efuse_path(cli: &Cli) -> PathBuf {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    utils::get_build_dir(cli.build_dir.as_deref(), &project_dir).join("efuses-virt.json")
+}
+
+/// Command line for espefuse.py with the chip, baud rate and port options, or the
+/// virtual efuses of the project's target with `virt`
+fn espefuse_command(cli: &Cli, virt: bool) -> Result<Vec<String>> {
+    let espefuse_path = utils::esptool_script("espefuse")?;
+    let mut command = vec![
+        utils::get_python_executable()?,
+        espefuse_path.to_string_lossy().to_string(),
+    ];
+
+    if virt {
+        // Virtual efuses need a chip to emulate, auto detection has no device to ask
+        let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+        let target = config::load_project_config(&project_dir)?
+            .target
+            .ok_or_else(|| {
+                anyhow::anyhow!("--virt needs a target, run 'idf-rs set-target' first")
+            })?;
+        let efuse_file = virtual_efuse_path(cli);
+        if let Some(dir) = efuse_file.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        command.extend([
+            "--chip".to_string(),
+            target,
+            "--virt".to_string(),
+            "--path-efuse-file".to_string(),
+            efuse_file.to_string_lossy().to_string(),
+        ]);
+        return Ok(command);
+    }
+
+    command.extend([
+        "--chip".to_string(),
+        "auto".to_string(),
+        "--baud".to_string(),
+        cli.baud.unwrap_or(115200).to_string(),
+    ]);
+    if let Some(port) = &cli.port {
+        command.extend(["--port".to_string(), port.clone()]);
+    }
+    Ok(command)
+}
+
+/// Read all efuses as reported by `espefuse.py summary --format json`
+pub async fn read_efuses(cli: &Cli, virt: bool) -> Result<Value> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let espefuse = espefuse_command(cli, virt)?;
+
+    // espefuse prints its connection log on stdout, so the JSON goes to a file
+    let summary_path =
+        std::env::temp_dir().join(format!("idf-rs-efuses-{}.json", std::process::id()));
+
+    let mut args: Vec<&str> = espefuse[1..].iter().map(String::as_str).collect();
+    args.extend_from_slice(&[
+        "summary",
+        "--format",
+        "json",
+        "--file",
+        summary_path.to_str().unwrap(),
+    ]);
+
+    if cli.verbose {
+        println!("Running: {} {}", espefuse[0], args.join(" "));
+    }
+    utils::run_command_with_output(&espefuse[0], &args, Some(&project_dir)).await?;
+
+    let content = std::fs::read_to_string(&summary_path)?;
+    let _ = std::fs::remove_file(&summary_path);
+    Ok(serde_json::from_str(&content)?)
+}
+
+/// An efuse of the summary, as shown in the table
+#[derive(Debug, Clone, PartialEq)]
+struct EfuseRow {
+    category: String,
+    name: String,
+    value: String,
+    /// "R/W", "R/-", "-/W" or "-/-"
+    access: String,
+    description: String,
+}
+
+fn summary_rows(efuses: &Value) -> Vec<EfuseRow> {
+    let mut rows: Vec<EfuseRow> = efuses
+        .as_object()
+        .into_iter()
+        .flatten()
+        .map(|(name, efuse)| {
+            let value = match &efuse["value"] {
+                Value::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+            let access = format!(
+                "{}/{}",
+                if efuse["readable"].as_bool() == Some(false) {
+                    "-"
+                } else {
+                    "R"
+                },
+                if efuse["writeable"].as_bool() == Some(false) {
+                    "-"
+                } else {
+                    "W"
+                }
+            );
+            EfuseRow {
+                category: efuse["category"].as_str().unwrap_or("other").to_string(),
+                name: name.clone(),
+                value,
+                access,
+                description: efuse["description"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+            }
+        })
+        .collect();
+    rows.sort_by(|a, b| a.category.cmp(&b.category).then(a.name.cmp(&b.name)));
+    rows
+}
+
+/// Print the efuses of the device grouped by category, or as JSON
+pub async fn execute_summary(cli: &Cli, json: bool, virt: bool) -> Result<()> {
+    utils::setup_idf_environment()?;
+
+    let efuses = read_efuses(cli, virt).await?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&efuses)?);
+        return Ok(());
+    }
+
+    let mut category = None;
+    for row in summary_rows(&efuses) {
+        if category.as_deref() != Some(row.category.as_str()) {
+            println!();
+            println!("{}:", row.category.to_uppercase());
+            category = Some(row.category.clone());
+        }
+        println!(
+            "  {:<32} {:<5} {:<24} {}",
+            row.name, row.access, row.value, row.description
+        );
+    }
+    println!();
+    Ok(())
+}
+
+/// Run an espefuse.py command that burns efuses. Burning can't be undone: espefuse
+/// asks for confirmation on a terminal unless --yes is given, and without a terminal
+/// --yes is required.
+async fn run_burn(cli: &Cli, virt: bool, command: &[&str]) -> Result<()> {
+    utils::setup_idf_environment()?;
+
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let espefuse = espefuse_command(cli, virt)?;
+    let mut args: Vec<&str> = espefuse[1..].iter().map(String::as_str).collect();
+    if virt || cli.yes {
+        args.push("--do-not-confirm");
+    } else if !cli.can_prompt() {
+        return Err(anyhow::anyhow!(
+            "Burning efuses is irreversible, confirm it with --yes when running without a terminal"
+        ));
+    }
+    args.extend_from_slice(command);
+
+    if virt {
+        println!(
+            "Using virtual efuses in {}",
+            virtual_efuse_path(cli).display()
+        );
+    }
+    utils::run_command(&espefuse[0], &args, Some(&project_dir), cli.verbose).await
+}
+
+pub async fn execute_burn(cli: &Cli, field: &str, value: &str, virt: bool) -> Result<()> {
+    run_burn(cli, virt, &["burn_efuse", field, value]).await
+}
+
+/// Disable software reading of a key block, e.g. BLOCK_KEY0
+pub async fn execute_read_protect(cli: &Cli, name: &str, virt: bool) -> Result<()> {
+    run_burn(cli, virt, &["read_protect_efuse", name]).await
+}
+
+/// Disable further writes to an efuse and the others sharing its write protection bit
+pub async fn execute_write_protect(cli: &Cli, name: &str, virt: bool) -> Result<()> {
+    run_burn(cli, virt, &["write_protect_efuse", name]).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_rows() {
+        let efuses: Value = serde_json::from_str(
+            r#"{
+                "WR_DIS": {"value": 0, "readable": true, "writeable": true,
+                           "category": "config", "description": "Disable programming of individual eFuses"},
+                "BLOCK_KEY0": {"value": "?? ??", "readable": false, "writeable": false,
+                               "category": "security", "description": "Key0 or user data"},
+                "DIS_USB_JTAG": {"value": false, "readable": true, "writeable": true,
+                                 "category": "config", "description": "Disables USB-JTAG"}
+            }"#,
+        )
+        .unwrap();
+
+        let rows = summary_rows(&efuses);
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].name, "DIS_USB_JTAG");
+        assert_eq!(rows[0].value, "false");
+        assert_eq!(rows[1].name, "WR_DIS");
+        assert_eq!(rows[2].category, "security");
+        assert_eq!(rows[2].value, "?? ??");
+        assert_eq!(rows[2].access, "-/-");
+    }
+}
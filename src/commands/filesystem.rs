@@ -0,0 +1,281 @@
+use crate::commands::{flash, partition_table as partition_commands};
+use crate::partition_table::{self, Partition};
+use crate::{config, utils, Cli};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Filesystem block size, the flash sector size
+const BLOCK_SIZE: u32 = 4096;
+
+/// A filesystem image for a partition of the project's partition table
+struct ImageTarget {
+    project_dir: PathBuf,
+    partition: Partition,
+    /// Directory with the files, absolute as the tools run in the project directory
+    dir: PathBuf,
+    /// `<build dir>/<partition>.bin`, where ESP-IDF's *_create_partition_image() put it
+    image: PathBuf,
+    settings: HashMap<String, String>,
+}
+
+impl ImageTarget {
+    fn load(cli: &Cli, dir: &Path, name: &str) -> Result<Self> {
+        if !dir.is_dir() {
+            return Err(anyhow::anyhow!("{} is not a directory", dir.display()));
+        }
+        let dir = std::fs::canonicalize(dir)?;
+        let (project_dir, partitions) = partition_commands::load_partitions(cli)?;
+        let partition = partition_table::find(&partitions, name)?.clone();
+        let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+        let image = build_dir.join(format!("{}.bin", partition.label));
+        let settings = config::load_project_config(&project_dir)?
+            .settings
+            .into_iter()
+            .map(|(key, value)| (key, value.trim_matches('"').to_string()))
+            .collect();
+        Ok(Self {
+            project_dir,
+            partition,
+            dir,
+            image,
+            settings,
+        })
+    }
+
+    fn setting(&self, key: &str, default: &str) -> String {
+        self.settings
+            .get(key)
+            .cloned()
+            .unwrap_or_else(|| default.to_string())
+    }
+
+    fn enabled(&self, key: &str, default: bool) -> bool {
+        match self.settings.get(key) {
+            Some(value) => value == "y",
+            None => default && self.settings.is_empty(),
+        }
+    }
+
+    fn size(&self) -> String {
+        self.partition.size.to_string()
+    }
+
+    /// Report the image and write it to its partition when asked to
+    async fn finish(&self, cli: &Cli, flash: bool) -> Result<()> {
+        println!(
+            "Generated {} for partition '{}' ({})",
+            self.image.display(),
+            self.partition.label,
+            partition_table::format_size(self.partition.size)
+        );
+        if !flash {
+            return Ok(());
+        }
+
+        utils::setup_idf_environment()?;
+        println!(
+            "Flashing {} to partition '{}' (0x{:x})...",
+            self.image.display(),
+            self.partition.label,
+            self.partition.offset
+        );
+        let offset = format!("0x{:x}", self.partition.offset);
+        let image = self.image.to_string_lossy();
+        flash::run_esptool(cli, &self.project_dir, &["write_flash", &offset, &image]).await?;
+        println!("Filesystem flash completed successfully!");
+        Ok(())
+    }
+}
+
+/// Build a SPIFFS image of a directory with ESP-IDF's spiffsgen.py, using the SPIFFS
+/// options from sdkconfig like spiffs_create_partition_image() does
+pub async fn execute_spiffs_gen(cli: &Cli, dir: &Path, partition: &str, flash: bool) -> Result<()> {
+    let target = ImageTarget::load(cli, dir, partition)?;
+    std::fs::create_dir_all(target.image.parent().unwrap_or(Path::new(".")))?;
+
+    let python = utils::get_python_executable()?;
+    let spiffsgen = utils::get_idf_path()?.join("components/spiffs/spiffsgen.py");
+    let size = target.size();
+    let page_size = format!(
+        "--page-size={}",
+        target.setting("CONFIG_SPIFFS_PAGE_SIZE", "256")
+    );
+    let name_len = format!(
+        "--obj-name-len={}",
+        target.setting("CONFIG_SPIFFS_OBJ_NAME_LEN", "32")
+    );
+    let meta_len = format!(
+        "--meta-len={}",
+        target.setting("CONFIG_SPIFFS_META_LENGTH", "4")
+    );
+    let block_size = format!("--block-size={}", BLOCK_SIZE);
+    let dir = target.dir.to_string_lossy();
+    let image = target.image.to_string_lossy();
+
+    let mut args = vec![
+        spiffsgen.to_str().unwrap_or_default(),
+        &size,
+        &dir,
+        &image,
+        &page_size,
+        &name_len,
+        &meta_len,
+        &block_size,
+    ];
+    if target.enabled("CONFIG_SPIFFS_USE_MAGIC", true) {
+        args.push("--use-magic");
+    }
+    if target.enabled("CONFIG_SPIFFS_USE_MAGIC_LENGTH", true) {
+        args.push("--use-magic-len");
+    }
+    if target.enabled("CONFIG_SPIFFS_FOLLOW_SYMLINKS", false) {
+        args.push("--follow-symlinks");
+    }
+    utils::run_command(&python, &args, Some(&target.project_dir), cli.verbose).await?;
+
+    target.finish(cli, flash).await
+}
+
+/// Build a LittleFS image of a directory with mklittlefs
+pub async fn execute_littlefs_gen(
+    cli: &Cli,
+    dir: &Path,
+    partition: &str,
+    flash: bool,
+) -> Result<()> {
+    let target = ImageTarget::load(cli, dir, partition)?;
+    std::fs::create_dir_all(target.image.parent().unwrap_or(Path::new(".")))?;
+
+    let size = target.size();
+    let page_size = target.setting("CONFIG_LITTLEFS_PAGE_SIZE", "256");
+    let block_size = BLOCK_SIZE.to_string();
+    let dir = target.dir.to_string_lossy();
+    let image = target.image.to_string_lossy();
+    let args = [
+        "-c",
+        &dir,
+        "-s",
+        &size,
+        "-p",
+        &page_size,
+        "-b",
+        &block_size,
+        &image,
+    ];
+    utils::run_command("mklittlefs", &args, Some(&target.project_dir), cli.verbose)
+        .await
+        .map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to run mklittlefs ({}). Install it from https://github.com/earlephilhower/mklittlefs and put it on PATH.",
+                e
+            )
+        })?;
+
+    target.finish(cli, flash).await
+}
+
+/// Build a FATFS image of a directory with ESP-IDF's wl_fatfsgen.py (or fatfsgen.py
+/// without wear levelling), like fatfs_create_spiflash_image() and
+/// fatfs_create_rawflash_image() do
+pub async fn execute_fatfs_gen(
+    cli: &Cli,
+    dir: &Path,
+    partition: &str,
+    no_wear_levelling: bool,
+    preserve_time: bool,
+    flash: bool,
+) -> Result<()> {
+    let target = ImageTarget::load(cli, dir, partition)?;
+    std::fs::create_dir_all(target.image.parent().unwrap_or(Path::new(".")))?;
+
+    let python = utils::get_python_executable()?;
+    let script = if no_wear_levelling {
+        "fatfsgen.py"
+    } else {
+        "wl_fatfsgen.py"
+    };
+    let fatfsgen = utils::get_idf_path()?.join("components/fatfs").join(script);
+    let size = target.size();
+    let sector_size = target.setting("CONFIG_WL_SECTOR_SIZE", "4096");
+    let dir = target.dir.to_string_lossy();
+    let image = target.image.to_string_lossy();
+
+    let mut args = vec![
+        fatfsgen.to_str().unwrap_or_default(),
+        &dir,
+        "--partition_size",
+        &size,
+        "--output_file",
+        &image,
+        "--sector_size",
+        &sector_size,
+    ];
+    if !target.enabled("CONFIG_FATFS_LFN_NONE", false) {
+        args.push("--long_name_support");
+    }
+    if !preserve_time {
+        args.push("--use_default_datetime");
+    }
+    utils::run_command(&python, &args, Some(&target.project_dir), cli.verbose).await?;
+
+    target.finish(cli, flash).await
+}
+
+/// Unpack a FATFS image, or the partition read back from the device, into a directory
+/// with ESP-IDF's fatfsparse.py, detecting the wear levelling layer
+pub async fn execute_fatfs_extract(
+    cli: &Cli,
+    output: &Path,
+    partition: Option<&str>,
+    image: Option<&Path>,
+) -> Result<()> {
+    let image = match (image, partition) {
+        (Some(image), _) => std::fs::canonicalize(image)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", image.display(), e))?,
+        (None, Some(partition)) => {
+            utils::setup_idf_environment()?;
+            let (project_dir, partitions) = partition_commands::load_partitions(cli)?;
+            let partition = partition_table::find(&partitions, partition)?;
+            let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+            std::fs::create_dir_all(&build_dir)?;
+            let image = build_dir.join(format!("{}-read.bin", partition.label));
+
+            println!(
+                "Reading partition '{}' (0x{:x}, {})...",
+                partition.label,
+                partition.offset,
+                partition_table::format_size(partition.size)
+            );
+            let offset = format!("0x{:x}", partition.offset);
+            let size = format!("0x{:x}", partition.size);
+            let image_arg = image.to_string_lossy();
+            flash::run_esptool(
+                cli,
+                &project_dir,
+                &["read_flash", &offset, &size, &image_arg],
+            )
+            .await?;
+            image
+        }
+        (None, None) => {
+            return Err(anyhow::anyhow!("Pass a partition to read or --image"));
+        }
+    };
+
+    // fatfsparse.py unpacks into the current directory
+    std::fs::create_dir_all(output)?;
+    let python = utils::get_python_executable()?;
+    let fatfsparse = utils::get_idf_path()?.join("components/fatfs/fatfsparse.py");
+    let image_arg = image.to_string_lossy();
+    let args = [
+        fatfsparse.to_str().unwrap_or_default(),
+        &image_arg,
+        "--wl-layer",
+        "detect",
+    ];
+    utils::run_command(&python, &args, Some(output), cli.verbose).await?;
+
+    println!("Extracted {} into {}", image.display(), output.display());
+    Ok(())
+}
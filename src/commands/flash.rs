@@ -1,5 +1,394 @@
-use crate::{utils, Cli};
+use crate::commands::{chip, security};
+use crate::flash_image::{self, Region};
+use crate::{
+    config, md5, output, partition_table, probe_rs, serial_ports, sha256, state, utils, Cli,
+};
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::io::AsyncReadExt;
+
+/// esptool sends (and reports) the image in blocks of this size
+const FLASH_WRITE_BLOCK: u32 = 0x4000;
+const FLASH_SECTOR_SIZE: u32 = 0x1000;
+
+/// Regions smaller than this are rewritten completely when resuming
+const MIN_PARTIAL_RESUME_SIZE: u64 = 0x40000;
+
+/// Rates a flash is retried at when the serial link fails, below the one that failed
+const FALLBACK_BAUD_RATES: [u32; 3] = [460800, 230400, 115200];
+
+/// esptool errors of a serial link that didn't keep up (lost sync, timeouts, corrupted
+/// packets), which a lower baud rate or the ROM loader may get through
+const LINK_FAILURES: [&str; 7] = [
+    "Failed to connect",
+    "Timed out waiting for packet",
+    "Invalid head of packet",
+    "Serial data stream stopped",
+    "Packet content transfer stopped",
+    "chip stopped responding",
+    "No serial data received",
+];
+
+/// One image of the flash set
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct FlashRegion {
+    /// Path relative to the build directory
+    file: String,
+    size: u64,
+    sha256: String,
+    /// Written and verified by esptool
+    #[serde(default)]
+    done: bool,
+    /// Flash address esptool had reached when the flash was interrupted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    written_until: Option<u32>,
+}
+
+/// Progress of the current flash, kept in the build directory until it completes
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct FlashProgress {
+    /// Regions by flash offset
+    regions: BTreeMap<u32, FlashRegion>,
+    /// Region esptool reported as written, waiting for its hash check
+    #[serde(skip)]
+    pending: Option<u32>,
+    /// esptool reported a serial link failure
+    #[serde(skip)]
+    link_failure: bool,
+}
+
+/// Output format of `merge-bin`
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeFormat {
+    /// Raw binary covering the flash from address 0
+    Bin,
+    /// Intel HEX
+    Hex,
+    /// UF2 for UF2 bootloaders and web flashers
+    Uf2,
+}
+
+/// Baud rate for esptool. It can't switch a raw TCP port (socket://) to a faster
+/// rate, so that stays at the ROM bootloader's 115200 the remote port is expected to
+/// run at.
+pub fn esptool_baud(cli: &Cli) -> Result<Option<u32>> {
+    let transport = cli
+        .port
+        .as_deref()
+        .map(serial_ports::transport)
+        .transpose()?;
+    Ok(match transport {
+        Some(port) if !port.supports_baud_change() => Some(115200),
+        _ => cli.baud,
+    })
+}
+
+/// Command line for running esptool with the chip, baud rate and port options.
+/// A standalone esptool executable set in `IDF_RS_ESPTOOL` is run directly, so
+/// flashing doesn't need the Python environment; otherwise esptool.py from ESP-IDF.
+pub fn esptool_command(cli: &Cli, chip: &str) -> Result<Vec<String>> {
+    let baud = esptool_baud(cli)?.unwrap_or(460800);
+
+    let mut command = match std::env::var("IDF_RS_ESPTOOL") {
+        Ok(esptool) if !esptool.is_empty() => vec![esptool],
+        _ => {
+            let esptool_path = utils::esptool_script("esptool")?;
+            vec![
+                utils::get_python_executable()?,
+                esptool_path.to_string_lossy().to_string(),
+            ]
+        }
+    };
+    command.extend([
+        "--chip".to_string(),
+        chip.to_string(),
+        "--baud".to_string(),
+        baud.to_string(),
+    ]);
+    if let Some(port) = &cli.port {
+        command.extend(["--port".to_string(), port.clone()]);
+    }
+    Ok(command)
+}
+
+/// Run an esptool command (e.g. "read_flash") with its arguments
+pub async fn run_esptool(cli: &Cli, dir: &Path, args: &[&str]) -> Result<()> {
+    let esptool = esptool_command(cli, "auto")?;
+    let mut esptool_args: Vec<&str> = esptool[1..].iter().map(String::as_str).collect();
+    esptool_args.extend_from_slice(args);
+    utils::run_command(&esptool[0], &esptool_args, Some(dir), cli.verbose).await
+}
+
+fn get_progress_path(build_dir: &Path) -> PathBuf {
+    build_dir.join("flash-progress.json")
+}
+
+pub fn parse_address(text: &str) -> Option<u32> {
+    let hex = text.trim().trim_end_matches(['.', ',']);
+    u32::from_str_radix(hex.strip_prefix("0x")?, 16).ok()
+}
+
+pub fn load_flasher_args(build_dir: &Path) -> Result<serde_json::Value> {
+    let path = build_dir.join("flasher_args.json");
+    let content = std::fs::read_to_string(&path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+/// Flash offset and file of an image flasher_args.json lists by name ("app",
+/// "bootloader", "partition-table"), the file relative to the build directory
+pub fn flasher_image(build_dir: &Path, name: &str) -> Result<(String, PathBuf)> {
+    let flasher_args = load_flasher_args(build_dir)?;
+    let image = &flasher_args[name];
+    match (image["offset"].as_str(), image["file"].as_str()) {
+        (Some(offset), Some(file)) => Ok((offset.to_string(), build_dir.join(file))),
+        _ => Err(anyhow::anyhow!("flasher_args.json has no {} image", name)),
+    }
+}
+
+impl FlashProgress {
+    /// The flash set of the current build, as listed in flasher_args.json
+    fn from_build(build_dir: &Path) -> Result<Self> {
+        let flasher_args = load_flasher_args(build_dir)?;
+        let files = flasher_args["flash_files"]
+            .as_object()
+            .ok_or_else(|| anyhow::anyhow!("flasher_args.json has no flash_files"))?;
+
+        let mut regions = BTreeMap::new();
+        for (offset, file) in files {
+            let offset = parse_address(offset)
+                .ok_or_else(|| anyhow::anyhow!("Invalid flash offset '{}'", offset))?;
+            let file = file.as_str().unwrap_or_default().to_string();
+            let data = std::fs::read(build_dir.join(&file))
+                .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", file, e))?;
+            regions.insert(
+                offset,
+                FlashRegion {
+                    file,
+                    size: data.len() as u64,
+                    sha256: sha256::hex_digest(&data),
+                    done: false,
+                    written_until: None,
+                },
+            );
+        }
+
+        Ok(FlashProgress {
+            regions,
+            ..Default::default()
+        })
+    }
+
+    fn load(build_dir: &Path) -> Option<Self> {
+        let content = std::fs::read_to_string(get_progress_path(build_dir)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    fn save(&self, build_dir: &Path) -> Result<()> {
+        std::fs::write(
+            get_progress_path(build_dir),
+            serde_json::to_string_pretty(self)?,
+        )?;
+        Ok(())
+    }
+
+    fn completed(&self) -> usize {
+        self.regions.values().filter(|region| region.done).count()
+    }
+
+    /// Whether the images are still the ones this progress was recorded for
+    fn matches(&self, other: &FlashProgress) -> bool {
+        self.regions.len() == other.regions.len()
+            && self.regions.iter().all(|(offset, region)| {
+                other
+                    .regions
+                    .get(offset)
+                    .is_some_and(|current| current.sha256 == region.sha256)
+            })
+    }
+
+    fn region_at(&self, address: u32) -> Option<u32> {
+        self.regions
+            .iter()
+            .find(|(offset, region)| {
+                address >= **offset && (address as u64) < **offset as u64 + region.size.max(1)
+            })
+            .map(|(offset, _)| *offset)
+    }
+
+    /// Track an esptool output line, returning whether the progress changed
+    fn observe(&mut self, line: &str) -> bool {
+        let line = line.trim();
+        if LINK_FAILURES.iter().any(|failure| line.contains(failure)) {
+            self.link_failure = true;
+        }
+        if let Some(rest) = line.strip_prefix("Writing at ") {
+            let address = rest.split_whitespace().next().and_then(parse_address);
+            if let Some((offset, address)) =
+                address.and_then(|address| Some((self.region_at(address)?, address)))
+            {
+                let region = self.regions.get_mut(&offset).unwrap();
+                region.written_until = Some(region.written_until.unwrap_or(0).max(address));
+                return true;
+            }
+        } else if line.starts_with("Wrote ") {
+            // Wrote 26384 bytes (16543 compressed) at 0x00001000 in 0.8 seconds...
+            self.pending = line
+                .split_once(" at ")
+                .and_then(|(_, rest)| rest.split_whitespace().next())
+                .and_then(parse_address)
+                .and_then(|address| self.region_at(address));
+        } else if line.starts_with("Hash of data verified") {
+            if let Some(region) = self
+                .pending
+                .take()
+                .and_then(|offset| self.regions.get_mut(&offset))
+            {
+                region.done = true;
+                region.written_until = None;
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// Run a flash command with its output passed through to the console, recording
+/// which regions esptool has written and verified
+async fn run_with_progress(
+    program: &str,
+    args: &[&str],
+    project_dir: &Path,
+    build_dir: &Path,
+    env: &utils::CommandEnv,
+    verbose: bool,
+    progress: &mut FlashProgress,
+) -> Result<()> {
+    if verbose {
+        println!("Running: {} {}", program, args.join(" "));
+    }
+    progress.save(build_dir)?;
+
+    let mut child = tokio::process::Command::new(program)
+        .args(args)
+        .current_dir(project_dir)
+        .envs(env.vars())
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Failed to execute {}: {}", program, e))?;
+
+    let mut stdout = child.stdout.take().unwrap();
+    let mut buffer = [0u8; 4096];
+    let mut pending = Vec::new();
+    let mut interrupted = false;
+    loop {
+        let read = tokio::select! {
+            read = stdout.read(&mut buffer) => read?,
+            // The flasher gets the Ctrl+C as well, keep reading until it has exited
+            _ = tokio::signal::ctrl_c(), if !interrupted => {
+                interrupted = true;
+                continue;
+            }
+        };
+        if read == 0 {
+            break;
+        }
+        let mut console = std::io::stdout();
+        console.write_all(&buffer[..read])?;
+        console.flush()?;
+
+        pending.extend_from_slice(&buffer[..read]);
+        let mut changed = false;
+        while let Some(end) = pending.iter().position(|&b| b == b'\n' || b == b'\r') {
+            let line: Vec<u8> = pending.drain(..=end).collect();
+            changed |= progress.observe(&String::from_utf8_lossy(&line));
+        }
+        if changed {
+            progress.save(build_dir)?;
+        }
+    }
+
+    let status = child.wait().await?;
+    if interrupted || !status.success() {
+        progress.save(build_dir)?;
+        return Err(anyhow::anyhow!(
+            "Flashing {} with {} of {} regions written. Run 'idf-rs flash --resume' to continue.",
+            if interrupted { "interrupted" } else { "failed" },
+            progress.completed(),
+            progress.regions.len()
+        ));
+    }
+
+    // Everything was written
+    let _ = std::fs::remove_file(get_progress_path(build_dir));
+    Ok(())
+}
+
+/// Refuse to flash an image built for another chip than the connected one, e.g. an
+/// esp32s3 build onto an esp32c3, unless forced
+async fn verify_target(cli: &Cli, project_dir: &Path, force: bool) -> Result<()> {
+    let Some(expected) = config::load_project_config(project_dir)?.target else {
+        return Ok(());
+    };
+
+    let info = match chip::read_chip_info(cli).await {
+        Ok(info) => info,
+        Err(e) => {
+            // Flashing reports the connection problem in more detail
+            if cli.verbose {
+                println!("Couldn't check the chip type before flashing: {}", e);
+            }
+            return Ok(());
+        }
+    };
+    let Some(connected) = info.target else {
+        return Ok(());
+    };
+    if connected == expected {
+        return Ok(());
+    }
+
+    if force {
+        output::warning(format!(
+            "flashing an {} build onto the connected {} ({})",
+            expected, connected, info.model
+        ));
+        return Ok(());
+    }
+    Err(anyhow::anyhow!(
+        "The connected chip is an {} ({}), but the project is built for {} (CONFIG_IDF_TARGET). \
+         Run 'idf-rs set-target {}' and build again, or flash anyway with --force.",
+        connected,
+        info.model,
+        expected,
+        connected
+    ))
+}
+
+/// Refuse to flash an unsigned app where secure boot needs a signed one, unless forced
+async fn check_signed_build(
+    cli: &Cli,
+    project_dir: &Path,
+    build_dir: &Path,
+    force: bool,
+) -> Result<()> {
+    if force {
+        return Ok(());
+    }
+    let Ok(flasher_args) = load_flasher_args(build_dir) else {
+        return Ok(());
+    };
+    match flasher_args["app"]["file"].as_str() {
+        Some(app) => security::check_signed_app(cli, project_dir, &build_dir.join(app)).await,
+        None => Ok(()),
+    }
+}
 
 pub async fn execute(
     cli: &Cli,
@@ -7,6 +396,7 @@ pub async fn execute(
     extra_args: Option<&str>,
     force: bool,
     trace: bool,
+    resume: bool,
 ) -> Result<()> {
     utils::setup_idf_environment()?;
 
@@ -27,39 +417,445 @@ pub async fn execute(
     // First, ensure the project is built
     if !build_dir.exists() {
         println!("Build directory doesn't exist. Building project first...");
-        crate::commands::build::execute(cli, &[]).await?;
+        crate::commands::build::execute(cli, &[], true, None).await?;
+    }
+
+    verify_target(cli, &project_dir, force).await?;
+    check_signed_build(cli, &project_dir, &build_dir, force).await?;
+
+    if resume {
+        resume_flash(cli, &project_dir, &build_dir).await?;
+        state::record_serial_settings(
+            &build_dir,
+            cli.port.as_deref(),
+            state::SessionBaud::Flash(cli.baud),
+        );
+        println!("Flash completed successfully!");
+        return Ok(());
     }
 
     // Use CMake flash target which handles all the complexity
     let flash_args = vec!["--build", build_dir.to_str().unwrap(), "--target", "flash"];
 
-    // Set environment variables for port and baud if specified
-    let mut env_vars = Vec::new();
-    let baud_str;
+    // The flash target reads the port and baud rate from the environment
+    let mut env = utils::CommandEnv::new();
     if let Some(port) = &cli.port {
-        env_vars.push(("ESPPORT", port.as_str()));
+        env.set("ESPPORT", port);
     }
-    if let Some(baud) = cli.baud {
-        baud_str = baud.to_string();
-        env_vars.push(("ESPBAUD", &baud_str));
+    if let Some(baud) = esptool_baud(cli)? {
+        env.set("ESPBAUD", baud.to_string());
     }
 
-    // Set environment variables
-    for (key, value) in &env_vars {
-        std::env::set_var(key, value);
+    match FlashProgress::from_build(&build_dir) {
+        Ok(mut progress) => {
+            if let Some(previous) = FlashProgress::load(&build_dir) {
+                if previous.matches(&progress) {
+                    println!(
+                        "Note: the previous flash of this build was interrupted, 'flash --resume' continues it instead of rewriting everything"
+                    );
+                }
+            }
+            let result = run_with_progress(
+                "cmake",
+                &flash_args,
+                &project_dir,
+                &build_dir,
+                &env,
+                cli.verbose,
+                &mut progress,
+            )
+            .await;
+            match result {
+                Err(e) if progress.link_failure => {
+                    flash_with_fallback(cli, &project_dir, &build_dir, e).await?
+                }
+                result => result?,
+            }
+        }
+        // Without flasher_args.json the progress can't be tracked
+        Err(_) => {
+            utils::run_command_with_env(
+                "cmake",
+                &flash_args,
+                Some(&project_dir),
+                cli.verbose,
+                &env,
+            )
+            .await?;
+        }
     }
 
-    utils::run_command("cmake", &flash_args, Some(&project_dir), cli.verbose).await?;
+    state::record_serial_settings(
+        &build_dir,
+        cli.port.as_deref(),
+        state::SessionBaud::Flash(cli.baud),
+    );
+
+    println!("Flash completed successfully!");
+    Ok(())
+}
+
+/// Flash again after the serial link failed: at lower baud rates, then also without
+/// the stub loader, reporting what worked
+async fn flash_with_fallback(
+    cli: &Cli,
+    project_dir: &Path,
+    build_dir: &Path,
+    error: anyhow::Error,
+) -> Result<()> {
+    let failed_baud = esptool_baud(cli)?.unwrap_or(460800);
+    let mut attempts: Vec<(u32, bool)> = FALLBACK_BAUD_RATES
+        .iter()
+        .filter(|baud| **baud < failed_baud)
+        .map(|baud| (*baud, false))
+        .collect();
+    attempts.push((115200, true));
+
+    let flasher_args = load_flasher_args(build_dir)?;
+    let mut last_error = error;
+    for (baud, no_stub) in attempts {
+        let loader = if no_stub {
+            " without the stub loader"
+        } else {
+            ""
+        };
+        println!(
+            "Flashing failed on the serial link, retrying at {} baud{}...",
+            baud, loader
+        );
 
-    // Clean up environment variables
-    for (key, _) in &env_vars {
-        std::env::remove_var(key);
+        let retry_cli = Cli {
+            baud: Some(baud),
+            ..cli.clone()
+        };
+        let mut esptool = build_esptool_command(&retry_cli, &flasher_args)?;
+        if no_stub && !esptool.iter().any(|arg| arg == "--no-stub") {
+            esptool.push("--no-stub".to_string());
+        }
+        let mut progress = FlashProgress::from_build(build_dir)?;
+        let mut write_args = write_flash_args(&flasher_args);
+        for (offset, region) in &progress.regions {
+            write_args.push(format!("0x{:x}", offset));
+            write_args.push(build_dir.join(&region.file).to_string_lossy().to_string());
+        }
+        let args: Vec<&str> = esptool[1..]
+            .iter()
+            .chain(&write_args)
+            .map(String::as_str)
+            .collect();
+
+        let result = run_with_progress(
+            &esptool[0],
+            &args,
+            project_dir,
+            build_dir,
+            &utils::CommandEnv::new(),
+            cli.verbose,
+            &mut progress,
+        )
+        .await;
+        match result {
+            Ok(()) => {
+                println!("Flashing worked at {} baud{}.", baud, loader);
+                println!(
+                    "To flash at this rate from the start, set 'baud = {}' under [project] in idf.toml, or pass -b {}",
+                    baud, baud
+                );
+                return Ok(());
+            }
+            Err(e) if progress.link_failure => last_error = e,
+            Err(e) => return Err(e),
+        }
     }
+    Err(last_error)
+}
 
-    println!("Flash completed successfully!");
+/// Options of the flash each device of a multi-device flash gets
+#[derive(Debug, Clone, Default)]
+pub struct MultiFlashOptions {
+    pub extra_args: Option<String>,
+    pub force: bool,
+    pub trace: bool,
+}
+
+/// Flash the build to several devices at once: the given ports, or with `all_ports`
+/// every connected ESP device. Each is flashed by an idf-rs of its own with its output
+/// prefixed with the port and logged to build/flash-logs, followed by a summary table.
+pub async fn execute_multi(
+    cli: &Cli,
+    ports: &[String],
+    all_ports: bool,
+    options: &MultiFlashOptions,
+) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+
+    let mut ports = ports.to_vec();
+    if all_ports {
+        for port in serial_ports::candidate_ports()? {
+            if serial_ports::locked_elsewhere(&port.port) {
+                println!("Skipping {}, in use by another idf-rs", port.port);
+            } else if !ports.contains(&port.port) {
+                ports.push(port.port);
+            }
+        }
+        if ports.is_empty() {
+            return Err(anyhow::anyhow!("No ESP devices are connected"));
+        }
+    }
+
+    // Built once here so the devices' flashes find the build up to date instead of
+    // rebuilding it concurrently
+    crate::commands::build::execute(cli, &[], false, None).await?;
+
+    let log_dir = build_dir.join("flash-logs");
+    std::fs::create_dir_all(&log_dir)?;
+    let exe = std::env::current_exe()?;
+    println!(
+        "=== Flashing {} device(s), logs in {} ===",
+        ports.len(),
+        log_dir.display()
+    );
+
+    let mut tasks = Vec::new();
+    for port in &ports {
+        let mut args = vec![
+            "--non-interactive".to_string(),
+            "-C".to_string(),
+            project_dir.display().to_string(),
+            "-B".to_string(),
+            build_dir.display().to_string(),
+            "-p".to_string(),
+            port.clone(),
+        ];
+        if let Some(baud) = cli.baud {
+            args.extend(["-b".to_string(), baud.to_string()]);
+        }
+        if cli.verbose {
+            args.push("-v".to_string());
+        }
+        args.push("flash".to_string());
+        if options.force {
+            args.push("--force".to_string());
+        }
+        if options.trace {
+            args.push("--trace".to_string());
+        }
+        if let Some(extra) = &options.extra_args {
+            args.extend(["--extra-args".to_string(), extra.clone()]);
+        }
+
+        let log_name: String = port
+            .trim_start_matches('/')
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let log_path = log_dir.join(format!("{}.log", log_name));
+        let (exe, name) = (exe.clone(), port.clone());
+        tasks.push((
+            port.clone(),
+            tokio::spawn(async move {
+                let started = std::time::Instant::now();
+                let result = crate::commands::fleet::run_device(exe, args, name, log_path).await;
+                (result, started.elapsed())
+            }),
+        ));
+    }
+
+    let mut results = Vec::new();
+    for (port, task) in tasks {
+        let (result, duration) = task.await?;
+        results.push((port, result, duration));
+    }
+
+    let width = results
+        .iter()
+        .map(|(port, _, _)| port.len())
+        .max()
+        .unwrap_or(0)
+        .max(4);
+    println!("=== Flash summary ===");
+    println!("{:<width$}  {:<6}  {:>7}", "PORT", "RESULT", "TIME");
+    for (port, result, duration) in &results {
+        let status = if result.is_ok() { "ok" } else { "FAILED" };
+        print!(
+            "{:<width$}  {:<6}  {:>6.1}s",
+            port,
+            status,
+            duration.as_secs_f64()
+        );
+        match result {
+            Ok(()) => println!(),
+            Err(e) => println!("  {}", e),
+        }
+    }
+
+    let failed = results
+        .iter()
+        .filter(|(_, result, _)| result.is_err())
+        .count();
+    if failed > 0 {
+        return Err(anyhow::anyhow!(
+            "{} of {} device(s) failed to flash",
+            failed,
+            results.len()
+        ));
+    }
+    println!("All {} device(s) flashed successfully!", results.len());
     Ok(())
 }
 
+/// Pick what to write for an interrupted region: when the blocks esptool had already
+/// sent pass verify_flash, only the rest of the image. Returns the offset and file.
+async fn partial_region(
+    esptool: &[String],
+    project_dir: &Path,
+    build_dir: &Path,
+    offset: u32,
+    region: &FlashRegion,
+) -> Result<(u32, PathBuf)> {
+    let image = build_dir.join(&region.file);
+    let written = match region.written_until {
+        Some(until) if region.size >= MIN_PARTIAL_RESUME_SIZE => {
+            // The block esptool was writing may be incomplete
+            until
+                .saturating_sub(offset)
+                .saturating_sub(FLASH_WRITE_BLOCK)
+                / FLASH_SECTOR_SIZE
+                * FLASH_SECTOR_SIZE
+        }
+        _ => 0,
+    };
+    if written == 0 {
+        return Ok((offset, image));
+    }
+
+    let data = std::fs::read(&image)?;
+    let resume_dir = build_dir.join("flash-resume");
+    std::fs::create_dir_all(&resume_dir)?;
+    let head = resume_dir.join(format!("{:x}-head.bin", offset));
+    let tail = resume_dir.join(format!("{:x}-tail.bin", offset));
+    std::fs::write(&head, &data[..written as usize])?;
+    std::fs::write(&tail, &data[written as usize..])?;
+
+    println!(
+        "Verifying the first {} KB of {} at 0x{:x}...",
+        written / 1024,
+        region.file,
+        offset
+    );
+    let offset_str = format!("0x{:x}", offset);
+    let mut args: Vec<&str> = esptool[1..].iter().map(String::as_str).collect();
+    args.extend_from_slice(&["verify_flash", &offset_str, head.to_str().unwrap()]);
+    let verified = utils::run_command_with_output(&esptool[0], &args, Some(project_dir))
+        .await
+        .is_ok();
+
+    if verified {
+        println!("Verified, continuing at 0x{:x}", offset + written);
+        Ok((offset + written, tail))
+    } else {
+        println!("Verification failed, rewriting {} completely", region.file);
+        Ok((offset, image))
+    }
+}
+
+/// The same esptool invocation the flash target uses, see flasher_args.json
+fn build_esptool_command(cli: &Cli, flasher_args: &serde_json::Value) -> Result<Vec<String>> {
+    let extra = &flasher_args["extra_esptool_args"];
+    let mut esptool = esptool_command(cli, extra["chip"].as_str().unwrap_or("auto"))?;
+    esptool.extend([
+        "--before".to_string(),
+        extra["before"]
+            .as_str()
+            .unwrap_or("default_reset")
+            .to_string(),
+        "--after".to_string(),
+        extra["after"].as_str().unwrap_or("hard_reset").to_string(),
+    ]);
+    if extra["stub"].as_bool() == Some(false) {
+        esptool.push("--no-stub".to_string());
+    }
+    Ok(esptool)
+}
+
+/// `write_flash` with the flash mode, frequency and size options of the build
+fn write_flash_args(flasher_args: &serde_json::Value) -> Vec<String> {
+    let mut write_args = vec!["write_flash".to_string()];
+    for arg in flasher_args["write_flash_args"]
+        .as_array()
+        .into_iter()
+        .flatten()
+    {
+        write_args.extend(arg.as_str().map(str::to_string));
+    }
+    write_args
+}
+
+/// Continue an interrupted flash with the regions that weren't written yet
+async fn resume_flash(cli: &Cli, project_dir: &Path, build_dir: &Path) -> Result<()> {
+    let current = FlashProgress::from_build(build_dir)?;
+    let mut progress = match FlashProgress::load(build_dir) {
+        Some(progress) if progress.matches(&current) => progress,
+        Some(_) => {
+            return Err(anyhow::anyhow!(
+                "The build changed since the interrupted flash, run 'flash' without --resume"
+            ))
+        }
+        None => {
+            return Err(anyhow::anyhow!(
+                "No interrupted flash to resume, run 'flash' without --resume"
+            ))
+        }
+    };
+
+    let remaining: Vec<(u32, FlashRegion)> = progress
+        .regions
+        .iter()
+        .filter(|(_, region)| !region.done)
+        .map(|(offset, region)| (*offset, region.clone()))
+        .collect();
+    if remaining.is_empty() {
+        println!("All regions were already written");
+        let _ = std::fs::remove_file(get_progress_path(build_dir));
+        return Ok(());
+    }
+    println!(
+        "Resuming flash: {} of {} regions already written",
+        progress.completed(),
+        progress.regions.len()
+    );
+
+    let flasher_args = load_flasher_args(build_dir)?;
+    let esptool = build_esptool_command(cli, &flasher_args)?;
+    let mut write_args = write_flash_args(&flasher_args);
+    for (offset, region) in &remaining {
+        let (address, file) =
+            partial_region(&esptool, project_dir, build_dir, *offset, region).await?;
+        write_args.push(format!("0x{:x}", address));
+        write_args.push(file.to_string_lossy().to_string());
+    }
+
+    let args: Vec<&str> = esptool[1..]
+        .iter()
+        .chain(&write_args)
+        .map(String::as_str)
+        .collect();
+    let result = run_with_progress(
+        &esptool[0],
+        &args,
+        project_dir,
+        build_dir,
+        &utils::CommandEnv::new(),
+        cli.verbose,
+        &mut progress,
+    )
+    .await;
+
+    let _ = std::fs::remove_dir_all(build_dir.join("flash-resume"));
+    result
+}
+
 pub async fn execute_app(
     cli: &Cli,
     extra_args: Option<&str>,
@@ -87,25 +883,15 @@ pub async fn execute_app(
         crate::commands::build::execute_app(cli).await?;
     }
 
-    // Flash app binary
-    let python = utils::get_python_executable()?;
-    let idf_path = utils::get_idf_path()?;
-    let esptool_path = idf_path.join("components/esptool_py/esptool/esptool.py");
-
-    let baud_str = cli.baud.unwrap_or(460800).to_string();
-    let mut flash_args = vec![
-        esptool_path.to_str().unwrap(),
-        "--chip",
-        "auto",
-        "--baud",
-        &baud_str,
-    ];
-
-    if let Some(port) = &cli.port {
-        flash_args.extend_from_slice(&["--port", port]);
+    verify_target(cli, &project_dir, force).await?;
+    if !force {
+        security::check_signed_app(cli, &project_dir, &app_bin_path).await?;
     }
 
-    flash_args.extend_from_slice(&["write_flash"]);
+    // Flash app binary
+    let esptool = esptool_command(cli, "auto")?;
+    let mut flash_args: Vec<&str> = esptool[1..].iter().map(String::as_str).collect();
+    flash_args.push("write_flash");
 
     // Add force flag if specified
     if force {
@@ -124,19 +910,26 @@ pub async fn execute_app(
         }
     }
 
-    flash_args.extend_from_slice(&[
-        "0x10000", // Default app offset
-        app_bin_path.to_str().unwrap(),
-    ]);
+    // The app partition offset depends on the partition table
+    let app_offset = flasher_image(&build_dir, "app")
+        .map(|(offset, _)| offset)
+        .unwrap_or_else(|_| "0x10000".to_string());
+    flash_args.extend_from_slice(&[&app_offset, app_bin_path.to_str().unwrap()]);
 
     utils::run_command(
-        &python,
+        &esptool[0],
         &flash_args,
         Some(&project_dir),
         cli.verbose || trace,
     )
     .await?;
 
+    state::record_serial_settings(
+        &build_dir,
+        cli.port.as_deref(),
+        state::SessionBaud::Flash(cli.baud),
+    );
+
     println!("App flash completed successfully!");
     Ok(())
 }
@@ -155,65 +948,548 @@ pub async fn execute_bootloader(cli: &Cli) -> Result<()> {
         crate::commands::build::execute_bootloader(cli).await?;
     }
 
-    // Flash bootloader binary
-    let python = utils::get_python_executable()?;
-    let idf_path = utils::get_idf_path()?;
-    let esptool_path = idf_path.join("components/esptool_py/esptool/esptool.py");
-
-    let baud_str = cli.baud.unwrap_or(460800).to_string();
-    let bootloader_bin_path = build_dir.join("bootloader").join("bootloader.bin");
-    let mut flash_args = vec![
-        esptool_path.to_str().unwrap(),
-        "--chip",
-        "auto",
-        "--baud",
-        &baud_str,
-    ];
-
-    if let Some(port) = &cli.port {
-        flash_args.extend_from_slice(&["--port", port]);
-    }
-
+    // The bootloader offset depends on the chip (0x1000 on ESP32 and S2, 0x0 on C3, S3,
+    // C6 and H2, 0x2000 on P4), flasher_args.json has the one of the target
+    let (offset, bootloader_bin_path) = flasher_image(&build_dir, "bootloader")?;
+    let esptool = esptool_command(cli, "auto")?;
+    let mut flash_args: Vec<&str> = esptool[1..].iter().map(String::as_str).collect();
     flash_args.extend_from_slice(&[
         "write_flash",
-        "0x1000", // Default bootloader offset
+        &offset,
         bootloader_bin_path.to_str().unwrap(),
     ]);
 
-    utils::run_command(&python, &flash_args, Some(&project_dir), cli.verbose).await?;
+    utils::run_command(&esptool[0], &flash_args, Some(&project_dir), cli.verbose).await?;
+
+    state::record_serial_settings(
+        &build_dir,
+        cli.port.as_deref(),
+        state::SessionBaud::Flash(cli.baud),
+    );
 
     println!("Bootloader flash completed successfully!");
     Ok(())
 }
 
+/// Program the app, bootloader and partition table over USB-JTAG with probe-rs
+pub async fn execute_jtag(cli: &Cli) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+
+    if !build_dir.exists() {
+        println!("Build directory doesn't exist. Building project first...");
+        crate::commands::build::execute(cli, &[], true, None).await?;
+    }
+
+    let target = crate::openocd::resolve_target(&project_dir, &build_dir);
+    let elf = utils::get_app_elf_path(&build_dir)?;
+    let probe_rs = probe_rs::program();
+
+    println!("Flashing {} over JTAG with probe-rs...", target);
+    for args in [
+        probe_rs::download_args(&target, &build_dir, &elf)?,
+        probe_rs::reset_args(&target)?,
+    ] {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        utils::run_command(&probe_rs, &args, Some(&project_dir), cli.verbose)
+            .await
+            .map_err(|e| {
+                anyhow::anyhow!(
+                    "Failed to run {} ({}). Install it from https://probe.rs and put it on PATH.",
+                    probe_rs,
+                    e
+                )
+            })?;
+    }
+
+    println!("Flash completed successfully!");
+    Ok(())
+}
+
+/// The binaries of the build at their flash offsets and the chip, from flasher_args.json
+pub fn load_flash_regions(build_dir: &Path) -> Result<(String, Vec<Region>)> {
+    let (chip, files) = load_flash_files(build_dir)?;
+    Ok((chip, files.into_iter().map(|(_, region)| region).collect()))
+}
+
+/// The flashed binaries with their paths relative to the build directory
+pub fn load_flash_files(build_dir: &Path) -> Result<(String, Vec<(String, Region)>)> {
+    let flasher_args = load_flasher_args(build_dir)?;
+    let files = flasher_args["flash_files"]
+        .as_object()
+        .ok_or_else(|| anyhow::anyhow!("flasher_args.json has no flash_files"))?;
+
+    let mut regions = Vec::new();
+    for (offset, file) in files {
+        let offset = parse_address(offset)
+            .ok_or_else(|| anyhow::anyhow!("Invalid flash offset '{}'", offset))?;
+        let file = file.as_str().unwrap_or_default();
+        let data = std::fs::read(build_dir.join(file))
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", file, e))?;
+        regions.push((file.to_string(), Region { offset, data }));
+    }
+    let chip = flasher_args["extra_esptool_args"]["chip"]
+        .as_str()
+        .unwrap_or("esp32")
+        .to_string();
+    Ok((chip, regions))
+}
+
+/// Combine the flashed binaries into one image for third-party tools and web flashers
+pub async fn execute_merge_bin(
+    cli: &Cli,
+    output: Option<&Path>,
+    format: MergeFormat,
+    fill_flash_size: Option<&str>,
+) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+    let (chip, regions) = load_flash_regions(&build_dir)?;
+
+    let (image, extension) = match format {
+        MergeFormat::Bin => {
+            let fill_size = fill_flash_size
+                .map(|size| {
+                    partition_table::parse_size(size.trim_end_matches('B'))
+                        .ok_or_else(|| anyhow::anyhow!("Invalid flash size '{}'", size))
+                })
+                .transpose()?;
+            (flash_image::merge_raw(&regions, fill_size)?, "bin")
+        }
+        MergeFormat::Hex => (flash_image::intel_hex(&regions)?.into_bytes(), "hex"),
+        MergeFormat::Uf2 => {
+            let family = flash_image::uf2_family(&chip)
+                .ok_or_else(|| anyhow::anyhow!("No UF2 family ID is known for {}", chip))?;
+            (flash_image::uf2(&regions, family)?, "uf2")
+        }
+    };
+    if fill_flash_size.is_some() && format != MergeFormat::Bin {
+        output::warning("--fill-flash-size only applies to the bin format");
+    }
+
+    let output = match output {
+        Some(output) => output.to_path_buf(),
+        None => build_dir.join(format!("merged-binary.{}", extension)),
+    };
+    std::fs::write(&output, &image)?;
+
+    println!(
+        "Merged {} binaries into {} ({} bytes)",
+        regions.len(),
+        output.display(),
+        image.len()
+    );
+    if cli.verbose {
+        let mut regions = regions;
+        regions.sort_by_key(|region| region.offset);
+        for region in &regions {
+            println!("  0x{:06x}  {} bytes", region.offset, region.data.len());
+        }
+    }
+    Ok(())
+}
+
 pub async fn execute_erase(cli: &Cli) -> Result<()> {
     utils::setup_idf_environment()?;
 
     let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
 
+    cli.confirm_destructive(&format!(
+        "This erases the entire flash of the device on {}, including NVS data.",
+        cli.port
+            .as_deref()
+            .unwrap_or("the first port esptool finds")
+    ))?;
     println!("Erasing flash...");
 
-    let python = utils::get_python_executable()?;
-    let idf_path = utils::get_idf_path()?;
-    let esptool_path = idf_path.join("components/esptool_py/esptool/esptool.py");
+    let esptool = esptool_command(cli, "auto")?;
+    let mut erase_args: Vec<&str> = esptool[1..].iter().map(String::as_str).collect();
+    erase_args.push("erase_flash");
 
-    let baud_str = cli.baud.unwrap_or(460800).to_string();
-    let mut erase_args = vec![
-        esptool_path.to_str().unwrap(),
-        "--chip",
-        "auto",
-        "--baud",
-        &baud_str,
-    ];
+    utils::run_command(&esptool[0], &erase_args, Some(&project_dir), cli.verbose).await?;
 
-    if let Some(port) = &cli.port {
-        erase_args.extend_from_slice(&["--port", port]);
+    println!("Flash erase completed successfully!");
+    Ok(())
+}
+
+/// Offsets of the images flasher_args.json marks as `"encrypted": "true"`, in the
+/// per-image entries next to flash_files (bootloader, app, partition-table, ...)
+fn encrypted_offsets(flasher_args: &serde_json::Value) -> Vec<u32> {
+    let mut offsets: Vec<u32> = flasher_args
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(_, entry)| entry["encrypted"].as_str() == Some("true"))
+        .filter_map(|(_, entry)| entry["offset"].as_str().and_then(parse_address))
+        .collect();
+    offsets.sort_unstable();
+    offsets.dedup();
+    offsets
+}
+
+/// Arguments writing `files` with the images at `encrypted` offsets encrypted by the
+/// device: `--encrypt` when that is all of them (or none is marked), otherwise the
+/// marked ones after `--encrypt-files` and the rest in plaintext
+fn encrypted_write_args(files: &[(u32, String)], encrypted: &[u32]) -> Vec<String> {
+    let (marked, plain): (Vec<_>, Vec<_>) = files
+        .iter()
+        .partition(|(offset, _)| encrypted.contains(offset));
+
+    let mut args = Vec::new();
+    if marked.is_empty() || plain.is_empty() {
+        args.push("--encrypt".to_string());
+        for (offset, file) in files {
+            args.extend([format!("0x{:x}", offset), file.clone()]);
+        }
+    } else {
+        for (offset, file) in plain {
+            args.extend([format!("0x{:x}", offset), file.clone()]);
+        }
+        args.push("--encrypt-files".to_string());
+        for (offset, file) in marked {
+            args.extend([format!("0x{:x}", offset), file.clone()]);
+        }
     }
+    args
+}
 
-    erase_args.push("erase_flash");
+/// Flash with encryption by the device, which needs flash encryption in development
+/// mode: the whole project, or only the app with `app_only`
+pub async fn execute_encrypted(cli: &Cli, app_only: bool, force: bool) -> Result<()> {
+    utils::setup_idf_environment()?;
 
-    utils::run_command(&python, &erase_args, Some(&project_dir), cli.verbose).await?;
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
 
-    println!("Flash erase completed successfully!");
+    let sdkconfig = config::load_project_config(&project_dir)?;
+    let enabled = |name: &str| sdkconfig.settings.get(name).map(String::as_str) == Some("y");
+    if !enabled("CONFIG_SECURE_FLASH_ENC_ENABLED") {
+        return Err(anyhow::anyhow!(
+            "Flash encryption is not enabled in sdkconfig (CONFIG_SECURE_FLASH_ENC_ENABLED), use 'flash' instead"
+        ));
+    }
+    if !enabled("CONFIG_SECURE_FLASH_ENCRYPTION_MODE_DEVELOPMENT") {
+        return Err(anyhow::anyhow!(
+            "Flash encryption is in release mode, the device no longer encrypts downloaded images. Update it over the air instead."
+        ));
+    }
+
+    if !build_dir.join("flasher_args.json").exists() {
+        println!("Build directory isn't complete. Building project first...");
+        crate::commands::build::execute(cli, &[], true, None).await?;
+    }
+    verify_target(cli, &project_dir, force).await?;
+    check_signed_build(cli, &project_dir, &build_dir, force).await?;
+
+    let flasher_args = load_flasher_args(&build_dir)?;
+    let files: Vec<(u32, String)> = if app_only {
+        let app = &flasher_args["app"];
+        let offset = app["offset"].as_str().and_then(parse_address);
+        let file = app["file"].as_str();
+        match (offset, file) {
+            (Some(offset), Some(file)) => vec![(offset, file.to_string())],
+            _ => return Err(anyhow::anyhow!("flasher_args.json has no app image")),
+        }
+    } else {
+        load_flash_files(&build_dir)?
+            .1
+            .into_iter()
+            .map(|(file, region)| (region.offset, file))
+            .collect()
+    };
+
+    println!(
+        "Flashing {} with encryption...",
+        if app_only { "app" } else { "project" }
+    );
+    let esptool = build_esptool_command(cli, &flasher_args)?;
+    let mut write_args = write_flash_args(&flasher_args);
+    if force {
+        write_args.push("--force".to_string());
+    }
+    // The app alone is always written encrypted
+    let encrypted = if app_only {
+        Vec::new()
+    } else {
+        encrypted_offsets(&flasher_args)
+    };
+    write_args.extend(encrypted_write_args(&files, &encrypted));
+
+    let args: Vec<&str> = esptool[1..]
+        .iter()
+        .chain(&write_args)
+        .map(String::as_str)
+        .collect();
+    utils::run_command(&esptool[0], &args, Some(&build_dir), cli.verbose).await?;
+
+    state::record_serial_settings(
+        &build_dir,
+        cli.port.as_deref(),
+        state::SessionBaud::Flash(cli.baud),
+    );
+
+    println!("Encrypted flash completed successfully!");
+    Ok(())
+}
+
+/// Parse a flash address or size: decimal or hex, with an optional K, M or MB suffix
+fn parse_flash_number(text: &str, what: &str) -> Result<u32> {
+    partition_table::parse_size(text.trim_end_matches(['B', 'b']))
+        .ok_or_else(|| anyhow::anyhow!("Invalid flash {} '{}'", what, text))
+}
+
+/// Read `size` bytes of the device's flash at `address` into a file. A size of ALL
+/// reads the whole flash.
+pub async fn execute_read_flash(cli: &Cli, address: &str, size: &str, output: &Path) -> Result<()> {
+    utils::setup_idf_environment()?;
+
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let address = format!("0x{:x}", parse_flash_number(address, "address")?);
+    let size = match size {
+        "ALL" | "all" => "ALL".to_string(),
+        size => format!("0x{:x}", parse_flash_number(size, "size")?),
+    };
+
+    let range = match size.as_str() {
+        "ALL" => "the whole flash".to_string(),
+        size => format!("{} bytes of flash at {}", size, address),
+    };
+    println!("Reading {} to {}...", range, output.display());
+    let output = output.to_string_lossy();
+    run_esptool(cli, &project_dir, &["read_flash", &address, &size, &output]).await?;
+
+    println!("Flash read completed successfully!");
+    Ok(())
+}
+
+/// Back up a partition, or the whole flash without one, e.g. before erasing
+pub async fn execute_dump_flash(
+    cli: &Cli,
+    partition: Option<&str>,
+    output: Option<&Path>,
+) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+    let output = match output {
+        Some(output) => output.to_path_buf(),
+        None => {
+            std::fs::create_dir_all(&build_dir)?;
+            build_dir.join(format!("{}-dump.bin", partition.unwrap_or("flash")))
+        }
+    };
+
+    match partition {
+        Some(name) => {
+            crate::commands::partition_table::execute_read_partition(cli, name, &output).await
+        }
+        None => execute_read_flash(cli, "0", "ALL", &output).await,
+    }
+}
+
+/// Outcome per flash address of `esptool verify_flash`, which compares the MD5 of each
+/// region on the chip with the one of the file
+fn parse_verify_output(output: &str) -> BTreeMap<u32, bool> {
+    let mut results = BTreeMap::new();
+    let mut current = None;
+    for line in output.lines().map(str::trim) {
+        // Verifying 0x6c10 (27664) bytes @ 0x00001000 in flash against bootloader/bootloader.bin...
+        if line.starts_with("Verifying ") {
+            current = line
+                .split_once(" @ ")
+                .and_then(|(_, rest)| rest.split_whitespace().next())
+                .and_then(parse_address);
+        } else if let Some(result) = line.strip_prefix("-- verify ") {
+            if let Some(address) = current.take() {
+                results.insert(address, result.starts_with("OK"));
+            }
+        }
+    }
+    results
+}
+
+/// Check that the device's flash holds the images of the build, region by region
+pub async fn execute_verify(cli: &Cli) -> Result<()> {
+    utils::setup_idf_environment()?;
+
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+    let (chip, files) = load_flash_files(&build_dir)?;
+
+    let esptool = esptool_command(cli, &chip)?;
+    let mut args: Vec<String> = esptool[1..].to_vec();
+    args.push("verify_flash".to_string());
+    for (file, region) in &files {
+        args.push(format!("0x{:x}", region.offset));
+        args.push(file.clone());
+    }
+
+    println!("Verifying {} regions against the build...", files.len());
+    if cli.verbose {
+        println!("Running: {} {}", esptool[0], args.join(" "));
+    }
+    let result = tokio::process::Command::new(&esptool[0])
+        .args(&args)
+        .current_dir(&build_dir)
+        .envs(utils::CommandEnv::new().vars())
+        .output()
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to execute {}: {}", esptool[0], e))?;
+    let stdout = String::from_utf8_lossy(&result.stdout);
+    if cli.verbose {
+        print!("{}", stdout);
+    }
+
+    let results = parse_verify_output(&stdout);
+    if results.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Flash verification failed: {}",
+            String::from_utf8_lossy(&result.stderr).trim()
+        ));
+    }
+
+    println!();
+    println!("{:<12} {:<34} {:<10} RESULT", "OFFSET", "FILE", "MD5");
+    let mut failed = 0;
+    for (file, region) in &files {
+        let md5 = sha256::to_hex(&md5::digest(&region.data));
+        let status = match results.get(&region.offset) {
+            Some(true) => "OK",
+            Some(false) => {
+                failed += 1;
+                "FAILED"
+            }
+            None => {
+                failed += 1;
+                "NOT CHECKED"
+            }
+        };
+        println!(
+            "0x{:<10x} {:<34} {:<10} {}",
+            region.offset,
+            file,
+            &md5[..8],
+            status
+        );
+    }
+    println!();
+
+    if failed > 0 {
+        return Err(anyhow::anyhow!(
+            "{} of {} regions don't match the build",
+            failed,
+            files.len()
+        ));
+    }
+    println!("Flash verified successfully!");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flasher_image() {
+        let dir = std::env::temp_dir().join(format!("idf-rs-flasher-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("flasher_args.json"),
+            r#"{"bootloader": {"offset": "0x0", "file": "bootloader/bootloader.bin"}}"#,
+        )
+        .unwrap();
+
+        let (offset, file) = flasher_image(&dir, "bootloader").unwrap();
+        assert_eq!(offset, "0x0");
+        assert_eq!(file, dir.join("bootloader/bootloader.bin"));
+        assert!(flasher_image(&dir, "app").is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_encrypted_write_args() {
+        let flasher_args = serde_json::json!({
+            "bootloader": { "offset": "0x1000", "file": "bootloader/bootloader.bin", "encrypted": "true" },
+            "app": { "offset": "0x10000", "file": "app.bin", "encrypted": "true" },
+            "storage": { "offset": "0x110000", "file": "storage.bin", "encrypted": "false" },
+        });
+        let encrypted = encrypted_offsets(&flasher_args);
+        assert_eq!(encrypted, vec![0x1000, 0x10000]);
+
+        let files = vec![
+            (0x1000, "bootloader/bootloader.bin".to_string()),
+            (0x10000, "app.bin".to_string()),
+            (0x110000, "storage.bin".to_string()),
+        ];
+        assert_eq!(
+            encrypted_write_args(&files, &encrypted),
+            vec![
+                "0x110000",
+                "storage.bin",
+                "--encrypt-files",
+                "0x1000",
+                "bootloader/bootloader.bin",
+                "0x10000",
+                "app.bin"
+            ]
+        );
+        assert_eq!(
+            encrypted_write_args(&files[..2], &encrypted),
+            vec![
+                "--encrypt",
+                "0x1000",
+                "bootloader/bootloader.bin",
+                "0x10000",
+                "app.bin"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_verify_output() {
+        let output = "Verifying 0x6c10 (27664) bytes @ 0x00001000 in flash against bootloader/bootloader.bin...\n\
+            -- verify OK (digest matched)\n\
+            Verifying 0xc00 (3072) bytes @ 0x00008000 in flash against partition_table/partition-table.bin...\n\
+            -- verify FAILED (digest mismatch)\n\
+            Verifying 0x2f4b0 (193712) bytes @ 0x00010000 in flash against app.bin...\n";
+        let results = parse_verify_output(output);
+        assert_eq!(results.get(&0x1000), Some(&true));
+        assert_eq!(results.get(&0x8000), Some(&false));
+        assert_eq!(results.get(&0x10000), None);
+    }
+
+    #[test]
+    fn test_parse_flash_number() {
+        assert_eq!(parse_flash_number("0x9000", "address").unwrap(), 0x9000);
+        assert_eq!(parse_flash_number("24K", "size").unwrap(), 0x6000);
+        assert_eq!(parse_flash_number("4MB", "size").unwrap(), 0x400000);
+        assert!(parse_flash_number("nvs", "address").is_err());
+    }
+
+    #[test]
+    fn test_flash_progress() {
+        let region = |size| FlashRegion {
+            file: String::new(),
+            size,
+            sha256: String::new(),
+            done: false,
+            written_until: None,
+        };
+        let mut progress = FlashProgress::default();
+        progress.regions.insert(0x1000, region(0x6000));
+        progress.regions.insert(0x10000, region(0x80000));
+
+        assert!(progress.observe("Writing at 0x00001000... (50 %)"));
+        assert!(!progress.observe("Wrote 26384 bytes (16543 compressed) at 0x00001000 in 0.8 seconds (effective 259.5 kbit/s)..."));
+        assert!(progress.observe("Hash of data verified."));
+        assert!(progress.observe("Writing at 0x00024000... (12 %)\r"));
+
+        assert!(progress.regions[&0x1000].done);
+        assert_eq!(progress.regions[&0x10000].written_until, Some(0x24000));
+        assert_eq!(progress.completed(), 1);
+        assert!(!progress.observe("Hash of data verified."));
+
+        assert!(!progress.link_failure);
+        progress.observe("A fatal error occurred: Timed out waiting for packet header");
+        assert!(progress.link_failure);
+    }
+}
@@ -0,0 +1,254 @@
+use crate::fleet::{self, Device};
+use crate::{config, utils, Cli};
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+/// What to run on each selected device after the variants are built
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FleetOperation {
+    Build,
+    Flash { monitor: bool },
+    Monitor,
+}
+
+fn variant_build_dir(project_dir: &Path, variant: &str) -> PathBuf {
+    project_dir.join(format!("build.{}", variant))
+}
+
+/// CMake cache entries selecting the device's target and sdkconfig profile
+fn variant_defines(project_dir: &Path, build_dir: &Path, device: &Device) -> Result<Vec<String>> {
+    let mut defines = vec![
+        format!("IDF_TARGET={}", device.target),
+        // Each variant keeps its own sdkconfig so variants don't overwrite each other
+        format!("SDKCONFIG={}", build_dir.join("sdkconfig").display()),
+    ];
+
+    // The device profile is applied on top of the project's defaults
+    let mut defaults = config::get_sdkconfig_defaults(project_dir, &[]);
+    if let Some(profile) = &device.profile {
+        let profile = project_dir.join(profile);
+        if !profile.exists() {
+            return Err(anyhow::anyhow!(
+                "Profile {} of device '{}' does not exist",
+                profile.display(),
+                device.name
+            ));
+        }
+        defaults.push(profile);
+    }
+    if !defaults.is_empty() {
+        defines.push(format!(
+            "SDKCONFIG_DEFAULTS={}",
+            defaults
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(";")
+        ));
+    }
+
+    Ok(defines)
+}
+
+/// Run idf-rs for one device, streaming its output prefixed with the device name to
+/// stdout and to the device's log file
+pub async fn run_device(
+    exe: PathBuf,
+    args: Vec<String>,
+    name: String,
+    log_path: PathBuf,
+) -> Result<()> {
+    let mut log = tokio::fs::File::create(&log_path).await?;
+
+    let mut child = tokio::process::Command::new(exe)
+        .args(&args)
+        .envs(utils::CommandEnv::new().vars())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let mut stdout = BufReader::new(child.stdout.take().unwrap()).lines();
+    let mut stderr = BufReader::new(child.stderr.take().unwrap()).lines();
+    let mut stdout_open = true;
+    let mut stderr_open = true;
+
+    while stdout_open || stderr_open {
+        let line = tokio::select! {
+            line = stdout.next_line(), if stdout_open => {
+                let line = line?;
+                stdout_open = line.is_some();
+                line
+            }
+            line = stderr.next_line(), if stderr_open => {
+                let line = line?;
+                stderr_open = line.is_some();
+                line
+            }
+        };
+
+        if let Some(line) = line {
+            println!("[{}] {}", name, line);
+            log.write_all(line.as_bytes()).await?;
+            log.write_all(b"\n").await?;
+        }
+    }
+
+    let status = child.wait().await?;
+    if !status.success() {
+        return Err(anyhow::anyhow!(
+            "exited with {} (log: {})",
+            status,
+            log_path.display()
+        ));
+    }
+
+    Ok(())
+}
+
+pub async fn execute(
+    cli: &Cli,
+    operation: FleetOperation,
+    group: Option<&str>,
+    names: &[String],
+) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let devices = fleet::load_inventory(&project_dir)?;
+    let selected = fleet::select(&devices, group, names);
+
+    if selected.is_empty() {
+        return Err(anyhow::anyhow!(
+            "No devices in {} match the selection",
+            fleet::INVENTORY_FILE
+        ));
+    }
+
+    // Build every variant once; builds already use all cores so they run one after another
+    if operation != FleetOperation::Monitor {
+        let mut variants: BTreeMap<String, &Device> = BTreeMap::new();
+        for device in &selected {
+            variants.entry(device.variant()).or_insert(device);
+        }
+
+        for (variant, device) in &variants {
+            println!("=== Building variant {} ===", variant);
+            let build_dir = variant_build_dir(&project_dir, variant);
+            let defines = variant_defines(&project_dir, &build_dir, device)?;
+
+            let mut variant_cli = cli.clone();
+            variant_cli.build_dir = Some(build_dir);
+            crate::commands::build::execute_variant(&variant_cli, &defines).await?;
+        }
+    }
+
+    let commands: &[&str] = match operation {
+        FleetOperation::Build => return Ok(()),
+        FleetOperation::Flash { monitor: false } => &["flash"],
+        FleetOperation::Flash { monitor: true } => &["flash", "monitor"],
+        FleetOperation::Monitor => &["monitor"],
+    };
+
+    let log_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir).join("fleet-logs");
+    std::fs::create_dir_all(&log_dir)?;
+    let exe = std::env::current_exe()?;
+
+    println!(
+        "=== Running '{}' on {} device(s), logs in {} ===",
+        commands.join(" "),
+        selected.len(),
+        log_dir.display()
+    );
+
+    let mut tasks = Vec::new();
+    for device in &selected {
+        let mut args = vec![
+            "-C".to_string(),
+            project_dir.display().to_string(),
+            "-B".to_string(),
+            variant_build_dir(&project_dir, &device.variant())
+                .display()
+                .to_string(),
+        ];
+        match (&device.port, &device.serial) {
+            (Some(port), _) => args.extend(["-p".to_string(), port.clone()]),
+            (None, Some(serial)) => args.extend(["--device-id".to_string(), serial.clone()]),
+            (None, None) => unreachable!("devices are validated when loading the inventory"),
+        }
+        if let Some(baud) = device.baud.or(cli.baud) {
+            args.extend(["-b".to_string(), baud.to_string()]);
+        }
+        if cli.verbose {
+            args.push("-v".to_string());
+        }
+        args.extend(commands.iter().map(|command| command.to_string()));
+
+        let log_path = log_dir.join(format!("{}.log", device.name));
+        let name = device.name.clone();
+        tasks.push((
+            device.name.clone(),
+            tokio::spawn(run_device(exe.clone(), args, name, log_path)),
+        ));
+    }
+
+    let mut failed = 0;
+    let mut results = Vec::new();
+    for (name, task) in tasks {
+        let result = task.await?;
+        if result.is_err() {
+            failed += 1;
+        }
+        results.push((name, result));
+    }
+
+    println!("=== Fleet summary ===");
+    for (name, result) in &results {
+        match result {
+            Ok(()) => println!("  ✓ {}", name),
+            Err(e) => println!("  ✗ {}: {}", name, e),
+        }
+    }
+
+    if failed > 0 {
+        return Err(anyhow::anyhow!(
+            "{} of {} device(s) failed",
+            failed,
+            results.len()
+        ));
+    }
+
+    Ok(())
+}
+
+pub async fn execute_list(cli: &Cli, group: Option<&str>) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let devices = fleet::load_inventory(&project_dir)?;
+
+    println!(
+        "{:<16} {:<24} {:<20} GROUPS",
+        "NAME", "PORT/SERIAL", "VARIANT"
+    );
+    for device in fleet::select(&devices, group, &[]) {
+        let location = device
+            .port
+            .clone()
+            .or_else(|| {
+                device
+                    .serial
+                    .as_ref()
+                    .map(|serial| format!("serial:{}", serial))
+            })
+            .unwrap_or_default();
+        println!(
+            "{:<16} {:<24} {:<20} {}",
+            device.name,
+            location,
+            device.variant(),
+            device.groups.join(",")
+        );
+    }
+
+    Ok(())
+}
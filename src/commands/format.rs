@@ -0,0 +1,104 @@
+use crate::{build_systems, utils, Cli};
+use anyhow::Result;
+use std::fs;
+use std::path::Path;
+
+/// Directories with the project's own sources
+const SOURCE_DIRS: &[&str] = &["main", "components"];
+
+/// Directories that are never formatted
+const EXCLUDED_DIRS: &[&str] = &["build", "managed_components"];
+
+const SOURCE_EXTENSIONS: &[&str] = &["c", "h", "cpp", "hpp", "cc", "cxx", "hh"];
+
+/// Number of files passed to a single clang-format invocation
+const FILES_PER_INVOCATION: usize = 100;
+
+const DEFAULT_CLANG_FORMAT: &str = r#"# Default style created by idf-rs, close to the ESP-IDF coding style
+BasedOnStyle: LLVM
+IndentWidth: 4
+ColumnLimit: 120
+BreakBeforeBraces: Linux
+IndentCaseLabels: true
+AllowShortIfStatementsOnASingleLine: false
+AllowShortFunctionsOnASingleLine: None
+SortIncludes: false
+"#;
+
+fn is_source_file(path: &Path) -> bool {
+    path.extension()
+        .map(|extension| SOURCE_EXTENSIONS.contains(&extension.to_string_lossy().as_ref()))
+        .unwrap_or(false)
+}
+
+pub async fn execute(cli: &Cli, check: bool) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+
+    if !build_systems::executable_exists(&["clang-format".to_string(), "--version".to_string()]) {
+        return Err(anyhow::anyhow!(
+            "clang-format not found in PATH. Install it (e.g. 'idf_tools.py install esp-clang') first."
+        ));
+    }
+
+    let clang_format_path = project_dir.join(".clang-format");
+    if !clang_format_path.exists() {
+        fs::write(&clang_format_path, DEFAULT_CLANG_FORMAT)?;
+        println!(
+            "Created default style file: {}",
+            clang_format_path.display()
+        );
+    }
+
+    let mut files = Vec::new();
+    for dir in SOURCE_DIRS {
+        let dir = project_dir.join(dir);
+        if dir.is_dir() {
+            utils::find_files(&dir, EXCLUDED_DIRS, &is_source_file, &mut files)?;
+        }
+    }
+    files.sort();
+
+    if files.is_empty() {
+        println!("No source files found to format.");
+        return Ok(());
+    }
+
+    if check {
+        println!("Checking formatting of {} files...", files.len());
+    } else {
+        println!("Formatting {} files...", files.len());
+    }
+
+    let mode_args: &[&str] = if check {
+        &["--dry-run", "--Werror"]
+    } else {
+        &["-i"]
+    };
+
+    for chunk in files.chunks(FILES_PER_INVOCATION) {
+        let mut format_args = mode_args.to_vec();
+        format_args.extend(chunk.iter().map(|file| file.to_str().unwrap()));
+
+        let result = utils::run_command(
+            "clang-format",
+            &format_args,
+            Some(&project_dir),
+            cli.verbose,
+        )
+        .await;
+
+        if check && result.is_err() {
+            return Err(anyhow::anyhow!(
+                "Some files are not formatted correctly. Run 'idf-rs format' to fix them."
+            ));
+        }
+        result?;
+    }
+
+    if check {
+        println!("All files are formatted correctly!");
+    } else {
+        println!("Format completed successfully!");
+    }
+    Ok(())
+}
@@ -0,0 +1,162 @@
+use crate::heap::{self, HeapSnapshot};
+use crate::Cli;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::Path;
+
+fn read_log(file: &Path) -> Result<String> {
+    if file == Path::new("-") {
+        let mut content = String::new();
+        std::io::stdin().read_to_string(&mut content)?;
+        return Ok(content);
+    }
+
+    // Monitor logs may contain invalid UTF-8 from garbled serial output
+    let bytes = std::fs::read(file)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", file.display(), e))?;
+    Ok(String::from_utf8_lossy(&bytes).to_string())
+}
+
+fn format_time(snapshot: &HeapSnapshot) -> String {
+    snapshot
+        .time_ms
+        .map(|time| time.to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+fn print_summary(caps: &str, snapshots: &[(usize, &HeapSnapshot)]) {
+    println!("Heap capabilities {}:", caps);
+    println!(
+        "  {:>4} {:>10} {:>10} {:>10} {:>10} {:>10} {:>6}",
+        "#", "TIME(ms)", "TOTAL", "FREE", "MIN_FREE", "LARGEST", "FRAG"
+    );
+    for (index, snapshot) in snapshots {
+        println!(
+            "  {:>4} {:>10} {:>10} {:>10} {:>10} {:>10} {:>5.1}%",
+            index,
+            format_time(snapshot),
+            snapshot.total(),
+            snapshot.free,
+            snapshot.min_free,
+            snapshot.largest_free_block,
+            snapshot.fragmentation() * 100.0
+        );
+    }
+
+    if let (Some((_, first)), Some((_, last))) = (snapshots.first(), snapshots.last()) {
+        if snapshots.len() > 1 {
+            let change = last.free as i64 - first.free as i64;
+            println!(
+                "  Free memory changed by {:+} bytes over {} snapshots, lowest min_free {} bytes",
+                change,
+                snapshots.len(),
+                snapshots
+                    .iter()
+                    .map(|(_, snapshot)| snapshot.min_free)
+                    .min()
+                    .unwrap_or(0)
+            );
+        }
+
+        println!("  Regions in the latest snapshot:");
+        println!(
+            "    {:<12} {:>8} {:>8} {:>6} {:>10} {:>11} {:>6}",
+            "ADDRESS", "SIZE", "FREE", "USED", "LARGEST", "FREE_BLOCKS", "FRAG"
+        );
+        for region in &last.regions {
+            let used = if region.len == 0 {
+                0.0
+            } else {
+                region.allocated as f64 / region.len as f64 * 100.0
+            };
+            println!(
+                "    {:<12} {:>8} {:>8} {:>5.1}% {:>10} {:>11} {:>5.1}%",
+                format!("0x{:08x}", region.address),
+                region.len,
+                region.free,
+                used,
+                region.largest_free_block,
+                region.free_blocks,
+                region.fragmentation() * 100.0
+            );
+        }
+    }
+    println!();
+}
+
+fn write_csv(path: &Path, snapshots: &[HeapSnapshot]) -> Result<()> {
+    let mut csv = String::from(
+        "snapshot,time_ms,caps,region,len,free,allocated,min_free,largest_free_block,free_blocks,fragmentation\n",
+    );
+
+    for (index, snapshot) in snapshots.iter().enumerate() {
+        let time = snapshot
+            .time_ms
+            .map(|time| time.to_string())
+            .unwrap_or_default();
+        for region in &snapshot.regions {
+            csv.push_str(&format!(
+                "{},{},{},0x{:08x},{},{},{},{},{},{},{:.4}\n",
+                index,
+                time,
+                snapshot.caps,
+                region.address,
+                region.len,
+                region.free,
+                region.allocated,
+                region.min_free,
+                region.largest_free_block,
+                region.free_blocks,
+                region.fragmentation()
+            ));
+        }
+        csv.push_str(&format!(
+            "{},{},{},total,{},{},{},{},{},,{:.4}\n",
+            index,
+            time,
+            snapshot.caps,
+            snapshot.total(),
+            snapshot.free,
+            snapshot.allocated,
+            snapshot.min_free,
+            snapshot.largest_free_block,
+            snapshot.fragmentation()
+        ));
+    }
+
+    std::fs::write(path, csv)?;
+    Ok(())
+}
+
+pub async fn execute(_cli: &Cli, file: &Path, csv: Option<&Path>) -> Result<()> {
+    let log = read_log(file)?;
+    let snapshots = heap::parse_heap_info(&log);
+
+    if snapshots.is_empty() {
+        return Err(anyhow::anyhow!(
+            "No heap info found. Capture the output of heap_caps_print_heap_info() from the device (e.g. with 'idf-rs monitor | tee heap.log')."
+        ));
+    }
+
+    println!("Found {} heap snapshot(s)\n", snapshots.len());
+
+    let mut by_caps: BTreeMap<&str, Vec<(usize, &HeapSnapshot)>> = BTreeMap::new();
+    for (index, snapshot) in snapshots.iter().enumerate() {
+        by_caps
+            .entry(snapshot.caps.as_str())
+            .or_default()
+            .push((index, snapshot));
+    }
+
+    for (caps, snapshots) in &by_caps {
+        print_summary(caps, snapshots);
+    }
+
+    if let Some(csv) = csv {
+        write_csv(csv, &snapshots)?;
+        println!("CSV written to: {}", csv.display());
+    }
+
+    Ok(())
+}
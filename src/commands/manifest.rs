@@ -0,0 +1,565 @@
+use crate::{utils, yaml, Cli};
+use anyhow::Result;
+use serde_json::Value;
+use std::fmt;
+use std::path::Path;
+
+const MANIFEST_FILE: &str = "idf_component.yml";
+
+/// Directories that never contain the project's own manifests
+const EXCLUDED_DIRS: &[&str] = &["build", "managed_components"];
+
+const TOP_LEVEL_KEYS: &[&str] = &[
+    "version",
+    "description",
+    "url",
+    "repository",
+    "repository_info",
+    "documentation",
+    "issues",
+    "discussion",
+    "license",
+    "maintainers",
+    "tags",
+    "targets",
+    "dependencies",
+    "files",
+    "examples",
+    "require",
+];
+
+const DEPENDENCY_KEYS: &[&str] = &[
+    "version",
+    "path",
+    "git",
+    "override_path",
+    "rules",
+    "matches",
+    "public",
+    "require",
+    "pre_release",
+    "service_url",
+    "registry_url",
+];
+
+const VERSION_OPERATORS: &[&str] = &[">=", "<=", "==", "!=", "~=", ">", "<", "~", "^", "="];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Issue {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Error => write!(f, "error: {}", self.message),
+            Severity::Warning => write!(f, "warning: {}", self.message),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Issues(Vec<Issue>);
+
+impl Issues {
+    fn error(&mut self, message: String) {
+        self.0.push(Issue {
+            severity: Severity::Error,
+            message,
+        });
+    }
+
+    fn warning(&mut self, message: String) {
+        self.0.push(Issue {
+            severity: Severity::Warning,
+            message,
+        });
+    }
+}
+
+/// Check a version such as `1.2.3`, `1.2.3-rc.1` or `1.2.3~1`. Missing minor and patch
+/// components and `*` wildcards are allowed in constraints but not in the manifest version.
+fn is_valid_version(version: &str, allow_partial: bool) -> bool {
+    let (version, revision) = match version.split_once('~') {
+        Some((version, revision)) => (version, Some(revision)),
+        None => (version, None),
+    };
+    if let Some(revision) = revision {
+        if revision.is_empty() || !revision.chars().all(|c| c.is_ascii_digit()) {
+            return false;
+        }
+    }
+
+    let core = version
+        .split_once('+')
+        .map(|(core, _)| core)
+        .unwrap_or(version);
+    let core = core.split_once('-').map(|(core, _)| core).unwrap_or(core);
+
+    let parts: Vec<&str> = core.split('.').collect();
+    if parts.len() > 3 || (!allow_partial && parts.len() != 3) {
+        return false;
+    }
+
+    parts.iter().all(|part| {
+        (!part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+            || (allow_partial && *part == "*")
+    })
+}
+
+/// Check a version constraint such as `>=4.4,<6.0`, `~1.2.0`, `^2` or `*`
+fn validate_version_spec(spec: &str) -> std::result::Result<(), String> {
+    if spec.trim().is_empty() {
+        return Err("empty version constraint".to_string());
+    }
+
+    for clause in spec.split(',') {
+        let clause = clause.trim();
+        if clause == "*" {
+            continue;
+        }
+
+        let version = VERSION_OPERATORS
+            .iter()
+            .find_map(|op| clause.strip_prefix(op))
+            .unwrap_or(clause)
+            .trim();
+
+        if !is_valid_version(version, true) {
+            return Err(format!("invalid version constraint '{}'", clause));
+        }
+    }
+
+    Ok(())
+}
+
+/// Parse a bracketed target list such as `[esp32, "esp32s3"]`
+fn parse_list(text: &str) -> Option<Vec<String>> {
+    let inner = text.trim().strip_prefix('[')?.strip_suffix(']')?;
+    Some(
+        inner
+            .split(',')
+            .map(|item| {
+                item.trim()
+                    .trim_matches(|c| c == '"' || c == '\'')
+                    .to_string()
+            })
+            .filter(|item| !item.is_empty())
+            .collect(),
+    )
+}
+
+/// Split a condition on `&&` / `||` (and the `and` / `or` keywords) into simple clauses
+fn split_clauses(condition: &str) -> Vec<String> {
+    let condition = condition.replace(['(', ')'], " ");
+    let mut clauses = vec![String::new()];
+    let mut in_list = false;
+
+    for word in condition.split_whitespace() {
+        match word {
+            "&&" | "||" | "and" | "or" if !in_list => {
+                clauses.push(String::new());
+                continue;
+            }
+            _ => {}
+        }
+        in_list = (in_list || word.contains('[')) && !word.contains(']');
+
+        let current = clauses.last_mut().unwrap();
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+
+    clauses
+}
+
+/// Validate an `if:` expression of a dependency rule, e.g. `target in [esp32, esp32s3]`
+fn validate_condition(condition: &str, issues: &mut Issues, context: &str) {
+    for clause in split_clauses(condition) {
+        if clause.is_empty() {
+            issues.error(format!("{}: empty clause in '{}'", context, condition));
+            continue;
+        }
+
+        let (left, rest) = if clause.starts_with("$CONFIG{") {
+            match clause.find('}') {
+                Some(end) => clause.split_at(end + 1),
+                None => {
+                    issues.error(format!(
+                        "{}: unterminated $CONFIG{{ in '{}'",
+                        context, clause
+                    ));
+                    continue;
+                }
+            }
+        } else {
+            let end = clause
+                .find(|c: char| c.is_whitespace() || "<>=!~^".contains(c))
+                .unwrap_or(clause.len());
+            clause.split_at(end)
+        };
+        let rest = rest.trim();
+
+        let (op, value) = if let Some(value) = rest.strip_prefix("not in ") {
+            ("not in", value)
+        } else if let Some(value) = rest.strip_prefix("in ") {
+            ("in", value)
+        } else {
+            match VERSION_OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+                Some(op) => (*op, &rest[op.len()..]),
+                None => {
+                    issues.error(format!(
+                        "{}: missing comparison operator in '{}'",
+                        context, clause
+                    ));
+                    continue;
+                }
+            }
+        };
+        let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+
+        match left {
+            "idf_version" => {
+                if op == "in" || op == "not in" {
+                    issues.error(format!(
+                        "{}: '{}' is not supported for idf_version",
+                        context, op
+                    ));
+                } else if let Err(e) = validate_version_spec(&format!("{}{}", op, value)) {
+                    issues.error(format!("{}: {}", context, e));
+                }
+            }
+            "target" => {
+                let targets = match op {
+                    "==" | "!=" => vec![value.to_string()],
+                    "in" | "not in" => match parse_list(value) {
+                        Some(targets) => targets,
+                        None => {
+                            issues.error(format!(
+                                "{}: '{}' expects a list like [esp32, esp32s3]",
+                                context, op
+                            ));
+                            continue;
+                        }
+                    },
+                    _ => {
+                        issues.error(format!(
+                            "{}: operator '{}' is not supported for target",
+                            context, op
+                        ));
+                        continue;
+                    }
+                };
+                for target in targets {
+                    if !utils::SUPPORTED_TARGETS.contains(&target.as_str()) {
+                        issues.warning(format!("{}: unknown target '{}'", context, target));
+                    }
+                }
+            }
+            left if left.starts_with("$CONFIG{") => {}
+            _ => issues.error(format!(
+                "{}: unknown condition subject '{}' (expected idf_version, target or $CONFIG{{...}})",
+                context, left
+            )),
+        }
+    }
+}
+
+fn validate_rules(value: &Value, issues: &mut Issues, context: &str) {
+    let rules = match value.as_array() {
+        Some(rules) => rules,
+        None => {
+            issues.error(format!("{}: must be a list of {{if: ...}} items", context));
+            return;
+        }
+    };
+
+    for (index, rule) in rules.iter().enumerate() {
+        let context = format!("{}[{}]", context, index);
+        match rule.get("if").and_then(Value::as_str) {
+            Some(condition) => validate_condition(condition, issues, &context),
+            None => issues.error(format!("{}: missing 'if' expression", context)),
+        }
+    }
+}
+
+fn validate_dependency(name: &str, value: &Value, issues: &mut Issues) {
+    let context = format!("dependencies.{}", name);
+
+    let map = match value {
+        Value::String(spec) => {
+            if let Err(e) = validate_version_spec(spec) {
+                issues.error(format!("{}: {}", context, e));
+            }
+            return;
+        }
+        Value::Object(map) => map,
+        Value::Null => {
+            issues.warning(format!(
+                "{}: no version given, any version will be used",
+                context
+            ));
+            return;
+        }
+        _ => {
+            issues.error(format!(
+                "{}: must be a version string or a mapping",
+                context
+            ));
+            return;
+        }
+    };
+
+    if name == "idf" {
+        issues.error(format!(
+            "{}: must be a version string such as '>=5.0'",
+            context
+        ));
+        return;
+    }
+
+    for key in map.keys() {
+        if !DEPENDENCY_KEYS.contains(&key.as_str()) {
+            issues.warning(format!("{}: unknown key '{}'", context, key));
+        }
+    }
+
+    if let Some(version) = map.get("version") {
+        match version.as_str() {
+            Some(spec) => {
+                if let Err(e) = validate_version_spec(spec) {
+                    issues.error(format!("{}.version: {}", context, e));
+                }
+            }
+            None => issues.error(format!("{}.version: must be a string", context)),
+        }
+    }
+
+    let is_local = map.contains_key("path") || map.contains_key("override_path");
+    if is_local && map.contains_key("git") {
+        issues.warning(format!(
+            "{}: both 'path' and 'git' are set, 'path' takes precedence",
+            context
+        ));
+    }
+    if !is_local && !map.contains_key("version") && !map.contains_key("git") {
+        issues.warning(format!(
+            "{}: no version constraint, builds may pick up breaking releases",
+            context
+        ));
+    }
+    if !is_local && !map.contains_key("git") && !name.contains('/') {
+        issues.warning(format!(
+            "{}: registry components are usually namespaced, e.g. 'espressif/{}'",
+            context, name
+        ));
+    }
+
+    for key in ["rules", "matches"] {
+        if let Some(rules) = map.get(key) {
+            validate_rules(rules, issues, &format!("{}.{}", context, key));
+        }
+    }
+}
+
+/// Validate the content of an idf_component.yml file
+pub fn lint(content: &str) -> Vec<Issue> {
+    let mut issues = Issues::default();
+
+    let manifest = match yaml::parse(content) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            issues.error(format!("invalid YAML: {}", e));
+            return issues.0;
+        }
+    };
+
+    let map = match manifest {
+        Value::Object(map) => map,
+        Value::Null => return issues.0,
+        _ => {
+            issues.error("the manifest must be a mapping".to_string());
+            return issues.0;
+        }
+    };
+
+    for key in map.keys() {
+        if !TOP_LEVEL_KEYS.contains(&key.as_str()) {
+            issues.warning(format!("unknown top-level key '{}'", key));
+        }
+    }
+
+    if let Some(version) = map.get("version") {
+        match version.as_str() {
+            Some(version) if is_valid_version(version, false) => {}
+            _ => issues.error(format!(
+                "version: '{}' is not a valid semantic version (expected e.g. 1.0.0)",
+                version.as_str().unwrap_or(&version.to_string())
+            )),
+        }
+    }
+
+    if let Some(targets) = map.get("targets") {
+        match targets.as_array() {
+            Some(targets) => {
+                for target in targets {
+                    match target.as_str() {
+                        Some(target) if utils::SUPPORTED_TARGETS.contains(&target) => {}
+                        Some(target) => {
+                            issues.warning(format!("targets: unknown target '{}'", target))
+                        }
+                        None => issues.error("targets: entries must be strings".to_string()),
+                    }
+                }
+            }
+            None => issues.error("targets: must be a list".to_string()),
+        }
+    }
+
+    match map.get("dependencies") {
+        Some(Value::Object(dependencies)) => {
+            for (name, value) in dependencies {
+                validate_dependency(name, value, &mut issues);
+            }
+        }
+        Some(Value::Null) | None => {}
+        Some(_) => issues.error("dependencies: must be a mapping".to_string()),
+    }
+
+    issues.0
+}
+
+fn is_manifest(path: &Path) -> bool {
+    path.file_name()
+        .map(|name| name == MANIFEST_FILE)
+        .unwrap_or(false)
+}
+
+pub async fn execute_lint(cli: &Cli) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+
+    let mut manifests = Vec::new();
+    utils::find_files(&project_dir, EXCLUDED_DIRS, &is_manifest, &mut manifests)?;
+    manifests.sort();
+
+    if manifests.is_empty() {
+        println!("No {} files found.", MANIFEST_FILE);
+        return Ok(());
+    }
+
+    let mut errors = 0;
+    let mut warnings = 0;
+
+    for manifest in &manifests {
+        let content = std::fs::read_to_string(manifest)?;
+        let issues = lint(&content);
+        let display_path = manifest.strip_prefix(&project_dir).unwrap_or(manifest);
+
+        if issues.is_empty() {
+            if cli.verbose {
+                println!("{}: OK", display_path.display());
+            }
+            continue;
+        }
+
+        for issue in &issues {
+            println!("{}: {}", display_path.display(), issue);
+            match issue.severity {
+                Severity::Error => errors += 1,
+                Severity::Warning => warnings += 1,
+            }
+        }
+    }
+
+    println!(
+        "Checked {} manifest(s): {} error(s), {} warning(s)",
+        manifests.len(),
+        errors,
+        warnings
+    );
+
+    if errors > 0 {
+        return Err(anyhow::anyhow!("Manifest lint found {} error(s)", errors));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(content: &str) -> Vec<String> {
+        lint(content)
+            .iter()
+            .map(|issue| issue.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_manifest() {
+        let content = r#"
+version: "1.2.0"
+targets: [esp32, esp32s3]
+dependencies:
+  idf: ">=4.4,<6.0"
+  espressif/button:
+    version: "^3.0"
+    rules:
+      - if: "target in [esp32, esp32s3]"
+      - if: "idf_version >=5.0 && $CONFIG{BSP_ENABLED} == True"
+  local_component:
+    path: ../local_component
+"#;
+        assert_eq!(messages(content), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_invalid_manifest() {
+        let content = r#"
+version: 1.2
+targets:
+  - esp32
+  - esp33
+dependencies:
+  idf:
+    version: ">=5.0"
+  espressif/led_strip: ">=2.x"
+  espressif/button:
+    version: "~3.0"
+    git: https://github.com/espressif/button.git
+    path: ../button
+    rules:
+      - if: "target = esp32"
+      - if: "chip == esp32"
+"#;
+        let messages = messages(content);
+
+        assert!(messages.contains(
+            &"error: version: '1.2' is not a valid semantic version (expected e.g. 1.0.0)"
+                .to_string()
+        ));
+        assert!(messages.contains(&"warning: targets: unknown target 'esp33'".to_string()));
+        assert!(messages.contains(
+            &"error: dependencies.idf: must be a version string such as '>=5.0'".to_string()
+        ));
+        assert!(messages.contains(
+            &"error: dependencies.espressif/led_strip: invalid version constraint '>=2.x'"
+                .to_string()
+        ));
+        assert!(messages.contains(&"warning: dependencies.espressif/button: both 'path' and 'git' are set, 'path' takes precedence".to_string()));
+        assert!(messages.contains(&"error: dependencies.espressif/button.rules[0]: operator '=' is not supported for target".to_string()));
+        assert!(messages
+            .iter()
+            .any(|message| message.contains("unknown condition subject 'chip'")));
+    }
+}
@@ -0,0 +1,327 @@
+use crate::config;
+use crate::kconfig::{self, KconfigTree, Symbol, SymbolType};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+
+/// Entries of a menu: its options (with a prompt) and submenus, in Kconfig order
+enum Entry<'a> {
+    Option(&'a Symbol),
+    Menu(String),
+}
+
+/// What is listed: a menu by its path of titles, or search results
+enum View<'a> {
+    Menu(Vec<String>),
+    Search(String, Vec<&'a Symbol>),
+}
+
+/// A configuration being edited, starting from the values in sdkconfig
+struct Session<'a> {
+    tree: &'a KconfigTree,
+    values: HashMap<String, String>,
+    /// Changed options in the order they were changed, with their sdkconfig values
+    changes: Vec<(String, String)>,
+}
+
+impl<'a> Session<'a> {
+    /// Current value of a symbol (without the CONFIG_ prefix), from sdkconfig or else
+    /// its first default that applies
+    fn value(&self, name: &str) -> Option<String> {
+        if let Some(value) = self.values.get(&format!("CONFIG_{}", name)) {
+            return Some(value.clone());
+        }
+        let symbol = self.tree.get(name)?;
+        let default = symbol.defaults.iter().find(|default| {
+            default
+                .condition
+                .as_deref()
+                .is_none_or(|condition| kconfig::evaluate(condition, &|name| self.value(name)))
+        })?;
+        // Bool defaults may name another symbol
+        match symbol.kind {
+            Some(SymbolType::Bool | SymbolType::Tristate)
+                if !matches!(default.value.as_str(), "y" | "m" | "n") =>
+            {
+                let enabled = kconfig::evaluate(&default.value, &|name| self.value(name));
+                Some(if enabled { "y" } else { "n" }.to_string())
+            }
+            _ => Some(default.value.clone()),
+        }
+    }
+
+    fn is_visible(&self, symbol: &Symbol) -> bool {
+        symbol.prompt.is_some()
+            && symbol
+                .depends_on
+                .iter()
+                .all(|condition| kconfig::evaluate(condition, &|name| self.value(name)))
+    }
+
+    fn set(&mut self, symbol: &Symbol, value: String) {
+        let name = format!("CONFIG_{}", symbol.name);
+        let sdkconfig_value = symbol.sdkconfig_value(&value);
+        self.changes.retain(|(option, _)| *option != name);
+        self.changes.push((name.clone(), sdkconfig_value));
+        self.values.insert(name, value);
+    }
+
+    /// Select an option of a choice, deselecting the others
+    fn select(&mut self, symbol: &Symbol) {
+        let others: Vec<&Symbol> = self
+            .tree
+            .symbols
+            .iter()
+            .filter(|other| other.choice == symbol.choice && other.name != symbol.name)
+            .collect();
+        for other in others {
+            if self.value(&other.name).as_deref() == Some("y") {
+                self.set(other, "n".to_string());
+            }
+        }
+        self.set(symbol, "y".to_string());
+    }
+
+    fn entries(&self, path: &[String]) -> Vec<Entry<'a>> {
+        let mut entries = Vec::new();
+        let mut menus: Vec<&String> = Vec::new();
+        for symbol in &self.tree.symbols {
+            if !symbol.menu.starts_with(path) || !self.is_visible(symbol) {
+                continue;
+            }
+            match symbol.menu.get(path.len()) {
+                None => entries.push(Entry::Option(symbol)),
+                Some(title) if !menus.contains(&title) => {
+                    menus.push(title);
+                    entries.push(Entry::Menu(title.clone()));
+                }
+                Some(_) => {}
+            }
+        }
+        entries
+    }
+
+    /// The selected option, when the menu at `path` is a choice
+    fn selected(&self, path: &[String]) -> Option<&'a Symbol> {
+        let options: Vec<&Symbol> = self
+            .tree
+            .symbols
+            .iter()
+            .filter(|symbol| symbol.menu == path && symbol.choice.is_some())
+            .collect();
+        options
+            .into_iter()
+            .find(|symbol| self.value(&symbol.name).as_deref() == Some("y"))
+    }
+
+    fn describe(&self, symbol: &Symbol) -> String {
+        let prompt = symbol.prompt.as_deref().unwrap_or(&symbol.name);
+        let value = self.value(&symbol.name).unwrap_or_default();
+        let marker = match symbol.kind {
+            Some(SymbolType::Bool | SymbolType::Tristate) => {
+                let set = if value == "n" || value.is_empty() {
+                    " "
+                } else if value == "m" {
+                    "M"
+                } else if symbol.choice.is_some() {
+                    "X"
+                } else {
+                    "*"
+                };
+                if symbol.choice.is_some() {
+                    format!("({})", set)
+                } else {
+                    format!("[{}]", set)
+                }
+            }
+            _ => format!("({})", value),
+        };
+        format!("{} {}", marker, prompt)
+    }
+
+    fn print_help(&self, symbol: &Symbol) {
+        println!();
+        println!(
+            "CONFIG_{} [{}]",
+            symbol.name,
+            symbol.kind.map(|kind| kind.to_string()).unwrap_or_default()
+        );
+        if let Some(prompt) = &symbol.prompt {
+            let mut location = symbol.menu.clone();
+            location.push(prompt.clone());
+            println!("  Location: {}", location.join(" → "));
+        }
+        println!(
+            "  Value: {}",
+            self.value(&symbol.name).as_deref().unwrap_or("(not set)")
+        );
+        for default in &symbol.defaults {
+            match &default.condition {
+                Some(condition) => println!("  Default: {} if {}", default.value, condition),
+                None => println!("  Default: {}", default.value),
+            }
+        }
+        if let Some((low, high)) = &symbol.range {
+            println!("  Range: {} to {}", low, high);
+        }
+        if !symbol.depends_on.is_empty() {
+            println!("  Depends on: {}", symbol.depends_on.join(" && "));
+        }
+        println!("  Defined at {}:{}", symbol.file, symbol.line);
+        if let Some(help) = &symbol.help {
+            println!();
+            for line in help.lines() {
+                println!("  {}", line);
+            }
+        }
+        println!();
+    }
+
+    /// Toggle a bool, select a choice option or ask for a new value
+    fn change(&mut self, symbol: &'a Symbol) -> Result<()> {
+        match (symbol.kind, &symbol.choice) {
+            (Some(SymbolType::Bool | SymbolType::Tristate), Some(_)) => self.select(symbol),
+            (Some(SymbolType::Bool | SymbolType::Tristate), None) => {
+                let value = match self.value(&symbol.name).as_deref() {
+                    Some("y") | Some("m") => "n",
+                    _ => "y",
+                };
+                self.set(symbol, value.to_string());
+            }
+            _ => {
+                let current = self.value(&symbol.name).unwrap_or_default();
+                let Some(input) = read_line(&format!(
+                    "{} [{}]: ",
+                    symbol.prompt.as_deref().unwrap_or(&symbol.name),
+                    current
+                ))?
+                else {
+                    return Ok(());
+                };
+                if input.is_empty() {
+                    return Ok(());
+                }
+                match symbol.parse_value(&input, &|name| self.value(name)) {
+                    Ok(value) => self.set(symbol, value),
+                    Err(e) => println!("{}", e),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read_line(prompt: &str) -> Result<Option<String>> {
+    print!("{}", prompt);
+    std::io::stdout().flush()?;
+    let mut line = String::new();
+    if std::io::stdin().read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim().to_string()))
+}
+
+/// Edit sdkconfig with a menu driven from the Kconfig tree parsed in Rust, without the
+/// Python and curses stack. Returns the changed options with their sdkconfig values,
+/// leaving writing them to the caller.
+pub fn run(build_dir: &Path, sdkconfig_path: &Path) -> Result<Vec<(String, String)>> {
+    let (tree, _) = kconfig::load(build_dir)?;
+    let content = std::fs::read_to_string(sdkconfig_path).unwrap_or_default();
+    let mut session = Session {
+        tree: &tree,
+        values: config::parse_option_values(&content),
+        changes: Vec::new(),
+    };
+
+    let mut view = View::Menu(Vec::new());
+    loop {
+        println!();
+        let entries = match &view {
+            View::Menu(path) => {
+                println!(
+                    "=== {} ===",
+                    path.last().map(String::as_str).unwrap_or("Configuration")
+                );
+                session.entries(path)
+            }
+            View::Search(pattern, results) => {
+                println!("=== Options matching '{}' ===", pattern);
+                results.iter().map(|symbol| Entry::Option(symbol)).collect()
+            }
+        };
+        for (index, entry) in entries.iter().enumerate() {
+            match (entry, &view) {
+                (Entry::Option(symbol), View::Search(..)) => println!(
+                    "{:>4}  {}  (CONFIG_{}, {})",
+                    index + 1,
+                    session.describe(symbol),
+                    symbol.name,
+                    symbol.menu.join(" → ")
+                ),
+                (Entry::Option(symbol), _) => {
+                    println!("{:>4}  {}", index + 1, session.describe(symbol))
+                }
+                (Entry::Menu(title), View::Menu(path)) => {
+                    let menu = [path.as_slice(), std::slice::from_ref(title)].concat();
+                    match session.selected(&menu) {
+                        Some(selected) => println!(
+                            "{:>4}  {} ({})  --->",
+                            index + 1,
+                            title,
+                            selected.prompt.as_deref().unwrap_or(&selected.name)
+                        ),
+                        None => println!("{:>4}  {}  --->", index + 1, title),
+                    }
+                }
+                (Entry::Menu(_), View::Search(..)) => {}
+            }
+        }
+
+        let Some(input) =
+            read_line("\nNumber to change or open, ?N help, /text search, .. back, q finish: ")?
+        else {
+            break;
+        };
+        match input.as_str() {
+            "q" => break,
+            ".." => {
+                view = match view {
+                    View::Menu(mut path) => {
+                        path.pop();
+                        View::Menu(path)
+                    }
+                    View::Search(..) => View::Menu(Vec::new()),
+                };
+            }
+            _ if input.starts_with('/') => {
+                let pattern = input[1..].trim().to_string();
+                let results = tree
+                    .search(&pattern)
+                    .into_iter()
+                    .filter(|symbol| symbol.prompt.is_some())
+                    .collect();
+                view = View::Search(pattern, results);
+            }
+            _ => {
+                let (help, number) = match input.strip_prefix('?') {
+                    Some(number) => (true, number),
+                    None => (false, input.as_str()),
+                };
+                let entry = number
+                    .trim()
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|number| entries.into_iter().nth(number.checked_sub(1)?));
+                match (entry, &mut view) {
+                    (Some(Entry::Option(symbol)), _) if help => session.print_help(symbol),
+                    (Some(Entry::Option(symbol)), _) => session.change(symbol)?,
+                    (Some(Entry::Menu(title)), View::Menu(path)) => path.push(title),
+                    _ => println!("Unknown selection '{}'", input),
+                }
+            }
+        }
+    }
+
+    Ok(session.changes)
+}
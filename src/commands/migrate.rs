@@ -0,0 +1,378 @@
+use crate::{utils, Cli};
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Result of converting a legacy component to idf_component_register
+#[derive(Debug, Default)]
+struct Migration {
+    cmake: String,
+    warnings: Vec<String>,
+}
+
+/// Arguments of idf_component_register in the order they are written
+const REGISTER_ARGS: &[&str] = &[
+    "SRCS",
+    "SRC_DIRS",
+    "EXCLUDE_SRCS",
+    "INCLUDE_DIRS",
+    "PRIV_INCLUDE_DIRS",
+    "REQUIRES",
+    "PRIV_REQUIRES",
+    "EMBED_FILES",
+    "EMBED_TXTFILES",
+];
+
+/// Builder for the idf_component_register call
+#[derive(Debug, Default)]
+struct Registration {
+    args: BTreeMap<&'static str, Vec<String>>,
+    extra: Vec<String>,
+}
+
+impl Registration {
+    fn add(&mut self, arg: &'static str, values: &[String]) {
+        self.args
+            .entry(arg)
+            .or_default()
+            .extend(values.iter().cloned());
+    }
+
+    fn render(&self) -> String {
+        let mut lines = vec!["idf_component_register(".to_string()];
+        for arg in REGISTER_ARGS {
+            if let Some(values) = self.args.get(arg) {
+                let values: Vec<String> = values.iter().map(|v| format!("\"{}\"", v)).collect();
+                lines.push(format!("    {} {}", arg, values.join(" ")));
+            }
+        }
+        lines.push(")".to_string());
+
+        let mut cmake = lines.join("\n");
+        cmake.push('\n');
+        for extra in &self.extra {
+            cmake.push('\n');
+            cmake.push_str(extra);
+            cmake.push('\n');
+        }
+        cmake
+    }
+}
+
+/// Join line continuations and drop comments from a makefile
+fn makefile_lines(content: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+
+    for line in content.lines() {
+        let line = line.split('#').next().unwrap_or("");
+        if let Some(stripped) = line.trim_end().strip_suffix('\\') {
+            current.push_str(stripped);
+            current.push(' ');
+            continue;
+        }
+
+        current.push_str(line);
+        if !current.trim().is_empty() {
+            lines.push(current.trim().to_string());
+        }
+        current.clear();
+    }
+
+    lines
+}
+
+/// Convert object file names (foo/bar.o) to the source they were built from
+fn objects_to_sources(objects: &[String], component_dir: &Path) -> (Vec<String>, Vec<String>) {
+    let mut sources = Vec::new();
+    let mut unresolved = Vec::new();
+
+    for object in objects {
+        let stem = object.trim_end_matches(".o");
+        let source = ["c", "cpp", "cc", "S"]
+            .iter()
+            .map(|ext| format!("{}.{}", stem, ext))
+            .find(|source| component_dir.join(source).exists());
+
+        match source {
+            Some(source) => sources.push(source),
+            None => {
+                unresolved.push(object.clone());
+                sources.push(format!("{}.c", stem));
+            }
+        }
+    }
+
+    (sources, unresolved)
+}
+
+/// Convert a GNU Make era component.mk
+fn convert_component_mk(content: &str, component_dir: &Path) -> Migration {
+    let mut registration = Registration::default();
+    let mut warnings = Vec::new();
+    let mut has_srcdirs = false;
+    let mut has_includedirs = false;
+    let mut has_objs = false;
+
+    for line in makefile_lines(content) {
+        let assignment = ["+=", ":=", "?=", "="]
+            .iter()
+            .find_map(|op| line.split_once(op).map(|(key, value)| (key.trim(), value)));
+
+        let (key, value) = match assignment {
+            Some(assignment) => assignment,
+            None => {
+                warnings.push(format!("Unsupported construct, migrate manually: {}", line));
+                continue;
+            }
+        };
+        let values: Vec<String> = value.split_whitespace().map(|v| v.to_string()).collect();
+
+        if value.contains("$(") {
+            warnings.push(format!(
+                "Make variable or function in '{}', check the converted value",
+                line
+            ));
+        }
+
+        match key {
+            "COMPONENT_ADD_INCLUDEDIRS" => {
+                has_includedirs = true;
+                registration.add("INCLUDE_DIRS", &values);
+            }
+            "COMPONENT_PRIV_INCLUDEDIRS" => registration.add("PRIV_INCLUDE_DIRS", &values),
+            "COMPONENT_SRCDIRS" => {
+                has_srcdirs = true;
+                registration.add("SRC_DIRS", &values);
+            }
+            "COMPONENT_OBJS" => {
+                has_objs = true;
+                let (sources, unresolved) = objects_to_sources(&values, component_dir);
+                registration.add("SRCS", &sources);
+                for object in unresolved {
+                    warnings.push(format!("Source for object '{}' not found", object));
+                }
+            }
+            "COMPONENT_OBJEXCLUDE" => {
+                let (sources, _) = objects_to_sources(&values, component_dir);
+                registration.add("EXCLUDE_SRCS", &sources);
+            }
+            "COMPONENT_DEPENDS" => registration.add("REQUIRES", &values),
+            "COMPONENT_EMBED_FILES" => registration.add("EMBED_FILES", &values),
+            "COMPONENT_EMBED_TXTFILES" => registration.add("EMBED_TXTFILES", &values),
+            "CFLAGS" | "CXXFLAGS" | "CPPFLAGS" => registration.extra.push(format!(
+                "target_compile_options(${{COMPONENT_LIB}} PRIVATE {})",
+                value.trim()
+            )),
+            "COMPONENT_ADD_LDFLAGS" => warnings.push(format!(
+                "Linker flags need manual migration (target_link_libraries / target_linker_script): {}",
+                line
+            )),
+            _ => warnings.push(format!("Unsupported variable, migrate manually: {}", line)),
+        }
+    }
+
+    // Apply the component.mk defaults that idf_component_register doesn't share
+    if !has_srcdirs && !has_objs {
+        registration.add("SRC_DIRS", &[".".to_string()]);
+    }
+    if !has_includedirs && component_dir.join("include").is_dir() {
+        registration.add("INCLUDE_DIRS", &["include".to_string()]);
+    }
+
+    Migration {
+        cmake: registration.render(),
+        warnings,
+    }
+}
+
+/// Convert a pre-IDF 4 CMakeLists.txt using set(COMPONENT_...) and register_component()
+fn convert_legacy_cmake(content: &str) -> Option<Migration> {
+    if !content.contains("register_component(") {
+        return None;
+    }
+
+    let mut registration = Registration::default();
+    let mut warnings = Vec::new();
+    let mut rest = Vec::new();
+
+    // Join multi-line set(...) calls before looking at them
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    for line in content.lines() {
+        if !current.is_empty() || line.trim_start().starts_with("set(") {
+            current.push_str(line.trim());
+            current.push(' ');
+            if line.contains(')') {
+                statements.push(std::mem::take(&mut current));
+            }
+        } else {
+            statements.push(line.to_string());
+        }
+    }
+
+    for statement in statements {
+        let trimmed = statement.trim();
+        if trimmed.starts_with("register_component(") {
+            continue;
+        }
+
+        let set_args = trimmed
+            .strip_prefix("set(")
+            .and_then(|args| args.trim_end().strip_suffix(')'));
+
+        let mapped = set_args.and_then(|args| {
+            let mut parts = args.split_whitespace();
+            let name = parts.next()?;
+            let values: Vec<String> = parts.map(|v| v.trim_matches('"').to_string()).collect();
+            let arg = match name {
+                "COMPONENT_SRCS" => "SRCS",
+                "COMPONENT_SRCDIRS" => "SRC_DIRS",
+                "COMPONENT_SRCEXCLUDE" => "EXCLUDE_SRCS",
+                "COMPONENT_ADD_INCLUDEDIRS" => "INCLUDE_DIRS",
+                "COMPONENT_PRIV_INCLUDEDIRS" => "PRIV_INCLUDE_DIRS",
+                "COMPONENT_REQUIRES" => "REQUIRES",
+                "COMPONENT_PRIV_REQUIRES" => "PRIV_REQUIRES",
+                "COMPONENT_EMBED_FILES" => "EMBED_FILES",
+                "COMPONENT_EMBED_TXTFILES" => "EMBED_TXTFILES",
+                _ => return None,
+            };
+            Some((arg, values))
+        });
+
+        match mapped {
+            Some((arg, values)) => registration.add(arg, &values),
+            None => {
+                if trimmed.contains("COMPONENT_TARGET") {
+                    warnings.push(format!(
+                        "COMPONENT_TARGET was replaced by COMPONENT_LIB: {}",
+                        trimmed
+                    ));
+                }
+                rest.push(statement.replace("COMPONENT_TARGET", "COMPONENT_LIB"));
+            }
+        }
+    }
+
+    let mut cmake = registration.render();
+    let rest = rest.join("\n");
+    if !rest.trim().is_empty() {
+        cmake.push('\n');
+        cmake.push_str(rest.trim());
+        cmake.push('\n');
+    }
+
+    Some(Migration { cmake, warnings })
+}
+
+pub async fn execute(cli: &Cli, component_dir: &Path, dry_run: bool) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let component_dir = if component_dir.is_absolute() {
+        component_dir.to_path_buf()
+    } else {
+        project_dir.join(component_dir)
+    };
+
+    let component_mk = component_dir.join("component.mk");
+    let cmake_lists = component_dir.join("CMakeLists.txt");
+
+    let legacy_cmake = fs::read_to_string(&cmake_lists)
+        .ok()
+        .and_then(|content| convert_legacy_cmake(&content));
+
+    let mut migration = match legacy_cmake {
+        Some(migration) => {
+            println!(
+                "Migrating legacy CMakeLists.txt in {}",
+                component_dir.display()
+            );
+            migration
+        }
+        None if component_mk.exists() => {
+            if cmake_lists.exists() {
+                println!(
+                    "{} already exists and uses the current registration, nothing to migrate.",
+                    cmake_lists.display()
+                );
+                return Ok(());
+            }
+            println!("Migrating component.mk in {}", component_dir.display());
+            convert_component_mk(&fs::read_to_string(&component_mk)?, &component_dir)
+        }
+        None => {
+            return Err(anyhow::anyhow!(
+                "No component.mk or legacy CMakeLists.txt found in {}",
+                component_dir.display()
+            ))
+        }
+    };
+
+    // ESP-IDF 5 no longer adds common components as implicit dependencies
+    migration.warnings.push(
+        "Check REQUIRES/PRIV_REQUIRES: since ESP-IDF 5.0 components like 'driver' and \
+         'esp_timer' must be listed explicitly"
+            .to_string(),
+    );
+
+    if dry_run {
+        println!("{}", migration.cmake);
+    } else {
+        if cmake_lists.exists() {
+            let backup = component_dir.join("CMakeLists.txt.bak");
+            fs::copy(&cmake_lists, &backup)?;
+            println!("Backed up existing CMakeLists.txt to {}", backup.display());
+        }
+        fs::write(&cmake_lists, &migration.cmake)?;
+        println!("Written {}", cmake_lists.display());
+    }
+
+    println!();
+    println!("Needs manual attention:");
+    for warning in &migration.warnings {
+        println!("  - {}", warning);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_component_mk() {
+        let content = "COMPONENT_ADD_INCLUDEDIRS := include \\\n    port/include\n\
+                       COMPONENT_SRCDIRS := . port\n\
+                       COMPONENT_DEPENDS := driver\n\
+                       CFLAGS += -Wno-unused\n\
+                       ifdef CONFIG_FOO\n";
+        let migration = convert_component_mk(content, Path::new("/nonexistent"));
+
+        assert!(migration
+            .cmake
+            .contains("INCLUDE_DIRS \"include\" \"port/include\""));
+        assert!(migration.cmake.contains("SRC_DIRS \".\" \"port\""));
+        assert!(migration.cmake.contains("REQUIRES \"driver\""));
+        assert!(migration
+            .cmake
+            .contains("target_compile_options(${COMPONENT_LIB} PRIVATE -Wno-unused)"));
+        assert_eq!(migration.warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_convert_legacy_cmake() {
+        let content = "set(COMPONENT_SRCS \"main.c\" \"util.c\")\n\
+                       set(COMPONENT_ADD_INCLUDEDIRS \".\")\n\
+                       register_component()\n\
+                       target_link_libraries(${COMPONENT_TARGET} m)\n";
+        let migration = convert_legacy_cmake(content).unwrap();
+
+        assert!(migration.cmake.contains("SRCS \"main.c\" \"util.c\""));
+        assert!(migration.cmake.contains("INCLUDE_DIRS \".\""));
+        assert!(migration
+            .cmake
+            .contains("target_link_libraries(${COMPONENT_LIB} m)"));
+        assert!(convert_legacy_cmake("idf_component_register(SRCS \"main.c\")").is_none());
+    }
+}
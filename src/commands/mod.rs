@@ -1,6 +1,33 @@
+pub mod analyze;
 pub mod build;
+pub mod cache;
+pub mod chip;
+pub mod compile;
+pub mod completions;
 pub mod config;
+pub mod coredump;
+pub mod daemon;
+pub mod debug;
+pub mod deps;
+pub mod dfu;
+pub mod efuse;
+pub mod filesystem;
 pub mod flash;
+pub mod fleet;
+pub mod format;
+pub mod heap;
+pub mod manifest;
+pub mod menuconfig;
+pub mod migrate;
 pub mod monitor;
+pub mod nvs;
+pub mod ota;
+pub mod partition_table;
+pub mod ports;
 pub mod project;
+pub mod security;
 pub mod size;
+pub mod stats;
+pub mod tools;
+pub mod trace;
+pub mod uf2;
@@ -1,7 +1,809 @@
-use crate::{utils, Cli};
+use crate::commands::coredump as coredump_commands;
+use crate::serial_ports::{self, SerialTransport};
+use crate::{
+    backtrace, build_systems, config, coredump, key_tap, openocd, output, probe_rs, state, utils,
+    websocket, Cli,
+};
 use anyhow::Result;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncReadExt;
+use tokio::sync::broadcast;
 
-pub async fn execute(cli: &Cli, args: &[String]) -> Result<()> {
+/// How often to check whether a disconnected port is back
+const RECONNECT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long after the monitor exits with an error the port is watched for having gone
+/// away. A port that stays available means the error wasn't a disconnect.
+const DISCONNECT_CHECK_TIME: Duration = Duration::from_secs(1);
+
+/// Options selecting the binary log mode, where the device sends the addresses of
+/// format strings (kept in the ELF only) instead of the formatted text
+const APP_BINARY_LOG_OPTION: &str = "CONFIG_LOG_MODE_BINARY";
+const BOOTLOADER_BINARY_LOG_OPTION: &str = "CONFIG_BOOTLOADER_LOG_MODE_BINARY";
+
+/// Lines buffered for WebSocket clients that fall behind
+const SERVE_BUFFER_LINES: usize = 1024;
+
+/// First lines of the panic handler output, saved until "Rebooting..."
+const PANIC_MARKERS: [&str; 3] = [
+    "Guru Meditation Error",
+    "abort() was called",
+    "assert failed:",
+];
+
+/// Panic output is saved in pieces if the reboot line never comes
+const PANIC_LOG_MAX_LINES: usize = 200;
+
+/// Console baud rates tried when the output at the configured rate is garbled, after
+/// CONFIG_ESP_CONSOLE_UART_BAUDRATE. 74880 is the ROM bootloader rate of some chips.
+const PROBE_BAUD_RATES: [u32; 2] = [115200, 74880];
+
+/// How long to listen at each baud rate while probing
+const PROBE_DURATION: &str = "1.0";
+
+/// Read the console for a moment without resetting the chip (DTR/RTS stay released,
+/// as in idf_monitor) and print what was received as hex
+const PROBE_SCRIPT: &str = r#"
+import sys, time, serial
+
+port = serial.serial_for_url(sys.argv[1], do_not_open=True)
+port.baudrate, port.timeout = int(sys.argv[2]), 0.1
+port.dtr = False
+port.rts = False
+port.open()
+data = b''
+deadline = time.time() + float(sys.argv[3])
+while time.time() < deadline and len(data) < 4096:
+    data += port.read(512)
+port.close()
+print(data.hex())
+"#;
+
+/// Ctrl+T Ctrl+V switches the print filter off and on again while monitoring
+const PRINT_FILTER_KEY: u8 = 0x16;
+
+/// Rotated monitor logs kept next to the current one, as <file>.1 (newest) to <file>.5
+const LOG_ROTATE_KEEP: usize = 5;
+
+/// Options of the monitor command
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct MonitorOptions {
+    /// Exit instead of waiting for the device when the port disappears
+    #[arg(long = "no-reconnect")]
+    pub no_reconnect: bool,
+    /// Also stream decoded log lines as JSON over a WebSocket on this address (e.g. 0.0.0.0:8765)
+    #[arg(long, value_name = "ADDR:PORT")]
+    pub serve: Option<String>,
+    /// Baud rate of the console (-b/ESPBAUD is the flash baud rate)
+    #[arg(long = "monitor-baud", env = "MONITORBAUD", value_name = "BAUD")]
+    pub monitor_baud: Option<u32>,
+    /// Don't probe other baud rates when the console output looks garbled
+    #[arg(long = "no-baud-detect")]
+    pub no_baud_detect: bool,
+    /// Show the RTT and semihosting output over USB-JTAG with probe-rs instead of the serial port
+    #[arg(long = "probe-rs")]
+    pub probe_rs: bool,
+    /// Serve the monitor events (lines, panics, core dumps, GDB stub) to IDEs over a WebSocket on
+    /// this localhost port, as idf_monitor's --ws JSON messages
+    #[arg(long, value_name = "PORT")]
+    pub ws: Option<u16>,
+    /// Only show log lines up to a level per tag, e.g. "wifi:W mqtt:D *:I" (as idf_monitor's --print_filter)
+    #[arg(long = "print-filter", value_name = "FILTER")]
+    pub print_filter: Option<String>,
+    /// Also write the output, without colors, to this file
+    #[arg(long = "log-file", value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+    /// Prefix the lines of the log file with the time they were received (ISO 8601, UTC)
+    #[arg(long, requires = "log_file")]
+    pub timestamps: bool,
+    /// Start a new log file at this size, keeping the previous ones as <file>.1 to <file>.5
+    #[arg(long = "log-max-size", value_name = "MB", requires = "log_file")]
+    pub log_max_size: Option<u64>,
+    /// Monitor arguments
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    pub args: Vec<String>,
+    /// Started right after flashing ("flash monitor"): the console runs at the rate of
+    /// the flashed sdkconfig, not at the flash baud rate, and the boot output isn't
+    /// spent on probing
+    #[arg(skip)]
+    pub after_flash: bool,
+}
+
+/// What the console sent at a baud rate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConsoleData {
+    /// Too little to judge, e.g. an idle device
+    Silent,
+    Readable,
+    Garbled,
+}
+
+/// Judge whether console output was received at the right baud rate: text that decodes
+/// as UTF-8 or contains ESP-IDF log framing. At the wrong rate bytes come out as noise.
+fn classify_console_data(data: &[u8]) -> ConsoleData {
+    if data.len() < 16 {
+        return ConsoleData::Silent;
+    }
+
+    let text = String::from_utf8_lossy(data);
+    let framed = ["I (", "W (", "E (", "rst:", "boot:", "ESP-ROM"]
+        .iter()
+        .any(|marker| text.contains(marker));
+    let readable = text
+        .chars()
+        .filter(|c| !c.is_control() || matches!(c, '\n' | '\r' | '\t' | '\x1b'))
+        .filter(|c| *c != char::REPLACEMENT_CHARACTER)
+        .count();
+
+    if framed || readable * 10 >= text.chars().count() * 9 {
+        ConsoleData::Readable
+    } else {
+        ConsoleData::Garbled
+    }
+}
+
+/// Candidate baud rates after the configured one, without duplicates
+fn probe_candidates(configured: u32, sdkconfig_baud: Option<u32>) -> Vec<u32> {
+    let mut candidates = Vec::new();
+    for baud in sdkconfig_baud.into_iter().chain(PROBE_BAUD_RATES) {
+        if baud != configured && !candidates.contains(&baud) {
+            candidates.push(baud);
+        }
+    }
+    candidates
+}
+
+async fn probe_baud(python: &str, port: &str, baud: u32) -> Option<ConsoleData> {
+    let baud = baud.to_string();
+    let output = utils::run_command_with_output(
+        python,
+        &["-c", PROBE_SCRIPT, port, &baud, PROBE_DURATION],
+        None,
+    )
+    .await
+    .ok()?;
+
+    let hex = output.trim();
+    let data: Option<Vec<u8>> = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect();
+    Some(classify_console_data(&data?))
+}
+
+/// Listen at the configured baud rate and, if the output is garbled, look for the
+/// rate producing readable output. Returns the detected rate when it differs.
+async fn detect_baud(
+    python: &str,
+    port: &str,
+    configured: u32,
+    sdkconfig: Option<&config::SdkConfig>,
+) -> Option<u32> {
+    if probe_baud(python, port, configured).await? != ConsoleData::Garbled {
+        return None;
+    }
+
+    let sdkconfig_baud = sdkconfig
+        .and_then(|sdkconfig| sdkconfig.settings.get("CONFIG_ESP_CONSOLE_UART_BAUDRATE"))
+        .and_then(|baud| baud.parse().ok());
+    println!(
+        "--- Console output at {} baud looks garbled, probing other baud rates ---",
+        configured
+    );
+    for baud in probe_candidates(configured, sdkconfig_baud) {
+        if probe_baud(python, port, baud).await == Some(ConsoleData::Readable) {
+            println!("--- Detected console baud rate {} ---", baud);
+            return Some(baud);
+        }
+    }
+
+    println!(
+        "--- No baud rate produced readable output, keeping {} ---",
+        configured
+    );
+    None
+}
+
+/// ELF files passed to the monitor for address and binary log decoding
+fn get_elf_files(build_dir: &Path, sdkconfig: Option<&config::SdkConfig>) -> Vec<PathBuf> {
+    let is_enabled = |option: &str| {
+        sdkconfig
+            .as_ref()
+            .and_then(|sdkconfig| sdkconfig.settings.get(option))
+            .is_some_and(|value| value == "y")
+    };
+
+    let app_elf =
+        utils::get_app_elf_path(build_dir).unwrap_or_else(|_| build_dir.join("project.elf"));
+    let mut elf_files = vec![(app_elf, is_enabled(APP_BINARY_LOG_OPTION))];
+    if is_enabled(BOOTLOADER_BINARY_LOG_OPTION) {
+        elf_files.push((build_dir.join("bootloader").join("bootloader.elf"), true));
+    }
+
+    let mut existing = Vec::new();
+    for (elf_file, binary_log) in elf_files {
+        if elf_file.exists() {
+            if binary_log {
+                println!(
+                    "Binary log mode enabled, decoding log messages with {}",
+                    elf_file.display()
+                );
+            }
+            existing.push(elf_file);
+        } else if binary_log {
+            output::warning(format!(
+                "binary log mode is enabled but {} does not exist, log messages can't be decoded. Build the project first.",
+                elf_file.display()
+            ));
+        }
+    }
+
+    existing
+}
+
+/// Whether the port is unavailable, checked for a moment after the monitor exited
+async fn port_went_away(port: &dyn SerialTransport) -> bool {
+    let started = Instant::now();
+    loop {
+        if !port.is_available() {
+            return true;
+        }
+        if started.elapsed() >= DISCONNECT_CHECK_TIME {
+            return false;
+        }
+        tokio::time::sleep(RECONNECT_POLL_INTERVAL).await;
+    }
+}
+
+/// Wait until the serial device node exists again, or the remote serial server
+/// accepts connections again
+async fn wait_for_port(port: &dyn SerialTransport) {
+    while !port.is_available() {
+        tokio::time::sleep(RECONNECT_POLL_INTERVAL).await;
+    }
+    // Give udev a moment to apply permissions to the new device node
+    tokio::time::sleep(Duration::from_millis(500)).await;
+}
+
+/// Command idf_monitor runs with "flash" or "app-flash" for its Ctrl+T Ctrl+F and
+/// Ctrl+T Ctrl+A shortcuts: this idf-rs with the project, port and baud rate of the
+/// session. idf_monitor splits it like a shell.
+fn make_command(cli: &Cli, project_dir: &Path, build_dir: &Path) -> Result<String> {
+    let mut command = vec![
+        std::env::current_exe()?.display().to_string(),
+        "--non-interactive".to_string(),
+        "-C".to_string(),
+        project_dir.display().to_string(),
+        "-B".to_string(),
+        build_dir.display().to_string(),
+    ];
+    if let Some(port) = &cli.port {
+        command.extend(["-p".to_string(), port.clone()]);
+    }
+    if let Some(baud) = cli.baud {
+        command.extend(["-b".to_string(), baud.to_string()]);
+    }
+    Ok(command
+        .iter()
+        .map(|arg| {
+            if arg.is_empty() || arg.contains(|c: char| c.is_whitespace() || "'\"\\$".contains(c)) {
+                format!("'{}'", arg.replace('\'', r"'\''"))
+            } else {
+                arg.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" "))
+}
+
+/// Remove ANSI color sequences added by the monitor
+fn strip_ansi(line: &str) -> String {
+    let mut output = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // CSI sequences end with a letter
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else if c != '\r' {
+            output.push(c);
+        }
+    }
+    output
+}
+
+/// "2024-05-01T12:00:00.123Z" for the current time
+fn iso_timestamp() -> String {
+    let elapsed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let time = elapsed.as_secs();
+    let (year, month, day) = crate::stats::civil_date(time / crate::stats::SECONDS_PER_DAY);
+    let seconds = time % crate::stats::SECONDS_PER_DAY;
+    format!(
+        "{}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        elapsed.subsec_millis()
+    )
+}
+
+/// The monitor output written to a file, appended to across sessions and rotated by size
+struct MonitorLog {
+    path: PathBuf,
+    file: std::fs::File,
+    size: u64,
+    max_size: Option<u64>,
+    timestamps: bool,
+}
+
+impl MonitorLog {
+    fn open(path: &Path, max_size_mb: Option<u64>, timestamps: bool) -> Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| anyhow::anyhow!("Failed to open {}: {}", path.display(), e))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            size: file.metadata()?.len(),
+            file,
+            max_size: max_size_mb.map(|mb| mb * 1024 * 1024),
+            timestamps,
+        })
+    }
+
+    /// Path of the n-th rotated log, 1 being the newest
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> Result<()> {
+        let _ = std::fs::remove_file(self.rotated_path(LOG_ROTATE_KEEP));
+        for n in (1..LOG_ROTATE_KEEP).rev() {
+            let _ = std::fs::rename(self.rotated_path(n), self.rotated_path(n + 1));
+        }
+        std::fs::rename(&self.path, self.rotated_path(1))?;
+        self.file = std::fs::File::create(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<()> {
+        let line = if self.timestamps {
+            format!("{} {}\n", iso_timestamp(), line)
+        } else {
+            format!("{}\n", line)
+        };
+        if self
+            .max_size
+            .is_some_and(|max_size| self.size > 0 && self.size + line.len() as u64 > max_size)
+        {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+}
+
+/// Split an ESP-IDF log line ("I (1234) wifi: connected") into its level, timestamp,
+/// tag and message
+fn parse_log_line(line: &str) -> Option<(&str, &str, &str, &str)> {
+    let (level, rest) = line.split_once(" (")?;
+    if !matches!(level, "E" | "W" | "I" | "D" | "V") {
+        return None;
+    }
+    let (timestamp, rest) = rest.split_once(") ")?;
+    let (tag, message) = rest.split_once(": ")?;
+    Some((level, timestamp, tag, message))
+}
+
+/// Verbosity of a log level letter: N(one) < E < W < I < D < V, "*" being everything
+fn log_level(letter: &str) -> Option<u8> {
+    ["N", "E", "W", "I", "D", "V"]
+        .iter()
+        .position(|level| *level == letter)
+        .or((letter == "*").then_some(5))
+        .map(|level| level as u8)
+}
+
+/// Log lines shown per tag, with idf_monitor's --print_filter semantics: "tag:L" shows
+/// the lines of the tag up to level L, a tag without a level shows all of them, and
+/// lines of other tags (and lines which aren't log lines) follow "*", which hides them
+/// unless given
+#[derive(Debug, Clone, PartialEq)]
+struct PrintFilter {
+    tags: Vec<(String, u8)>,
+    default: u8,
+}
+
+impl PrintFilter {
+    fn parse(filter: &str) -> Result<Self> {
+        let mut tags = Vec::new();
+        let mut default = None;
+        for item in filter.split_whitespace() {
+            let (tag, letter) = item.split_once(':').unwrap_or((item, "V"));
+            let level = log_level(letter).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Invalid level '{}' in print filter '{}' (expected N, E, W, I, D, V or *)",
+                    letter,
+                    item
+                )
+            })?;
+            if tag == "*" {
+                default = Some(level);
+            } else {
+                tags.push((tag.to_string(), level));
+            }
+        }
+        let default = default.unwrap_or(if tags.is_empty() { 5 } else { 0 });
+        Ok(Self { tags, default })
+    }
+
+    fn allows(&self, line: &str) -> bool {
+        let Some((letter, _, tag, _)) = parse_log_line(line) else {
+            return self.default > 0;
+        };
+        let shown = self
+            .tags
+            .iter()
+            .find(|(name, _)| name == tag)
+            .map_or(self.default, |(_, level)| *level);
+        log_level(letter).is_some_and(|level| level <= shown)
+    }
+}
+
+/// Where the lines of a piped monitor go besides the console
+#[derive(Default)]
+struct LineSinks<'a> {
+    /// Port the lines come from, named in the WebSocket frames
+    port: Option<&'a str>,
+    /// Lines hidden from the console and the other sinks. The console then gets the
+    /// output a complete line at a time.
+    filter: Option<&'a PrintFilter>,
+    messages: Option<&'a broadcast::Sender<String>>,
+    /// Clients of --ws, sent idf_monitor's event messages
+    events: Option<&'a broadcast::Sender<String>>,
+    /// App ELF, the "prog" of the events
+    app_elf: Option<&'a Path>,
+    decoder: Option<&'a mut backtrace::Addr2Line>,
+    log: Option<&'a mut MonitorLog>,
+}
+
+impl LineSinks<'_> {
+    /// Send an idf_monitor event, e.g. {"event": "coredump", "file": ..., "prog": ...}
+    fn send_event(&self, event: &str, mut fields: serde_json::Value) {
+        let Some(events) = self.events else {
+            return;
+        };
+        fields["event"] = serde_json::json!(event);
+        if matches!(event, "coredump" | "gdb_stub") {
+            fields["prog"] = serde_json::json!(self.app_elf);
+        }
+        // Sending only fails when no client is connected
+        let _ = events.send(fields.to_string());
+    }
+
+    fn save_panic_log(&self, build_dir: &Path, log: &[String]) {
+        if let Some(path) = coredump_commands::save_panic_log(build_dir, log) {
+            self.send_event("panic", serde_json::json!({ "file": path }));
+        }
+    }
+}
+
+/// Whether a line carries the stop reply ("$T<signal>#<checksum>") the GDB stub
+/// prints when the app stops, as idf_monitor detects it
+fn has_gdb_stub_packet(line: &str) -> bool {
+    line.match_indices("$T").any(|(start, _)| {
+        let packet = &line[start + 1..];
+        let (Some(body), Some(checksum)) = (packet.get(..3), packet.get(4..6)) else {
+            return false;
+        };
+        packet.as_bytes()[3] == b'#'
+            && u8::from_str_radix(checksum, 16).is_ok_and(|checksum| {
+                body.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte)) == checksum
+            })
+    })
+}
+
+/// Decode an ESP-IDF log line (e.g. "I (1234) wifi: connected") into a JSON frame.
+/// Lines in other formats are sent with only the raw text.
+fn log_line_to_json(line: &str, port: Option<&str>) -> serde_json::Value {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0);
+    let mut frame = serde_json::json!({ "time": time, "line": line });
+    if let Some(port) = port {
+        frame["port"] = serde_json::json!(port);
+    }
+
+    if let Some((level, timestamp, tag, message)) = parse_log_line(line) {
+        frame["level"] = serde_json::json!(level);
+        // Either milliseconds since boot or wall clock time (CONFIG_LOG_TIMESTAMP_SOURCE_SYSTEM)
+        frame["timestamp"] = match timestamp.parse::<u64>() {
+            Ok(ms) => serde_json::json!(ms),
+            Err(_) => serde_json::json!(timestamp),
+        };
+        frame["tag"] = serde_json::json!(tag);
+        frame["message"] = serde_json::json!(message);
+    }
+
+    frame
+}
+
+/// Run the monitor with its output passed through to the console. Every complete line
+/// is broadcast to the WebSocket clients and written to the log file, code addresses in
+/// it are decoded and core dumps printed by the panic handler are saved and reported.
+/// With a print filter, Ctrl+T Ctrl+V shows the unfiltered output until pressed again.
+async fn run_piped_monitor(
+    cli: &Cli,
+    program: &str,
+    args: &[&str],
+    dir: &Path,
+    sinks: &mut LineSinks<'_>,
+) -> Result<()> {
+    let (mut tap, stdin) = match sinks.filter.and_then(|_| key_tap::start(PRINT_FILTER_KEY)) {
+        Some((tap, stdin)) => (Some(tap), stdin),
+        None => (None, Stdio::inherit()),
+    };
+    let mut child = tokio::process::Command::new(program)
+        .args(args)
+        .current_dir(dir)
+        .envs(utils::CommandEnv::new().vars())
+        .stdin(stdin)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        // Stopping a daemon's monitor drops it
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Failed to execute {}: {}", program, e))?;
+
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), dir);
+    let mut stdout = child.stdout.take().unwrap();
+    let mut buffer = [0u8; 4096];
+    let mut pending = Vec::new();
+    let mut core_dump: Option<String> = None;
+    let mut panic_log: Option<Vec<String>> = None;
+    // From the GDB stub taking over until the app logs again, the output is the
+    // session of the GDB idf_monitor attached, passed through as it comes
+    let mut gdb_session = false;
+    let mut filtering = true;
+    loop {
+        let read = tokio::select! {
+            read = stdout.read(&mut buffer) => read?,
+            _ = key_tap::pressed(&mut tap) => {
+                filtering = !filtering;
+                println!(
+                    "--- Print filter {} (Ctrl+T Ctrl+V to toggle) ---",
+                    if filtering { "on" } else { "off" }
+                );
+                continue;
+            }
+        };
+        if read == 0 {
+            break;
+        }
+        let filter = sinks.filter.filter(|_| filtering);
+        // Unfiltered, the console gets the output unchanged, including partial lines and colors
+        let mut console = std::io::stdout();
+        if filter.is_none() || gdb_session {
+            console.write_all(&buffer[..read])?;
+            console.flush()?;
+        }
+
+        pending.extend_from_slice(&buffer[..read]);
+        while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+            let raw: Vec<u8> = pending.drain(..=end).collect();
+            let line = strip_ansi(String::from_utf8_lossy(&raw).trim_end_matches('\n'));
+            // The chunk was passed through already
+            let passed_through = gdb_session;
+            if gdb_session {
+                if parse_log_line(&line).is_none() {
+                    continue;
+                }
+                gdb_session = false;
+            }
+            let shown = filter.is_none_or(|filter| filter.allows(&line));
+            if filter.is_some() && shown && !passed_through {
+                console.write_all(&raw)?;
+                console.flush()?;
+            }
+            if line.is_empty() {
+                continue;
+            }
+
+            if line.contains(coredump::UART_START) {
+                // The panic handler prints the core dump after the panic details
+                if let Some(log) = panic_log.take() {
+                    sinks.save_panic_log(&build_dir, &log);
+                }
+                core_dump = Some(String::new());
+            } else if let Some(encoded) = core_dump.as_mut() {
+                if line.contains(coredump::UART_END) {
+                    match crate::nvs::decode_base64(encoded) {
+                        Ok(data) => {
+                            let path =
+                                coredump_commands::report_captured(cli, dir, &build_dir, &data)
+                                    .await;
+                            if let Some(path) = path {
+                                sinks.send_event("coredump", serde_json::json!({ "file": path }));
+                            }
+                        }
+                        Err(e) => {
+                            output::warning(format!("Could not decode the core dump ({})", e))
+                        }
+                    }
+                    core_dump = None;
+                } else {
+                    encoded.push_str(line.trim());
+                }
+                continue;
+            }
+            if has_gdb_stub_packet(&line) {
+                sinks.send_event("gdb_stub", serde_json::json!({}));
+                // The panic output ends here, before GDB's
+                if let Some(log) = panic_log.take() {
+                    sinks.save_panic_log(&build_dir, &log);
+                }
+                gdb_session = true;
+                continue;
+            }
+            let mut lines = vec![line];
+
+            if let Some(decoder) = sinks.decoder.as_deref_mut() {
+                decoder.observe(&lines[0]);
+                let addresses = backtrace::code_addresses(&lines[0]);
+                if shown && !addresses.is_empty() {
+                    for frame in decoder.decode(&addresses).await {
+                        // Yellow, as idf_monitor prints decoded addresses
+                        console.write_all(format!("\x1b[0;33m{}\x1b[0m\n", frame).as_bytes())?;
+                        lines.extend(frame.lines().map(str::to_string));
+                    }
+                    console.flush()?;
+                }
+            }
+
+            if panic_log.is_none() && PANIC_MARKERS.iter().any(|m| lines[0].contains(m)) {
+                panic_log = Some(Vec::new());
+            }
+            if let Some(log) = panic_log.as_mut() {
+                log.extend(lines.iter().cloned());
+                if lines[0].contains("Rebooting...") || log.len() >= PANIC_LOG_MAX_LINES {
+                    sinks.save_panic_log(&build_dir, log);
+                    panic_log = None;
+                }
+            }
+
+            // Panics are captured whatever the filter hides
+            if !shown {
+                continue;
+            }
+
+            if let Some(log) = sinks.log.as_deref_mut() {
+                for line in &lines {
+                    log.write_line(line)?;
+                }
+            }
+
+            if let Some(messages) = sinks.messages {
+                for line in &lines {
+                    // Sending only fails when no client is connected
+                    let _ = messages.send(log_line_to_json(line, sinks.port).to_string());
+                }
+            }
+
+            for line in &lines {
+                sinks.send_event("line", log_line_to_json(line, sinks.port));
+            }
+        }
+    }
+
+    if let Some(log) = panic_log {
+        sinks.save_panic_log(&build_dir, &log);
+    }
+
+    let status = child.wait().await?;
+    if !status.success() {
+        return Err(anyhow::anyhow!(
+            "Command failed with exit code: {:?}",
+            status.code()
+        ));
+    }
+    Ok(())
+}
+
+/// Start the WebSocket server for the idf_monitor events of --ws, when asked to
+async fn serve_events(port: Option<u16>) -> Result<Option<broadcast::Sender<String>>> {
+    let Some(port) = port else {
+        return Ok(None);
+    };
+    let address = format!("127.0.0.1:{}", port);
+    let (events, _) = broadcast::channel(SERVE_BUFFER_LINES);
+    websocket::serve(&address, events.clone()).await?;
+    println!("Serving monitor events to IDEs at ws://{}", address);
+    Ok(Some(events))
+}
+
+/// Start the WebSocket server the log lines are streamed to, when asked to
+async fn serve_log_lines(serve: Option<&str>) -> Result<Option<broadcast::Sender<String>>> {
+    let Some(address) = serve else {
+        return Ok(None);
+    };
+    let (messages, _) = broadcast::channel(SERVE_BUFFER_LINES);
+    websocket::serve(address, messages.clone()).await?;
+    println!(
+        "Streaming log lines to WebSocket clients at ws://{} (viewer at http://{})",
+        address, address
+    );
+    Ok(Some(messages))
+}
+
+/// Monitor the RTT and semihosting output of the app over USB-JTAG with probe-rs attach,
+/// decoding backtraces and streaming the lines like the serial monitor does
+async fn execute_probe_rs(cli: &Cli, options: &MonitorOptions) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+    let target = openocd::resolve_target(&project_dir, &build_dir);
+    let elf = utils::get_app_elf_path(&build_dir)?;
+
+    let args = probe_rs::attach_args(&target, &elf)?;
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let mut decoder = backtrace::Addr2Line::find(&build_dir, Some(&target));
+    let messages = serve_log_lines(options.serve.as_deref()).await?;
+    let events = serve_events(options.ws).await?;
+    let mut log = open_log(options)?;
+
+    println!("Starting monitor over JTAG with probe-rs...");
+    if cli.verbose {
+        println!("Running: {} {}", probe_rs::program(), args.join(" "));
+    }
+    let print_filter = options
+        .print_filter
+        .as_deref()
+        .map(PrintFilter::parse)
+        .transpose()?;
+    let mut sinks = LineSinks {
+        port: None,
+        filter: print_filter.as_ref(),
+        messages: messages.as_ref(),
+        events: events.as_ref(),
+        app_elf: Some(&elf),
+        decoder: decoder.as_mut(),
+        log: log.as_mut(),
+    };
+    run_piped_monitor(cli, &probe_rs::program(), &args, &project_dir, &mut sinks).await
+}
+
+fn open_log(options: &MonitorOptions) -> Result<Option<MonitorLog>> {
+    options
+        .log_file
+        .as_deref()
+        .map(|path| MonitorLog::open(path, options.log_max_size, options.timestamps))
+        .transpose()
+}
+
+pub async fn execute(cli: &Cli, options: &MonitorOptions) -> Result<()> {
+    if options.probe_rs {
+        return execute_probe_rs(cli, options).await;
+    }
+    let args = &options.args;
     utils::setup_idf_environment()?;
 
     let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
@@ -19,15 +821,108 @@ pub async fn execute(cli: &Cli, args: &[String]) -> Result<()> {
         monitor_args.extend_from_slice(&["--port", port]);
     }
 
-    // Add baud rate
-    let baud_str = cli.baud.unwrap_or(115200).to_string();
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+    // The build may use its own sdkconfig (e.g. fleet variants)
+    let sdkconfig_path = build_systems::get_cmake_cache_entry(&build_dir, "SDKCONFIG")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| config::get_sdkconfig_path(&project_dir));
+    let sdkconfig = config::SdkConfig::load_from_file(&sdkconfig_path).ok();
+
+    // Add baud rate, detecting it when the configured one gives garbled output. A raw
+    // TCP port (socket://) runs at the rate set on the remote machine.
+    let transport = cli
+        .port
+        .as_deref()
+        .map(serial_ports::transport)
+        .transpose()?;
+    let console_baud = sdkconfig
+        .as_ref()
+        .and_then(|sdkconfig| sdkconfig.settings.get("CONFIG_ESP_CONSOLE_UART_BAUDRATE"))
+        .and_then(|baud| baud.parse().ok());
+    let saved_baud = state::ProjectState::load(&build_dir).monitor_baud;
+    let configured_baud = match options
+        .monitor_baud
+        .or(console_baud.filter(|_| options.after_flash))
+    {
+        Some(baud) => baud,
+        None => saved_baud.unwrap_or(115200),
+    };
+    let detected_baud = match (!options.no_baud_detect, &transport) {
+        (true, Some(port)) if port.supports_baud_change() && !options.after_flash => {
+            detect_baud(&python, port.name(), configured_baud, sdkconfig.as_ref()).await
+        }
+        _ => None,
+    };
+    let baud_str = detected_baud.unwrap_or(configured_baud).to_string();
     monitor_args.extend_from_slice(&["--baud", &baud_str]);
 
-    // Add ELF file for symbol resolution
-    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
-    let elf_file = build_dir.join("project.elf"); // This might need to be project-specific
+    // Backtraces are decoded here with the toolchain's addr2line, which also knows
+    // the ROM ELF; idf_monitor decodes them itself when the toolchain isn't found
+    let target = sdkconfig
+        .as_ref()
+        .and_then(|sdkconfig| sdkconfig.settings.get("CONFIG_IDF_TARGET"))
+        .map(|target| target.trim_matches('"').to_string());
+    // idf_monitor attaches the target's GDB over the serial port when the panic handler
+    // starts the GDB stub (CONFIG_ESP_SYSTEM_PANIC_GDBSTUB)
+    let toolchain_prefix = target.as_deref().map(openocd::toolchain_prefix);
+    if let (Some(target), Some(prefix)) = (&target, &toolchain_prefix) {
+        monitor_args.extend_from_slice(&["--target", target, "--toolchain-prefix", prefix]);
+    }
+
+    // Ctrl+T Ctrl+F and Ctrl+T Ctrl+A rebuild and flash with idf-rs, encrypted by the
+    // device when flash encryption is in development mode
+    let make = make_command(cli, &project_dir, &build_dir)?;
+    monitor_args.extend_from_slice(&["--make", &make]);
+    let enabled = |name: &str| {
+        sdkconfig
+            .as_ref()
+            .is_some_and(|sdkconfig| sdkconfig.settings.get(name).is_some_and(|v| v == "y"))
+    };
+    if enabled("CONFIG_SECURE_FLASH_ENC_ENABLED")
+        && enabled("CONFIG_SECURE_FLASH_ENCRYPTION_MODE_DEVELOPMENT")
+    {
+        monitor_args.push("--encrypted");
+    }
 
-    if elf_file.exists() {
+    let mut decoder = backtrace::Addr2Line::find(&build_dir, target.as_deref());
+    if let Some(decoder) = &decoder {
+        monitor_args.push("--disable-address-decoding");
+        if cli.verbose {
+            println!("Decoding backtraces with {}", decoder.tool());
+        }
+    }
+    // The print filter is applied here, as the output is piped anyway or for the other
+    // sinks to be filtered too; idf_monitor only gets one given in its own arguments
+    let print_filter = match &options.print_filter {
+        Some(filter) => Some(filter.clone()),
+        None => config::load_project_settings(&project_dir)?.print_filter,
+    }
+    .filter(|_| {
+        !args
+            .iter()
+            .any(|arg| arg.starts_with("--print_filter") || arg.starts_with("--print-filter"))
+    })
+    .map(|filter| PrintFilter::parse(&filter))
+    .transpose()?;
+
+    let messages = serve_log_lines(options.serve.as_deref()).await?;
+    let events = serve_events(options.ws).await?;
+    let mut log = open_log(options)?;
+    let piped = messages.is_some()
+        || events.is_some()
+        || decoder.is_some()
+        || log.is_some()
+        || print_filter.is_some();
+
+    // The piped output is watched for core dumps here
+    if piped {
+        monitor_args.extend_from_slice(&["--decode-coredumps", "disable"]);
+    }
+
+    // Add ELF files for symbol resolution and binary log decoding
+    let elf_files = get_elf_files(&build_dir, sdkconfig.as_ref());
+
+    for elf_file in &elf_files {
         monitor_args.push(elf_file.to_str().unwrap());
     }
 
@@ -36,7 +931,159 @@ pub async fn execute(cli: &Cli, args: &[String]) -> Result<()> {
         monitor_args.push(arg);
     }
 
-    utils::run_command(&python, &monitor_args, Some(&project_dir), cli.verbose).await?;
+    let watched_port = transport
+        .as_deref()
+        .filter(|port| !options.no_reconnect && port.can_reconnect());
+
+    loop {
+        let result = if piped {
+            let mut sinks = LineSinks {
+                port: cli.port.as_deref(),
+                filter: print_filter.as_ref(),
+                messages: messages.as_ref(),
+                events: events.as_ref(),
+                app_elf: elf_files.first().map(PathBuf::as_path),
+                decoder: decoder.as_mut(),
+                log: log.as_mut(),
+            };
+            run_piped_monitor(cli, &python, &monitor_args, &project_dir, &mut sinks).await
+        } else {
+            utils::run_command(&python, &monitor_args, Some(&project_dir), cli.verbose).await
+        };
+
+        match (result, watched_port) {
+            (Ok(()), _) => break,
+            (Err(e), Some(port)) if port_went_away(port).await => {
+                println!(
+                    "--- Port {} disconnected, waiting for the device to come back (Ctrl+C to quit) ---",
+                    port.name()
+                );
+                if cli.verbose {
+                    println!("--- Monitor exited: {} ---", e);
+                }
+                wait_for_port(port).await;
+                println!(
+                    "--- Port {} reconnected, reattaching monitor ---",
+                    port.name()
+                );
+            }
+            (Err(e), _) => return Err(e),
+        }
+    }
+
+    // A detected baud rate is remembered, so the next session doesn't need to probe.
+    // The console rate is kept apart from the flash baud rate.
+    let baud = detected_baud.or(options.monitor_baud);
+    state::record_serial_settings(
+        &build_dir,
+        cli.port.as_deref(),
+        state::SessionBaud::Monitor(baud),
+    );
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_line_to_json() {
+        let line = strip_ansi("\x1b[0;32mI (1234) wifi: connected: ap\x1b[0m\r");
+        let frame = log_line_to_json(&line, Some("/dev/ttyUSB0"));
+        assert_eq!(frame["level"], "I");
+        assert_eq!(frame["timestamp"], 1234);
+        assert_eq!(frame["tag"], "wifi");
+        assert_eq!(frame["message"], "connected: ap");
+        assert_eq!(frame["line"], "I (1234) wifi: connected: ap");
+        assert_eq!(frame["port"], "/dev/ttyUSB0");
+
+        let frame = log_line_to_json("rst:0x1 (POWERON_RESET),boot:0x13", None);
+        assert!(frame.get("level").is_none());
+        assert!(frame.get("port").is_none());
+    }
+
+    #[test]
+    fn test_make_command() {
+        let cli = Cli {
+            port: Some("/dev/ttyUSB0".to_string()),
+            ..Cli::for_project("/tmp/my project").unwrap()
+        };
+        let make =
+            make_command(&cli, Path::new("/tmp/my project"), Path::new("/tmp/build")).unwrap();
+        assert!(
+            make.contains(" --non-interactive -C '/tmp/my project' -B /tmp/build -p /dev/ttyUSB0")
+        );
+        assert!(!make.contains(" -b "));
+    }
+
+    #[test]
+    fn test_has_gdb_stub_packet() {
+        assert!(has_gdb_stub_packet("$T0b#e6"));
+        assert!(has_gdb_stub_packet("Entering gdb stub now.\x1b$T0b#e6"));
+        assert!(!has_gdb_stub_packet("$T0b#00"));
+        assert!(!has_gdb_stub_packet("cost: $T1"));
+    }
+
+    #[test]
+    fn test_print_filter() {
+        let filter = PrintFilter::parse("wifi:W mqtt:D *:I").unwrap();
+        assert!(filter.allows("W (100) wifi: disconnected"));
+        assert!(!filter.allows("I (100) wifi: connected"));
+        assert!(filter.allows("D (100) mqtt: publish"));
+        assert!(filter.allows("I (100) main: started"));
+        assert!(!filter.allows("D (100) main: details"));
+        assert!(filter.allows("Guru Meditation Error: Core  0 panic'ed"));
+
+        // Without "*", only the listed tags are shown
+        let filter = PrintFilter::parse("wifi mqtt:E").unwrap();
+        assert!(filter.allows("V (100) wifi: scan"));
+        assert!(filter.allows("E (100) mqtt: error"));
+        assert!(!filter.allows("W (100) mqtt: warning"));
+        assert!(!filter.allows("E (100) main: error"));
+        assert!(!filter.allows("rst:0x1 (POWERON_RESET)"));
+
+        assert!(PrintFilter::parse("").unwrap().allows("V (1) any: thing"));
+        assert!(PrintFilter::parse("wifi:X").is_err());
+    }
+
+    #[test]
+    fn test_monitor_log_rotation() {
+        let dir = std::env::temp_dir().join(format!("idf-rs-monitor-log-{}", std::process::id()));
+        let path = dir.join("monitor.log");
+        let mut log = MonitorLog::open(&path, None, false).unwrap();
+        log.max_size = Some(16);
+        log.write_line("first line").unwrap();
+        log.write_line("second line").unwrap();
+        log.write_line("third line").unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "third line\n");
+        assert_eq!(
+            std::fs::read_to_string(log.rotated_path(1)).unwrap(),
+            "second line\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(log.rotated_path(2)).unwrap(),
+            "first line\n"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let timestamp = iso_timestamp();
+        assert_eq!(timestamp.len(), 24);
+        assert!(timestamp.ends_with('Z'));
+    }
+
+    #[test]
+    fn test_classify_console_data() {
+        assert_eq!(classify_console_data(b"I (5"), ConsoleData::Silent);
+        assert_eq!(
+            classify_console_data(b"I (318) app_start: Starting scheduler on CPU0\r\n"),
+            ConsoleData::Readable
+        );
+        assert_eq!(
+            classify_console_data(&[0xf8, 0x80, 0x1c, 0xfe, 0x00, 0x92, 0xe0, 0x06].repeat(4)),
+            ConsoleData::Garbled
+        );
+        assert_eq!(probe_candidates(115200, Some(921600)), vec![921600, 74880]);
+    }
+}
@@ -0,0 +1,72 @@
+use crate::aes::Xts;
+use crate::{nvs, output, partition_table, Cli};
+use anyhow::Result;
+use std::path::Path;
+
+/// XTS keys from a key file as nvs_partition_gen.py writes it: the 32 byte data key and
+/// the 32 byte tweak key, optionally followed by a CRC and padding
+fn load_key(path: &Path) -> Result<Xts> {
+    let data = std::fs::read(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read key file {}: {}", path.display(), e))?;
+    let key: &[u8; 64] = data
+        .get(..64)
+        .and_then(|key| key.try_into().ok())
+        .ok_or_else(|| anyhow::anyhow!("Key file {} is shorter than 64 bytes", path.display()))?;
+    Ok(Xts::new(key))
+}
+
+pub async fn execute_gen(
+    cli: &Cli,
+    csv: &Path,
+    output: &Path,
+    size: &str,
+    keyfile: Option<&Path>,
+) -> Result<()> {
+    let size = partition_table::parse_size(size)
+        .ok_or_else(|| anyhow::anyhow!("Invalid size '{}'", size))? as usize;
+    let content = std::fs::read_to_string(csv)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", csv.display(), e))?;
+    let base_dir = csv.parent().unwrap_or(Path::new("."));
+    let rows = nvs::parse_csv(&content, base_dir)
+        .map_err(|e| anyhow::anyhow!("{}: {}", csv.display(), e))?;
+
+    let xts = keyfile.map(load_key).transpose()?;
+    let image = nvs::generate(&rows, size, xts.as_ref())?;
+    std::fs::write(output, image)?;
+
+    let entries = rows
+        .iter()
+        .filter(|row| matches!(row, nvs::Row::Entry { .. }))
+        .count();
+    println!(
+        "Generated {}{} ({} entries, {} bytes)",
+        output.display(),
+        if xts.is_some() { ", encrypted" } else { "" },
+        entries,
+        size
+    );
+    if cli.verbose {
+        println!("From {}", csv.display());
+    }
+    Ok(())
+}
+
+pub async fn execute_dump(_cli: &Cli, input: &Path, keyfile: Option<&Path>) -> Result<()> {
+    let image = std::fs::read(input)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", input.display(), e))?;
+    let xts = keyfile.map(load_key).transpose()?;
+    let items = nvs::dump(&image, xts.as_ref())?;
+    if items.is_empty() {
+        output::warning(format!(
+            "No valid entries in {}{}",
+            input.display(),
+            if xts.is_some() {
+                " (is the key right?)"
+            } else {
+                ""
+            }
+        ));
+    }
+    print!("{}", nvs::to_csv(&items));
+    Ok(())
+}
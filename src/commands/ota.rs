@@ -0,0 +1,477 @@
+use crate::commands::{flash, partition_table as partition_commands};
+use crate::partition_table::Partition;
+use crate::{app_image, config, otadata, sha256, utils, Cli};
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// HTTP(S) server for esp_https_ota tests, run with the ESP-IDF Python. It serves
+/// the image with Range support (used by partial HTTP downloads) and logs every request.
+const SERVER_SCRIPT: &str = include_str!("ota_server.py");
+
+/// Sign the app binary with an OpenSSL private key and return the signature
+async fn sign(key: &Path, bin: &Path, signature: &Path) -> Result<Vec<u8>> {
+    if !key.exists() {
+        return Err(anyhow::anyhow!(
+            "Signing key {} does not exist",
+            key.display()
+        ));
+    }
+
+    utils::run_command_with_output(
+        "openssl",
+        &[
+            "dgst",
+            "-sha256",
+            "-sign",
+            key.to_str().unwrap(),
+            "-out",
+            signature.to_str().unwrap(),
+            bin.to_str().unwrap(),
+        ],
+        None,
+    )
+    .await?;
+
+    Ok(std::fs::read(signature)?)
+}
+
+/// Package the app binary with its metadata as <project>-<version>-<target>.tar.gz and .json
+pub async fn execute_package(
+    cli: &Cli,
+    output: Option<&Path>,
+    signing_key: Option<&Path>,
+) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+    let settings = config::load_ota_settings(&project_dir)?;
+
+    let app_bin = utils::get_app_bin_path(&build_dir)?;
+    let image = std::fs::read(&app_bin)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", app_bin.display(), e))?;
+    let app = app_image::parse_app_description(&image)
+        .map_err(|e| anyhow::anyhow!("{}: {}", app_bin.display(), e))?;
+    let target = utils::load_project_description(&build_dir)?["target"]
+        .as_str()
+        .unwrap_or(utils::DEFAULT_TARGET)
+        .to_string();
+
+    let output_dir = match output {
+        Some(output) => output.to_path_buf(),
+        None => settings
+            .output_dir
+            .as_ref()
+            .map(|dir| project_dir.join(dir))
+            .unwrap_or_else(|| build_dir.join("ota")),
+    };
+    let name = format!(
+        "{}-{}-{}",
+        app.project_name,
+        app.version.replace(['/', ' '], "_"),
+        target
+    );
+    let staging_dir = output_dir.join(&name);
+    if staging_dir.exists() {
+        std::fs::remove_dir_all(&staging_dir)?;
+    }
+    std::fs::create_dir_all(&staging_dir)?;
+
+    let bin_name = format!("{}.bin", app.project_name);
+    let bin_path = staging_dir.join(&bin_name);
+    std::fs::copy(&app_bin, &bin_path)?;
+    let mut files = vec![bin_name.clone()];
+
+    let mut manifest = serde_json::json!({
+        "project": app.project_name,
+        "version": app.version,
+        "target": target,
+        "idf_version": app.idf_version,
+        "secure_version": app.secure_version,
+        "build_date": format!("{} {}", app.date, app.time).trim(),
+        "elf_sha256": sha256::to_hex(&app.elf_sha256),
+        "file": bin_name,
+        "size": image.len(),
+        "sha256": sha256::hex_digest(&image),
+    });
+    if let Some(channel) = &settings.channel {
+        manifest["channel"] = serde_json::json!(channel);
+    }
+    if let Some(base_url) = &settings.base_url {
+        manifest["url"] = serde_json::json!(format!(
+            "{}/{}/{}",
+            base_url.trim_end_matches('/'),
+            name,
+            bin_name
+        ));
+    }
+
+    let signing_key: Option<PathBuf> = signing_key.map(Path::to_path_buf).or_else(|| {
+        settings
+            .signing_key
+            .as_ref()
+            .map(|key| project_dir.join(key))
+    });
+    if let Some(key) = &signing_key {
+        let signature_name = format!("{}.sig", bin_name);
+        let signature = sign(key, &bin_path, &staging_dir.join(&signature_name)).await?;
+        manifest["signature"] = serde_json::json!({
+            "algorithm": "sha256",
+            "file": signature_name,
+            "value": sha256::to_hex(&signature),
+        });
+        files.push(signature_name);
+    }
+
+    let manifest = serde_json::to_string_pretty(&manifest)?;
+    std::fs::write(staging_dir.join("manifest.json"), &manifest)?;
+    files.push("manifest.json".to_string());
+
+    let archive = output_dir.join(format!("{}.tar.gz", name));
+    let mut tar_args = vec![
+        "-czf",
+        archive.to_str().unwrap(),
+        "-C",
+        staging_dir.to_str().unwrap(),
+    ];
+    tar_args.extend(files.iter().map(String::as_str));
+    utils::run_command("tar", &tar_args, None, cli.verbose).await?;
+
+    let metadata = output_dir.join(format!("{}.json", name));
+    std::fs::write(&metadata, &manifest)?;
+    std::fs::remove_dir_all(&staging_dir)?;
+
+    println!(
+        "Packaged {} {} for {} ({} bytes{})",
+        app.project_name,
+        app.version,
+        target,
+        image.len(),
+        if signing_key.is_some() {
+            ", signed"
+        } else {
+            ""
+        }
+    );
+    println!("  Archive:  {}", archive.display());
+    println!("  Metadata: {}", metadata.display());
+    Ok(())
+}
+
+/// Address of this machine on the network the devices use to reach it
+fn local_address() -> Option<String> {
+    // Connecting a UDP socket only selects the route, nothing is sent
+    let socket = std::net::UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("8.8.8.8:80").ok()?;
+    Some(socket.local_addr().ok()?.ip().to_string())
+}
+
+/// Generate a self-signed certificate for the host, unless one already exists
+async fn ensure_certificate(
+    cert_dir: &Path,
+    host: &str,
+    verbose: bool,
+) -> Result<(PathBuf, PathBuf)> {
+    let cert = cert_dir.join(format!("{}.crt", host));
+    let key = cert_dir.join(format!("{}.key", host));
+    if cert.exists() && key.exists() {
+        return Ok((cert, key));
+    }
+
+    std::fs::create_dir_all(cert_dir)?;
+    let subject = format!("/CN={}", host);
+    let kind = if host.parse::<std::net::IpAddr>().is_ok() {
+        "IP"
+    } else {
+        "DNS"
+    };
+    let alt_names = format!("subjectAltName={}:{},DNS:localhost", kind, host);
+    utils::run_command(
+        "openssl",
+        &[
+            "req",
+            "-x509",
+            "-newkey",
+            "rsa:2048",
+            "-nodes",
+            "-days",
+            "365",
+            "-subj",
+            &subject,
+            "-addext",
+            &alt_names,
+            "-keyout",
+            key.to_str().unwrap(),
+            "-out",
+            cert.to_str().unwrap(),
+        ],
+        None,
+        verbose,
+    )
+    .await?;
+
+    println!("Generated self-signed certificate: {}", cert.display());
+    Ok((cert, key))
+}
+
+/// Serve the app image over HTTP or HTTPS for esp_https_ota tests
+pub async fn execute_serve(
+    cli: &Cli,
+    port: u16,
+    https: bool,
+    host: Option<&str>,
+    file: Option<&Path>,
+) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+
+    let image = match file {
+        Some(file) => file.to_path_buf(),
+        None => utils::get_app_bin_path(&build_dir)?,
+    };
+    if !image.exists() {
+        return Err(anyhow::anyhow!(
+            "{} does not exist, build the project first",
+            image.display()
+        ));
+    }
+    let name = image
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow::anyhow!("Invalid image path {}", image.display()))?
+        .to_string();
+
+    let host = host
+        .map(str::to_string)
+        .or_else(local_address)
+        .unwrap_or_else(|| "localhost".to_string());
+
+    let serve_dir = build_dir.join("ota-serve");
+    std::fs::create_dir_all(&serve_dir)?;
+    let script = serve_dir.join("ota_server.py");
+    std::fs::write(&script, SERVER_SCRIPT)?;
+
+    let python = utils::get_python_executable()?;
+    let port_str = port.to_string();
+    let mut args = vec![
+        script.to_string_lossy().to_string(),
+        image.to_string_lossy().to_string(),
+        name.clone(),
+        port_str,
+    ];
+
+    let scheme = if https {
+        let (cert, key) = ensure_certificate(&serve_dir, &host, cli.verbose).await?;
+        println!(
+            "Embed {} as the server certificate in the firmware (e.g. server_certs/ca_cert.pem)",
+            cert.display()
+        );
+        args.push(cert.to_string_lossy().to_string());
+        args.push(key.to_string_lossy().to_string());
+        "https"
+    } else {
+        "http"
+    };
+
+    println!(
+        "Serving {} at {}://{}:{}/{}",
+        image.display(),
+        scheme,
+        host,
+        port,
+        name
+    );
+    println!("Press Ctrl+C to stop");
+
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    utils::run_command(&python, &args, Some(&project_dir), cli.verbose).await
+}
+
+/// The otadata partition and the OTA app partitions by slot number
+fn ota_partitions(partitions: &[Partition]) -> Result<(&Partition, Vec<&Partition>)> {
+    let otadata = partitions
+        .iter()
+        .find(|partition| partition.is_otadata())
+        .ok_or_else(|| anyhow::anyhow!("The partition table has no otadata partition"))?;
+    let mut slots: Vec<&Partition> = partitions
+        .iter()
+        .filter(|partition| partition.ota_slot().is_some())
+        .collect();
+    slots.sort_by_key(|partition| partition.ota_slot());
+    if slots.is_empty() {
+        return Err(anyhow::anyhow!(
+            "The partition table has no OTA app partitions"
+        ));
+    }
+    Ok((otadata, slots))
+}
+
+/// Read the otadata partition from the device, through a file in the build directory
+async fn read_otadata(cli: &Cli, project_dir: &Path, otadata: &Partition) -> Result<Vec<u8>> {
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), project_dir);
+    std::fs::create_dir_all(&build_dir)?;
+    let path = build_dir.join("otadata-read.bin");
+
+    let offset = format!("0x{:x}", otadata.offset);
+    let size = format!("0x{:x}", otadata.size);
+    let path_arg = path.to_string_lossy();
+    flash::run_esptool(cli, project_dir, &["read_flash", &offset, &size, &path_arg]).await?;
+
+    let data = std::fs::read(&path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+    let _ = std::fs::remove_file(&path);
+    Ok(data)
+}
+
+/// Report the otadata entries and the boot and next update partitions, like
+/// `otatool.py read_otadata`
+pub async fn execute_info(cli: &Cli) -> Result<()> {
+    utils::setup_idf_environment()?;
+
+    let (project_dir, partitions) = partition_commands::load_partitions(cli)?;
+    let (otadata, slots) = ota_partitions(&partitions)?;
+    let entries = otadata::parse(&read_otadata(cli, &project_dir, otadata).await?)?;
+    let active = otadata::active_entry(&entries);
+
+    println!();
+    println!(
+        "{:<8} {:>10} {:<16} {:<6} SLOT",
+        "SECTOR", "SEQ", "STATE", "CRC"
+    );
+    for (index, entry) in entries.iter().enumerate() {
+        if entry.seq == u32::MAX {
+            println!("{:<8} {:>10} (empty)", index, "-");
+            continue;
+        }
+        println!(
+            "{:<8} {:>10} {:<16} {:<6} {}{}",
+            index,
+            entry.seq,
+            entry.state_name(),
+            if entry.is_valid() { "ok" } else { "bad" },
+            slots[entry.slot(slots.len())].label,
+            if active == Some(index) {
+                " (active)"
+            } else {
+                ""
+            }
+        );
+    }
+    println!();
+
+    // Without a valid entry the bootloader starts the factory app, or ota_0 without one
+    let factory = partitions
+        .iter()
+        .find(|partition| partition.is_app() && partition.subtype == 0x00);
+    let boot = match (active, factory) {
+        (Some(active), _) => slots[entries[active].slot(slots.len())],
+        (None, Some(factory)) => factory,
+        (None, None) => slots[0],
+    };
+    // esp_ota_get_next_update_partition(): the slot after the running one
+    let next = match boot.ota_slot() {
+        Some(_) => {
+            let index = slots.iter().position(|slot| slot.label == boot.label);
+            slots[index.map_or(0, |index| (index + 1) % slots.len())]
+        }
+        None => slots[0],
+    };
+    println!(
+        "Boot partition:        {} (0x{:x})",
+        boot.label, boot.offset
+    );
+    println!(
+        "Next update partition: {} (0x{:x})",
+        next.label, next.offset
+    );
+    Ok(())
+}
+
+/// Select the OTA slot the bootloader starts, by label ("ota_1") or slot number ("1")
+pub async fn execute_switch(cli: &Cli, slot: &str) -> Result<()> {
+    utils::setup_idf_environment()?;
+
+    let (project_dir, partitions) = partition_commands::load_partitions(cli)?;
+    let (otadata, slots) = ota_partitions(&partitions)?;
+    let index = slots
+        .iter()
+        .position(|partition| {
+            partition.label == slot
+                || partition.ota_slot().map(|n| n.to_string()) == Some(slot.to_string())
+        })
+        .ok_or_else(|| {
+            let labels: Vec<&str> = slots.iter().map(|p| p.label.as_str()).collect();
+            anyhow::anyhow!(
+                "No OTA partition '{}' (OTA partitions: {})",
+                slot,
+                labels.join(", ")
+            )
+        })?;
+
+    let mut data = read_otadata(cli, &project_dir, otadata).await?;
+    otadata::select_slot(&mut data, index, slots.len())?;
+
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+    let path = build_dir.join("otadata-write.bin");
+    std::fs::write(&path, &data)?;
+
+    println!(
+        "Switching the boot partition to '{}' (0x{:x})...",
+        slots[index].label, slots[index].offset
+    );
+    let offset = format!("0x{:x}", otadata.offset);
+    let path_arg = path.to_string_lossy();
+    let result = flash::run_esptool(cli, &project_dir, &["write_flash", &offset, &path_arg]).await;
+    let _ = std::fs::remove_file(&path);
+    result?;
+
+    println!("OTA partition switch completed successfully!");
+    Ok(())
+}
+
+/// Erase otadata, so the bootloader starts the factory app (or ota_0 without one)
+pub async fn execute_erase_otadata(cli: &Cli) -> Result<()> {
+    utils::setup_idf_environment()?;
+
+    let (project_dir, partitions) = partition_commands::load_partitions(cli)?;
+    let otadata = partitions
+        .iter()
+        .find(|partition| partition.is_otadata())
+        .ok_or_else(|| anyhow::anyhow!("The partition table has no otadata partition"))?;
+
+    cli.confirm_destructive(
+        "This erases otadata, the device boots the factory app (or ota_0) afterwards.",
+    )?;
+    println!("Erasing otadata (0x{:x})...", otadata.offset);
+    let offset = format!("0x{:x}", otadata.offset);
+    let size = format!("0x{:x}", otadata.size);
+    flash::run_esptool(cli, &project_dir, &["erase_region", &offset, &size]).await?;
+
+    println!("otadata erase completed successfully!");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_server_byte_range() {
+        let check = "from ota_server import byte_range\n\
+            assert byte_range('', 100) == (0, 99, 200)\n\
+            assert byte_range('bytes=10-19', 100) == (10, 19, 206)\n\
+            assert byte_range('bytes=-10', 100) == (90, 99, 206)\n\
+            assert byte_range('bytes=90-200', 100) == (90, 99, 206)\n\
+            assert byte_range('bytes=100-', 100) is None\n";
+        let output = std::process::Command::new("python3")
+            .args(["-c", check])
+            .current_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/src/commands"))
+            .env("PYTHONDONTWRITEBYTECODE", "1")
+            .output();
+        // The server runs with the ESP-IDF Python, which isn't required for the tests
+        let Ok(output) = output else {
+            return;
+        };
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+}
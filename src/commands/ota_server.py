@@ -0,0 +1,94 @@
+"""HTTP(S) server for esp_https_ota tests, run by `idf-rs ota serve` with the ESP-IDF
+Python. It serves one image with Range support (used by partial HTTP downloads) and logs
+every request.
+
+Usage: ota_server.py IMAGE NAME PORT [CERT KEY]
+"""
+import http.server
+import os
+import ssl
+import sys
+import time
+
+
+def byte_range(header, size):
+    """Return (start, end, status) for a Range header of a file of `size` bytes, with
+    an inclusive end, or None when the range can't be satisfied."""
+    if not header.startswith('bytes='):
+        return 0, size - 1, 200
+    first, _, last = header[6:].partition('-')
+    start = int(first) if first else max(size - int(last), 0)
+    end = int(last) if first and last else size - 1
+    if start >= size or end < start:
+        return None
+    return start, min(end, size - 1), 206
+
+
+def make_handler(image, name):
+    class Handler(http.server.BaseHTTPRequestHandler):
+        protocol_version = 'HTTP/1.1'
+
+        def log_message(self, format, *args):
+            pass
+
+        def log(self, status, length):
+            print('%s %s %s %s -> %d (%d bytes) range=%s agent=%s' % (
+                time.strftime('%H:%M:%S'), self.client_address[0], self.command, self.path,
+                status, length, self.headers.get('Range', '-'),
+                self.headers.get('User-Agent', '-')), flush=True)
+
+        def send_empty(self, status, headers=()):
+            self.send_response(status)
+            for header in headers:
+                self.send_header(*header)
+            self.send_header('Content-Length', '0')
+            self.end_headers()
+            self.log(status, 0)
+
+        def send(self, head):
+            if self.path.split('?')[0] != '/' + name:
+                self.send_empty(404)
+                return
+            size = os.path.getsize(image)
+            requested = byte_range(self.headers.get('Range', ''), size)
+            if requested is None:
+                self.send_empty(416, [('Content-Range', 'bytes */%d' % size)])
+                return
+            start, end, status = requested
+            self.send_response(status)
+            self.send_header('Content-Type', 'application/octet-stream')
+            self.send_header('Content-Length', str(end - start + 1))
+            self.send_header('Accept-Ranges', 'bytes')
+            if status == 206:
+                self.send_header('Content-Range', 'bytes %d-%d/%d' % (start, end, size))
+            self.end_headers()
+            if not head:
+                with open(image, 'rb') as f:
+                    f.seek(start)
+                    self.wfile.write(f.read(end - start + 1))
+            self.log(status, end - start + 1)
+
+        def do_GET(self):
+            self.send(False)
+
+        def do_HEAD(self):
+            self.send(True)
+
+    return Handler
+
+
+def main(argv):
+    image, name, port = argv[1], argv[2], int(argv[3])
+    server = http.server.ThreadingHTTPServer(('', port), make_handler(image, name))
+    if len(argv) > 5:
+        context = ssl.SSLContext(ssl.PROTOCOL_TLS_SERVER)
+        context.load_cert_chain(argv[4], argv[5])
+        server.socket = context.wrap_socket(server.socket, server_side=True)
+    try:
+        server.serve_forever()
+    except KeyboardInterrupt:
+        pass
+
+
+if __name__ == '__main__':
+    main(sys.argv)
@@ -0,0 +1,314 @@
+use crate::commands::{flash, size};
+use crate::partition_table::{self, CsvEntry, PartitionCsv};
+use crate::{config, utils, Cli};
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// The project's partition CSV with the table offset and flash size from sdkconfig
+struct PartitionSettings {
+    csv_path: PathBuf,
+    table_offset: u32,
+    flash_size: Option<u32>,
+    /// Whether the table ends with an MD5 checksum entry
+    md5: bool,
+}
+
+/// Settings for the custom partition table, or with `builtin` also for the ESP-IDF
+/// table the project selected
+fn load_settings(
+    project_dir: &Path,
+    file: Option<&Path>,
+    builtin: bool,
+) -> Result<PartitionSettings> {
+    let sdk_config = config::load_project_config(project_dir)?;
+    let setting = |key: &str| {
+        sdk_config
+            .settings
+            .get(key)
+            .map(|value| value.trim_matches('"').to_string())
+    };
+
+    let csv_path = match file {
+        Some(file) => file.to_path_buf(),
+        None if setting("CONFIG_PARTITION_TABLE_CUSTOM").as_deref() == Some("y") => {
+            let filename = setting("CONFIG_PARTITION_TABLE_CUSTOM_FILENAME")
+                .unwrap_or_else(|| "partitions.csv".to_string());
+            project_dir.join(filename)
+        }
+        None if builtin => {
+            let filename = setting("CONFIG_PARTITION_TABLE_FILENAME")
+                .unwrap_or_else(|| "partitions_singleapp.csv".to_string());
+            utils::get_idf_path()?
+                .join("components")
+                .join("partition_table")
+                .join(filename)
+        }
+        None => {
+            return Err(anyhow::anyhow!(
+                "The project uses a built-in partition table. Enable CONFIG_PARTITION_TABLE_CUSTOM or pass --file."
+            ));
+        }
+    };
+
+    let table_offset = setting("CONFIG_PARTITION_TABLE_OFFSET")
+        .and_then(|offset| partition_table::parse_size(&offset))
+        .unwrap_or(partition_table::DEFAULT_TABLE_OFFSET);
+    let flash_size = setting("CONFIG_ESPTOOLPY_FLASHSIZE")
+        .and_then(|size| partition_table::parse_size(size.trim_end_matches('B')));
+
+    // Enabled by default; once sdkconfig exists a disabled option is only a comment
+    let md5 = sdk_config.settings.is_empty()
+        || setting("CONFIG_PARTITION_TABLE_MD5").as_deref() == Some("y");
+
+    Ok(PartitionSettings {
+        csv_path,
+        table_offset,
+        flash_size,
+        md5,
+    })
+}
+
+fn load_csv(settings: &PartitionSettings) -> Result<PartitionCsv> {
+    let content = std::fs::read_to_string(&settings.csv_path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", settings.csv_path.display(), e))?;
+    PartitionCsv::parse(&content, settings.table_offset)
+        .map_err(|e| anyhow::anyhow!("{}: {}", settings.csv_path.display(), e))
+}
+
+fn print_layout(csv: &PartitionCsv) {
+    println!(
+        "{:<16} {:<6} {:<10} {:>10} {:>10}",
+        "LABEL", "TYPE", "SUBTYPE", "OFFSET", "SIZE"
+    );
+    for (entry, offset) in csv.layout() {
+        println!(
+            "{:<16} {:<6} {:<10} {:>10} {:>10}",
+            entry.label,
+            entry.ptype,
+            entry.subtype,
+            format!("0x{:x}", offset),
+            partition_table::format_size(entry.size)
+        );
+    }
+}
+
+/// Load the CSV, apply the edit, validate the result and write it back
+fn edit(
+    cli: &Cli,
+    file: Option<&Path>,
+    apply: impl FnOnce(&mut PartitionCsv) -> Result<()>,
+) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let settings = load_settings(&project_dir, file, false)?;
+    let mut csv = load_csv(&settings)?;
+
+    apply(&mut csv)?;
+
+    let errors = csv.validate(settings.flash_size);
+    if !errors.is_empty() {
+        print_layout(&csv);
+        return Err(anyhow::anyhow!(
+            "The edited partition table is invalid, {} was not changed:\n  {}",
+            settings.csv_path.display(),
+            errors.join("\n  ")
+        ));
+    }
+
+    std::fs::write(&settings.csv_path, csv.to_string())?;
+    print_layout(&csv);
+    println!("\nUpdated {}", settings.csv_path.display());
+    println!("Run 'idf-rs build' to regenerate the partition table");
+    Ok(())
+}
+
+pub async fn execute_resize(cli: &Cli, label: &str, size: &str, file: Option<&Path>) -> Result<()> {
+    let size = partition_table::parse_size(size).ok_or_else(|| {
+        anyhow::anyhow!("Invalid size '{}', use e.g. 0x100000, 1536K or 2M", size)
+    })?;
+    edit(cli, file, |csv| csv.resize(label, size))
+}
+
+pub async fn execute_add(
+    cli: &Cli,
+    spec: &str,
+    after: Option<&str>,
+    file: Option<&Path>,
+) -> Result<()> {
+    let entry = CsvEntry::parse(spec)?;
+    edit(cli, file, |csv| csv.add(entry, after))
+}
+
+/// Validate the project's partition table and write its binary to the build directory,
+/// like gen_esp32part.py does during the build
+fn generate(
+    cli: &Cli,
+    project_dir: &Path,
+    build_dir: &Path,
+) -> Result<(PartitionSettings, PathBuf)> {
+    let settings = load_settings(project_dir, None, true)?;
+    let csv = load_csv(&settings)?;
+
+    let errors = csv.validate(settings.flash_size);
+    if !errors.is_empty() {
+        return Err(anyhow::anyhow!(
+            "{} is invalid:\n  {}",
+            settings.csv_path.display(),
+            errors.join("\n  ")
+        ));
+    }
+
+    let binary = partition_table::generate_binary(&csv.partitions()?, settings.md5)?;
+    let bin_path = partition_table::get_partition_table_bin_path(build_dir);
+    if let Some(dir) = bin_path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&bin_path, binary)?;
+    if cli.verbose {
+        println!(
+            "Generated {} from {}",
+            bin_path.display(),
+            settings.csv_path.display()
+        );
+    }
+
+    Ok((settings, bin_path))
+}
+
+/// Print the partition table and check that the app fits its partitions
+pub async fn execute_print(cli: &Cli) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+
+    let (settings, bin_path) = generate(cli, &project_dir, &build_dir)?;
+    let partitions = partition_table::parse_binary(&std::fs::read(&bin_path)?)?;
+
+    println!("Partition table {}:", settings.csv_path.display());
+    println!(
+        "{:<16} {:<6} {:<10} {:>10} {:>10}  FLAGS",
+        "LABEL", "TYPE", "SUBTYPE", "OFFSET", "SIZE"
+    );
+    for partition in &partitions {
+        println!(
+            "{:<16} {:<6} {:<10} {:>10} {:>10}  {}",
+            partition.label,
+            partition_table::type_name(partition.ptype),
+            partition_table::subtype_name(partition.ptype, partition.subtype),
+            format!("0x{:x}", partition.offset),
+            partition_table::format_size(partition.size),
+            partition.flag_names().join(":")
+        );
+    }
+    println!();
+
+    size::check_app_partition_size(&build_dir, 100)
+}
+
+pub async fn execute_flash(cli: &Cli) -> Result<()> {
+    utils::setup_idf_environment()?;
+
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+
+    let (settings, bin_path) = generate(cli, &project_dir, &build_dir)?;
+
+    println!("Flashing partition table...");
+    let offset = format!("0x{:x}", settings.table_offset);
+    let bin_path = bin_path.to_string_lossy();
+    flash::run_esptool(cli, &project_dir, &["write_flash", &offset, &bin_path]).await?;
+
+    println!("Partition table flash completed successfully!");
+    Ok(())
+}
+
+/// The project's partitions, from the table generated for it, with the project directory
+pub fn load_partitions(cli: &Cli) -> Result<(PathBuf, Vec<partition_table::Partition>)> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+
+    let (_, bin_path) = generate(cli, &project_dir, &build_dir)?;
+    let partitions = partition_table::parse_binary(&std::fs::read(&bin_path)?)?;
+    Ok((project_dir, partitions))
+}
+
+pub async fn execute_erase_partition(cli: &Cli, name: &str) -> Result<()> {
+    utils::setup_idf_environment()?;
+
+    let (project_dir, partitions) = load_partitions(cli)?;
+    let partition = partition_table::find(&partitions, name)?;
+
+    cli.confirm_destructive(&format!(
+        "This erases the data of partition '{}' on the device.",
+        partition.label
+    ))?;
+    println!(
+        "Erasing partition '{}' (0x{:x}, {})...",
+        partition.label,
+        partition.offset,
+        partition_table::format_size(partition.size)
+    );
+    let offset = format!("0x{:x}", partition.offset);
+    let size = format!("0x{:x}", partition.size);
+    flash::run_esptool(cli, &project_dir, &["erase_region", &offset, &size]).await?;
+
+    println!("Partition erase completed successfully!");
+    Ok(())
+}
+
+pub async fn execute_read_partition(cli: &Cli, name: &str, output: &Path) -> Result<()> {
+    utils::setup_idf_environment()?;
+
+    let (project_dir, partitions) = load_partitions(cli)?;
+    let partition = partition_table::find(&partitions, name)?;
+
+    println!(
+        "Reading partition '{}' (0x{:x}, {}) to {}...",
+        partition.label,
+        partition.offset,
+        partition_table::format_size(partition.size),
+        output.display()
+    );
+    let offset = format!("0x{:x}", partition.offset);
+    let size = format!("0x{:x}", partition.size);
+    let output = output.to_string_lossy();
+    flash::run_esptool(cli, &project_dir, &["read_flash", &offset, &size, &output]).await?;
+
+    println!("Partition read completed successfully!");
+    Ok(())
+}
+
+pub async fn execute_write_partition(cli: &Cli, name: &str, input: &Path) -> Result<()> {
+    utils::setup_idf_environment()?;
+
+    let (project_dir, partitions) = load_partitions(cli)?;
+    let partition = partition_table::find(&partitions, name)?;
+
+    let input_size = std::fs::metadata(input)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", input.display(), e))?
+        .len();
+    if input_size > partition.size as u64 {
+        return Err(anyhow::anyhow!(
+            "{} ({} bytes) does not fit partition '{}' ({} bytes)",
+            input.display(),
+            input_size,
+            partition.label,
+            partition.size
+        ));
+    }
+
+    println!(
+        "Writing {} to partition '{}' (0x{:x}, {})...",
+        input.display(),
+        partition.label,
+        partition.offset,
+        partition_table::format_size(partition.size)
+    );
+    // Like parttool.py, clear the whole partition so no stale data follows the image
+    let offset = format!("0x{:x}", partition.offset);
+    let size = format!("0x{:x}", partition.size);
+    flash::run_esptool(cli, &project_dir, &["erase_region", &offset, &size]).await?;
+    let input = input.to_string_lossy();
+    flash::run_esptool(cli, &project_dir, &["write_flash", &offset, &input]).await?;
+
+    println!("Partition write completed successfully!");
+    Ok(())
+}
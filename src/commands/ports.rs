@@ -0,0 +1,173 @@
+use crate::commands::flash;
+use crate::serial_ports::SerialPortInfo;
+use crate::{serial_ports, state, utils, Cli};
+use anyhow::Result;
+
+pub async fn execute_list(names: bool) -> Result<()> {
+    let ports = serial_ports::available_ports()?;
+
+    if names {
+        for port in &ports {
+            println!("{}", port.port);
+        }
+        return Ok(());
+    }
+
+    if ports.is_empty() {
+        println!("No USB serial ports found.");
+        return Ok(());
+    }
+
+    println!(
+        "{:<20} {:<10} {:<14} {:<24} DESCRIPTION",
+        "PORT", "VID:PID", "ADAPTER", "SERIAL NUMBER"
+    );
+    for port in &ports {
+        println!(
+            "{:<20} {:<10} {:<14} {:<24} {}",
+            port.port,
+            port.vid_pid().as_deref().unwrap_or("-"),
+            port.adapter().unwrap_or("-"),
+            port.serial_number.as_deref().unwrap_or("-"),
+            port.description()
+        );
+        if let Some(by_id) = &port.by_id {
+            println!("{:<20} by-id: {}", "", by_id.display());
+        }
+    }
+
+    println!();
+    println!("Select a device by serial number with: idf-rs --device-id <SERIAL NUMBER> <command>");
+    Ok(())
+}
+
+/// Whether an ESP chip answers on `port`, asking for its ID with esptool
+async fn has_esp_chip(cli: &Cli, port: &str) -> bool {
+    let mut probe = cli.clone();
+    probe.port = Some(port.to_string());
+    let Ok(esptool) = flash::esptool_command(&probe, "auto") else {
+        return false;
+    };
+    let mut args: Vec<&str> = esptool[1..].iter().map(String::as_str).collect();
+    args.push("chip_id");
+    crate::utils::run_command_with_output(&esptool[0], &args, None)
+        .await
+        .is_ok()
+}
+
+/// Ask which of several ports to use, failing when the user can't be asked
+fn select_port(cli: &Cli, ports: &[SerialPortInfo]) -> Result<SerialPortInfo> {
+    let port_names = ports
+        .iter()
+        .map(|port| port.port.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    if !cli.can_prompt() {
+        return Err(anyhow::anyhow!(
+            "Several serial ports may have an ESP chip ({}), select one with --port or --device-id",
+            port_names
+        ));
+    }
+
+    let choices: Vec<String> = ports
+        .iter()
+        .map(|port| {
+            let description = match port.description() {
+                description if description.is_empty() => {
+                    port.adapter().unwrap_or("USB serial").to_string()
+                }
+                description => description,
+            };
+            format!(
+                "{:<20} {:<32} {}",
+                port.port,
+                description,
+                port.vid_pid().as_deref().unwrap_or("-")
+            )
+        })
+        .collect();
+
+    println!("Several serial ports may have an ESP chip:");
+    match utils::choose("Serial port to use?", &choices)? {
+        Some(index) => Ok(ports[index].clone()),
+        None => Err(anyhow::anyhow!("No serial port selected")),
+    }
+}
+
+/// Let the user pick one of several ports and remember the choice for the project
+fn select_and_remember(cli: &Cli, ports: &[SerialPortInfo]) -> Result<String> {
+    let selected = select_port(cli, ports)?;
+    // Don't ask again, later commands use the remembered port
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+    state::record_serial_settings(
+        &build_dir,
+        Some(&selected.port),
+        state::SessionBaud::Flash(None),
+    );
+    Ok(selected.port)
+}
+
+/// Find the port of the ESP device when none was given: the only port with a known
+/// ESP VID:PID, or among several, unless the user declines probing them, the only one
+/// where esptool finds a chip. Otherwise the user picks one, and the choice is
+/// remembered for the project; without a prompt that fails. None when no candidate
+/// is connected, leaving the port to the tools.
+pub async fn detect_port(cli: &Cli) -> Result<Option<String>> {
+    let candidates = match serial_ports::candidate_ports() {
+        Ok(candidates) => candidates,
+        // Enumeration isn't available everywhere, the tools then look for the port
+        Err(_) => return Ok(None),
+    };
+    // Probing a port another idf-rs uses would disturb its session
+    let (locked, candidates): (Vec<_>, Vec<_>) = candidates
+        .into_iter()
+        .partition(|port| serial_ports::locked_elsewhere(&port.port));
+    for port in &locked {
+        println!("Skipping {}, in use by another idf-rs", port.port);
+    }
+
+    let found = match candidates.as_slice() {
+        [] => return Ok(None),
+        [port] => port.clone(),
+        // Probing resets the boards, so the user is asked first. --yes, and running
+        // without anybody to ask (--non-interactive, CI), count as agreeing.
+        _ if cli.can_prompt()
+            && !cli.yes
+            && !utils::confirm(
+                &format!(
+                    "{} serial ports may have an ESP chip. Probe them with esptool (resets the boards)?",
+                    candidates.len()
+                ),
+                false,
+            )? =>
+        {
+            return select_and_remember(cli, &candidates).map(Some);
+        }
+        _ => {
+            println!(
+                "Probing {} serial ports for an ESP chip...",
+                candidates.len()
+            );
+            let mut responding = Vec::new();
+            for port in &candidates {
+                if has_esp_chip(cli, &port.port).await {
+                    responding.push(port.clone());
+                }
+            }
+            match responding.as_slice() {
+                [port] => port.clone(),
+                [] => return Ok(None),
+                _ => return select_and_remember(cli, &responding).map(Some),
+            }
+        }
+    };
+
+    println!(
+        "Auto-detected port {} ({})",
+        found.port,
+        found.adapter().unwrap_or("USB serial")
+    );
+    Ok(Some(found.port))
+}
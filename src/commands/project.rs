@@ -1,4 +1,4 @@
-use crate::{utils, Cli};
+use crate::{build_systems, config, output, utils, version_spec, yaml, Cli};
 use anyhow::Result;
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -150,3 +150,225 @@ dependencies.lock
 
     Ok(())
 }
+
+/// Where the project's target was found
+fn detect_target(project_dir: &Path, build_dir: &Path) -> (String, &'static str) {
+    let from_file = |path: PathBuf| {
+        config::SdkConfig::load_from_file(&path)
+            .ok()
+            .and_then(|sdk_config| sdk_config.get_target().cloned())
+    };
+
+    if let Some(target) = from_file(config::get_sdkconfig_path(project_dir)) {
+        return (target, "sdkconfig");
+    }
+    let defaults = config::get_sdkconfig_defaults(project_dir, &[]);
+    if let Some(target) = config::load_sdkconfig_defaults(&defaults)
+        .ok()
+        .and_then(|defaults| defaults.get_target().cloned())
+    {
+        return (target, "sdkconfig defaults");
+    }
+    if let Some(target) = build_systems::get_cmake_cache_entry(build_dir, "IDF_TARGET") {
+        return (target, "CMakeCache.txt");
+    }
+    (utils::DEFAULT_TARGET.to_string(), "default")
+}
+
+/// Project name from `project(<name>)` in the root CMakeLists.txt
+fn project_name(cmake: &str) -> Option<String> {
+    cmake.lines().find_map(|line| {
+        let name = line.trim().strip_prefix("project(")?;
+        let name = name.split([')', ' ']).next()?.trim();
+        (!name.is_empty()).then(|| name.to_string())
+    })
+}
+
+/// Check a component directory for a supported CMakeLists.txt
+fn check_component(dir: &Path, warnings: &mut Vec<String>) {
+    let name = dir.display();
+    match fs::read_to_string(dir.join("CMakeLists.txt")) {
+        Ok(cmake) if cmake.contains("idf_component_register") => {}
+        Ok(cmake) if cmake.contains("register_component") => warnings.push(format!(
+            "{} uses the pre-IDF 4 register_component(), convert it with 'idf-rs migrate-component {}'",
+            name, name
+        )),
+        Ok(_) => warnings.push(format!(
+            "{}/CMakeLists.txt does not call idf_component_register()",
+            name
+        )),
+        Err(_) if dir.join("component.mk").exists() => warnings.push(format!(
+            "{} only has a GNU Make component.mk, convert it with 'idf-rs migrate-component {}'",
+            name, name
+        )),
+        Err(_) => warnings.push(format!("{} has no CMakeLists.txt", name)),
+    }
+}
+
+/// Things in the project layout that the build or idf-rs will trip over
+fn check_project_structure(
+    project_dir: &Path,
+    target: &str,
+    idf_version: Option<&str>,
+) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    let cmake = fs::read_to_string(project_dir.join("CMakeLists.txt")).unwrap_or_default();
+    if !cmake.contains("tools/cmake/project.cmake") {
+        warnings.push(
+            "CMakeLists.txt does not include $ENV{IDF_PATH}/tools/cmake/project.cmake".to_string(),
+        );
+    }
+    if project_dir.join("Makefile").exists() {
+        warnings
+            .push("Makefile found, GNU Make builds are no longer supported by ESP-IDF".to_string());
+    }
+
+    let main_dir = project_dir.join("main");
+    if main_dir.is_dir() {
+        check_component(&main_dir, &mut warnings);
+    } else {
+        warnings.push("No main component (main/ directory)".to_string());
+    }
+
+    if let Ok(entries) = fs::read_dir(project_dir.join("components")) {
+        let mut components: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_dir())
+            .collect();
+        components.sort();
+        for component in components {
+            check_component(&component, &mut warnings);
+        }
+    }
+
+    if let Ok(defaults) =
+        config::load_sdkconfig_defaults(&config::get_sdkconfig_defaults(project_dir, &[]))
+    {
+        if let Some(defaults_target) = defaults.get_target() {
+            if defaults_target != target {
+                warnings.push(format!(
+                    "sdkconfig is for {} but the sdkconfig defaults set {}",
+                    target, defaults_target
+                ));
+            }
+        }
+    }
+
+    // The IDF version required by the main component's manifest
+    if let (Some(idf_version), Ok(manifest)) = (
+        idf_version,
+        fs::read_to_string(main_dir.join("idf_component.yml")),
+    ) {
+        let required = yaml::parse(&manifest).ok().and_then(|manifest| {
+            manifest["dependencies"]["idf"]
+                .as_str()
+                .or_else(|| manifest["dependencies"]["idf"]["version"].as_str())
+                .map(str::to_string)
+        });
+        if let Some(required) = required {
+            if version_spec::matches(idf_version, &required) == Some(false) {
+                warnings.push(format!(
+                    "main/idf_component.yml requires ESP-IDF {}, but IDF_PATH is {}",
+                    required, idf_version
+                ));
+            }
+        }
+    }
+
+    warnings
+}
+
+fn render_settings(name: Option<&str>, target: &str, idf_version: Option<&str>) -> String {
+    let mut settings =
+        String::from("# idf-rs project settings, created by 'idf-rs init'\n\n[project]\n");
+    if let Some(name) = name {
+        settings.push_str(&format!("name = \"{}\"\n", name));
+    }
+    settings.push_str(&format!("target = \"{}\"\n", target));
+    if let Some(idf_version) = idf_version {
+        settings.push_str(&format!("idf_version = \"{}\"\n", idf_version));
+    }
+
+    settings.push_str(
+        r#"# port = "/dev/ttyUSB0"
+# baud = 921600
+# generator = "Ninja"
+# defines = ["CCACHE_ENABLE=1"]
+# sdkconfig_defaults = ["sdkconfig.defaults", "sdkconfig.ci"]
+
+# Defaults for 'idf-rs monitor'
+# [monitor]
+# print_filter = "*:I"
+
+# Shared compiler cache, check it with 'idf-rs cache doctor'
+# [cache]
+# backend = "ccache"
+# remote_url = "http://cache.example.com/ccache"
+
+# Defaults for 'idf-rs ota-package'
+# [ota]
+# output_dir = "dist"
+# channel = "stable"
+"#,
+    );
+    settings
+}
+
+/// Adopt an existing ESP-IDF project: detect its target and IDF version, check the layout and write idf.toml
+pub async fn execute_init(cli: &Cli, force: bool) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+
+    let cmake_path = project_dir.join("CMakeLists.txt");
+    if !cmake_path.exists() {
+        return Err(anyhow::anyhow!(
+            "{} is not an ESP-IDF project (no CMakeLists.txt), use 'idf-rs create-project' for new projects",
+            project_dir.display()
+        ));
+    }
+
+    let settings_path = config::get_project_settings_path(&project_dir);
+    if settings_path.exists() && !force {
+        return Err(anyhow::anyhow!(
+            "{} already exists, use --force to overwrite it",
+            settings_path.display()
+        ));
+    }
+
+    let name = project_name(&fs::read_to_string(&cmake_path)?);
+    let (target, target_source) = detect_target(&project_dir, &build_dir);
+    let idf_version = utils::get_idf_version();
+
+    println!("Initializing idf-rs for {}", project_dir.display());
+    if let Some(name) = &name {
+        println!("  Project:      {}", name);
+    }
+    println!("  Target:       {} (from {})", target, target_source);
+    match &idf_version {
+        Some(version) => println!("  ESP-IDF:      {}", version),
+        None => println!("  ESP-IDF:      unknown (IDF_PATH not set)"),
+    }
+
+    let warnings = check_project_structure(&project_dir, &target, idf_version.as_deref());
+    if !config::get_sdkconfig_path(&project_dir).exists() {
+        println!(
+            "  No sdkconfig yet, run 'idf-rs set-target {}' to create it",
+            target
+        );
+    }
+    for warning in &warnings {
+        output::warning(warning.clone());
+    }
+
+    fs::write(
+        &settings_path,
+        render_settings(name.as_deref(), &target, idf_version.as_deref()),
+    )?;
+    println!("Created {}", settings_path.display());
+    if warnings.is_empty() {
+        println!("Project structure looks good");
+    }
+
+    Ok(())
+}
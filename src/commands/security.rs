@@ -0,0 +1,493 @@
+use crate::commands::{efuse, flash};
+use crate::config::{self, SdkConfig};
+use crate::{app_image, utils, Cli};
+use anyhow::Result;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// An efuse key block and what it is used for
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyBlock {
+    pub name: String,
+    pub purpose: String,
+    pub read_protected: bool,
+    pub empty: bool,
+}
+
+impl KeyBlock {
+    /// Keys that must never be readable by software
+    fn is_secret(&self) -> bool {
+        self.purpose.contains("XTS_AES")
+            || self.purpose.starts_with("HMAC")
+            || self.purpose.starts_with("ECDSA")
+            || self.purpose == "FLASH_ENCRYPTION"
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SecurityStatus {
+    /// Secure boot version, if enabled
+    pub secure_boot: Option<&'static str>,
+    pub flash_encryption: bool,
+    /// Flash encryption release mode: plaintext downloads can no longer be encrypted on the device
+    pub flash_encryption_release: bool,
+    /// Disabled JTAG interfaces: "pad", "usb" or "soft"
+    pub jtag_disabled: Vec<&'static str>,
+    pub download_mode: &'static str,
+    pub keys: Vec<KeyBlock>,
+}
+
+fn efuse_value<'a>(efuses: &'a Value, name: &str) -> Option<&'a Value> {
+    efuses.get(name).and_then(|efuse| efuse.get("value"))
+}
+
+fn flag(efuses: &Value, name: &str) -> bool {
+    match efuse_value(efuses, name) {
+        Some(Value::Bool(value)) => *value,
+        Some(Value::Number(value)) => value.as_u64().unwrap_or(0) != 0,
+        _ => false,
+    }
+}
+
+/// Crypt counters enable encryption when an odd number of bits is set
+fn odd_bit_count(efuses: &Value, name: &str) -> bool {
+    efuse_value(efuses, name)
+        .and_then(Value::as_u64)
+        .is_some_and(|count| count.count_ones() % 2 == 1)
+}
+
+fn key_block(efuses: &Value, name: &str, purpose: String) -> Option<KeyBlock> {
+    let efuse = efuses.get(name)?;
+    let read_protected = efuse.get("readable").and_then(Value::as_bool) == Some(false);
+    let value = efuse
+        .get("value")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let empty = !read_protected && value.chars().all(|c| c == '0' || c == ' ');
+
+    Some(KeyBlock {
+        name: name.to_string(),
+        purpose,
+        read_protected,
+        empty,
+    })
+}
+
+fn key_blocks(efuses: &Value) -> Vec<KeyBlock> {
+    let keys: Vec<KeyBlock> = (0..6)
+        .filter_map(|index| {
+            let purpose = match efuse_value(efuses, &format!("KEY_PURPOSE_{}", index)) {
+                Some(Value::String(purpose)) => purpose.clone(),
+                Some(purpose) => purpose.to_string(),
+                None => "USER".to_string(),
+            };
+            key_block(efuses, &format!("BLOCK_KEY{}", index), purpose)
+        })
+        .collect();
+    if !keys.is_empty() {
+        return keys;
+    }
+
+    // ESP32 has dedicated key blocks
+    [
+        ("BLOCK1", "FLASH_ENCRYPTION"),
+        ("BLOCK2", "SECURE_BOOT"),
+        ("BLOCK3", "USER"),
+    ]
+    .iter()
+    .filter_map(|(name, purpose)| key_block(efuses, name, purpose.to_string()))
+    .collect()
+}
+
+/// Derive the security status from the output of `espefuse.py summary --format json`
+pub fn parse_security_status(efuses: &Value) -> SecurityStatus {
+    let is_esp32 = efuses.get("FLASH_CRYPT_CNT").is_some();
+
+    let secure_boot = if flag(efuses, "ABS_DONE_1") {
+        Some("v2")
+    } else if flag(efuses, "ABS_DONE_0") {
+        Some("v1")
+    } else if flag(efuses, "SECURE_BOOT_EN") {
+        Some("v2")
+    } else {
+        None
+    };
+
+    let (flash_encryption, flash_encryption_release) = if is_esp32 {
+        (
+            odd_bit_count(efuses, "FLASH_CRYPT_CNT"),
+            flag(efuses, "DISABLE_DL_ENCRYPT"),
+        )
+    } else {
+        (
+            odd_bit_count(efuses, "SPI_BOOT_CRYPT_CNT"),
+            flag(efuses, "DIS_DOWNLOAD_MANUAL_ENCRYPT"),
+        )
+    };
+
+    let mut jtag_disabled = Vec::new();
+    if flag(efuses, "JTAG_DISABLE") || flag(efuses, "DIS_PAD_JTAG") {
+        jtag_disabled.push("pad");
+    }
+    if flag(efuses, "DIS_USB_JTAG") {
+        jtag_disabled.push("usb");
+    }
+    if odd_bit_count(efuses, "SOFT_DIS_JTAG") {
+        jtag_disabled.push("soft");
+    }
+
+    let download_mode = if flag(efuses, "UART_DOWNLOAD_DIS") || flag(efuses, "DIS_DOWNLOAD_MODE") {
+        "disabled"
+    } else if flag(efuses, "ENABLE_SECURITY_DOWNLOAD") {
+        "secure"
+    } else {
+        "enabled"
+    };
+
+    SecurityStatus {
+        secure_boot,
+        flash_encryption,
+        flash_encryption_release,
+        jtag_disabled,
+        download_mode,
+        keys: key_blocks(efuses),
+    }
+}
+
+/// Reasons the device is not locked down for production
+pub fn production_issues(status: &SecurityStatus) -> Vec<String> {
+    let mut issues = Vec::new();
+
+    if status.secure_boot.is_none() {
+        issues.push("secure boot is disabled".to_string());
+    }
+    if !status.flash_encryption {
+        issues.push("flash encryption is disabled".to_string());
+    } else if !status.flash_encryption_release {
+        issues.push("flash encryption is in development mode".to_string());
+    }
+    if status.jtag_disabled.is_empty() {
+        issues.push("JTAG is enabled".to_string());
+    }
+    if status.download_mode == "enabled" {
+        issues.push("UART download mode is not restricted".to_string());
+    }
+    for key in &status.keys {
+        if key.is_secret() && !key.empty && !key.read_protected {
+            issues.push(format!(
+                "{} ({}) is not read-protected",
+                key.name, key.purpose
+            ));
+        }
+    }
+
+    issues
+}
+
+/// Report secure boot, flash encryption, JTAG and key block state of the connected device
+pub async fn execute(cli: &Cli) -> Result<()> {
+    utils::setup_idf_environment()?;
+
+    println!("Reading efuses...");
+    let efuses = efuse::read_efuses(cli, false).await?;
+    let status = parse_security_status(&efuses);
+
+    println!();
+    println!("Security status:");
+    match status.secure_boot {
+        Some(version) => println!("  Secure boot:       enabled ({})", version),
+        None => println!("  Secure boot:       disabled"),
+    }
+    if status.flash_encryption {
+        let mode = if status.flash_encryption_release {
+            "release"
+        } else {
+            "development"
+        };
+        println!("  Flash encryption:  enabled ({} mode)", mode);
+    } else {
+        println!("  Flash encryption:  disabled");
+    }
+    if status.jtag_disabled.is_empty() {
+        println!("  JTAG:              enabled");
+    } else {
+        println!(
+            "  JTAG:              disabled ({})",
+            status.jtag_disabled.join(", ")
+        );
+    }
+    println!("  Download mode:     {}", status.download_mode);
+
+    if !status.keys.is_empty() {
+        println!();
+        println!("Key blocks:");
+        for key in &status.keys {
+            let state = if key.read_protected {
+                "read-protected"
+            } else if key.empty {
+                "empty"
+            } else {
+                "readable"
+            };
+            println!("  {:<12} {:<28} {}", key.name, key.purpose, state);
+        }
+    }
+
+    println!();
+    let issues = production_issues(&status);
+    if issues.is_empty() {
+        println!("Device is locked down for production");
+    } else {
+        println!("Device is not locked down for production:");
+        for issue in &issues {
+            println!("  - {}", issue);
+        }
+    }
+
+    Ok(())
+}
+
+fn enabled(sdkconfig: &SdkConfig, name: &str) -> bool {
+    sdkconfig.settings.get(name).map(String::as_str) == Some("y")
+}
+
+/// Secure boot version the project is configured for, None when it is disabled
+pub fn configured_secure_boot(sdkconfig: &SdkConfig) -> Option<u8> {
+    if !enabled(sdkconfig, "CONFIG_SECURE_BOOT") {
+        None
+    } else if enabled(sdkconfig, "CONFIG_SECURE_BOOT_V1_ENABLED") {
+        Some(1)
+    } else {
+        Some(2)
+    }
+}
+
+/// espsecure signing scheme of the project's secure boot v2 configuration
+fn signing_scheme(sdkconfig: &SdkConfig) -> &'static str {
+    if !enabled(sdkconfig, "CONFIG_SECURE_SIGNED_APPS_ECDSA_V2_SCHEME") {
+        "rsa3072"
+    } else if enabled(sdkconfig, "CONFIG_SECURE_BOOT_ECDSA_KEY_LEN_384_BITS") {
+        "ecdsa384"
+    } else if enabled(sdkconfig, "CONFIG_SECURE_BOOT_ECDSA_KEY_LEN_192_BITS") {
+        "ecdsa192"
+    } else {
+        "ecdsa256"
+    }
+}
+
+/// The signing key set in sdkconfig (CONFIG_SECURE_BOOT_SIGNING_KEY), relative to the project
+fn configured_signing_key(project_dir: &Path, sdkconfig: &SdkConfig) -> Option<PathBuf> {
+    let key = sdkconfig.settings.get("CONFIG_SECURE_BOOT_SIGNING_KEY")?;
+    let key = key.trim_matches('"');
+    (!key.is_empty()).then(|| project_dir.join(key))
+}
+
+/// Run espsecure.py of the ESP-IDF esptool with the ESP-IDF Python
+async fn run_espsecure(cli: &Cli, args: &[&str]) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let python = utils::get_python_executable()?;
+    let espsecure_path = utils::esptool_script("espsecure")?;
+
+    let mut espsecure_args = vec![espsecure_path.to_str().unwrap()];
+    espsecure_args.extend_from_slice(args);
+    utils::run_command(&python, &espsecure_args, Some(&project_dir), cli.verbose).await
+}
+
+/// Sign a bootloader or app image, with the key and secure boot version of sdkconfig
+/// unless given. Without an output file the image is signed in place.
+pub async fn execute_sign_data(
+    cli: &Cli,
+    datafile: &Path,
+    keyfile: Option<&Path>,
+    output: Option<&Path>,
+) -> Result<()> {
+    utils::setup_idf_environment()?;
+
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let sdkconfig = config::load_project_config(&project_dir)?;
+    let keyfile = match keyfile {
+        Some(keyfile) => keyfile.to_path_buf(),
+        None => configured_signing_key(&project_dir, &sdkconfig).ok_or_else(|| {
+            anyhow::anyhow!("No signing key given and CONFIG_SECURE_BOOT_SIGNING_KEY is not set")
+        })?,
+    };
+    let version = configured_secure_boot(&sdkconfig).unwrap_or(2).to_string();
+
+    let keyfile = keyfile.to_string_lossy();
+    let datafile = datafile.to_string_lossy();
+    let mut args = vec!["sign_data", "--version", &version, "--keyfile", &keyfile];
+    let output = output.map(|output| output.to_string_lossy());
+    if let Some(output) = &output {
+        args.extend_from_slice(&["--output", output]);
+    }
+    args.push(&datafile);
+    run_espsecure(cli, &args).await?;
+
+    println!("Signed {}", output.as_deref().unwrap_or(&datafile));
+    Ok(())
+}
+
+/// Generate a secure boot signing key, with the scheme of sdkconfig unless given
+pub async fn execute_generate_signing_key(
+    cli: &Cli,
+    keyfile: &Path,
+    scheme: Option<&str>,
+) -> Result<()> {
+    utils::setup_idf_environment()?;
+
+    if keyfile.exists() {
+        return Err(anyhow::anyhow!(
+            "{} already exists, refusing to overwrite a signing key",
+            keyfile.display()
+        ));
+    }
+
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let sdkconfig = config::load_project_config(&project_dir)?;
+    let version = configured_secure_boot(&sdkconfig).unwrap_or(2);
+
+    let keyfile = keyfile.to_string_lossy();
+    let version_str = version.to_string();
+    let mut args = vec!["generate_signing_key", "--version", &version_str];
+    if version == 2 {
+        args.extend_from_slice(&["--scheme", scheme.unwrap_or(signing_scheme(&sdkconfig))]);
+    }
+    args.push(&keyfile);
+    run_espsecure(cli, &args).await?;
+
+    println!(
+        "Keep {} secret, anyone with it can sign firmware for your devices",
+        keyfile
+    );
+    Ok(())
+}
+
+/// Whether `esptool get_security_info` reports secure boot as enabled, None when it
+/// doesn't say (the ESP32 doesn't support the command)
+fn parse_secure_boot_info(output: &str) -> Option<bool> {
+    output.lines().find_map(|line| {
+        let state = line.trim().strip_prefix("Secure Boot:")?.trim();
+        Some(state.starts_with("Enabled"))
+    })
+}
+
+/// Refuse to flash an app without a signature when the project or the connected device
+/// use secure boot v2: the bootloader wouldn't start it
+pub async fn check_signed_app(cli: &Cli, project_dir: &Path, app: &Path) -> Result<()> {
+    let Ok(image) = std::fs::read(app) else {
+        return Ok(());
+    };
+    if app_image::has_signature_block(&image) {
+        return Ok(());
+    }
+
+    let sdkconfig = config::load_project_config(project_dir)?;
+    match configured_secure_boot(&sdkconfig) {
+        // The v1 signature can't be told apart from the image without the key
+        Some(1) => Ok(()),
+        Some(_) => Err(anyhow::anyhow!(
+            "{} is not signed, but secure boot is enabled in sdkconfig. Sign it with \
+             'idf-rs secure-sign-data' (or enable CONFIG_SECURE_BOOT_BUILD_SIGNED_BINARIES), \
+             or flash anyway with --force.",
+            app.display()
+        )),
+        None => {
+            let esptool = flash::esptool_command(cli, "auto")?;
+            let mut args: Vec<&str> = esptool[1..].iter().map(String::as_str).collect();
+            args.push("get_security_info");
+            let output = utils::run_command_with_output(&esptool[0], &args, None).await;
+            match output.ok().as_deref().and_then(parse_secure_boot_info) {
+                Some(true) => Err(anyhow::anyhow!(
+                    "Secure boot is enabled on the device, but {} is not signed and wouldn't boot. \
+                     Enable secure boot in the project or flash anyway with --force.",
+                    app.display()
+                )),
+                _ => Ok(()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_security_status() {
+        let efuses: Value = serde_json::from_str(
+            r#"{
+                "SECURE_BOOT_EN": {"value": true, "readable": true},
+                "SPI_BOOT_CRYPT_CNT": {"value": 1, "readable": true},
+                "DIS_DOWNLOAD_MANUAL_ENCRYPT": {"value": false, "readable": true},
+                "DIS_PAD_JTAG": {"value": true, "readable": true},
+                "DIS_USB_JTAG": {"value": false, "readable": true},
+                "SOFT_DIS_JTAG": {"value": 0, "readable": true},
+                "ENABLE_SECURITY_DOWNLOAD": {"value": true, "readable": true},
+                "KEY_PURPOSE_0": {"value": "XTS_AES_128_KEY", "readable": true},
+                "KEY_PURPOSE_1": {"value": "USER", "readable": true},
+                "BLOCK_KEY0": {"value": "?? ?? ??", "readable": false},
+                "BLOCK_KEY1": {"value": "00 00 00", "readable": true}
+            }"#,
+        )
+        .unwrap();
+
+        let status = parse_security_status(&efuses);
+        assert_eq!(status.secure_boot, Some("v2"));
+        assert!(status.flash_encryption);
+        assert!(!status.flash_encryption_release);
+        assert_eq!(status.jtag_disabled, vec!["pad"]);
+        assert_eq!(status.download_mode, "secure");
+        assert_eq!(status.keys.len(), 2);
+        assert!(status.keys[0].read_protected);
+        assert!(status.keys[1].empty);
+        assert_eq!(
+            production_issues(&status),
+            vec!["flash encryption is in development mode"]
+        );
+
+        let esp32: Value = serde_json::from_str(
+            r#"{
+                "FLASH_CRYPT_CNT": {"value": 3, "readable": true},
+                "BLOCK1": {"value": "12 34", "readable": true}
+            }"#,
+        )
+        .unwrap();
+        let status = parse_security_status(&esp32);
+        assert!(!status.flash_encryption);
+        assert_eq!(status.keys[0].purpose, "FLASH_ENCRYPTION");
+        assert_eq!(production_issues(&status).len(), 5);
+    }
+
+    #[test]
+    fn test_secure_boot_config() {
+        let sdkconfig = SdkConfig::parse_sdkconfig(
+            "CONFIG_SECURE_BOOT=y\n\
+             CONFIG_SECURE_BOOT_V2_ENABLED=y\n\
+             CONFIG_SECURE_SIGNED_APPS_ECDSA_V2_SCHEME=y\n\
+             CONFIG_SECURE_BOOT_SIGNING_KEY=\"keys/signing.pem\"\n",
+        )
+        .unwrap();
+        assert_eq!(configured_secure_boot(&sdkconfig), Some(2));
+        assert_eq!(signing_scheme(&sdkconfig), "ecdsa256");
+        assert_eq!(
+            configured_signing_key(Path::new("/project"), &sdkconfig),
+            Some(PathBuf::from("/project/keys/signing.pem"))
+        );
+
+        let sdkconfig = SdkConfig::parse_sdkconfig("CONFIG_SECURE_BOOT=n\n").unwrap();
+        assert_eq!(configured_secure_boot(&sdkconfig), None);
+        assert_eq!(signing_scheme(&sdkconfig), "rsa3072");
+    }
+
+    #[test]
+    fn test_parse_secure_boot_info() {
+        let output = "Security Information:\n\
+            =====================\n\
+            Flags: 0x00000000 (0b0)\n\
+            Secure Boot: Disabled\n\
+            Flash Encryption: Disabled\n";
+        assert_eq!(parse_secure_boot_info(output), Some(false));
+        assert_eq!(parse_secure_boot_info("Secure Boot: Enabled\n"), Some(true));
+        assert_eq!(parse_secure_boot_info("A fatal error occurred"), None);
+    }
+}
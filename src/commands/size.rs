@@ -1,28 +1,21 @@
-use crate::{utils, Cli};
+use crate::{output, partition_table, utils, Cli};
 use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// Size report that can be chained after a build with `build --size`
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeReport {
+    /// Basic size information (same as `size`)
+    Summary,
+    /// Per-component size information (same as `size-components`)
+    Components,
+    /// Per-source-file size information (same as `size-files`)
+    Files,
+}
 
-pub async fn execute(cli: &Cli) -> Result<()> {
-    utils::setup_idf_environment()?;
-
-    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
-    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
-
-    if !build_dir.exists() {
-        return Err(anyhow::anyhow!(
-            "Build directory doesn't exist. Run 'build' command first."
-        ));
-    }
-
-    println!("Getting project size information...");
-
-    let python = utils::get_python_executable()?;
-    let idf_path = utils::get_idf_path()?;
-    let size_tool_path = idf_path.join("tools/idf_size.py");
-
-    let mut size_args = vec![size_tool_path.to_str().unwrap()];
-
-    // Find the ELF file - typically project_name.elf in build directory
-    let elf_files: Vec<_> = std::fs::read_dir(&build_dir)?
+/// Find the ELF file - typically project_name.elf in build directory
+fn find_elf_file(build_dir: &Path) -> Result<PathBuf> {
+    let elf_files: Vec<_> = std::fs::read_dir(build_dir)?
         .filter_map(|entry| entry.ok())
         .filter(|entry| {
             if let Some(extension) = entry.path().extension() {
@@ -33,25 +26,15 @@ pub async fn execute(cli: &Cli) -> Result<()> {
         })
         .collect();
 
-    if elf_files.is_empty() {
-        return Err(anyhow::anyhow!(
-            "No ELF files found in build directory. Build the project first."
-        ));
-    }
-
-    let elf_path_str;
     // Use the first ELF file found
-    if let Some(elf_file) = elf_files.first() {
-        elf_path_str = elf_file.path().to_string_lossy().to_string();
-        size_args.push(&elf_path_str);
-    }
-
-    utils::run_command(&python, &size_args, Some(&project_dir), cli.verbose).await?;
-
-    Ok(())
+    elf_files.first().map(|entry| entry.path()).ok_or_else(|| {
+        anyhow::anyhow!("No ELF files found in build directory. Build the project first.")
+    })
 }
 
-pub async fn execute_components(cli: &Cli) -> Result<()> {
+/// Command line of idf_size.py on the project ELF with the given extra arguments, and
+/// the project directory to run it in
+fn size_tool_command(cli: &Cli, extra_args: &[&str]) -> Result<(String, Vec<String>, PathBuf)> {
     utils::setup_idf_environment()?;
 
     let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
@@ -63,94 +46,281 @@ pub async fn execute_components(cli: &Cli) -> Result<()> {
         ));
     }
 
-    println!("Getting per-component size information...");
-
     let python = utils::get_python_executable()?;
     let idf_path = utils::get_idf_path()?;
     let size_tool_path = idf_path.join("tools/idf_size.py");
 
-    let mut size_args = vec![
-        size_tool_path.to_str().unwrap(),
-        "--archives", // Show per-component (archive) sizes
-    ];
+    let elf_path = find_elf_file(&build_dir)?;
 
-    // Find the ELF file
-    let elf_files: Vec<_> = std::fs::read_dir(&build_dir)?
-        .filter_map(|entry| entry.ok())
-        .filter(|entry| {
-            if let Some(extension) = entry.path().extension() {
-                extension == "elf"
-            } else {
-                false
-            }
-        })
-        .collect();
+    let mut size_args = vec![size_tool_path.to_string_lossy().to_string()];
+    size_args.extend(extra_args.iter().map(|arg| arg.to_string()));
+    size_args.push(elf_path.to_string_lossy().to_string());
 
-    if elf_files.is_empty() {
-        return Err(anyhow::anyhow!(
-            "No ELF files found in build directory. Build the project first."
-        ));
-    }
+    Ok((python, size_args, project_dir))
+}
 
-    let elf_path_str;
-    if let Some(elf_file) = elf_files.first() {
-        elf_path_str = elf_file.path().to_string_lossy().to_string();
-        size_args.push(&elf_path_str);
-    }
+/// Run idf_size.py on the project ELF with the given extra arguments
+async fn run_size_tool(cli: &Cli, extra_args: &[&str]) -> Result<()> {
+    let (python, size_args, project_dir) = size_tool_command(cli, extra_args)?;
+    let size_args: Vec<&str> = size_args.iter().map(String::as_str).collect();
+    utils::run_command(&python, &size_args, Some(&project_dir), cli.verbose).await
+}
 
-    utils::run_command(&python, &size_args, Some(&project_dir), cli.verbose).await?;
+/// Print the basic size information of idf_size.py
+async fn execute_summary(cli: &Cli) -> Result<()> {
+    println!("Getting project size information...");
 
-    Ok(())
+    run_size_tool(cli, &[]).await
 }
 
-pub async fn execute_files(cli: &Cli) -> Result<()> {
-    utils::setup_idf_environment()?;
+pub async fn execute(cli: &Cli) -> Result<()> {
+    execute_summary(cli).await?;
 
     let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
     let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+    check_app_partition_size(&build_dir, cli.app_size_threshold)?;
 
-    if !build_dir.exists() {
-        return Err(anyhow::anyhow!(
-            "Build directory doesn't exist. Run 'build' command first."
-        ));
-    }
+    Ok(())
+}
 
+pub async fn execute_components(cli: &Cli) -> Result<()> {
+    println!("Getting per-component size information...");
+
+    // Show per-component (archive) sizes
+    run_size_tool(cli, &["--archives"]).await
+}
+
+pub async fn execute_files(cli: &Cli) -> Result<()> {
     println!("Getting per-source-file size information...");
 
-    let python = utils::get_python_executable()?;
-    let idf_path = utils::get_idf_path()?;
-    let size_tool_path = idf_path.join("tools/idf_size.py");
+    // Show per-file sizes
+    run_size_tool(cli, &["--files"]).await
+}
 
-    let mut size_args = vec![
-        size_tool_path.to_str().unwrap(),
-        "--files", // Show per-file sizes
-    ];
+/// Run the given size report after a build, which checks the app partition size itself
+pub async fn execute_report(cli: &Cli, report: SizeReport) -> Result<()> {
+    match report {
+        SizeReport::Summary => execute_summary(cli).await,
+        SizeReport::Components => execute_components(cli).await,
+        SizeReport::Files => execute_files(cli).await,
+    }
+}
 
-    // Find the ELF file
-    let elf_files: Vec<_> = std::fs::read_dir(&build_dir)?
-        .filter_map(|entry| entry.ok())
-        .filter(|entry| {
-            if let Some(extension) = entry.path().extension() {
-                extension == "elf"
-            } else {
-                false
-            }
+/// Usage of one memory type of the `--format json2` output of idf_size.py
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryUsage {
+    /// Memory type, e.g. "DIRAM", "IRAM" or "Flash Code"
+    pub name: String,
+    pub used: u64,
+    /// Size of the memory type, 0 for flash which is bounded by the app partition
+    pub total: u64,
+}
+
+impl MemoryUsage {
+    pub fn is_flash(&self) -> bool {
+        self.name.starts_with("Flash")
+    }
+}
+
+/// Parse the memory types of the `layout` of idf_size.py's `--format json2` output
+pub fn parse_memory_usage(json: &str) -> Option<Vec<MemoryUsage>> {
+    let value: serde_json::Value = serde_json::from_str(json).ok()?;
+    value
+        .get("layout")?
+        .as_array()?
+        .iter()
+        .map(|memory| {
+            Some(MemoryUsage {
+                name: memory.get("name")?.as_str()?.to_string(),
+                used: memory.get("used")?.as_u64()?,
+                total: memory
+                    .get("total")
+                    .and_then(|total| total.as_u64())
+                    .unwrap_or(0),
+            })
         })
+        .collect()
+}
+
+/// Summary lines of the used RAM types and of flash, with the percentage used. Flash
+/// is compared against the app partition, when there is one.
+pub fn memory_summary_lines(usage: &[MemoryUsage], app_partition_size: Option<u64>) -> Vec<String> {
+    let line = |name: &str, used: u64, total: Option<u64>| match total.filter(|total| *total > 0) {
+        Some(total) => format!(
+            "  {:<6} {:>8} / {:>8} bytes ({:.1}%)",
+            name,
+            used,
+            total,
+            used as f64 * 100.0 / total as f64
+        ),
+        None => format!("  {:<6} {:>8} bytes", name, used),
+    };
+
+    let mut lines: Vec<String> = usage
+        .iter()
+        .filter(|memory| !memory.is_flash() && memory.used > 0)
+        .map(|memory| line(&memory.name, memory.used, Some(memory.total)))
         .collect();
+    let flash: u64 = usage
+        .iter()
+        .filter(|memory| memory.is_flash())
+        .map(|memory| memory.used)
+        .sum();
+    if flash > 0 {
+        lines.push(line("Flash", flash, app_partition_size));
+    }
+    lines
+}
 
-    if elf_files.is_empty() {
+/// Print the DRAM/IRAM/flash usage summary after a successful build. Failures are
+/// reported as warnings since the build itself already succeeded.
+pub async fn print_memory_summary(cli: &Cli) {
+    let summary = async {
+        let (python, size_args, project_dir) = size_tool_command(cli, &["--format", "json2"])?;
+        let size_args: Vec<&str> = size_args.iter().map(String::as_str).collect();
+        let json = utils::run_command_with_output(&python, &size_args, Some(&project_dir)).await?;
+        let usage = parse_memory_usage(&json)
+            .ok_or_else(|| anyhow::anyhow!("unexpected idf_size.py json2 output"))?;
+
+        let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+        let partition_size = app_size(&build_dir)
+            .ok()
+            .flatten()
+            .map(|app| app.partition_size);
+        Ok::<_, anyhow::Error>(memory_summary_lines(&usage, partition_size))
+    };
+
+    match summary.await {
+        Ok(lines) => {
+            println!("Memory usage summary:");
+            for line in lines {
+                println!("{}", line);
+            }
+        }
+        Err(e) => output::warning(format!("failed to print memory usage summary: {}", e)),
+    }
+}
+
+/// Percentage of the app partition above which a warning is printed
+const APP_PARTITION_WARN_PERCENT: f64 = 90.0;
+
+/// Size of the app binary against the smallest app partition, which it has to fit
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppSize {
+    /// File name of the app binary
+    pub binary: String,
+    pub size: u64,
+    pub partition: String,
+    pub partition_size: u64,
+}
+
+impl AppSize {
+    pub fn percent_used(&self) -> f64 {
+        self.size as f64 * 100.0 / self.partition_size as f64
+    }
+}
+
+/// The app size of the build, None when it has no app binary or app partition yet
+pub fn app_size(build_dir: &Path) -> Result<Option<AppSize>> {
+    let partitions = match partition_table::load_from_build_dir(build_dir)? {
+        Some(partitions) => partitions,
+        None => return Ok(None),
+    };
+
+    let partition = match partition_table::smallest_app_partition(&partitions) {
+        Some(partition) => partition,
+        None => return Ok(None),
+    };
+
+    let app_bin_path = match utils::get_app_bin_path(build_dir) {
+        Ok(path) if path.exists() => path,
+        _ => return Ok(None),
+    };
+
+    Ok(Some(AppSize {
+        binary: app_bin_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        size: std::fs::metadata(&app_bin_path)?.len(),
+        partition: partition.label.clone(),
+        partition_size: u64::from(partition.size),
+    }))
+}
+
+/// Compare the app binary size against the smallest app partition and report
+/// how much of it is used. Fails when usage exceeds `threshold` percent.
+pub fn check_app_partition_size(build_dir: &Path, threshold: u8) -> Result<()> {
+    let Some(app) = app_size(build_dir)? else {
+        return Ok(());
+    };
+    let percent = app.percent_used();
+    output::emit(&output::Event::AppSize {
+        binary: app.binary.clone(),
+        size: app.size,
+        partition: app.partition.clone(),
+        partition_size: app.partition_size,
+        percent_used: (percent * 10.0).round() / 10.0,
+    });
+
+    let summary = format!(
+        "{} binary size 0x{:x} bytes. Smallest app partition '{}' is 0x{:x} bytes ({:.1}% used, {} bytes free).",
+        app.binary,
+        app.size,
+        app.partition,
+        app.partition_size,
+        percent,
+        app.partition_size.saturating_sub(app.size)
+    );
+
+    if percent > f64::from(threshold) {
         return Err(anyhow::anyhow!(
-            "No ELF files found in build directory. Build the project first."
+            "{} App partition usage exceeds the {}% threshold.",
+            summary,
+            threshold
         ));
     }
 
-    let elf_path_str;
-    if let Some(elf_file) = elf_files.first() {
-        elf_path_str = elf_file.path().to_string_lossy().to_string();
-        size_args.push(&elf_path_str);
+    if percent >= APP_PARTITION_WARN_PERCENT {
+        output::warning(summary);
+    } else {
+        println!("{}", summary);
     }
 
-    utils::run_command(&python, &size_args, Some(&project_dir), cli.verbose).await?;
-
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_summary() {
+        let json = r#"{
+            "version": "1.1",
+            "target": "esp32c3",
+            "layout": [
+                {"name": "Flash Code", "total": 0, "used": 90000, "free": 0, "parts": {}},
+                {"name": "DIRAM", "total": 321296, "used": 64259, "free": 257037, "parts": {}},
+                {"name": "Flash Data", "total": 0, "used": 10000, "free": 0, "parts": {}},
+                {"name": "RTC SLOW", "total": 8192, "used": 0, "free": 8192, "parts": {}}
+            ]
+        }"#;
+        let usage = parse_memory_usage(json).unwrap();
+        assert_eq!(usage.len(), 4);
+        assert!(usage[0].is_flash());
+
+        assert_eq!(
+            memory_summary_lines(&usage, Some(1_000_000)),
+            vec![
+                "  DIRAM     64259 /   321296 bytes (20.0%)",
+                "  Flash    100000 /  1000000 bytes (10.0%)",
+            ]
+        );
+        assert_eq!(
+            memory_summary_lines(&usage, None)[1],
+            "  Flash    100000 bytes"
+        );
+        assert_eq!(parse_memory_usage("{}"), None);
+    }
+}
@@ -0,0 +1,201 @@
+use crate::stats::{self, CommandSummary, Invocation};
+use crate::{commands, config, output, utils, Cli};
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Instant;
+
+fn is_build_command(command: &str) -> bool {
+    matches!(command, "build" | "app" | "bootloader")
+}
+
+fn format_duration(ms: u64) -> String {
+    if ms >= 60_000 {
+        format!("{}m{:02}s", ms / 60_000, ms / 1000 % 60)
+    } else {
+        format!("{:.1}s", ms as f64 / 1000.0)
+    }
+}
+
+fn format_rate(rate: Option<f64>) -> String {
+    rate.map(|rate| format!("{:.0}%", rate))
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// Measures one invocation while metrics collection is enabled
+pub struct Recorder {
+    path: PathBuf,
+    project: String,
+    command: &'static str,
+    started: Instant,
+    cache_counters: Option<(u64, u64)>,
+}
+
+/// Start measuring a command, if the user opted into metrics collection
+pub async fn start(cli: &Cli, command: &'static str) -> Option<Recorder> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    if !config::load_stats_enabled(&project_dir).unwrap_or(false) {
+        return None;
+    }
+
+    let cache_counters = if is_build_command(command) {
+        commands::cache::hit_counters(cli, &project_dir).await
+    } else {
+        None
+    };
+
+    Some(Recorder {
+        path: stats::get_stats_path()?,
+        project: project_dir.to_string_lossy().to_string(),
+        command,
+        started: Instant::now(),
+        cache_counters,
+    })
+}
+
+impl Recorder {
+    pub async fn finish(self, cli: &Cli, result: &Result<()>) {
+        let duration_ms = self.started.elapsed().as_millis() as u64;
+
+        let mut cache_hits = None;
+        let mut cache_misses = None;
+        if let Some((hits_before, misses_before)) = self.cache_counters {
+            let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+            if let Some((hits, misses)) = commands::cache::hit_counters(cli, &project_dir).await {
+                // The counters go down when the statistics were zeroed during the build
+                cache_hits = Some(hits.saturating_sub(hits_before));
+                cache_misses = Some(misses.saturating_sub(misses_before));
+            }
+        }
+
+        let invocation = Invocation {
+            time: stats::now(),
+            project: self.project,
+            command: self.command.to_string(),
+            duration_ms,
+            success: result.is_ok(),
+            failure: result
+                .as_ref()
+                .err()
+                .map(|e| stats::failure_category(&format!("{:#}", e)).to_string()),
+            cache_hits,
+            cache_misses,
+        };
+
+        if let Err(e) = stats::append(&self.path, &invocation) {
+            output::warning(format!("failed to record stats: {}", e));
+        }
+    }
+}
+
+/// Summarize the recorded metrics of the past days
+pub async fn execute(cli: &Cli, days: u64, all_projects: bool) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let path = stats::get_stats_path()
+        .ok_or_else(|| anyhow::anyhow!("Cannot determine the idf-rs configuration directory"))?;
+
+    if !config::load_stats_enabled(&project_dir)? {
+        println!("Stats collection is disabled. Metrics are only recorded locally, to opt in add");
+        println!();
+        println!("  [stats]");
+        println!("  enabled = true");
+        println!();
+        if let Some(settings) = config::get_global_settings_path() {
+            println!("to {} (or the project's idf.toml).", settings.display());
+        }
+        if !path.exists() {
+            return Ok(());
+        }
+        println!();
+    }
+
+    let since = stats::now().saturating_sub(days * stats::SECONDS_PER_DAY);
+    let project = project_dir.to_string_lossy();
+    let invocations: Vec<Invocation> = stats::load(&path)?
+        .into_iter()
+        .filter(|invocation| invocation.time >= since)
+        .filter(|invocation| all_projects || invocation.project == project)
+        .collect();
+
+    if invocations.is_empty() {
+        println!("No invocations recorded in the last {} days", days);
+        return Ok(());
+    }
+
+    println!(
+        "Stats for {} over the last {} days ({} invocations)",
+        if all_projects {
+            "all projects"
+        } else {
+            &project
+        },
+        days,
+        invocations.len()
+    );
+
+    let mut commands: BTreeMap<&str, CommandSummary> = BTreeMap::new();
+    let mut weeks: BTreeMap<u64, CommandSummary> = BTreeMap::new();
+    let mut failures: BTreeMap<&str, usize> = BTreeMap::new();
+    for invocation in &invocations {
+        commands
+            .entry(invocation.command.as_str())
+            .or_default()
+            .add(invocation);
+        if is_build_command(&invocation.command) {
+            // Weeks start on Monday, the Unix epoch was a Thursday
+            let day = invocation.time / stats::SECONDS_PER_DAY;
+            weeks
+                .entry(day - (day + 3) % 7)
+                .or_default()
+                .add(invocation);
+        }
+        if let Some(failure) = &invocation.failure {
+            *failures.entry(failure.as_str()).or_default() += 1;
+        }
+    }
+
+    println!();
+    println!(
+        "{:<18} {:>6} {:>7} {:>9} {:>11}",
+        "Command", "Runs", "Failed", "Avg time", "Cache hits"
+    );
+    for (command, summary) in &commands {
+        println!(
+            "{:<18} {:>6} {:>7} {:>9} {:>11}",
+            command,
+            summary.runs,
+            summary.failures,
+            format_duration(summary.average_ms()),
+            format_rate(summary.cache_hit_rate())
+        );
+    }
+
+    if !weeks.is_empty() {
+        println!();
+        println!("Build trend:");
+        println!(
+            "{:<12} {:>6} {:>9} {:>11}",
+            "Week of", "Builds", "Avg time", "Cache hits"
+        );
+        for (day, summary) in &weeks {
+            let (year, month, date) = stats::civil_date(*day);
+            println!(
+                "{:<12} {:>6} {:>9} {:>11}",
+                format!("{}-{:02}-{:02}", year, month, date),
+                summary.runs,
+                format_duration(summary.average_ms()),
+                format_rate(summary.cache_hit_rate())
+            );
+        }
+    }
+
+    if !failures.is_empty() {
+        println!();
+        println!("Failures by category:");
+        for (category, count) in &failures {
+            println!("  {:<16} {}", category, count);
+        }
+    }
+
+    Ok(())
+}
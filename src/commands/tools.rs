@@ -0,0 +1,116 @@
+//! Escape hatch to the esptool, espefuse and espsecure of the ESP-IDF environment,
+//! run with the arguments as given plus the project's port and baud rate
+
+use crate::{utils, Cli};
+use anyhow::Result;
+
+/// A tool of the esptool package that can be run directly
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    Esptool,
+    Espefuse,
+    Espsecure,
+}
+
+impl Tool {
+    fn name(self) -> &'static str {
+        match self {
+            Tool::Esptool => "esptool",
+            Tool::Espefuse => "espefuse",
+            Tool::Espsecure => "espsecure",
+        }
+    }
+
+    /// espsecure works on files only
+    pub fn uses_serial_port(self) -> bool {
+        self != Tool::Espsecure
+    }
+}
+
+/// Whether the arguments set an option, as `--port X`, `--port=X` or `-p X`
+fn has_option(args: &[String], long: &str, short: &str) -> bool {
+    args.iter()
+        .any(|arg| arg == long || arg == short || arg.starts_with(&format!("{}=", long)))
+}
+
+/// Whether the arguments choose the serial port themselves
+pub fn has_port_option(args: &[String]) -> bool {
+    has_option(args, "--port", "-p")
+}
+
+/// The port and baud rate options of the project, for those the arguments don't set
+fn default_options(cli: &Cli, tool: Tool, args: &[String]) -> Vec<String> {
+    let mut options = Vec::new();
+    if !tool.uses_serial_port() {
+        return options;
+    }
+    if let Some(port) = &cli.port {
+        if !has_port_option(args) {
+            options.extend(["--port".to_string(), port.clone()]);
+        }
+    }
+    if let Some(baud) = cli.baud {
+        if !has_option(args, "--baud", "-b") {
+            options.extend(["--baud".to_string(), baud.to_string()]);
+        }
+    }
+    options
+}
+
+pub async fn execute(cli: &Cli, tool: Tool, args: &[String]) -> Result<()> {
+    utils::setup_idf_environment()?;
+
+    // A standalone esptool set for flashing is used here as well
+    let mut command = match std::env::var("IDF_RS_ESPTOOL") {
+        Ok(esptool) if tool == Tool::Esptool && !esptool.is_empty() => vec![esptool],
+        _ => vec![
+            utils::get_python_executable()?,
+            utils::esptool_script(tool.name())?
+                .to_string_lossy()
+                .to_string(),
+        ],
+    };
+    command.extend(default_options(cli, tool, args));
+    command.extend(args.iter().cloned());
+
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let command_args: Vec<&str> = command[1..].iter().map(String::as_str).collect();
+    utils::run_command(&command[0], &command_args, Some(&project_dir), cli.verbose).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_options() {
+        let mut cli = Cli::for_project("/project").unwrap();
+        cli.port = Some("/dev/ttyUSB0".to_string());
+        cli.baud = Some(921600);
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            default_options(&cli, Tool::Esptool, &args(&["chip_id"])),
+            args(&["--port", "/dev/ttyUSB0", "--baud", "921600"])
+        );
+        assert_eq!(
+            default_options(
+                &cli,
+                Tool::Espefuse,
+                &args(&["--port=/dev/ttyACM0", "summary"])
+            ),
+            args(&["--baud", "921600"])
+        );
+        assert_eq!(
+            default_options(
+                &cli,
+                Tool::Esptool,
+                &args(&["-p", "COM3", "-b", "115200", "read_mac"])
+            ),
+            Vec::<String>::new()
+        );
+        assert!(
+            default_options(&cli, Tool::Espsecure, &args(&["digest_sbv2_public_key"])).is_empty()
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use crate::{openocd, utils, Cli};
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+fn get_trace_dir(build_dir: &Path) -> PathBuf {
+    build_dir.join("trace")
+}
+
+/// Make sure the target has TRAX trace memory and return it
+fn check_target(project_dir: &Path, build_dir: &Path) -> Result<String> {
+    let target = openocd::resolve_target(project_dir, build_dir);
+    if !openocd::is_xtensa(&target) {
+        return Err(anyhow::anyhow!(
+            "TRAX trace memory is only available on Xtensa targets (esp32, esp32s2, esp32s3), not {}",
+            target
+        ));
+    }
+    Ok(target)
+}
+
+/// Look up the address of a symbol in the app ELF
+async fn resolve_symbol(build_dir: &Path, symbol: &str) -> Result<String> {
+    if symbol.starts_with("0x") {
+        return Ok(symbol.to_string());
+    }
+
+    let symbols = utils::read_elf_symbols(build_dir).await?;
+    symbols
+        .get(symbol)
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("Symbol '{}' not found in the app ELF", symbol))
+}
+
+/// Start tracing, optionally stopping automatically when `stop_at` (symbol or address) is executed
+pub async fn execute_start(cli: &Cli, stop_at: Option<&str>) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+    let target = check_target(&project_dir, &build_dir)?;
+
+    let mut start = "xtensa tracestart".to_string();
+    if let Some(stop_at) = stop_at {
+        let address = resolve_symbol(&build_dir, stop_at).await?;
+        println!("Trace will stop when {} ({}) is executed", stop_at, address);
+        start.push_str(&format!(" pc {}", address));
+    }
+
+    let commands = vec![
+        "init".to_string(),
+        "halt".to_string(),
+        start,
+        "resume".to_string(),
+    ];
+    openocd::run_commands(&target, &commands, &project_dir, cli.verbose).await?;
+
+    println!("Trace started. Run 'idf-rs trace dump' after the crash or event of interest.");
+    Ok(())
+}
+
+/// Stop tracing, save the trace memory in the build directory and decode it
+pub async fn execute_dump(cli: &Cli, decoder: Option<&str>) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+    let target = check_target(&project_dir, &build_dir)?;
+
+    let trace_dir = get_trace_dir(&build_dir);
+    std::fs::create_dir_all(&trace_dir)?;
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+    let dump_path = trace_dir.join(format!("trax-{}.bin", timestamp));
+
+    let commands = vec![
+        "init".to_string(),
+        "halt".to_string(),
+        "xtensa tracestop".to_string(),
+        format!("xtensa tracedump {}", dump_path.display()),
+        "resume".to_string(),
+    ];
+    openocd::run_commands(&target, &commands, &project_dir, cli.verbose).await?;
+
+    if !dump_path.exists() {
+        return Err(anyhow::anyhow!(
+            "OpenOCD did not write the trace dump, was the trace started with 'idf-rs trace start'?"
+        ));
+    }
+    println!("Trace memory saved to: {}", dump_path.display());
+
+    let decoder = match decoder {
+        Some(decoder) => decoder,
+        None => {
+            println!(
+                "To decode the instruction trace, pass --decoder (or set IDF_RS_TRAX_DECODER), e.g. \"xt-traxdump {{dump}} {{elf}}\""
+            );
+            return Ok(());
+        }
+    };
+
+    let elf = utils::get_app_elf_path(&build_dir)?;
+    let command = decoder
+        .replace("{dump}", dump_path.to_str().unwrap())
+        .replace("{elf}", elf.to_str().unwrap());
+    let args = crate::compile_commands::split_command_line(&command);
+    let (program, args) = args
+        .split_first()
+        .ok_or_else(|| anyhow::anyhow!("Empty trace decoder command"))?;
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let decoded = utils::run_command_with_output(program, &args, Some(&project_dir)).await?;
+    let decoded_path = dump_path.with_extension("txt");
+    std::fs::write(&decoded_path, &decoded)?;
+
+    println!("Decoded trace written to: {}", decoded_path.display());
+    Ok(())
+}
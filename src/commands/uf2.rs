@@ -0,0 +1,147 @@
+use crate::commands::flash;
+use crate::{flash_image, utils, Cli};
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// File present in the root of every UF2 bootloader drive
+const UF2_INFO_FILE: &str = "INFO_UF2.TXT";
+
+/// How long to wait for the drive to disappear after copying the image
+const REENUMERATION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Directories that may contain mounted removable drives
+fn mount_roots() -> Vec<PathBuf> {
+    let mut roots = Vec::new();
+
+    if cfg!(target_os = "macos") {
+        roots.push(PathBuf::from("/Volumes"));
+    } else if cfg!(windows) {
+        // Drive letters are mount points themselves
+        return ('D'..='Z')
+            .map(|letter| PathBuf::from(format!("{}:\\", letter)))
+            .collect();
+    } else {
+        if let Ok(user) = std::env::var("USER") {
+            roots.push(PathBuf::from("/media").join(&user));
+            roots.push(PathBuf::from("/run/media").join(&user));
+        }
+        roots.push(PathBuf::from("/media"));
+        roots.push(PathBuf::from("/mnt"));
+    }
+
+    roots
+        .into_iter()
+        .flat_map(|root| {
+            std::fs::read_dir(root)
+                .into_iter()
+                .flatten()
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+        })
+        .collect()
+}
+
+/// Find mounted UF2 bootloader drives, optionally matching the volume name
+fn find_uf2_drives(volume: Option<&str>) -> Vec<PathBuf> {
+    mount_roots()
+        .into_iter()
+        .filter(|path| path.join(UF2_INFO_FILE).exists())
+        .filter(|path| match volume {
+            Some(volume) => path
+                .file_name()
+                .map(|name| {
+                    name.to_string_lossy()
+                        .to_lowercase()
+                        .contains(&volume.to_lowercase())
+                })
+                .unwrap_or(false),
+            None => true,
+        })
+        .collect()
+}
+
+/// Wait until the drive disappears, which means the device rebooted into the new app
+async fn wait_for_reenumeration(drive: &Path) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < REENUMERATION_TIMEOUT {
+        if !drive.join(UF2_INFO_FILE).exists() {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    }
+    false
+}
+
+/// Write the UF2 image of the build (`uf2.bin`, or `uf2-app.bin` with only the app, the
+/// files idf.py uf2 and uf2-app produce) with the family ID of the target
+fn generate(build_dir: &Path, app_only: bool) -> Result<PathBuf> {
+    let (chip, mut regions) = flash::load_flash_regions(build_dir)?;
+    if app_only {
+        let flasher_args = flash::load_flasher_args(build_dir)?;
+        let app_offset = flasher_args["app"]["offset"]
+            .as_str()
+            .and_then(flash::parse_address)
+            .ok_or_else(|| anyhow::anyhow!("flasher_args.json has no app offset"))?;
+        regions.retain(|region| region.offset == app_offset);
+    }
+    let family = flash_image::uf2_family(&chip)
+        .ok_or_else(|| anyhow::anyhow!("No UF2 family ID is known for {}", chip))?;
+
+    let path = build_dir.join(if app_only { "uf2-app.bin" } else { "uf2.bin" });
+    std::fs::write(&path, flash_image::uf2(&regions, family)?)?;
+    Ok(path)
+}
+
+pub async fn execute(cli: &Cli, app_only: bool) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+
+    let path = generate(&build_dir, app_only)?;
+    println!("Generated UF2 image {}", path.display());
+    Ok(())
+}
+
+pub async fn execute_flash(cli: &Cli, volume: Option<&str>, app_only: bool) -> Result<()> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+
+    let uf2_path = generate(&build_dir, app_only)?;
+
+    let drives = find_uf2_drives(volume);
+    let drive = match drives.as_slice() {
+        [] => {
+            return Err(anyhow::anyhow!(
+                "No UF2 bootloader drive found. Put the device into UF2 bootloader mode \
+                 (usually by double-tapping reset) and make sure the drive is mounted."
+            ))
+        }
+        [drive] => drive,
+        _ => {
+            let names: Vec<String> = drives.iter().map(|d| d.display().to_string()).collect();
+            return Err(anyhow::anyhow!(
+                "Multiple UF2 drives found: {}. Use --volume to select one.",
+                names.join(", ")
+            ));
+        }
+    };
+
+    println!(
+        "Copying {} to UF2 drive {}...",
+        uf2_path.display(),
+        drive.display()
+    );
+    std::fs::copy(&uf2_path, drive.join("firmware.uf2"))?;
+
+    println!("Waiting for the device to reboot...");
+    if wait_for_reenumeration(drive).await {
+        println!("UF2 flash completed successfully!");
+    } else {
+        println!(
+            "Image copied, but {} is still mounted. The device may need a manual reset.",
+            drive.display()
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,226 @@
+use anyhow::Result;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// An entry of the compile_commands.json compilation database generated by CMake
+#[derive(Debug, Clone, Deserialize)]
+pub struct CompileCommand {
+    pub directory: PathBuf,
+    pub file: PathBuf,
+    pub command: Option<String>,
+    pub arguments: Option<Vec<String>>,
+    pub output: Option<PathBuf>,
+}
+
+impl CompileCommand {
+    /// Compiler invocation split into individual arguments
+    pub fn args(&self) -> Vec<String> {
+        match (&self.arguments, &self.command) {
+            (Some(arguments), _) => arguments.clone(),
+            (None, Some(command)) => split_command_line(command),
+            (None, None) => Vec::new(),
+        }
+    }
+
+    /// Object file produced by this command, relative to the build directory
+    pub fn object_path(&self) -> Option<PathBuf> {
+        if let Some(output) = &self.output {
+            return Some(output.clone());
+        }
+
+        let args = self.args();
+        args.iter()
+            .position(|arg| arg == "-o")
+            .and_then(|index| args.get(index + 1))
+            .map(PathBuf::from)
+    }
+
+    /// Compiler invocation with the object output replaced by another mode,
+    /// e.g. `-E` to preprocess or `-S` to generate assembly
+    pub fn args_for_mode(&self, mode: &str, output: Option<&Path>) -> Vec<String> {
+        let mut args = Vec::new();
+        let mut iter = self.args().into_iter();
+
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "-c" | "-MD" | "-MMD" => {}
+                "-o" | "-MF" | "-MT" | "-MQ" => {
+                    iter.next();
+                }
+                _ => args.push(arg),
+            }
+        }
+
+        args.push(mode.to_string());
+        if let Some(output) = output {
+            args.push("-o".to_string());
+            args.push(output.to_string_lossy().to_string());
+        }
+
+        args
+    }
+
+    /// Absolute path of the source file
+    pub fn source_path(&self) -> PathBuf {
+        if self.file.is_absolute() {
+            self.file.clone()
+        } else {
+            self.directory.join(&self.file)
+        }
+    }
+}
+
+pub fn get_compile_commands_path(build_dir: &Path) -> PathBuf {
+    build_dir.join("compile_commands.json")
+}
+
+pub fn load(build_dir: &Path) -> Result<Vec<CompileCommand>> {
+    let path = get_compile_commands_path(build_dir);
+    let content = std::fs::read_to_string(&path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+
+    Ok(serde_json::from_str(&content)?)
+}
+
+/// Find the compile command for the given source file
+pub fn find_for_file<'a>(
+    commands: &'a [CompileCommand],
+    source: &Path,
+) -> Option<&'a CompileCommand> {
+    let source = source
+        .canonicalize()
+        .unwrap_or_else(|_| source.to_path_buf());
+
+    commands.iter().find(|command| {
+        let path = command.source_path();
+        path.canonicalize().unwrap_or(path) == source
+    })
+}
+
+/// Split a command line into arguments, following POSIX shell quoting rules
+pub fn split_command_line(command: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut chars = command.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_arg = true;
+                for c in chars.by_ref() {
+                    if c == '\'' {
+                        break;
+                    }
+                    current.push(c);
+                }
+            }
+            '"' => {
+                in_arg = true;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => {
+                            if let Some(next) = chars.next() {
+                                if !matches!(next, '"' | '\\' | '$' | '`') {
+                                    current.push('\\');
+                                }
+                                current.push(next);
+                            }
+                        }
+                        _ => current.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                in_arg = true;
+                if let Some(next) = chars.next() {
+                    current.push(next);
+                }
+            }
+            c if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            c => {
+                in_arg = true;
+                current.push(c);
+            }
+        }
+    }
+
+    if in_arg {
+        args.push(current);
+    }
+
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_command_line() {
+        let args = split_command_line(
+            r#"xtensa-esp32-elf-gcc -DIDF_VER=\"v5.1\" -I"/path with space" -o obj.o -c main.c"#,
+        );
+        assert_eq!(
+            args,
+            vec![
+                "xtensa-esp32-elf-gcc",
+                "-DIDF_VER=\"v5.1\"",
+                "-I/path with space",
+                "-o",
+                "obj.o",
+                "-c",
+                "main.c",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_object_path_from_command() {
+        let command = CompileCommand {
+            directory: PathBuf::from("/project/build"),
+            file: PathBuf::from("/project/main/main.c"),
+            command: Some(
+                "gcc -o esp-idf/main/CMakeFiles/__idf_main.dir/main.c.obj -c /project/main/main.c"
+                    .to_string(),
+            ),
+            arguments: None,
+            output: None,
+        };
+
+        assert_eq!(
+            command.object_path(),
+            Some(PathBuf::from(
+                "esp-idf/main/CMakeFiles/__idf_main.dir/main.c.obj"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_args_for_mode() {
+        let command = CompileCommand {
+            directory: PathBuf::from("/project/build"),
+            file: PathBuf::from("main.c"),
+            command: Some(
+                "gcc -Iinc -MD -MT main.c.obj -MF main.c.obj.d -o main.c.obj -c main.c".to_string(),
+            ),
+            arguments: None,
+            output: None,
+        };
+
+        assert_eq!(
+            command.args_for_mode("-E", None),
+            vec!["gcc", "-Iinc", "main.c", "-E"]
+        );
+        assert_eq!(
+            command.args_for_mode("-S", Some(Path::new("main.s"))),
+            vec!["gcc", "-Iinc", "main.c", "-S", "-o", "main.s"]
+        );
+    }
+}
@@ -29,7 +29,7 @@ impl SdkConfig {
         Ok(())
     }
 
-    fn parse_sdkconfig(content: &str) -> Result<Self> {
+    pub fn parse_sdkconfig(content: &str) -> Result<Self> {
         let mut settings = HashMap::new();
         let mut target = None;
 
@@ -85,14 +85,211 @@ impl SdkConfig {
     }
 }
 
+/// A single difference between two configurations
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigChange {
+    Added {
+        key: String,
+        value: String,
+    },
+    Removed {
+        key: String,
+        value: String,
+    },
+    Changed {
+        key: String,
+        old: String,
+        new: String,
+    },
+}
+
+impl std::fmt::Display for ConfigChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigChange::Added { key, value } => write!(f, "+ {}={}", key, value),
+            ConfigChange::Removed { key, value } => {
+                write!(f, "- {}={} (now not set)", key, value)
+            }
+            ConfigChange::Changed { key, old, new } => write!(f, "~ {}: {} -> {}", key, old, new),
+        }
+    }
+}
+
+/// Compute the changes needed to go from `old` to `new`, sorted by option name
+pub fn diff(old: &SdkConfig, new: &SdkConfig) -> Vec<ConfigChange> {
+    let mut keys: Vec<_> = old.settings.keys().chain(new.settings.keys()).collect();
+    keys.sort();
+    keys.dedup();
+
+    keys.into_iter()
+        .filter_map(|key| match (old.settings.get(key), new.settings.get(key)) {
+            (None, Some(value)) => Some(ConfigChange::Added {
+                key: key.clone(),
+                value: value.clone(),
+            }),
+            (Some(value), None) => Some(ConfigChange::Removed {
+                key: key.clone(),
+                value: value.clone(),
+            }),
+            (Some(old), Some(new)) if old != new => Some(ConfigChange::Changed {
+                key: key.clone(),
+                old: old.clone(),
+                new: new.clone(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Option a sdkconfig line sets, either `CONFIG_FOO=value` or `# CONFIG_FOO is not set`
+fn option_name(line: &str) -> Option<&str> {
+    let line = line.trim();
+    match line.strip_prefix("# ") {
+        Some(comment) => comment.strip_suffix(" is not set"),
+        None if !line.starts_with('#') => line.split_once('=').map(|(name, _)| name.trim()),
+        None => None,
+    }
+}
+
+/// Quote a string option value for sdkconfig
+pub fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn unquote(value: &str) -> String {
+    match value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+    {
+        Some(inner) => inner.replace("\\\"", "\"").replace("\\\\", "\\"),
+        None => value.to_string(),
+    }
+}
+
+/// Option values of sdkconfig content by name. Unlike [`SdkConfig`], options written as
+/// `# CONFIG_FOO is not set` are kept (as `n`), and strings are unquoted.
+pub fn parse_option_values(content: &str) -> HashMap<String, String> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let name = option_name(line)?;
+            let value = match line.split_once('=') {
+                Some((_, value)) if !line.trim_start().starts_with('#') => unquote(value.trim()),
+                _ => "n".to_string(),
+            };
+            Some((name.to_string(), value))
+        })
+        .collect()
+}
+
+/// Set options in sdkconfig content in place, keeping the other lines and comments.
+/// Values are written as given (strings already quoted), except `n` which becomes
+/// `# CONFIG_FOO is not set`. Options that aren't in the file yet are appended.
+pub fn update_sdkconfig_content(content: &str, values: &[(String, String)]) -> String {
+    let format_option = |name: &str, value: &str| match value {
+        "n" => format!("# {} is not set", name),
+        value => format!("{}={}", name, value),
+    };
+
+    let mut written = vec![false; values.len()];
+    let mut lines: Vec<String> = content
+        .lines()
+        .map(|line| {
+            let index = option_name(line)
+                .and_then(|name| values.iter().position(|(option, _)| option == name));
+            match index {
+                Some(index) => {
+                    written[index] = true;
+                    format_option(&values[index].0, &values[index].1)
+                }
+                None => line.to_string(),
+            }
+        })
+        .collect();
+    for ((name, value), written) in values.iter().zip(written) {
+        if !written {
+            lines.push(format_option(name, value));
+        }
+    }
+
+    let mut content = lines.join("\n");
+    content.push('\n');
+    content
+}
+
+/// The project's sdkconfig, `$SDKCONFIG` (relative to the project) like idf.py when set
 pub fn get_sdkconfig_path(project_dir: &Path) -> PathBuf {
-    project_dir.join("sdkconfig")
+    match crate::utils::env_var("SDKCONFIG").filter(|path| !path.is_empty()) {
+        Some(path) => project_dir.join(path),
+        None => project_dir.join("sdkconfig"),
+    }
 }
 
 pub fn get_sdkconfig_defaults_path(project_dir: &Path) -> PathBuf {
     project_dir.join("sdkconfig.defaults")
 }
 
+/// Value of a `SDKCONFIG_DEFAULTS=...` (or `SDKCONFIG_DEFAULTS:STRING=...`) cache entry
+fn defaults_cache_entry(define: &str) -> Option<&str> {
+    let (name, value) = define.split_once('=')?;
+    (name.split(':').next() == Some("SDKCONFIG_DEFAULTS")).then(|| value.trim_matches('"'))
+}
+
+/// The sdkconfig defaults fragments the project is configured with, in the order they
+/// are applied (later fragments override earlier ones): the `;` separated list from the
+/// last `-D SDKCONFIG_DEFAULTS=...` cache entry or the `SDKCONFIG_DEFAULTS` environment
+/// variable, otherwise sdkconfig.defaults if it exists. Relative paths are resolved
+/// against the project directory.
+pub fn get_sdkconfig_defaults(project_dir: &Path, defines: &[String]) -> Vec<PathBuf> {
+    let list = defines
+        .iter()
+        .rev()
+        .find_map(|define| defaults_cache_entry(define))
+        .map(str::to_string)
+        .or_else(|| crate::utils::env_var("SDKCONFIG_DEFAULTS"));
+
+    match list {
+        Some(list) => list
+            .split(';')
+            .map(str::trim)
+            .filter(|path| !path.is_empty())
+            .map(|path| project_dir.join(path))
+            .collect(),
+        None => {
+            let path = get_sdkconfig_defaults_path(project_dir);
+            if path.exists() {
+                vec![path]
+            } else {
+                Vec::new()
+            }
+        }
+    }
+}
+
+/// Whether the defaults come from a `-D` cache entry rather than the environment or
+/// the project's sdkconfig.defaults
+pub fn defines_sdkconfig_defaults(defines: &[String]) -> bool {
+    defines
+        .iter()
+        .any(|define| defaults_cache_entry(define).is_some())
+}
+
+/// Merge sdkconfig defaults fragments in order
+pub fn load_sdkconfig_defaults(paths: &[PathBuf]) -> Result<SdkConfig> {
+    let mut merged = SdkConfig {
+        target: None,
+        settings: HashMap::new(),
+    };
+    for path in paths {
+        let fragment = SdkConfig::load_from_file(path)?;
+        if fragment.target.is_some() {
+            merged.target = fragment.target;
+        }
+        merged.settings.extend(fragment.settings);
+    }
+    Ok(merged)
+}
+
 pub fn load_project_config(project_dir: &Path) -> Result<SdkConfig> {
     let sdkconfig_path = get_sdkconfig_path(project_dir);
     SdkConfig::load_from_file(&sdkconfig_path)
@@ -102,3 +299,410 @@ pub fn save_project_config(project_dir: &Path, config: &SdkConfig) -> Result<()>
     let sdkconfig_path = get_sdkconfig_path(project_dir);
     config.save_to_file(&sdkconfig_path)
 }
+
+/// Project settings file in the project root
+pub const PROJECT_SETTINGS_FILE: &str = "idf.toml";
+
+/// Alternative name of the project settings file, used when there is no idf.toml
+pub const HIDDEN_PROJECT_SETTINGS_FILE: &str = ".idf-rs.toml";
+
+pub fn get_project_settings_path(project_dir: &Path) -> PathBuf {
+    let path = project_dir.join(PROJECT_SETTINGS_FILE);
+    let hidden = project_dir.join(HIDDEN_PROJECT_SETTINGS_FILE);
+    if !path.exists() && hidden.exists() {
+        hidden
+    } else {
+        path
+    }
+}
+
+/// User-wide settings, e.g. ~/.config/idf-rs/config.toml
+pub fn get_global_settings_path() -> Option<PathBuf> {
+    let config_dir = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+
+    config_dir.map(|dir| dir.join("idf-rs").join("config.toml"))
+}
+
+fn load_settings_file(path: &Path) -> Result<serde_json::Value> {
+    if !path.exists() {
+        return Ok(serde_json::Value::Null);
+    }
+
+    let content = fs::read_to_string(path)?;
+    crate::toml::parse(&content).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))
+}
+
+/// Load a settings table from the global config, overridden key by key by the project's idf.toml
+pub fn load_settings_section(
+    project_dir: &Path,
+    section: &str,
+) -> Result<serde_json::Map<String, serde_json::Value>> {
+    let mut merged = serde_json::Map::new();
+
+    let global = match get_global_settings_path() {
+        Some(path) => load_settings_file(&path)?,
+        None => serde_json::Value::Null,
+    };
+    let project = load_settings_file(&get_project_settings_path(project_dir))?;
+
+    for settings in [global, project] {
+        if let Some(table) = settings.get(section).and_then(|value| value.as_object()) {
+            merged.extend(table.clone());
+        }
+    }
+
+    Ok(merged)
+}
+
+/// Compiler cache used for builds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CacheBackend {
+    #[default]
+    Ccache,
+    Sccache,
+}
+
+impl std::fmt::Display for CacheBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CacheBackend::Ccache => write!(f, "ccache"),
+            CacheBackend::Sccache => write!(f, "sccache"),
+        }
+    }
+}
+
+/// The `[cache]` section of idf.toml / config.toml
+#[derive(Debug, Clone, Default)]
+pub struct CacheSettings {
+    pub backend: CacheBackend,
+    /// Whether to use the compiler cache, `None` leaves the ESP-IDF default
+    pub enabled: Option<bool>,
+    /// Remote/shared storage, e.g. `http://cache.local/ccache`, `redis://host` or `s3://bucket/prefix`
+    pub remote_url: Option<String>,
+    /// Only use the remote storage, without a local cache
+    pub remote_only: bool,
+    /// Environment variable holding the token used to authenticate to the remote storage
+    pub auth_token_env: Option<String>,
+}
+
+impl CacheSettings {
+    pub fn from_table(table: &serde_json::Map<String, serde_json::Value>) -> Result<Self> {
+        let string = |key: &str| -> Result<Option<String>> {
+            match table.get(key) {
+                None => Ok(None),
+                Some(serde_json::Value::String(value)) => Ok(Some(value.clone())),
+                Some(_) => Err(anyhow::anyhow!("cache.{} must be a string", key)),
+            }
+        };
+        let boolean = |key: &str| -> Result<Option<bool>> {
+            match table.get(key) {
+                None => Ok(None),
+                Some(serde_json::Value::Bool(value)) => Ok(Some(*value)),
+                Some(_) => Err(anyhow::anyhow!("cache.{} must be true or false", key)),
+            }
+        };
+
+        let backend = match string("backend")?.as_deref() {
+            None | Some("ccache") => CacheBackend::Ccache,
+            Some("sccache") => CacheBackend::Sccache,
+            Some(other) => {
+                return Err(anyhow::anyhow!(
+                    "cache.backend '{}' is not supported (use 'ccache' or 'sccache')",
+                    other
+                ))
+            }
+        };
+
+        Ok(CacheSettings {
+            backend,
+            // A configured cache section enables the cache unless told otherwise
+            enabled: boolean("enabled")?.or(if table.is_empty() { None } else { Some(true) }),
+            remote_url: string("remote_url")?,
+            remote_only: boolean("remote_only")?.unwrap_or(false),
+            auth_token_env: string("auth_token_env")?,
+        })
+    }
+
+    /// Read the auth token from the configured environment variable
+    pub fn auth_token(&self) -> Option<String> {
+        self.auth_token_env
+            .as_ref()
+            .and_then(|name| std::env::var(name).ok())
+            .filter(|token| !token.is_empty())
+    }
+}
+
+pub fn load_cache_settings(project_dir: &Path) -> Result<CacheSettings> {
+    CacheSettings::from_table(&load_settings_section(project_dir, "cache")?)
+}
+
+/// The `[ota]` section of idf.toml / config.toml
+#[derive(Debug, Clone, Default)]
+pub struct OtaSettings {
+    /// Where packages are written, relative to the project (default: <build>/ota)
+    pub output_dir: Option<PathBuf>,
+    /// PEM private key used to sign the app binary with OpenSSL
+    pub signing_key: Option<PathBuf>,
+    /// URL the OTA server publishes packages under, recorded in the metadata
+    pub base_url: Option<String>,
+    /// Release channel recorded in the metadata, e.g. "stable" or "beta"
+    pub channel: Option<String>,
+}
+
+impl OtaSettings {
+    pub fn from_table(table: &serde_json::Map<String, serde_json::Value>) -> Result<Self> {
+        let string = |key: &str| -> Result<Option<String>> {
+            match table.get(key) {
+                None => Ok(None),
+                Some(serde_json::Value::String(value)) => Ok(Some(value.clone())),
+                Some(_) => Err(anyhow::anyhow!("ota.{} must be a string", key)),
+            }
+        };
+
+        Ok(OtaSettings {
+            output_dir: string("output_dir")?.map(PathBuf::from),
+            signing_key: string("signing_key")?.map(PathBuf::from),
+            base_url: string("base_url")?,
+            channel: string("channel")?,
+        })
+    }
+}
+
+pub fn load_ota_settings(project_dir: &Path) -> Result<OtaSettings> {
+    OtaSettings::from_table(&load_settings_section(project_dir, "ota")?)
+}
+
+/// Project defaults from the `[project]` and `[monitor]` sections of idf.toml /
+/// config.toml, applied where the command line leaves an option unset
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProjectSettings {
+    pub target: Option<String>,
+    pub port: Option<String>,
+    pub baud: Option<u32>,
+    pub generator: Option<String>,
+    /// Extra `-D` cache entries, before those given with -D
+    pub defines: Vec<String>,
+    /// sdkconfig defaults fragments, unless given with -D SDKCONFIG_DEFAULTS or the environment
+    pub sdkconfig_defaults: Vec<String>,
+    /// idf_monitor's --print_filter, e.g. "wifi:W *:I"
+    pub print_filter: Option<String>,
+}
+
+impl ProjectSettings {
+    pub fn from_tables(
+        project: &serde_json::Map<String, serde_json::Value>,
+        monitor: &serde_json::Map<String, serde_json::Value>,
+    ) -> Result<Self> {
+        let string = |table: &serde_json::Map<String, serde_json::Value>,
+                      section: &str,
+                      key: &str|
+         -> Result<Option<String>> {
+            match table.get(key) {
+                None => Ok(None),
+                Some(serde_json::Value::String(value)) => Ok(Some(value.clone())),
+                Some(_) => Err(anyhow::anyhow!("{}.{} must be a string", section, key)),
+            }
+        };
+        let strings = |key: &str| -> Result<Vec<String>> {
+            match project.get(key) {
+                None => Ok(Vec::new()),
+                Some(serde_json::Value::Array(items)) => items
+                    .iter()
+                    .map(|item| {
+                        item.as_str().map(str::to_string).ok_or_else(|| {
+                            anyhow::anyhow!("project.{} must be a list of strings", key)
+                        })
+                    })
+                    .collect(),
+                Some(_) => Err(anyhow::anyhow!("project.{} must be a list of strings", key)),
+            }
+        };
+        let baud = match project.get("baud") {
+            None => None,
+            Some(value) => Some(
+                value
+                    .as_u64()
+                    .and_then(|baud| u32::try_from(baud).ok())
+                    .ok_or_else(|| anyhow::anyhow!("project.baud must be a number"))?,
+            ),
+        };
+
+        Ok(ProjectSettings {
+            target: string(project, "project", "target")?,
+            port: string(project, "project", "port")?,
+            baud,
+            generator: string(project, "project", "generator")?,
+            defines: strings("defines")?,
+            sdkconfig_defaults: strings("sdkconfig_defaults")?,
+            print_filter: string(monitor, "monitor", "print_filter")?,
+        })
+    }
+}
+
+/// Resolve the project defaults, the project's idf.toml overriding the global config.toml
+pub fn load_project_settings(project_dir: &Path) -> Result<ProjectSettings> {
+    ProjectSettings::from_tables(
+        &load_settings_section(project_dir, "project")?,
+        &load_settings_section(project_dir, "monitor")?,
+    )
+}
+
+/// A named profile from the `[profiles.<name>]` tables of config.toml / idf.toml,
+/// e.g. the board and adapter of one desk
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Profile {
+    pub port: Option<String>,
+    pub baud: Option<u32>,
+    /// OpenOCD configuration, like $OPENOCD_COMMANDS (e.g. "-f board/esp32s3-builtin.cfg")
+    pub openocd_commands: Option<String>,
+}
+
+impl Profile {
+    pub fn from_table(
+        name: &str,
+        table: &serde_json::Map<String, serde_json::Value>,
+    ) -> Result<Self> {
+        let string = |key: &str| -> Result<Option<String>> {
+            match table.get(key) {
+                None => Ok(None),
+                Some(serde_json::Value::String(value)) => Ok(Some(value.clone())),
+                Some(_) => Err(anyhow::anyhow!(
+                    "profiles.{}.{} must be a string",
+                    name,
+                    key
+                )),
+            }
+        };
+        let baud = match table.get("baud") {
+            None => None,
+            Some(value) => Some(
+                value
+                    .as_u64()
+                    .and_then(|baud| u32::try_from(baud).ok())
+                    .ok_or_else(|| anyhow::anyhow!("profiles.{}.baud must be a number", name))?,
+            ),
+        };
+
+        Ok(Profile {
+            port: string("port")?,
+            baud,
+            openocd_commands: string("openocd_commands")?,
+        })
+    }
+}
+
+/// Load a profile by name, a project profile replacing a global one of the same name
+pub fn load_profile(project_dir: &Path, name: &str) -> Result<Profile> {
+    let profiles = load_settings_section(project_dir, "profiles")?;
+    match profiles.get(name) {
+        Some(serde_json::Value::Object(table)) => Profile::from_table(name, table),
+        Some(_) => Err(anyhow::anyhow!("profiles.{} must be a table", name)),
+        None => {
+            let mut names: Vec<&str> = profiles.keys().map(String::as_str).collect();
+            names.sort_unstable();
+            Err(anyhow::anyhow!(
+                "Profile '{}' is not defined (available: {})",
+                name,
+                if names.is_empty() {
+                    "none".to_string()
+                } else {
+                    names.join(", ")
+                }
+            ))
+        }
+    }
+}
+
+/// Whether invocation metrics are recorded locally, opted into with `[stats] enabled = true`
+pub fn load_stats_enabled(project_dir: &Path) -> Result<bool> {
+    match load_settings_section(project_dir, "stats")?.get("enabled") {
+        None => Ok(false),
+        Some(serde_json::Value::Bool(enabled)) => Ok(*enabled),
+        Some(_) => Err(anyhow::anyhow!("stats.enabled must be true or false")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project_settings() {
+        let settings = crate::toml::parse(
+            "[project]\ntarget = \"esp32s3\"\nbaud = 921600\ndefines = [\"FOO=1\"]\nsdkconfig_defaults = [\"sdkconfig.defaults\", \"sdkconfig.ci\"]\n[monitor]\nprint_filter = \"*:W\"\n",
+        )
+        .unwrap();
+        let table = |name: &str| settings[name].as_object().unwrap().clone();
+        let settings = ProjectSettings::from_tables(&table("project"), &table("monitor")).unwrap();
+        assert_eq!(settings.target.as_deref(), Some("esp32s3"));
+        assert_eq!(settings.baud, Some(921600));
+        assert_eq!(settings.port, None);
+        assert_eq!(settings.defines, vec!["FOO=1"]);
+        assert_eq!(settings.sdkconfig_defaults.len(), 2);
+        assert_eq!(settings.print_filter.as_deref(), Some("*:W"));
+
+        let mut project = serde_json::Map::new();
+        project.insert("baud".to_string(), serde_json::json!("fast"));
+        assert!(ProjectSettings::from_tables(&project, &serde_json::Map::new()).is_err());
+    }
+
+    #[test]
+    fn test_profile() {
+        let settings = crate::toml::parse(
+            "[profiles.office-bench]\nport = \"/dev/ttyACM0\"\nbaud = 460800\nopenocd_commands = \"-f board/esp32s3-builtin.cfg\"\n",
+        )
+        .unwrap();
+        let table = settings["profiles"]["office-bench"].as_object().unwrap();
+        assert_eq!(
+            Profile::from_table("office-bench", table).unwrap(),
+            Profile {
+                port: Some("/dev/ttyACM0".to_string()),
+                baud: Some(460800),
+                openocd_commands: Some("-f board/esp32s3-builtin.cfg".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn test_update_sdkconfig_content() {
+        let content = "# Comment\nCONFIG_A=y\n# CONFIG_B is not set\nCONFIG_NAME=\"a \\\"b\\\"\"\n";
+        let values = parse_option_values(content);
+        assert_eq!(values["CONFIG_A"], "y");
+        assert_eq!(values["CONFIG_B"], "n");
+        assert_eq!(values["CONFIG_NAME"], "a \"b\"");
+
+        let updated = update_sdkconfig_content(
+            content,
+            &[
+                ("CONFIG_A".to_string(), "n".to_string()),
+                ("CONFIG_B".to_string(), "y".to_string()),
+                ("CONFIG_C".to_string(), quote("x")),
+            ],
+        );
+        assert_eq!(
+            get_sdkconfig_defaults(
+                Path::new("/p"),
+                &[
+                    "SDKCONFIG_DEFAULTS=old".to_string(),
+                    "SDKCONFIG_DEFAULTS=\"sdkconfig.defaults;sdkconfig.ci\"".to_string()
+                ]
+            ),
+            vec![
+                PathBuf::from("/p/sdkconfig.defaults"),
+                PathBuf::from("/p/sdkconfig.ci")
+            ]
+        );
+        assert!(!defines_sdkconfig_defaults(&["FOO=1".to_string()]));
+
+        assert_eq!(
+            updated,
+            "# Comment\n# CONFIG_A is not set\nCONFIG_B=y\nCONFIG_NAME=\"a \\\"b\\\"\"\nCONFIG_C=\"x\"\n"
+        );
+    }
+}
@@ -0,0 +1,262 @@
+//! ESP-IDF core dumps in ELF format, as stored in the coredump partition (a header, the
+//! ELF core file and a CRC32 or SHA-256 checksum) or printed base64 encoded to the UART
+
+use anyhow::Result;
+
+/// Markers around the base64 encoded core dump in the console output
+pub const UART_START: &str = "CORE DUMP START";
+pub const UART_END: &str = "CORE DUMP END";
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+
+/// A core dump taken out of its partition container
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoreDump {
+    pub elf: Vec<u8>,
+    /// Checksum the container was verified with, None for a bare ELF core file
+    pub checksum: Option<&'static str>,
+}
+
+/// What the ELF notes and segments of a core dump say about the crash
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Summary {
+    /// Tasks with saved registers (NT_PRSTATUS notes)
+    pub tasks: usize,
+    pub memory_segments: usize,
+    /// Prefix of the SHA-256 of the ELF of the app that crashed
+    pub app_sha256: Option<String>,
+    /// TCB of the task that crashed
+    pub crashed_task: Option<u32>,
+    pub panic_reason: Option<String>,
+}
+
+/// An erased partition holds no core dump
+pub fn is_empty(data: &[u8]) -> bool {
+    data.len() < 4 || data[..4] == [0xFF; 4]
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+/// The ELF core file of a partition image (or of a bare ELF core file), checking the
+/// container's checksum
+pub fn extract(data: &[u8]) -> Result<CoreDump> {
+    if data.starts_with(ELF_MAGIC) {
+        return Ok(CoreDump {
+            elf: data.to_vec(),
+            checksum: None,
+        });
+    }
+    if is_empty(data) {
+        return Err(anyhow::anyhow!("The core dump partition is empty"));
+    }
+
+    let length = u32_at(data, 0).unwrap_or(0) as usize;
+    if length > data.len() || length < 64 {
+        return Err(anyhow::anyhow!(
+            "Invalid core dump length {} (image has {} bytes)",
+            length,
+            data.len()
+        ));
+    }
+    let dump = &data[..length];
+    // The header grew over the versions, the ELF file follows it
+    let start = dump[..64]
+        .windows(ELF_MAGIC.len())
+        .position(|window| window == ELF_MAGIC)
+        .ok_or_else(|| {
+            anyhow::anyhow!("No ELF core file found, only ELF format core dumps are supported")
+        })?;
+
+    let (body, crc) = dump.split_at(length - 4);
+    let (sha_body, sha) = dump.split_at(length - 32);
+    let (end, checksum) = if crate::crc32::crc32_le(0, body).to_le_bytes() == crc {
+        (length - 4, "CRC32")
+    } else if crate::sha256::digest(sha_body)[..] == *sha {
+        (length - 32, "SHA256")
+    } else {
+        return Err(anyhow::anyhow!(
+            "Core dump checksum mismatch, the data is corrupted"
+        ));
+    };
+
+    Ok(CoreDump {
+        elf: dump[start..end].to_vec(),
+        checksum: Some(checksum),
+    })
+}
+
+/// Decode the core dump printed between the UART markers, if the text has one
+pub fn from_uart_text(text: &str) -> Result<Option<Vec<u8>>> {
+    let Some(start) = text.find(UART_START) else {
+        return Ok(None);
+    };
+    let after = &text[start..];
+    let body_start = after.find('\n').map(|i| i + 1).unwrap_or(after.len());
+    let body = &after[body_start..];
+    let end = body
+        .find(UART_END)
+        .ok_or_else(|| anyhow::anyhow!("The core dump output ends before '{}'", UART_END))?;
+    // Drop the "=====" of the end marker line
+    let body = &body[..body[..end].rfind('\n').unwrap_or(0)];
+    let encoded: String = body
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .collect();
+    crate::nvs::decode_base64(&encoded).map(Some)
+}
+
+/// Read the notes and segments of an ELF core file
+pub fn summarize(elf: &[u8]) -> Result<Summary> {
+    if !elf.starts_with(ELF_MAGIC) || elf.get(4) != Some(&1) {
+        return Err(anyhow::anyhow!("Not a 32-bit ELF core file"));
+    }
+    let invalid = || anyhow::anyhow!("Truncated ELF core file");
+    let ph_offset = u32_at(elf, 0x1c).ok_or_else(invalid)? as usize;
+    let ph_size = u16_at(elf, 0x2a).ok_or_else(invalid)? as usize;
+    let ph_count = u16_at(elf, 0x2c).ok_or_else(invalid)? as usize;
+
+    let mut summary = Summary::default();
+    for index in 0..ph_count {
+        let header = ph_offset + index * ph_size;
+        let kind = u32_at(elf, header).ok_or_else(invalid)?;
+        let offset = u32_at(elf, header + 4).ok_or_else(invalid)? as usize;
+        let size = u32_at(elf, header + 16).ok_or_else(invalid)? as usize;
+        match kind {
+            PT_LOAD => summary.memory_segments += 1,
+            PT_NOTE => {
+                let notes = elf.get(offset..offset + size).ok_or_else(invalid)?;
+                read_notes(notes, &mut summary);
+            }
+            _ => {}
+        }
+    }
+    Ok(summary)
+}
+
+fn c_string(data: &[u8]) -> String {
+    let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).to_string()
+}
+
+fn read_notes(notes: &[u8], summary: &mut Summary) {
+    let mut offset = 0;
+    while let (Some(name_size), Some(desc_size)) =
+        (u32_at(notes, offset), u32_at(notes, offset + 4))
+    {
+        let name_start = offset + 12;
+        let desc_start = name_start + (name_size as usize).next_multiple_of(4);
+        let desc_end = desc_start + desc_size as usize;
+        let (Some(name), Some(desc)) = (
+            notes.get(name_start..name_start + name_size as usize),
+            notes.get(desc_start..desc_end),
+        ) else {
+            break;
+        };
+        match c_string(name).as_str() {
+            "CORE" => summary.tasks += 1,
+            // Version, then the app's ELF SHA-256 as text
+            "ESP_CORE_DUMP_INFO" => {
+                summary.app_sha256 = desc.get(4..).map(c_string).filter(|s| !s.is_empty())
+            }
+            // Crashed task TCB, then exception register index and value pairs
+            "EXTRA_INFO" => summary.crashed_task = u32_at(desc, 0),
+            "ESP_PANIC_DETAILS" => {
+                summary.panic_reason =
+                    Some(c_string(desc).trim().to_string()).filter(|reason| !reason.is_empty())
+            }
+            _ => {}
+        }
+        offset = desc_end.next_multiple_of(4);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(name: &str, kind: u32, desc: &[u8]) -> Vec<u8> {
+        let mut note = Vec::new();
+        note.extend_from_slice(&(name.len() as u32 + 1).to_le_bytes());
+        note.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+        note.extend_from_slice(&kind.to_le_bytes());
+        note.extend_from_slice(name.as_bytes());
+        note.push(0);
+        note.resize(note.len().next_multiple_of(4), 0);
+        note.extend_from_slice(desc);
+        note.resize(note.len().next_multiple_of(4), 0);
+        note
+    }
+
+    #[test]
+    fn test_extract_and_summarize() {
+        let mut notes = note("CORE", 1, &[0; 72]);
+        notes.extend(note("CORE", 1, &[0; 72]));
+        let mut info = 1u32.to_le_bytes().to_vec();
+        info.extend_from_slice(b"a1b2c3d4e\0");
+        notes.extend(note("ESP_CORE_DUMP_INFO", 8266, &info));
+        notes.extend(note("EXTRA_INFO", 677, &0x3ffb_5a10u32.to_le_bytes()));
+        notes.extend(note(
+            "ESP_PANIC_DETAILS",
+            4919,
+            b"assert failed: app_main\0",
+        ));
+
+        // ELF header, a PT_NOTE and a PT_LOAD program header, then the notes
+        let mut elf = vec![0u8; 52];
+        elf[..5].copy_from_slice(b"\x7fELF\x01");
+        elf[0x1c..0x20].copy_from_slice(&52u32.to_le_bytes());
+        elf[0x2a..0x2c].copy_from_slice(&32u16.to_le_bytes());
+        elf[0x2c..0x2e].copy_from_slice(&2u16.to_le_bytes());
+        let mut note_header = [0u8; 32];
+        note_header[..4].copy_from_slice(&PT_NOTE.to_le_bytes());
+        note_header[4..8].copy_from_slice(&116u32.to_le_bytes());
+        note_header[16..20].copy_from_slice(&(notes.len() as u32).to_le_bytes());
+        elf.extend_from_slice(&note_header);
+        let mut load_header = [0u8; 32];
+        load_header[..4].copy_from_slice(&PT_LOAD.to_le_bytes());
+        elf.extend_from_slice(&load_header);
+        elf.extend_from_slice(&notes);
+
+        // Partition container: length, version and task counts, the ELF and its CRC32
+        let mut image = Vec::new();
+        image.extend_from_slice(&((24 + elf.len() + 4) as u32).to_le_bytes());
+        image.extend_from_slice(&[0; 20]);
+        image.extend_from_slice(&elf);
+        let crc = crate::crc32::crc32_le(0, &image);
+        image.extend_from_slice(&crc.to_le_bytes());
+        image.resize(0x1000, 0xFF);
+
+        let dump = extract(&image).unwrap();
+        assert_eq!(dump.elf, elf);
+        assert_eq!(dump.checksum, Some("CRC32"));
+        assert_eq!(extract(&elf).unwrap().checksum, None);
+        image[30] ^= 1;
+        assert!(extract(&image).is_err());
+        assert!(is_empty(&[0xFF; 16]));
+
+        let summary = summarize(&dump.elf).unwrap();
+        assert_eq!(summary.tasks, 2);
+        assert_eq!(summary.memory_segments, 1);
+        assert_eq!(summary.app_sha256.as_deref(), Some("a1b2c3d4e"));
+        assert_eq!(summary.crashed_task, Some(0x3ffb_5a10));
+        assert_eq!(
+            summary.panic_reason.as_deref(),
+            Some("assert failed: app_main")
+        );
+
+        let text = "I (10) boot\n================= CORE DUMP START =================\nf0VM\nRgE=\n================= CORE DUMP END =================\n";
+        assert_eq!(from_uart_text(text).unwrap().unwrap(), b"\x7fELF\x01");
+        assert_eq!(from_uart_text("no dump here").unwrap(), None);
+    }
+}
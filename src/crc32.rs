@@ -0,0 +1,17 @@
+//! CRC-32 as computed by esp_rom_crc32_le, used by otadata and NVS
+
+/// CRC-32 (IEEE) continuing from `init`; matches `zlib.crc32(data, init)`
+pub fn crc32_le(init: u32, data: &[u8]) -> u32 {
+    let mut crc = !init;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
@@ -0,0 +1,159 @@
+//! idf.py extension actions: an `idf_ext.py` in the project or in a component defines
+//! actions with `action_extensions(base_actions, project_path)`. idf-rs lists them and
+//! leaves running them to idf.py, which loads the same files.
+
+use crate::utils;
+use anyhow::Result;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+const EXTENSION_FILE: &str = "idf_ext.py";
+
+/// Imports each extension file and prints its actions as JSON
+const LIST_ACTIONS_SCRIPT: &str = r#"
+import importlib.util, json, os, sys
+sys.path.insert(0, os.path.join(os.environ.get('IDF_PATH', ''), 'tools'))
+project, files = sys.argv[1], sys.argv[2:]
+actions = []
+for index, path in enumerate(files):
+    try:
+        spec = importlib.util.spec_from_file_location('idf_ext_%d' % index, path)
+        module = importlib.util.module_from_spec(spec)
+        spec.loader.exec_module(module)
+        extension = module.action_extensions({}, project) or {}
+    except Exception as e:
+        print('%s: %s' % (path, e), file=sys.stderr)
+        continue
+    for name, action in extension.get('actions', {}).items():
+        actions.append({
+            'name': name,
+            'help': ((action.get('help') or '').strip().splitlines() or [''])[0],
+            'aliases': list(action.get('aliases', [])),
+            'file': path,
+        })
+print(json.dumps(actions))
+"#;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Action {
+    pub name: String,
+    pub help: String,
+    pub aliases: Vec<String>,
+    /// The idf_ext.py defining the action
+    pub file: String,
+}
+
+impl Action {
+    pub fn matches(&self, command: &str) -> bool {
+        self.name == command || self.aliases.iter().any(|alias| alias == command)
+    }
+}
+
+/// The extension files idf.py would load: the project's and those of the components of
+/// the last build
+pub fn extension_files(project_dir: &Path, build_dir: &Path) -> Vec<PathBuf> {
+    let mut dirs = vec![project_dir.to_path_buf()];
+    if let Ok(description) = utils::load_project_description(build_dir) {
+        if let Some(components) = description["build_component_info"].as_object() {
+            dirs.extend(
+                components
+                    .values()
+                    .filter_map(|component| component["dir"].as_str())
+                    .map(PathBuf::from),
+            );
+        }
+    }
+
+    let mut files: Vec<PathBuf> = Vec::new();
+    for file in dirs.iter().map(|dir| dir.join(EXTENSION_FILE)) {
+        if file.is_file() && !files.contains(&file) {
+            files.push(file);
+        }
+    }
+    files
+}
+
+/// The actions of the extension files, asking Python to load them
+pub fn list_actions(project_dir: &Path, build_dir: &Path) -> Result<Vec<Action>> {
+    let files = extension_files(project_dir, build_dir);
+    if files.is_empty() {
+        return Ok(Vec::new());
+    }
+    let python = utils::get_python_executable()?;
+    let project = project_dir.to_string_lossy().to_string();
+    let mut args = vec!["-c", LIST_ACTIONS_SCRIPT, &project];
+    let files: Vec<String> = files
+        .iter()
+        .map(|file| file.to_string_lossy().to_string())
+        .collect();
+    args.extend(files.iter().map(String::as_str));
+    let output = std::process::Command::new(python).args(&args).output()?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "Failed to load the idf.py extensions: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    parse_actions(&String::from_utf8_lossy(&output.stdout))
+}
+
+pub fn parse_actions(json: &str) -> Result<Vec<Action>> {
+    Ok(serde_json::from_str(json.trim())?)
+}
+
+/// Section appended to --help, listing the actions with their help
+pub fn help_section(actions: &[Action]) -> String {
+    let width = actions
+        .iter()
+        .map(|action| action.name.len())
+        .max()
+        .unwrap_or(0);
+    let mut section = String::from("Extension actions (run by idf.py):\n");
+    for action in actions {
+        let line = format!("  {:width$}  {}", action.name, action.help, width = width);
+        section.push_str(line.trim_end());
+        section.push('\n');
+    }
+    section
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extension_actions() {
+        let dir = std::env::temp_dir().join(format!("idf-rs-ext-{}", std::process::id()));
+        let component = dir.join("components/tools");
+        std::fs::create_dir_all(&component).unwrap();
+        std::fs::create_dir_all(dir.join("build")).unwrap();
+        std::fs::write(dir.join(EXTENSION_FILE), "").unwrap();
+        std::fs::write(component.join(EXTENSION_FILE), "").unwrap();
+        std::fs::write(
+            dir.join("build/project_description.json"),
+            format!(
+                r#"{{"build_component_info": {{"tools": {{"dir": "{}"}}, "main": {{"dir": "{}"}}}}}}"#,
+                component.display(),
+                dir.join("main").display()
+            ),
+        )
+        .unwrap();
+        assert_eq!(
+            extension_files(&dir, &dir.join("build")),
+            vec![dir.join(EXTENSION_FILE), component.join(EXTENSION_FILE)]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let actions = parse_actions(
+            r#"[{"name": "provision", "help": "Provision the device", "aliases": ["prov"], "file": "/p/idf_ext.py"},
+                {"name": "lint-all", "help": "", "aliases": [], "file": "/p/idf_ext.py"}]"#,
+        )
+        .unwrap();
+        assert!(actions[0].matches("prov"));
+        assert!(!actions[1].matches("prov"));
+        assert_eq!(
+            help_section(&actions),
+            "Extension actions (run by idf.py):\n  provision  Provision the device\n  lint-all\n"
+        );
+    }
+}
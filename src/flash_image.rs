@@ -0,0 +1,325 @@
+//! Combined flash images: binaries at their flash offsets written as one raw binary,
+//! Intel HEX, UF2 or DFU file, in the layouts of `esptool merge_bin` and ESP-IDF's
+//! mkuf2.py and mkdfu.py
+
+use anyhow::Result;
+
+/// A binary and the flash offset it is written to
+#[derive(Debug, Clone, PartialEq)]
+pub struct Region {
+    pub offset: u32,
+    pub data: Vec<u8>,
+}
+
+/// UF2 family IDs of the chips (https://github.com/microsoft/uf2/blob/master/utils/uf2families.json)
+const UF2_FAMILIES: [(&str, u32); 10] = [
+    ("esp32", 0x1c5f21b0),
+    ("esp32s2", 0xbfdd4eee),
+    ("esp32c2", 0x2b88d29c),
+    ("esp32c3", 0xd42ba06c),
+    ("esp32s3", 0xc47e5767),
+    ("esp32c6", 0x540ddf62),
+    ("esp32h2", 0x332726f6),
+    ("esp32p4", 0x3d308e94),
+    ("esp32c5", 0xf71c0343),
+    ("esp32c61", 0x77d850c4),
+];
+
+const UF2_MAGIC_START: [u32; 2] = [0x0A32_4655, 0x9E5D_5157];
+const UF2_MAGIC_END: u32 = 0x0AB1_6F30;
+const UF2_FLAG_FAMILY_ID: u32 = 0x0000_2000;
+const UF2_FLAG_MD5: u32 = 0x0000_4000;
+const UF2_BLOCK_SIZE: usize = 512;
+/// Payload area of a block
+const UF2_DATA_SIZE: usize = 476;
+/// Flash offset, length and MD5 of the chunk, after the chunk
+const UF2_MD5_SIZE: usize = 24;
+/// The largest chunk that leaves room for the MD5 part, as mkuf2.py uses by default
+const UF2_CHUNK_SIZE: usize = UF2_DATA_SIZE - UF2_MD5_SIZE;
+
+/// Espressif's USB vendor ID, used by the DFU suffix and dfu-util
+pub const ESPRESSIF_VID: u16 = 0x303a;
+
+/// USB product IDs of the chips with a DFU capable ROM bootloader
+const DFU_PIDS: [(&str, u16); 2] = [("esp32s2", 0x0002), ("esp32s3", 0x0009)];
+
+const CPIO_MAGIC: &str = "070701";
+const CPIO_TRAILER: &str = "TRAILER!!!";
+/// cpio archives are padded to whole blocks
+const CPIO_BLOCK_SIZE: usize = 10240;
+/// The index of the flashed files, the first file of the archive
+const DFU_INFO_FILE: &str = "dfuinfo0.dat";
+
+pub fn uf2_family(target: &str) -> Option<u32> {
+    UF2_FAMILIES
+        .iter()
+        .find(|(name, _)| *name == target)
+        .map(|(_, id)| *id)
+}
+
+pub fn dfu_pid(target: &str) -> Option<u16> {
+    DFU_PIDS
+        .iter()
+        .find(|(name, _)| *name == target)
+        .map(|(_, pid)| *pid)
+}
+
+/// Regions ordered by offset, failing if any of them overlap
+fn sorted(regions: &[Region]) -> Result<Vec<&Region>> {
+    let mut sorted: Vec<&Region> = regions.iter().collect();
+    sorted.sort_by_key(|region| region.offset);
+    for pair in sorted.windows(2) {
+        let end = pair[0].offset as u64 + pair[0].data.len() as u64;
+        if end > pair[1].offset as u64 {
+            return Err(anyhow::anyhow!(
+                "The image at 0x{:x} overlaps the image at 0x{:x}",
+                pair[0].offset,
+                pair[1].offset
+            ));
+        }
+    }
+    Ok(sorted)
+}
+
+/// One binary covering the flash from address 0, with erased (0xFF) gaps, optionally
+/// padded to the flash size
+pub fn merge_raw(regions: &[Region], fill_size: Option<u32>) -> Result<Vec<u8>> {
+    let mut image = Vec::new();
+    for region in sorted(regions)? {
+        image.resize(region.offset as usize, 0xFF);
+        image.extend_from_slice(&region.data);
+    }
+    if let Some(size) = fill_size {
+        if image.len() > size as usize {
+            return Err(anyhow::anyhow!(
+                "The merged image ({} bytes) is larger than the flash ({} bytes)",
+                image.len(),
+                size
+            ));
+        }
+        image.resize(size as usize, 0xFF);
+    }
+    Ok(image)
+}
+
+fn hex_record(kind: u8, address: u16, data: &[u8]) -> String {
+    let mut bytes = vec![data.len() as u8];
+    bytes.extend_from_slice(&address.to_be_bytes());
+    bytes.push(kind);
+    bytes.extend_from_slice(data);
+    let checksum = bytes
+        .iter()
+        .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+        .wrapping_neg();
+    bytes.push(checksum);
+    format!(":{}\n", crate::sha256::to_hex(&bytes).to_uppercase())
+}
+
+/// Intel HEX with each region at its own addresses, so the gaps aren't written
+pub fn intel_hex(regions: &[Region]) -> Result<String> {
+    let mut hex = String::new();
+    let mut upper = None;
+    for region in sorted(regions)? {
+        for (index, chunk) in region.data.chunks(16).enumerate() {
+            let address = region.offset + (index * 16) as u32;
+            if upper != Some(address >> 16) {
+                upper = Some(address >> 16);
+                hex.push_str(&hex_record(4, 0, &((address >> 16) as u16).to_be_bytes()));
+            }
+            hex.push_str(&hex_record(0, address as u16, chunk));
+        }
+    }
+    hex.push_str(&hex_record(1, 0, &[]));
+    Ok(hex)
+}
+
+/// UF2 blocks for a UF2 bootloader, each with the MD5 of its chunk. Like mkuf2.py the
+/// blocks are numbered per region.
+pub fn uf2(regions: &[Region], family: u32) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+    for region in sorted(regions)? {
+        let blocks = region.data.len().div_ceil(UF2_CHUNK_SIZE);
+        for (index, chunk) in region.data.chunks(UF2_CHUNK_SIZE).enumerate() {
+            let address = region.offset + (index * UF2_CHUNK_SIZE) as u32;
+            let header = [
+                UF2_MAGIC_START[0],
+                UF2_MAGIC_START[1],
+                UF2_FLAG_FAMILY_ID | UF2_FLAG_MD5,
+                address,
+                chunk.len() as u32,
+                index as u32,
+                blocks as u32,
+                family,
+            ];
+            let start = output.len();
+            for word in header {
+                output.extend_from_slice(&word.to_le_bytes());
+            }
+            output.extend_from_slice(chunk);
+            output.extend_from_slice(&address.to_le_bytes());
+            output.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+            output.extend_from_slice(&crate::md5::digest(chunk));
+            output.resize(start + UF2_BLOCK_SIZE - 4, 0);
+            output.extend_from_slice(&UF2_MAGIC_END.to_le_bytes());
+        }
+    }
+    Ok(output)
+}
+
+fn pad(data: &mut Vec<u8>, multiple: usize) {
+    data.resize(data.len().next_multiple_of(multiple), 0);
+}
+
+/// A "new ASCII" cpio entry, name and data each padded to 4 bytes
+fn cpio_entry(name: &str, data: &[u8], trailer: bool) -> Vec<u8> {
+    let (mode, nlink) = if trailer { (0, 0) } else { (0o100644, 1) };
+    let name_size = name.len() + 1;
+    let fields = [
+        0,
+        mode,
+        0,
+        0,
+        nlink,
+        0,
+        data.len(),
+        0,
+        0,
+        0,
+        0,
+        name_size,
+        0,
+    ];
+    let mut entry = CPIO_MAGIC.as_bytes().to_vec();
+    for field in fields {
+        entry.extend_from_slice(format!("{:08x}", field).as_bytes());
+    }
+    entry.extend_from_slice(name.as_bytes());
+    entry.push(0);
+    pad(&mut entry, 4);
+    entry.extend_from_slice(data);
+    pad(&mut entry, 4);
+    entry
+}
+
+/// A DFU image for the ROM's USB DFU bootloader: a cpio archive of the binaries, split
+/// into parts of `part_size` bytes, indexed by dfuinfo0.dat with the flash offset and MD5
+/// of each part, followed by the DFU suffix. `files` pairs each region with its file name.
+pub fn dfu(files: &[(String, Region)], pid: u16, part_size: usize) -> Result<Vec<u8>> {
+    let regions: Vec<Region> = files.iter().map(|(_, region)| region.clone()).collect();
+    sorted(&regions)?;
+
+    let mut index = Vec::new();
+    let mut entries = Vec::new();
+    for (file, region) in files {
+        let name = file.rsplit(['/', '\\']).next().unwrap_or(file);
+        let parts: Vec<&[u8]> = if part_size > 0 && region.data.len() > part_size {
+            region.data.chunks(part_size).collect()
+        } else {
+            vec![&region.data]
+        };
+        for (number, part) in parts.iter().enumerate() {
+            let part_name = if parts.len() > 1 {
+                format!("{}.{}", name, number)
+            } else {
+                name.to_string()
+            };
+            let address = region.offset + (number * part_size) as u32;
+            let mut info_name = [0u8; 64];
+            let length = part_name.len().min(info_name.len());
+            info_name[..length].copy_from_slice(&part_name.as_bytes()[..length]);
+
+            index.extend_from_slice(&address.to_le_bytes());
+            index.extend_from_slice(&0u32.to_le_bytes());
+            index.extend_from_slice(&info_name);
+            index.extend_from_slice(&crate::md5::digest(part));
+            entries.extend(cpio_entry(&part_name, part, false));
+        }
+    }
+
+    let mut output = cpio_entry(DFU_INFO_FILE, &index, false);
+    output.extend(entries);
+    output.extend(cpio_entry(CPIO_TRAILER, &[], true));
+    pad(&mut output, CPIO_BLOCK_SIZE);
+
+    // bcdDevice, idProduct, idVendor, bcdDFU, "UFD" and the suffix length
+    output.extend_from_slice(&0xffffu16.to_le_bytes());
+    output.extend_from_slice(&pid.to_le_bytes());
+    output.extend_from_slice(&ESPRESSIF_VID.to_le_bytes());
+    output.extend_from_slice(&0x0100u16.to_le_bytes());
+    output.extend_from_slice(b"UFD");
+    output.push(16);
+    let crc = !crate::crc32::crc32_le(0, &output);
+    output.extend_from_slice(&crc.to_le_bytes());
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_formats() {
+        let regions = vec![
+            Region {
+                offset: 0x10000,
+                data: vec![0xAA; 500],
+            },
+            Region {
+                offset: 0x1000,
+                data: vec![1, 2, 3],
+            },
+        ];
+
+        let raw = merge_raw(&regions, None).unwrap();
+        assert_eq!(raw.len(), 0x10000 + 500);
+        assert_eq!(&raw[0x1000..0x1004], &[1, 2, 3, 0xFF]);
+        assert_eq!(merge_raw(&regions, Some(0x20000)).unwrap().len(), 0x20000);
+        assert!(merge_raw(&regions, Some(0x10000)).is_err());
+
+        let overlapping = vec![
+            regions[1].clone(),
+            Region {
+                offset: 0x1002,
+                data: vec![0],
+            },
+        ];
+        assert!(merge_raw(&overlapping, None).is_err());
+
+        let hex = intel_hex(&regions).unwrap();
+        let lines: Vec<&str> = hex.lines().collect();
+        assert_eq!(lines[0], ":020000040000FA");
+        assert_eq!(lines[1], ":03100000010203E7");
+        assert_eq!(lines[2], ":020000040001F9");
+        assert_eq!(*lines.last().unwrap(), ":00000001FF");
+
+        let uf2 = uf2(&regions, uf2_family("esp32s3").unwrap()).unwrap();
+        assert_eq!(uf2.len(), 3 * UF2_BLOCK_SIZE);
+        let word = |block: usize, index: usize| {
+            let offset = block * UF2_BLOCK_SIZE + index * 4;
+            u32::from_le_bytes(uf2[offset..offset + 4].try_into().unwrap())
+        };
+        // Second region: two blocks, the second one with the rest of the data
+        assert_eq!(word(2, 3), 0x10000 + UF2_CHUNK_SIZE as u32);
+        assert_eq!(word(2, 4), 500 - UF2_CHUNK_SIZE as u32);
+        assert_eq!((word(2, 5), word(2, 6)), (1, 2));
+        assert_eq!(word(2, 7), 0xc47e5767);
+        assert_eq!(word(2, 127), UF2_MAGIC_END);
+
+        let files = vec![
+            ("bootloader/bootloader.bin".to_string(), regions[1].clone()),
+            ("app.bin".to_string(), regions[0].clone()),
+        ];
+        let dfu = dfu(&files, dfu_pid("esp32s3").unwrap(), 256).unwrap();
+        assert_eq!(dfu.len(), CPIO_BLOCK_SIZE + 16);
+        assert!(dfu.starts_with(b"070701"));
+        // dfuinfo0.dat: the bootloader and two parts of the app
+        assert_eq!(&dfu[110..123], b"dfuinfo0.dat\0");
+        let info_size = u32::from_str_radix(std::str::from_utf8(&dfu[54..62]).unwrap(), 16);
+        assert_eq!(info_size.unwrap(), 3 * 88);
+        let suffix = &dfu[dfu.len() - 16..];
+        assert_eq!(&suffix[2..6], &[0x09, 0x00, 0x3a, 0x30]);
+        assert_eq!(&suffix[8..12], b"UFD\x10");
+        let crc = !crate::crc32::crc32_le(0, &dfu[..dfu.len() - 4]);
+        assert_eq!(&suffix[12..], &crc.to_le_bytes());
+    }
+}
@@ -0,0 +1,164 @@
+use anyhow::Result;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+pub const INVENTORY_FILE: &str = "devices.toml";
+
+/// A device listed in devices.toml:
+///
+/// ```toml
+/// [devices.bench-1]
+/// port = "/dev/ttyUSB0"          # or serial = "A50285BI"
+/// target = "esp32s3"
+/// profile = "sdkconfig.bench"    # defaults file layered on top of sdkconfig.defaults
+/// groups = ["bench"]
+/// baud = 921600
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Device {
+    pub name: String,
+    pub port: Option<String>,
+    /// USB serial number or /dev/serial/by-id name, resolved like --device-id
+    pub serial: Option<String>,
+    pub target: String,
+    pub profile: Option<PathBuf>,
+    pub groups: Vec<String>,
+    pub baud: Option<u32>,
+}
+
+impl Device {
+    /// Name of the build variant, shared by devices with the same target and profile
+    pub fn variant(&self) -> String {
+        match self
+            .profile
+            .as_ref()
+            .and_then(|profile| profile.file_name())
+        {
+            Some(profile) => {
+                let profile = profile.to_string_lossy();
+                let profile = profile.trim_start_matches("sdkconfig.");
+                format!("{}-{}", self.target, profile.replace(['/', '.'], "_"))
+            }
+            None => self.target.clone(),
+        }
+    }
+}
+
+fn parse_device(name: &str, value: &Value) -> Result<Device> {
+    let string = |key: &str| -> Result<Option<String>> {
+        match value.get(key) {
+            None => Ok(None),
+            Some(Value::String(value)) => Ok(Some(value.clone())),
+            Some(_) => Err(anyhow::anyhow!("devices.{}.{} must be a string", name, key)),
+        }
+    };
+
+    let target =
+        string("target")?.ok_or_else(|| anyhow::anyhow!("devices.{}: missing 'target'", name))?;
+
+    let groups = match value.get("groups") {
+        None => Vec::new(),
+        Some(Value::Array(groups)) => groups
+            .iter()
+            .map(|group| {
+                group.as_str().map(str::to_string).ok_or_else(|| {
+                    anyhow::anyhow!("devices.{}.groups entries must be strings", name)
+                })
+            })
+            .collect::<Result<_>>()?,
+        Some(_) => anyhow::bail!("devices.{}.groups must be a list", name),
+    };
+
+    let baud = match value.get("baud") {
+        None => None,
+        Some(baud) => Some(
+            baud.as_u64()
+                .and_then(|baud| u32::try_from(baud).ok())
+                .ok_or_else(|| anyhow::anyhow!("devices.{}.baud must be a number", name))?,
+        ),
+    };
+
+    let device = Device {
+        name: name.to_string(),
+        port: string("port")?,
+        serial: string("serial")?,
+        target,
+        profile: string("profile")?.map(PathBuf::from),
+        groups,
+        baud,
+    };
+
+    if device.port.is_none() && device.serial.is_none() {
+        anyhow::bail!("devices.{}: set either 'port' or 'serial'", name);
+    }
+
+    Ok(device)
+}
+
+pub fn parse_inventory(content: &str) -> Result<Vec<Device>> {
+    let value = crate::toml::parse(content)?;
+    let devices = value
+        .get("devices")
+        .and_then(Value::as_object)
+        .ok_or_else(|| anyhow::anyhow!("no [devices.<name>] tables found"))?;
+
+    devices
+        .iter()
+        .map(|(name, value)| parse_device(name, value))
+        .collect()
+}
+
+pub fn load_inventory(project_dir: &Path) -> Result<Vec<Device>> {
+    let path = project_dir.join(INVENTORY_FILE);
+    let content = std::fs::read_to_string(&path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+
+    parse_inventory(&content).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))
+}
+
+/// Select devices by group and/or name; no filters selects all devices
+pub fn select<'a>(devices: &'a [Device], group: Option<&str>, names: &[String]) -> Vec<&'a Device> {
+    devices
+        .iter()
+        .filter(|device| group.is_none_or(|group| device.groups.iter().any(|g| g == group)))
+        .filter(|device| names.is_empty() || names.contains(&device.name))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INVENTORY: &str = r#"
+[devices.bench-1]
+port = "/dev/ttyUSB0"
+target = "esp32s3"
+profile = "sdkconfig.bench"
+groups = ["bench"]
+
+[devices.bench-2]
+serial = "A50285BI"
+target = "esp32c3"
+groups = ["bench", "ci"]
+baud = 921600
+"#;
+
+    #[test]
+    fn test_parse_inventory() {
+        let devices = parse_inventory(INVENTORY).unwrap();
+
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].variant(), "esp32s3-bench");
+        assert_eq!(devices[1].variant(), "esp32c3");
+        assert_eq!(devices[1].serial.as_deref(), Some("A50285BI"));
+        assert_eq!(devices[1].baud, Some(921600));
+
+        let ci: Vec<_> = select(&devices, Some("ci"), &[])
+            .iter()
+            .map(|device| device.name.as_str())
+            .collect();
+        assert_eq!(ci, vec!["bench-2"]);
+
+        assert!(parse_inventory("[devices.x]\ntarget = \"esp32\"\n").is_err());
+    }
+}
@@ -0,0 +1,210 @@
+/// One heap region of a heap_caps_print_heap_info() report
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HeapRegion {
+    pub address: u64,
+    pub len: u64,
+    pub free: u64,
+    pub allocated: u64,
+    pub min_free: u64,
+    pub largest_free_block: u64,
+    pub free_blocks: u64,
+}
+
+/// Share of the free memory that can't be used for the largest allocation
+pub fn fragmentation(free: u64, largest_free_block: u64) -> f64 {
+    if free == 0 {
+        0.0
+    } else {
+        1.0 - largest_free_block as f64 / free as f64
+    }
+}
+
+impl HeapRegion {
+    pub fn fragmentation(&self) -> f64 {
+        fragmentation(self.free, self.largest_free_block)
+    }
+}
+
+/// A heap_caps_print_heap_info() report captured from the device log
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HeapSnapshot {
+    pub caps: String,
+    /// Timestamp in milliseconds of the last ESP log line before the report
+    pub time_ms: Option<u64>,
+    pub regions: Vec<HeapRegion>,
+    pub free: u64,
+    pub allocated: u64,
+    pub min_free: u64,
+    pub largest_free_block: u64,
+}
+
+impl HeapSnapshot {
+    pub fn total(&self) -> u64 {
+        self.free + self.allocated
+    }
+
+    pub fn fragmentation(&self) -> f64 {
+        fragmentation(self.free, self.largest_free_block)
+    }
+}
+
+/// Parse `key value` pairs such as `free 1234 allocated 5678`
+fn parse_fields(text: &str) -> Vec<(&str, u64)> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    words
+        .windows(2)
+        .filter_map(|pair| {
+            let value = pair[1];
+            let value = match value.strip_prefix("0x") {
+                Some(hex) => u64::from_str_radix(hex, 16).ok()?,
+                None => value.parse().ok()?,
+            };
+            Some((pair[0], value))
+        })
+        .collect()
+}
+
+fn field(fields: &[(&str, u64)], name: &str) -> u64 {
+    fields
+        .iter()
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| *value)
+        .unwrap_or(0)
+}
+
+/// Timestamp of an ESP log line such as `I (12345) tag: message`
+fn log_timestamp(line: &str) -> Option<u64> {
+    let start = line.find(" (")? + 2;
+    let end = start + line[start..].find(')')?;
+    line[start..end].parse().ok()
+}
+
+/// Extract all heap info reports from a device log. Monitor prefixes such as
+/// timestamps or colour codes before the report text are ignored.
+pub fn parse_heap_info(log: &str) -> Vec<HeapSnapshot> {
+    let mut snapshots = Vec::new();
+    let mut current: Option<HeapSnapshot> = None;
+    let mut last_time = None;
+
+    let lines: Vec<&str> = log.lines().collect();
+    let mut index = 0;
+    while index < lines.len() {
+        let line = lines[index];
+        index += 1;
+
+        if let Some(position) = line.find("Heap summary for capabilities") {
+            if let Some(snapshot) = current.take() {
+                snapshots.push(snapshot);
+            }
+            let caps = line[position..]
+                .trim_end_matches(':')
+                .rsplit(' ')
+                .next()
+                .unwrap_or_default()
+                .trim_end_matches(':')
+                .to_string();
+            current = Some(HeapSnapshot {
+                caps,
+                time_ms: last_time,
+                ..Default::default()
+            });
+            continue;
+        }
+
+        let snapshot = match current.as_mut() {
+            Some(snapshot) => snapshot,
+            None => {
+                last_time = log_timestamp(line).or(last_time);
+                continue;
+            }
+        };
+
+        if let Some(position) = line.find("At 0x") {
+            // The region details continue on the next line
+            let mut text = line[position + 3..].to_string();
+            if let Some(next) = lines.get(index) {
+                if next.contains("largest_free_block") && !next.contains("At 0x") {
+                    text.push(' ');
+                    text.push_str(next);
+                    index += 1;
+                }
+            }
+
+            let fields = parse_fields(&text);
+            let address = text
+                .split_whitespace()
+                .next()
+                .and_then(|address| u64::from_str_radix(address.trim_start_matches("0x"), 16).ok())
+                .unwrap_or(0);
+            snapshot.regions.push(HeapRegion {
+                address,
+                len: field(&fields, "len"),
+                free: field(&fields, "free"),
+                allocated: field(&fields, "allocated"),
+                min_free: field(&fields, "min_free"),
+                largest_free_block: field(&fields, "largest_free_block"),
+                free_blocks: field(&fields, "free_blocks"),
+            });
+        } else if line.contains("Totals:") {
+            let text = match lines.get(index) {
+                Some(next) => {
+                    index += 1;
+                    next
+                }
+                None => "",
+            };
+            let fields = parse_fields(text);
+            snapshot.free = field(&fields, "free");
+            snapshot.allocated = field(&fields, "allocated");
+            snapshot.min_free = field(&fields, "min_free");
+            snapshot.largest_free_block = field(&fields, "largest_free_block");
+            snapshots.push(current.take().unwrap());
+        } else if !line.trim().is_empty() && !line.contains("largest_free_block") {
+            // Any other output ends a report that had no totals line
+            snapshots.push(current.take().unwrap());
+            last_time = log_timestamp(line).or(last_time);
+        }
+    }
+
+    if let Some(snapshot) = current {
+        snapshots.push(snapshot);
+    }
+
+    snapshots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_heap_info() {
+        let log = "I (1520) app: dumping heap
+Heap summary for capabilities 0x00001800:
+  At 0x3ffae6e0 len 6432 free 0 allocated 6288 min_free 0
+    largest_free_block 0 alloc_blocks 37 free_blocks 0 total_blocks 37
+  At 0x3ffb6388 len 7288 free 4096 allocated 3000 min_free 2048
+    largest_free_block 1024 alloc_blocks 59 free_blocks 4 total_blocks 63
+  Totals:
+    free 4096 allocated 9288 min_free 2048 largest_free_block 1024
+I (2520) app: next
+Heap summary for capabilities 0x00000004:
+  At 0x3ffb6388 len 7288 free 2048 allocated 5000 min_free 1024
+    largest_free_block 2048 alloc_blocks 60 free_blocks 1 total_blocks 61
+  Totals:
+    free 2048 allocated 5000 min_free 1024 largest_free_block 2048
+";
+        let snapshots = parse_heap_info(log);
+
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots[0].caps, "0x00001800");
+        assert_eq!(snapshots[0].time_ms, Some(1520));
+        assert_eq!(snapshots[0].regions.len(), 2);
+        assert_eq!(snapshots[0].regions[1].address, 0x3ffb6388);
+        assert_eq!(snapshots[0].regions[1].free_blocks, 4);
+        assert_eq!(snapshots[0].total(), 13384);
+        assert!((snapshots[0].fragmentation() - 0.75).abs() < 1e-9);
+        assert_eq!(snapshots[1].time_ms, Some(2520));
+        assert_eq!(snapshots[1].fragmentation(), 0.0);
+    }
+}
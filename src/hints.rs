@@ -0,0 +1,258 @@
+//! Hints on how to resolve errors, from ESP-IDF's hints.yml: entries with a regular
+//! expression (`re`) and the hint printed when the output matches it. `{}` in the hint
+//! takes the matched text with `match_to_output`, and `variables` instantiates one entry
+//! for several values of its `re` and `hint` placeholders.
+
+use crate::utils;
+use anyhow::Result;
+use regex::Regex;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hint {
+    pub re: String,
+    pub hint: String,
+    pub match_to_output: bool,
+    /// Values for the placeholders of `re` and `hint`, one pair per instance
+    pub variables: Vec<(Vec<String>, Vec<String>)>,
+}
+
+/// The hints file of ESP-IDF, where idf.py reads it from
+pub fn idf_hints_path(idf_path: &Path) -> PathBuf {
+    idf_path.join("tools/idf_py_actions/hints.yml")
+}
+
+fn strings(value: &serde_json::Value) -> Vec<String> {
+    value
+        .as_array()
+        .map(|items| {
+            items
+                .iter()
+                .map(|item| match item {
+                    serde_json::Value::String(text) => text.clone(),
+                    other => other.to_string(),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn parse(content: &str) -> Result<Vec<Hint>> {
+    let document = crate::yaml::parse(content)?;
+    let entries = document.as_array().cloned().unwrap_or_default();
+    Ok(entries
+        .iter()
+        .filter_map(|entry| {
+            let re = entry["re"].as_str()?.to_string();
+            let hint = entry["hint"].as_str()?.to_string();
+            let match_to_output = match &entry["match_to_output"] {
+                serde_json::Value::Bool(value) => *value,
+                serde_json::Value::String(value) => value.eq_ignore_ascii_case("true"),
+                _ => false,
+            };
+            let variables = entry["variables"]
+                .as_array()
+                .map(|variables| {
+                    variables
+                        .iter()
+                        .map(|v| (strings(&v["re_variables"]), strings(&v["hint_variables"])))
+                        .collect()
+                })
+                .unwrap_or_default();
+            Some(Hint {
+                re,
+                hint,
+                match_to_output,
+                variables,
+            })
+        })
+        .collect())
+}
+
+/// Python's str.format for positional fields: `{}`, `{0}` and the `{{`/`}}` escapes
+pub fn format(template: &str, args: &[String]) -> String {
+    let mut output = String::new();
+    let mut next = 0;
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                output.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                output.push('}');
+            }
+            '{' => {
+                let mut field = String::new();
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        break;
+                    }
+                    field.push(c);
+                }
+                let index = field.parse().unwrap_or_else(|_| {
+                    next += 1;
+                    next - 1
+                });
+                output.push_str(args.get(index).map(String::as_str).unwrap_or(""));
+            }
+            _ => output.push(c),
+        }
+    }
+    output
+}
+
+/// The first line of the output a pattern matches, with its groups (or the whole
+/// match when the pattern has none)
+fn search(re: &str, output: &str) -> Option<Vec<String>> {
+    // Patterns using syntax the regex crate lacks (backreferences, lookaround) are skipped
+    let regex = Regex::new(re).ok()?;
+    output.lines().find_map(|line| {
+        let captures = regex.captures(line)?;
+        let group = |group: Option<regex::Match>| {
+            group
+                .map(|group| group.as_str().to_string())
+                .unwrap_or_default()
+        };
+        let groups: Vec<String> = captures.iter().skip(1).map(group).collect();
+        Some(if groups.is_empty() {
+            vec![group(captures.get(0))]
+        } else {
+            groups
+        })
+    })
+}
+
+/// The hints whose patterns match the output, each once
+pub fn generate(output: &str, hints: &[Hint]) -> Vec<String> {
+    let mut messages: Vec<String> = Vec::new();
+    for hint in hints {
+        let found: Vec<String> = if hint.variables.is_empty() {
+            search(&hint.re, output)
+                .map(|matched| {
+                    if hint.match_to_output {
+                        format(&hint.hint, &matched)
+                    } else {
+                        hint.hint.clone()
+                    }
+                })
+                .into_iter()
+                .collect()
+        } else {
+            hint.variables
+                .iter()
+                .filter(|(re_variables, _)| {
+                    search(&format(&hint.re, re_variables), output).is_some()
+                })
+                .map(|(_, hint_variables)| format(&hint.hint, hint_variables))
+                .collect()
+        };
+        for message in found {
+            let message = message.trim().to_string();
+            if !messages.contains(&message) {
+                messages.push(message);
+            }
+        }
+    }
+    messages
+}
+
+/// ESP-IDF's hints and those of the components of the build (a hints.yml in the
+/// component directory)
+pub fn load(build_dir: &Path) -> Vec<Hint> {
+    let mut files = Vec::new();
+    if let Ok(idf_path) = utils::get_idf_path() {
+        files.push(idf_hints_path(&idf_path));
+    }
+    if let Ok(description) = utils::load_project_description(build_dir) {
+        if let Some(components) = description["build_component_info"].as_object() {
+            for component in components.values() {
+                if let Some(dir) = component["dir"].as_str() {
+                    files.push(Path::new(dir).join("hints.yml"));
+                }
+            }
+        }
+    }
+
+    files
+        .iter()
+        .filter_map(|file| std::fs::read_to_string(file).ok())
+        .filter_map(|content| parse(&content).ok())
+        .flatten()
+        .collect()
+}
+
+/// Print the hints for a failed command, matching its error and the tool output
+/// logged under <build>/log
+pub fn print_for_failure(build_dir: &Path, error: &anyhow::Error) {
+    let hints = load(build_dir);
+    if hints.is_empty() {
+        return;
+    }
+
+    let mut output = format!("{:#}\n", error);
+    if let Ok(entries) = std::fs::read_dir(build_dir.join("log")) {
+        let mut logs: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+        logs.sort();
+        for log in logs {
+            if let Ok(content) = std::fs::read_to_string(&log) {
+                output.push_str(&content);
+            }
+        }
+    }
+
+    for hint in generate(&output, &hints) {
+        println!("HINT: {}", hint);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_hints() {
+        let hints = parse(
+            r#"
+-
+    re: "error: '(\\w+)' undeclared"
+    hint: "'{}' was removed. Use the new API instead."
+    match_to_output: True
+
+-
+    re: "fatal error: ({}): No such file or directory"
+    hint: "{} was moved to the {} component."
+    variables:
+        -
+            re_variables: ['esp_spi_flash.h']
+            hint_variables: ['esp_spi_flash.h', 'spi_flash']
+        -
+            re_variables: ['driver/adc.h']
+            hint_variables: ['driver/adc.h', 'esp_adc']
+
+-
+    re: "region `iram0_0_seg' overflowed"
+    hint: "The code doesn't fit in IRAM, move functions to flash."
+"#,
+        )
+        .unwrap();
+        assert_eq!(hints.len(), 3);
+        assert!(hints[0].match_to_output);
+        assert_eq!(hints[1].variables.len(), 2);
+
+        let output = "main.c:3:10: fatal error: esp_spi_flash.h: No such file or directory\n\
+                      main.c:9:5: error: 'portTICK_RATE_MS' undeclared (first use)\n\
+                      main.c:9:5: error: 'portTICK_RATE_MS' undeclared here\n";
+        assert_eq!(
+            generate(output, &hints),
+            vec![
+                "'portTICK_RATE_MS' was removed. Use the new API instead.",
+                "esp_spi_flash.h was moved to the spi_flash component.",
+            ]
+        );
+        assert!(generate("all good", &hints).is_empty());
+        assert_eq!(format("{{{}}} {1}", &["a".into(), "b".into()]), "{a} b");
+    }
+}
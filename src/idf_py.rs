@@ -0,0 +1,44 @@
+//! The Python idf.py of ESP-IDF, which runs the commands idf-rs doesn't implement
+//! (e.g. efuse-summary, docs or the actions of component extensions)
+
+use crate::utils;
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// Set for the delegated idf.py, so that an idf.py that turns out to be idf-rs again
+/// fails instead of calling itself forever
+const DELEGATED_ENV: &str = "IDF_RS_DELEGATED";
+
+fn is_idf_rs(path: &Path) -> bool {
+    let current = std::env::current_exe().and_then(|exe| exe.canonicalize());
+    match (path.canonicalize(), current) {
+        (Ok(path), Ok(current)) => path == current,
+        _ => false,
+    }
+}
+
+/// The original idf.py: the backup install-alias made of it, or the one in
+/// $IDF_PATH/tools while it is still the Python script
+pub fn find() -> Result<PathBuf> {
+    if std::env::var_os(DELEGATED_ENV).is_some() {
+        return Err(anyhow::anyhow!(
+            "idf.py delegated the command back to idf-rs"
+        ));
+    }
+    let tools = utils::get_idf_path()?.join("tools");
+    [tools.join("idf-old.py"), tools.join("idf.py")]
+        .into_iter()
+        .find(|path| path.is_file() && !is_idf_rs(path))
+        .ok_or_else(|| anyhow::anyhow!("The Python idf.py was not found in {}", tools.display()))
+}
+
+/// Run idf.py with the arguments of this invocation, returning its exit code
+pub fn delegate(idf_py: &Path, args: &[String]) -> Result<i32> {
+    let status = std::process::Command::new(utils::get_python_executable()?)
+        .arg(idf_py)
+        .args(args)
+        .env(DELEGATED_ENV, "1")
+        .status()
+        .map_err(|e| anyhow::anyhow!("Failed to run {}: {}", idf_py.display(), e))?;
+    Ok(status.code().unwrap_or(1))
+}
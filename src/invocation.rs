@@ -0,0 +1,708 @@
+//! The command line of the idf-rs binary: `@file` expansion, global options given after
+//! a command, repeated `--port`, command chains like `build flash monitor`, and running
+//! unknown commands with plugins or the Python idf.py. Errors of the arguments exit the
+//! process like clap does, so this is for the binary rather than for embedding.
+
+use crate::cli::{execute_command, resolve_defaults, Cli, Commands};
+use crate::{compile_commands, extensions, idf_py, output, plugins, serial_ports, utils};
+use anyhow::Result;
+use clap::{CommandFactory, Parser};
+use std::path::{Path, PathBuf};
+
+/// Parser for a single command of a chained invocation
+#[derive(Parser, Debug)]
+#[command(name = "idf-rs", no_binary_name = true)]
+struct SingleCommand {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Debug, Clone)]
+struct ParsedCommand {
+    name: String,
+    args: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct MultipleCommands {
+    global_args: Cli,
+    commands: Vec<ParsedCommand>,
+}
+
+/// Global options idf.py also accepts after the action name, e.g. `build -DFOO=1`
+/// or `flash -p COM7 -b 921600`, as (short, long) flags
+const COMMAND_OVERRIDES: &[(&str, &str)] = &[
+    ("-D", "--define-cache-entry"),
+    ("-p", "--port"),
+    ("-b", "--baud"),
+];
+
+/// Split the arguments of a command into its own arguments and the global options
+/// given after it. Options the command defines itself (create-project's -p) stay.
+fn split_command_overrides(command: &str, args: &[String]) -> (Vec<String>, Vec<String>) {
+    let cli_command = Cli::command();
+    let subcommand = cli_command.find_subcommand(command);
+    let is_own_option = |short: &str, long: &str| {
+        subcommand.is_some_and(|subcommand| {
+            subcommand.get_arguments().any(|arg| {
+                arg.get_short().map(|c| format!("-{}", c)).as_deref() == Some(short)
+                    || arg.get_long().map(|l| format!("--{}", l)).as_deref() == Some(long)
+            })
+        })
+    };
+
+    let mut remaining = Vec::new();
+    let mut overrides = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        // Everything after "--" is passed through untouched
+        if arg == "--" {
+            remaining.push(arg.clone());
+            remaining.extend(iter.by_ref().cloned());
+            break;
+        }
+
+        let option = COMMAND_OVERRIDES.iter().find(|(short, long)| {
+            !is_own_option(short, long)
+                && (arg.starts_with(short) || arg == long || arg.starts_with(&format!("{}=", long)))
+        });
+        match option {
+            Some((short, long)) if arg == short || arg == long => {
+                overrides.push(arg.clone());
+                overrides.extend(iter.next().cloned());
+            }
+            Some(_) => overrides.push(arg.clone()),
+            None => remaining.push(arg.clone()),
+        }
+    }
+
+    (remaining, overrides)
+}
+
+/// Index of the command in the arguments, skipping the values of global options
+/// (e.g. "-B build")
+fn command_index(args: &[String]) -> usize {
+    let cli_command = Cli::command();
+    let takes_value = |arg: &str| {
+        cli_command.get_arguments().any(|option| {
+            let short = option.get_short().map(|c| format!("-{}", c));
+            let long = option.get_long().map(|l| format!("--{}", l));
+            option.get_action().takes_values()
+                && (short.as_deref() == Some(arg) || long.as_deref() == Some(arg))
+        })
+    };
+
+    // Find the command, skipping the values of global options (e.g. "-B build")
+    let mut index = 1;
+    while index < args.len() {
+        let arg = &args[index];
+        if cli_command.find_subcommand(arg).is_some() {
+            break;
+        }
+        index += if takes_value(arg) { 2 } else { 1 };
+    }
+    index
+}
+
+/// Move global options given after the command in front of it, so clap sees them as globals
+fn hoist_command_overrides(args: &[String]) -> Vec<String> {
+    let index = command_index(args);
+    if index >= args.len() {
+        return args.to_vec();
+    }
+
+    let (command_args, overrides) = split_command_overrides(&args[index], &args[index + 1..]);
+    let mut hoisted = args[..index].to_vec();
+    hoisted.extend(overrides);
+    hoisted.push(args[index].clone());
+    hoisted.extend(command_args);
+    hoisted
+}
+
+/// Take the ports of a repeated global -p/--port out of the arguments, keeping the first
+/// for clap, so `flash -p A -p B` can flash several devices
+fn split_extra_ports(args: &[String]) -> (Vec<String>, Vec<String>) {
+    let index = command_index(args).min(args.len());
+    let mut remaining = Vec::new();
+    let mut ports = Vec::new();
+    let mut iter = args[..index].iter();
+    while let Some(arg) = iter.next() {
+        let port = if arg == "-p" || arg == "--port" {
+            let value = iter.next().cloned();
+            if ports.is_empty() {
+                remaining.push(arg.clone());
+                remaining.extend(value.clone());
+            }
+            value
+        } else if let Some(value) = arg
+            .strip_prefix("--port=")
+            .or_else(|| arg.strip_prefix("-p").filter(|value| !value.is_empty()))
+        {
+            if ports.is_empty() {
+                remaining.push(arg.clone());
+            }
+            Some(value.to_string())
+        } else {
+            remaining.push(arg.clone());
+            continue;
+        };
+        ports.extend(port);
+    }
+    remaining.extend_from_slice(&args[index..]);
+
+    // The first port stays with clap
+    if !ports.is_empty() {
+        ports.remove(0);
+    }
+    (remaining, ports)
+}
+
+/// Expand `@file` arguments like idf.py: each line of the file holds arguments with shell
+/// quoting, `#` starts a comment line, and `@file` arguments inside it are expanded
+/// relative to the file's directory
+fn expand_file_arguments(args: &[String]) -> Result<Vec<String>> {
+    fn expand(args: &[String], dir: &Path, stack: &mut Vec<PathBuf>) -> Result<Vec<String>> {
+        let mut expanded = Vec::new();
+        for arg in args {
+            let Some(name) = arg.strip_prefix('@') else {
+                expanded.push(arg.clone());
+                continue;
+            };
+            let path = dir.join(name);
+            if stack.contains(&path) {
+                return Err(anyhow::anyhow!(
+                    "Circular dependency in file argument expansion: {}",
+                    path.display()
+                ));
+            }
+            let content = std::fs::read_to_string(&path).map_err(|e| {
+                anyhow::anyhow!("Failed to read argument file {}: {}", path.display(), e)
+            })?;
+
+            let file_args: Vec<String> = content
+                .lines()
+                .filter(|line| !line.trim_start().starts_with('#'))
+                .flat_map(compile_commands::split_command_line)
+                .collect();
+            stack.push(path.clone());
+            let file_dir = path.parent().unwrap_or(Path::new(""));
+            expanded.extend(expand(&file_args, file_dir, stack)?);
+            stack.pop();
+        }
+        Ok(expanded)
+    }
+
+    let Some((program, rest)) = args.split_first() else {
+        return Ok(Vec::new());
+    };
+    let mut expanded = vec![program.clone()];
+    expanded.extend(expand(rest, Path::new(""), &mut Vec::new())?);
+    Ok(expanded)
+}
+
+/// Parse command line arguments to detect multiple commands
+fn parse_multiple_commands(args: &[String]) -> Result<MultipleCommands> {
+    // List of known commands that can be chained
+    let known_commands = [
+        "build",
+        "all",
+        "app",
+        "bootloader",
+        "clean",
+        "fullclean",
+        "flash",
+        "app-flash",
+        "bootloader-flash",
+        "encrypted-flash",
+        "encrypted-app-flash",
+        "monitor",
+        "menuconfig",
+        "set-config",
+        "get-config",
+        "set-target",
+        "erase-flash",
+        "list-ports",
+        "chip-info",
+        "verify-flash",
+        "read-flash",
+        "dump-flash",
+        "uf2",
+        "uf2-flash",
+        "dfu",
+        "dfu-flash",
+        "dfu-list",
+        "merge-bin",
+        "size",
+        "size-components",
+        "size-files",
+        "reconfigure",
+        "create-project",
+        "init",
+        "build-system-targets",
+        "migrate-component",
+        "compile",
+        "preprocess",
+        "asm",
+        "format",
+        "analyze",
+        "config",
+        "manifest",
+        "deps",
+        "cache",
+        "fleet",
+        "trace",
+        "heap-analyze",
+        "openocd",
+        "gdb",
+        "gdbtui",
+        "debug",
+        "coredump-info",
+        "coredump-debug",
+        "ota-package",
+        "ota-serve",
+        "partition-table",
+        "partition-table-flash",
+        "erase-partition",
+        "nvs-gen",
+        "spiffs-gen",
+        "littlefs-gen",
+        "fatfs-gen",
+        "fatfs-extract",
+        "nvs-dump",
+        "ota-info",
+        "switch-ota-partition",
+        "erase-otadata",
+        "read-partition",
+        "write-partition",
+        "security-info",
+        "efuse-summary",
+        "efuse-burn",
+        "efuse-read-protect",
+        "efuse-write-protect",
+        "esptool",
+        "espefuse",
+        "espsecure",
+        "secure-sign-data",
+        "secure-generate-signing-key",
+        "stats",
+        "ccache-stats",
+        "daemon",
+        "completions",
+        "install-alias",
+        "uninstall-alias",
+    ];
+
+    // Commands with their own subcommands (e.g. "fleet flash") and the tool passthroughs
+    // (e.g. "esptool -- read_mac") take the rest of the line
+    let nested_commands = [
+        "config",
+        "manifest",
+        "deps",
+        "cache",
+        "fleet",
+        "trace",
+        "partition-table",
+        "esptool",
+        "espefuse",
+        "espsecure",
+    ];
+
+    if args.len() < 2 {
+        return Err(anyhow::anyhow!("No commands provided"));
+    }
+
+    let mut commands = Vec::new();
+    let mut global_args = Vec::new();
+    let mut current_command: Option<String> = None;
+    let mut current_args = Vec::new();
+    let mut found_multiple_commands = false;
+
+    // Skip program name
+    let mut i = 1;
+    while i < args.len() {
+        let arg = &args[i];
+
+        let in_nested_command = current_command
+            .as_deref()
+            .is_some_and(|command| nested_commands.contains(&command));
+
+        // Check if this is a known command
+        if known_commands.contains(&arg.as_str()) && !in_nested_command {
+            // Save previous command if exists
+            if let Some(cmd) = current_command.take() {
+                commands.push(ParsedCommand {
+                    name: cmd,
+                    args: current_args.clone(),
+                });
+                current_args.clear();
+                found_multiple_commands = true;
+            } else if !commands.is_empty() {
+                found_multiple_commands = true;
+            }
+
+            current_command = Some(arg.clone());
+        } else if current_command.is_some() {
+            // This is an argument for the current command
+            current_args.push(arg.clone());
+        } else {
+            // This is a global argument (before any commands)
+            global_args.push(arg.clone());
+        }
+
+        i += 1;
+    }
+
+    // Save the last command
+    if let Some(cmd) = current_command {
+        commands.push(ParsedCommand {
+            name: cmd,
+            args: current_args,
+        });
+    }
+
+    // Only return Ok if we found multiple commands or no commands at all
+    if commands.len() > 1 || (commands.len() == 1 && found_multiple_commands) {
+        // Parse global arguments (everything before the first command)
+        let cli = Cli::parse_from(std::iter::once(args[0].clone()).chain(global_args));
+
+        Ok(MultipleCommands {
+            global_args: cli,
+            commands,
+        })
+    } else {
+        Err(anyhow::anyhow!(
+            "Single command detected, use normal parsing"
+        ))
+    }
+}
+
+/// Execute multiple commands in sequence
+async fn execute_multiple_commands(parsed: MultipleCommands) -> Result<()> {
+    output::init(parsed.global_args.output_format)?;
+    let _lock_chain = serial_ports::LockChain::start();
+    println!(
+        "Executing {} commands in sequence...",
+        parsed.commands.len()
+    );
+
+    for (i, cmd) in parsed.commands.iter().enumerate() {
+        println!(
+            "[{}/{}] Executing command: {}",
+            i + 1,
+            parsed.commands.len(),
+            cmd.name
+        );
+
+        // Options after the command name override the globals for this command only
+        let (args, overrides) = split_command_overrides(&cmd.name, &cmd.args);
+        let mut cli = parsed.global_args.clone();
+        if !overrides.is_empty() {
+            cli.try_update_from(std::iter::once("idf-rs".to_string()).chain(overrides))?;
+        }
+        let cmd = &ParsedCommand {
+            name: cmd.name.clone(),
+            args,
+        };
+        let previous = i.checked_sub(1).map(|previous| &parsed.commands[previous]);
+        let command = parse_single_command(cmd, previous)?;
+        cli.command = Some(command.clone());
+
+        // Resolve defaults for each command, an earlier one (e.g. set-target)
+        // may have changed what they resolve to
+        resolve_defaults(&mut cli)?;
+
+        // Execute each command
+        match execute_command(&cli, &command).await {
+            Ok(()) => {
+                println!(
+                    "[{}/{}] Command '{}' completed successfully",
+                    i + 1,
+                    parsed.commands.len(),
+                    cmd.name
+                );
+            }
+            Err(e) => {
+                eprintln!(
+                    "[{}/{}] Command '{}' failed: {}",
+                    i + 1,
+                    parsed.commands.len(),
+                    cmd.name,
+                    e
+                );
+                return Err(e);
+            }
+        }
+    }
+
+    println!("All commands completed successfully!");
+    Ok(())
+}
+
+/// Whether a command writes the app to the device, so a monitor chained after it
+/// ("flash monitor") watches the new app boot
+fn flashes_app(command: &str) -> bool {
+    matches!(
+        command,
+        "flash" | "app-flash" | "encrypted-flash" | "encrypted-app-flash"
+    )
+}
+
+/// Parse a command of the chain, `previous` being the one before it
+fn parse_single_command(cmd: &ParsedCommand, previous: Option<&ParsedCommand>) -> Result<Commands> {
+    let mut parsed = SingleCommand::try_parse_from(std::iter::once(&cmd.name).chain(&cmd.args))?;
+    if let (Commands::Monitor { options }, Some(previous)) = (&mut parsed.command, previous) {
+        // A monitor right after flashing starts on the port the flash used, at the
+        // console baud rate, and over USB-JTAG after "flash --jtag"
+        if flashes_app(&previous.name) {
+            options.after_flash = true;
+            options.probe_rs |= previous.args.iter().any(|arg| arg == "--jtag");
+        }
+    }
+    Ok(parsed.command)
+}
+
+/// Whether the arguments name a subcommand, e.g. for `build --help`
+fn has_subcommand(args: &[String]) -> bool {
+    let command = Cli::command();
+    args.iter()
+        .skip(1)
+        .any(|arg| command.find_subcommand(arg).is_some())
+}
+
+/// Project and build directory given by the arguments, before they are parsed
+fn dirs_from_args(args: &[String]) -> (PathBuf, PathBuf) {
+    let value = |short: &str, long: &str| {
+        args.iter().enumerate().find_map(|(i, arg)| {
+            if arg == short || arg == long {
+                args.get(i + 1).map(PathBuf::from)
+            } else {
+                arg.strip_prefix(&format!("{}=", long)).map(PathBuf::from)
+            }
+        })
+    };
+    let project_dir = utils::get_project_dir(value("-C", "--project-dir").as_deref());
+    let build_dir = utils::get_build_dir(value("-B", "--build-dir").as_deref(), &project_dir);
+    (project_dir, build_dir)
+}
+
+/// Run the plugin of a command with the arguments after it, the ones before it are the
+/// global options
+fn run_plugin(plugin: &std::path::Path, command: &str, args: &[String]) -> Result<i32> {
+    let position = args
+        .iter()
+        .skip(1)
+        .position(|arg| arg == command)
+        .map_or(args.len(), |index| index + 1);
+    let mut cli = Cli::try_parse_from(&args[..position]).unwrap_or_else(|e| e.exit());
+    resolve_defaults(&mut cli)?;
+    plugins::run(plugin, args.get(position + 1..).unwrap_or_default(), &cli)
+}
+
+/// Run a command idf-rs doesn't know with the Python idf.py, which also runs the
+/// actions of the idf_ext.py extensions. None when there is no idf.py to run it.
+fn run_with_idf_py(command: &str, args: &[String]) -> Result<Option<i32>> {
+    let (project_dir, build_dir) = dirs_from_args(args);
+    let action = extensions::list_actions(&project_dir, &build_dir)
+        .unwrap_or_default()
+        .into_iter()
+        .find(|action| action.matches(command));
+    match (idf_py::find(), action) {
+        (Ok(idf_py), action) => {
+            match &action {
+                Some(action) => eprintln!(
+                    "Running extension action '{}' of {} with {}",
+                    command,
+                    action.file,
+                    idf_py.display()
+                ),
+                None => eprintln!(
+                    "idf-rs doesn't implement '{}', running {}",
+                    command,
+                    idf_py.display()
+                ),
+            }
+            idf_py::delegate(&idf_py, &args[1..]).map(Some)
+        }
+        (Err(e), Some(action)) => Err(anyhow::anyhow!(
+            "'{}' is an extension action of {}, running it needs the Python idf.py: {}",
+            command,
+            action.file,
+            e
+        )),
+        (Err(_), None) => Ok(None),
+    }
+}
+
+/// Run an idf-rs invocation with its arguments, the program name first
+pub async fn run(args: Vec<String>) -> Result<()> {
+    // Parse raw arguments to detect multiple commands, after expanding @file arguments
+    let args = if args.iter().skip(1).any(|arg| arg.starts_with('@')) {
+        let expanded = expand_file_arguments(&args)?;
+        eprintln!("Expanded arguments: {}", expanded[1..].join(" "));
+        expanded
+    } else {
+        args
+    };
+
+    // Handle multiple commands (e.g., "idf-rs build flash monitor")
+    if let Ok(parsed_commands) = parse_multiple_commands(&args) {
+        return execute_multiple_commands(parsed_commands).await;
+    }
+
+    let (cli_args, extra_ports) = split_extra_ports(&hoist_command_overrides(&args));
+    let mut cli = match Cli::try_parse_from(cli_args) {
+        Ok(cli) => cli,
+        // Commands idf-rs doesn't implement are run by the Python idf.py, if there is one
+        Err(e) if e.kind() == clap::error::ErrorKind::InvalidSubcommand => {
+            if let Some(clap::error::ContextValue::String(command)) =
+                e.get(clap::error::ContextKind::InvalidSubcommand)
+            {
+                if let Some(plugin) = plugins::find(command) {
+                    std::process::exit(run_plugin(&plugin, command, &args)?);
+                }
+                if let Some(code) = run_with_idf_py(command, &args)? {
+                    std::process::exit(code);
+                }
+            }
+            e.exit()
+        }
+        Err(e) if e.kind() == clap::error::ErrorKind::DisplayHelp && !has_subcommand(&args) => {
+            e.print()?;
+            let (project_dir, build_dir) = dirs_from_args(&args);
+            if let Ok(actions) = extensions::list_actions(&project_dir, &build_dir) {
+                if !actions.is_empty() {
+                    print!("\n{}", extensions::help_section(&actions));
+                }
+            }
+            let plugins = plugins::list();
+            if !plugins.is_empty() {
+                println!("\nPlugins (idf-rs-<command> on PATH):");
+                for plugin in plugins {
+                    println!("  {}", plugin);
+                }
+            }
+            return Ok(());
+        }
+        Err(e) => e.exit(),
+    };
+    if !extra_ports.is_empty() {
+        match &mut cli.command {
+            Some(Commands::Flash { ports, .. }) => {
+                *ports = cli.port.take().into_iter().chain(extra_ports).collect();
+            }
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Several --port options are only supported by flash, without monitor"
+                ))
+            }
+        }
+    }
+    resolve_defaults(&mut cli)?;
+
+    // Handle global flags first
+    if cli.idf_version {
+        println!("ESP-IDF Rust CLI v{}", env!("CARGO_PKG_VERSION"));
+        return Ok(());
+    }
+
+    if cli.list_targets {
+        utils::list_targets();
+        return Ok(());
+    }
+
+    // Execute the command
+    match &cli.command {
+        Some(command) => execute_command(&cli, command).await,
+        None => {
+            // Default behavior - show help
+            println!("No command specified. Use --help for available commands.");
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_command_overrides() {
+        let (args, overrides) = split_command_overrides(
+            "flash",
+            &strings(&["-p", "COM7", "--baud=921600", "--force"]),
+        );
+        assert_eq!(args, strings(&["--force"]));
+        assert_eq!(overrides, strings(&["-p", "COM7", "--baud=921600"]));
+
+        let (args, overrides) =
+            split_command_overrides("build", &strings(&["-DFOO=1", "--", "-DBAR=2"]));
+        assert_eq!(args, strings(&["--", "-DBAR=2"]));
+        assert_eq!(overrides, strings(&["-DFOO=1"]));
+
+        // create-project has its own -p (--path)
+        let (args, overrides) =
+            split_command_overrides("create-project", &strings(&["-p", "dir", "x"]));
+        assert_eq!(args, strings(&["-p", "dir", "x"]));
+        assert!(overrides.is_empty());
+
+        assert_eq!(
+            hoist_command_overrides(&strings(&["idf-rs", "-B", "build", "flash", "-p", "COM7"])),
+            strings(&["idf-rs", "-B", "build", "-p", "COM7", "flash"])
+        );
+    }
+
+    #[test]
+    fn test_split_extra_ports() {
+        let args = hoist_command_overrides(&strings(&[
+            "idf-rs",
+            "-B",
+            "build",
+            "flash",
+            "-p",
+            "/dev/ttyUSB0",
+            "--port=/dev/ttyUSB1",
+            "-p/dev/ttyACM0",
+        ]));
+        let (args, ports) = split_extra_ports(&args);
+        assert_eq!(
+            args,
+            strings(&["idf-rs", "-B", "build", "-p", "/dev/ttyUSB0", "flash"])
+        );
+        assert_eq!(ports, strings(&["/dev/ttyUSB1", "/dev/ttyACM0"]));
+
+        let args = strings(&["idf-rs", "create-project", "-p", "a", "-p", "b"]);
+        assert_eq!(split_extra_ports(&args), (args.clone(), Vec::new()));
+    }
+
+    #[test]
+    fn test_expand_file_arguments() {
+        let dir = std::env::temp_dir().join(format!("idf-rs-args-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("ci")).unwrap();
+        std::fs::write(
+            dir.join("args.txt"),
+            "# CI build\n-D \"SDKCONFIG_DEFAULTS=a;b\"\n@ci/more.txt\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("ci/more.txt"), "-p /dev/ttyUSB0\n").unwrap();
+        std::fs::write(dir.join("ci/loop.txt"), "@loop.txt\n").unwrap();
+
+        let file = |name: &str| format!("@{}", dir.join(name).display());
+        assert_eq!(
+            expand_file_arguments(&strings(&["idf-rs", &file("args.txt"), "build"])).unwrap(),
+            strings(&[
+                "idf-rs",
+                "-D",
+                "SDKCONFIG_DEFAULTS=a;b",
+                "-p",
+                "/dev/ttyUSB0",
+                "build"
+            ])
+        );
+        assert!(expand_file_arguments(&strings(&["idf-rs", &file("ci/loop.txt")])).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
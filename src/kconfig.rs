@@ -0,0 +1,916 @@
+use crate::{output, sha256};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Parsed tree, reused while the Kconfig files don't change
+const CACHE_FILE: &str = "idf-rs-kconfig-cache.json";
+
+/// Bump when the cached structure changes
+const CACHE_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SymbolType {
+    Bool,
+    Tristate,
+    Int,
+    Hex,
+    String,
+}
+
+impl SymbolType {
+    fn from_keyword(keyword: &str) -> Option<Self> {
+        match keyword {
+            "bool" | "def_bool" => Some(SymbolType::Bool),
+            "tristate" | "def_tristate" => Some(SymbolType::Tristate),
+            "int" => Some(SymbolType::Int),
+            "hex" => Some(SymbolType::Hex),
+            "string" => Some(SymbolType::String),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for SymbolType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            SymbolType::Bool => "bool",
+            SymbolType::Tristate => "tristate",
+            SymbolType::Int => "int",
+            SymbolType::Hex => "hex",
+            SymbolType::String => "string",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DefaultValue {
+    pub value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub condition: Option<String>,
+}
+
+/// A config symbol (without the CONFIG_ prefix)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Symbol {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<SymbolType>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub help: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub defaults: Vec<DefaultValue>,
+    /// Conditions from `depends on` and the enclosing menus and `if` blocks
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range: Option<(String, String)>,
+    /// Titles of the enclosing menus and choices
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub menu: Vec<String>,
+    /// Name (or prompt) of the choice this symbol is an option of
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub choice: Option<String>,
+    pub file: String,
+    pub line: usize,
+}
+
+impl Symbol {
+    /// Check a new value against the symbol's type and range, returning it normalized
+    /// (unquoted). Range bounds that name symbols are looked up with `value`.
+    pub fn parse_value(
+        &self,
+        input: &str,
+        value: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<String> {
+        let kind = self
+            .kind
+            .ok_or_else(|| anyhow::anyhow!("CONFIG_{} has no type", self.name))?;
+        let number = match kind {
+            SymbolType::Bool | SymbolType::Tristate => {
+                let allowed: &[&str] = if kind == SymbolType::Bool {
+                    &["y", "n"]
+                } else {
+                    &["y", "m", "n"]
+                };
+                if !allowed.contains(&input) {
+                    anyhow::bail!(
+                        "CONFIG_{} is a {}, expected {}",
+                        self.name,
+                        kind,
+                        allowed.join(" or ")
+                    );
+                }
+                return Ok(input.to_string());
+            }
+            SymbolType::String => return Ok(input.to_string()),
+            SymbolType::Int => input.parse::<i64>().ok(),
+            SymbolType::Hex => {
+                let digits = input
+                    .strip_prefix("0x")
+                    .or_else(|| input.strip_prefix("0X"))
+                    .unwrap_or(input);
+                i64::from_str_radix(digits, 16).ok()
+            }
+        };
+        let number = number.ok_or_else(|| {
+            anyhow::anyhow!(
+                "'{}' is not a valid {} for CONFIG_{}",
+                input,
+                kind,
+                self.name
+            )
+        })?;
+
+        if let Some((low, high)) = &self.range {
+            let bound = |bound: &str| parse_number(bound).or_else(|| parse_number(&value(bound)?));
+            if let (Some(low), Some(high)) = (bound(low), bound(high)) {
+                if number < low || number > high {
+                    anyhow::bail!(
+                        "{} is out of range for CONFIG_{} ({} to {})",
+                        input,
+                        self.name,
+                        low,
+                        high
+                    );
+                }
+            }
+        }
+
+        Ok(match kind {
+            SymbolType::Hex if !input.starts_with("0x") && !input.starts_with("0X") => {
+                format!("0x{}", input)
+            }
+            _ => input.to_string(),
+        })
+    }
+
+    /// A value as written to sdkconfig, quoted for strings
+    pub fn sdkconfig_value(&self, value: &str) -> String {
+        match self.kind {
+            Some(SymbolType::String) => crate::config::quote(value),
+            _ => value.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct KconfigTree {
+    pub symbols: Vec<Symbol>,
+    /// Every Kconfig file read while parsing
+    pub files: Vec<PathBuf>,
+}
+
+impl KconfigTree {
+    /// Look up a symbol, with or without the CONFIG_ prefix
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        let name = name.strip_prefix("CONFIG_").unwrap_or(name);
+        self.symbols.iter().find(|symbol| symbol.name == name)
+    }
+
+    /// Symbols whose name, prompt or help contains the pattern (case insensitive)
+    pub fn search(&self, pattern: &str) -> Vec<&Symbol> {
+        let pattern = pattern.to_lowercase();
+        let pattern = pattern.strip_prefix("config_").unwrap_or(&pattern);
+        self.symbols
+            .iter()
+            .filter(|symbol| {
+                [
+                    Some(&symbol.name),
+                    symbol.prompt.as_ref(),
+                    symbol.help.as_ref(),
+                ]
+                .into_iter()
+                .flatten()
+                .any(|text| text.to_lowercase().contains(pattern))
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Text(String),
+    Operator(&'static str),
+}
+
+fn tokenize(expr: &str) -> Vec<Token> {
+    // Longer operators first, so "!=" isn't read as "!"
+    const OPERATORS: [&str; 11] = ["&&", "||", "!=", "<=", ">=", "!", "(", ")", "=", "<", ">"];
+    let mut tokens = Vec::new();
+    let mut rest = expr.trim_start();
+    while !rest.is_empty() {
+        if let Some((text, after)) = parse_string(rest) {
+            tokens.push(Token::Text(text));
+            rest = after;
+        } else if let Some(operator) = OPERATORS
+            .into_iter()
+            .find(|operator| rest.starts_with(operator))
+        {
+            tokens.push(Token::Operator(operator));
+            rest = &rest[operator.len()..];
+        } else {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len())
+                .max(1);
+            tokens.push(Token::Word(rest[..end].to_string()));
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    tokens
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(digits) => i64::from_str_radix(digits, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+struct Evaluator<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    value: &'a dyn Fn(&str) -> Option<String>,
+}
+
+impl Evaluator<'_> {
+    fn accept(&mut self, operator: &str) -> bool {
+        let found =
+            matches!(self.tokens.get(self.position), Some(Token::Operator(o)) if *o == operator);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> bool {
+        let mut result = self.and();
+        while self.accept("||") {
+            result |= self.and();
+        }
+        result
+    }
+
+    fn and(&mut self) -> bool {
+        let mut result = self.unary();
+        while self.accept("&&") {
+            result &= self.unary();
+        }
+        result
+    }
+
+    fn unary(&mut self) -> bool {
+        if self.accept("!") {
+            return !self.unary();
+        }
+        if self.accept("(") {
+            let result = self.or();
+            self.accept(")");
+            return result;
+        }
+
+        let left = self.term();
+        for operator in ["=", "!=", "<", ">", "<=", ">="] {
+            if self.accept(operator) {
+                let right = self.term();
+                let ordering = match (parse_number(&left), parse_number(&right)) {
+                    (Some(left), Some(right)) => left.cmp(&right),
+                    _ => left.cmp(&right),
+                };
+                return match operator {
+                    "=" => ordering.is_eq(),
+                    "!=" => ordering.is_ne(),
+                    "<" => ordering.is_lt(),
+                    ">" => ordering.is_gt(),
+                    "<=" => ordering.is_le(),
+                    _ => ordering.is_ge(),
+                };
+            }
+        }
+        left == "y" || left == "m"
+    }
+
+    /// Value of a constant or symbol, n for symbols that aren't set
+    fn term(&mut self) -> String {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        match token {
+            Some(Token::Text(text)) => text,
+            Some(Token::Word(word)) if matches!(word.as_str(), "y" | "n" | "m") => word,
+            Some(Token::Word(word)) if parse_number(&word).is_some() => word,
+            Some(Token::Word(word)) => (self.value)(&word).unwrap_or_else(|| "n".to_string()),
+            _ => "n".to_string(),
+        }
+    }
+}
+
+/// Evaluate a `depends on` or `if` condition, looking up symbol values (without the
+/// CONFIG_ prefix; y/n, a number or an unquoted string) with `value`. Conditions that
+/// can't be parsed are treated as met.
+pub fn evaluate(expr: &str, value: &dyn Fn(&str) -> Option<String>) -> bool {
+    let mut evaluator = Evaluator {
+        tokens: tokenize(expr),
+        position: 0,
+        value,
+    };
+    let result = evaluator.or();
+    result || evaluator.position != evaluator.tokens.len()
+}
+
+/// Expand `$VAR`, `${VAR}` and `$(VAR)` references
+fn expand(text: &str, env: &HashMap<String, String>) -> String {
+    let mut output = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('$') {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let (name, consumed) = match after.chars().next() {
+            Some(open @ ('(' | '{')) => {
+                let close = if open == '(' { ')' } else { '}' };
+                match after.find(close) {
+                    Some(end) => (&after[1..end], end + 1),
+                    None => ("", 0),
+                }
+            }
+            _ => {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], end)
+            }
+        };
+        if consumed == 0 {
+            output.push('$');
+        } else {
+            output.push_str(env.get(name).map(String::as_str).unwrap_or_default());
+        }
+        rest = &after[consumed..];
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Parse a quoted string at the start of `text`, returning it and the rest
+fn parse_string(text: &str) -> Option<(String, &str)> {
+    let text = text.trim_start();
+    let quote = text.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let mut value = String::new();
+    let mut chars = text[1..].char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '\\' => value.extend(chars.next().map(|(_, c)| c)),
+            c if c == quote => return Some((value, &text[index + 2..])),
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+/// Split `value if condition`, ignoring `if` inside strings
+fn split_condition(text: &str) -> (&str, Option<&str>) {
+    let mut quote = None;
+    let bytes = text.as_bytes();
+    for (index, c) in text.char_indices() {
+        match (c, quote) {
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(open)) if c == open => quote = None,
+            (' ' | '\t', None)
+                if text[index + 1..].starts_with("if")
+                    && bytes
+                        .get(index + 3)
+                        .is_some_and(|b| b.is_ascii_whitespace()) =>
+            {
+                return (text[..index].trim(), Some(text[index + 3..].trim()));
+            }
+            _ => {}
+        }
+    }
+    (text.trim(), None)
+}
+
+/// Remove a `#` comment outside of strings
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (index, c) in line.char_indices() {
+        match (c, quote) {
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(open)) if c == open => quote = None,
+            ('#', None) => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn indentation(line: &str) -> usize {
+    line.chars()
+        .take_while(|c| c.is_whitespace())
+        .map(|c| if c == '\t' { 8 } else { 1 })
+        .sum()
+}
+
+enum Block {
+    Menu {
+        title: String,
+    },
+    /// Choices are named by their symbol (if any) and titled by their prompt
+    Choice {
+        name: String,
+        title: String,
+    },
+    If,
+}
+
+/// What the attribute lines that follow belong to
+#[derive(Clone, Copy)]
+enum Entry {
+    None,
+    Symbol(usize),
+    Menu,
+    Choice,
+}
+
+struct Parser<'a> {
+    env: &'a HashMap<String, String>,
+    tree: KconfigTree,
+    by_name: HashMap<String, usize>,
+    blocks: Vec<Block>,
+    /// Conditions of the open blocks, one entry per block
+    conditions: Vec<Vec<String>>,
+}
+
+impl Parser<'_> {
+    fn menu_path(&self) -> Vec<String> {
+        self.blocks
+            .iter()
+            .filter_map(|block| match block {
+                Block::Menu { title } => Some(title.clone()),
+                Block::Choice { title, .. } => Some(title.clone()),
+                Block::If => None,
+            })
+            .collect()
+    }
+
+    fn current_choice(&self) -> Option<String> {
+        self.blocks.iter().rev().find_map(|block| match block {
+            Block::Choice { name, .. } => Some(name.clone()),
+            Block::Menu { .. } => None,
+            Block::If => None,
+        })
+    }
+
+    /// Files matched by a source statement, with `*` allowed in the file name
+    fn source_files(&self, pattern: &str, current: &Path, relative: bool) -> Vec<PathBuf> {
+        let path = PathBuf::from(expand(pattern, self.env));
+        let path = match (relative, current.parent()) {
+            (true, Some(dir)) => dir.join(path),
+            _ => path,
+        };
+
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let Some((prefix, suffix)) = name.split_once('*') else {
+            return vec![path];
+        };
+
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| entry.path())
+                    .filter(|path| {
+                        path.file_name()
+                            .and_then(|name| name.to_str())
+                            .is_some_and(|name| name.starts_with(prefix) && name.ends_with(suffix))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        files.sort();
+        files
+    }
+
+    fn parse_file(&mut self, path: &Path, optional: bool) -> Result<()> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(_) if optional => return Ok(()),
+            Err(e) => return Err(anyhow::anyhow!("Failed to read {}: {}", path.display(), e)),
+        };
+        self.tree.files.push(path.to_path_buf());
+
+        let lines: Vec<&str> = content.lines().collect();
+        let mut entry = Entry::None;
+        let mut index = 0;
+        while index < lines.len() {
+            let line_number = index + 1;
+
+            // Join continuation lines
+            let mut line = lines[index].to_string();
+            while line.ends_with('\\') && index + 1 < lines.len() {
+                line.pop();
+                index += 1;
+                line.push_str(lines[index]);
+            }
+            index += 1;
+
+            let statement = strip_comment(&line).trim();
+            if statement.is_empty() {
+                continue;
+            }
+            let (keyword, rest) = statement
+                .split_once(char::is_whitespace)
+                .map(|(keyword, rest)| (keyword, rest.trim()))
+                .unwrap_or((statement, ""));
+
+            match keyword {
+                "config" | "menuconfig" => {
+                    let symbol_index = match self.by_name.get(rest) {
+                        Some(&existing) => existing,
+                        None => {
+                            self.tree.symbols.push(Symbol {
+                                name: rest.to_string(),
+                                kind: None,
+                                prompt: None,
+                                help: None,
+                                defaults: Vec::new(),
+                                depends_on: self.conditions.concat(),
+                                range: None,
+                                menu: self.menu_path(),
+                                choice: self.current_choice(),
+                                file: path.to_string_lossy().to_string(),
+                                line: line_number,
+                            });
+                            self.by_name
+                                .insert(rest.to_string(), self.tree.symbols.len() - 1);
+                            self.tree.symbols.len() - 1
+                        }
+                    };
+                    entry = Entry::Symbol(symbol_index);
+                }
+                "menu" => {
+                    let title = parse_string(rest)
+                        .map(|(title, _)| title)
+                        .unwrap_or_default();
+                    self.blocks.push(Block::Menu { title });
+                    self.conditions.push(Vec::new());
+                    entry = Entry::Menu;
+                }
+                "choice" => {
+                    self.blocks.push(Block::Choice {
+                        name: rest.to_string(),
+                        title: rest.to_string(),
+                    });
+                    self.conditions.push(Vec::new());
+                    entry = Entry::Choice;
+                }
+                "if" => {
+                    self.blocks.push(Block::If);
+                    self.conditions.push(vec![rest.to_string()]);
+                    entry = Entry::None;
+                }
+                "endmenu" | "endchoice" | "endif" => {
+                    self.blocks.pop();
+                    self.conditions.pop();
+                    entry = Entry::None;
+                }
+                "source" | "rsource" | "osource" | "orsource" => {
+                    let Some((pattern, _)) = parse_string(rest) else {
+                        continue;
+                    };
+                    let relative = keyword.starts_with('r') || keyword.starts_with("or");
+                    let optional = keyword.starts_with('o');
+                    for file in self.source_files(&pattern, path, relative) {
+                        self.parse_file(&file, optional)?;
+                    }
+                    entry = Entry::None;
+                }
+                "mainmenu" | "comment" => entry = Entry::None,
+                "help" | "---help---" => {
+                    // The help text is the following block indented deeper than the first line
+                    let mut text = Vec::new();
+                    let mut indent = None;
+                    while index < lines.len() {
+                        let help_line = lines[index];
+                        if help_line.trim().is_empty() {
+                            text.push("");
+                            index += 1;
+                            continue;
+                        }
+                        let line_indent = indentation(help_line);
+                        match indent {
+                            None => indent = Some(line_indent),
+                            Some(indent) if line_indent < indent => break,
+                            _ => {}
+                        }
+                        text.push(help_line.trim());
+                        index += 1;
+                    }
+                    let help = text.join("\n").trim().to_string();
+                    if let Entry::Symbol(symbol) = entry {
+                        self.tree.symbols[symbol].help.get_or_insert(help);
+                    }
+                }
+                _ => self.parse_attribute(entry, keyword, rest),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_attribute(&mut self, entry: Entry, keyword: &str, rest: &str) {
+        match entry {
+            Entry::Symbol(index) => {
+                let symbol = &mut self.tree.symbols[index];
+                if let Some(kind) = SymbolType::from_keyword(keyword) {
+                    symbol.kind.get_or_insert(kind);
+                    if keyword.starts_with("def_") {
+                        let (value, condition) = split_condition(rest);
+                        symbol.defaults.push(DefaultValue {
+                            value: value.to_string(),
+                            condition: condition.map(str::to_string),
+                        });
+                    } else if let Some((prompt, _)) = parse_string(rest) {
+                        symbol.prompt.get_or_insert(prompt);
+                    }
+                    return;
+                }
+                match keyword {
+                    "prompt" => {
+                        if let Some((prompt, _)) = parse_string(rest) {
+                            symbol.prompt.get_or_insert(prompt);
+                        }
+                    }
+                    "default" => {
+                        let (value, condition) = split_condition(rest);
+                        let value = parse_string(value)
+                            .map(|(value, _)| value)
+                            .unwrap_or_else(|| value.to_string());
+                        symbol.defaults.push(DefaultValue {
+                            value,
+                            condition: condition.map(str::to_string),
+                        });
+                    }
+                    "depends" => {
+                        let condition = rest.strip_prefix("on").unwrap_or(rest).trim();
+                        symbol.depends_on.push(condition.to_string());
+                    }
+                    "range" => {
+                        let (range, _) = split_condition(rest);
+                        let mut bounds = range.split_whitespace();
+                        if let (Some(low), Some(high)) = (bounds.next(), bounds.next()) {
+                            symbol.range = Some((low.to_string(), high.to_string()));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Entry::Menu | Entry::Choice => match keyword {
+                "depends" | "visible" => {
+                    let condition = rest
+                        .strip_prefix("on")
+                        .or_else(|| rest.strip_prefix("if"))
+                        .unwrap_or(rest)
+                        .trim();
+                    if let Some(conditions) = self.conditions.last_mut() {
+                        conditions.push(condition.to_string());
+                    }
+                }
+                "prompt" => {
+                    if let (Some((prompt, _)), Some(Block::Choice { name, title })) =
+                        (parse_string(rest), self.blocks.last_mut())
+                    {
+                        if name.is_empty() {
+                            *name = prompt.clone();
+                        }
+                        *title = prompt;
+                    }
+                }
+                _ => {}
+            },
+            Entry::None => {}
+        }
+    }
+}
+
+/// Parse a Kconfig file and everything it sources
+pub fn parse(root: &Path, env: &HashMap<String, String>) -> Result<KconfigTree> {
+    let mut parser = Parser {
+        env,
+        tree: KconfigTree::default(),
+        by_name: HashMap::new(),
+        blocks: Vec::new(),
+        conditions: Vec::new(),
+    };
+    parser.parse_file(root, false)?;
+    Ok(parser.tree)
+}
+
+/// What a cached tree was parsed for
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CacheKey {
+    version: u32,
+    idf_version: String,
+    target: String,
+    /// SHA-256 of the lists of component Kconfig files
+    components: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Cache {
+    key: CacheKey,
+    /// Modification times (seconds since the epoch) of the parsed files
+    mtimes: Vec<u64>,
+    tree: KconfigTree,
+}
+
+fn mtime(path: &Path) -> u64 {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|time| time.as_secs())
+        .unwrap_or(0)
+}
+
+/// The environment the build parses Kconfig with (IDF_PATH, IDF_TARGET and the lists
+/// of component Kconfig files), written to config.env when the project is configured
+pub fn load_config_env(build_dir: &Path) -> Result<HashMap<String, String>> {
+    let path = build_dir.join("config.env");
+    let content = std::fs::read_to_string(&path).map_err(|_| {
+        anyhow::anyhow!(
+            "{} does not exist, run 'idf-rs reconfigure' first",
+            path.display()
+        )
+    })?;
+    let values: HashMap<String, serde_json::Value> = serde_json::from_str(&content)?;
+    Ok(values
+        .into_iter()
+        .map(|(name, value)| {
+            let value = match value {
+                serde_json::Value::String(value) => value,
+                other => other.to_string(),
+            };
+            (name, value)
+        })
+        .collect())
+}
+
+/// Load the Kconfig tree of a configured build, parsing it only when the IDF version,
+/// target, component set or any Kconfig file changed since the cached parse.
+/// Returns the tree and whether it came from the cache.
+pub fn load(build_dir: &Path) -> Result<(KconfigTree, bool)> {
+    let mut env = load_config_env(build_dir)?;
+    let idf_path = match env.get("IDF_PATH") {
+        Some(path) if !path.is_empty() => PathBuf::from(path),
+        _ => crate::utils::get_idf_path()?,
+    };
+    env.insert(
+        "IDF_PATH".to_string(),
+        idf_path.to_string_lossy().to_string(),
+    );
+
+    let mut components = String::new();
+    for name in [
+        "COMPONENT_KCONFIGS_SOURCE_FILE",
+        "COMPONENT_KCONFIGS_PROJBUILD_SOURCE_FILE",
+    ] {
+        if let Some(file) = env.get(name) {
+            components.push_str(&std::fs::read_to_string(file).unwrap_or_default());
+        }
+    }
+    let key = CacheKey {
+        version: CACHE_VERSION,
+        idf_version: crate::utils::get_idf_version().unwrap_or_default(),
+        target: env.get("IDF_TARGET").cloned().unwrap_or_default(),
+        components: sha256::hex_digest(components.as_bytes()),
+    };
+
+    let cache_path = build_dir.join(CACHE_FILE);
+    let cached: Option<Cache> = std::fs::read_to_string(&cache_path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok());
+    if let Some(cache) = cached {
+        let unchanged = cache.key == key
+            && cache.tree.files.len() == cache.mtimes.len()
+            && cache
+                .tree
+                .files
+                .iter()
+                .zip(&cache.mtimes)
+                .all(|(file, &time)| mtime(file) == time);
+        if unchanged {
+            return Ok((cache.tree, true));
+        }
+    }
+
+    let tree = parse(&idf_path.join("Kconfig"), &env)?;
+    let cache = Cache {
+        key,
+        mtimes: tree.files.iter().map(|file| mtime(file)).collect(),
+        tree,
+    };
+    if let Err(e) = std::fs::write(&cache_path, serde_json::to_string(&cache)?) {
+        output::warning(format!("failed to cache the Kconfig tree: {}", e));
+    }
+
+    Ok((cache.tree, false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let dir = std::env::temp_dir().join(format!("idf-rs-kconfig-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("wifi")).unwrap();
+        std::fs::write(
+            dir.join("Kconfig"),
+            r#"mainmenu "Test"
+menu "Networking"  # comment
+    config WIFI_ENABLED
+        bool "Enable Wi-Fi"
+        default y
+        help
+            Turn on the radio.
+
+            Uses more power.
+
+    if WIFI_ENABLED
+        rsource "$SUB/Kconfig"
+    endif
+endmenu
+
+choice LOG_LEVEL
+    prompt "Log level"
+    default LOG_INFO
+    config LOG_INFO
+        bool "Info"
+endchoice
+"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("wifi").join("Kconfig"),
+            "config WIFI_CHANNEL\n    int \"Channel\"\n    range 1 13\n    default 6 if WIFI_ENABLED\n    depends on !BT_ONLY\n",
+        )
+        .unwrap();
+
+        let env = HashMap::from([("SUB".to_string(), "wifi".to_string())]);
+        let tree = parse(&dir.join("Kconfig"), &env).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(tree.files.len(), 2);
+        let wifi = tree.get("CONFIG_WIFI_ENABLED").unwrap();
+        assert_eq!(wifi.kind, Some(SymbolType::Bool));
+        assert_eq!(wifi.prompt.as_deref(), Some("Enable Wi-Fi"));
+        assert_eq!(
+            wifi.help.as_deref(),
+            Some("Turn on the radio.\n\nUses more power.")
+        );
+        assert_eq!(wifi.menu, vec!["Networking"]);
+
+        let channel = tree.get("WIFI_CHANNEL").unwrap();
+        assert_eq!(channel.kind, Some(SymbolType::Int));
+        assert_eq!(channel.range, Some(("1".to_string(), "13".to_string())));
+        assert_eq!(channel.defaults[0].value, "6");
+        assert_eq!(
+            channel.defaults[0].condition.as_deref(),
+            Some("WIFI_ENABLED")
+        );
+        assert_eq!(channel.depends_on, vec!["WIFI_ENABLED", "!BT_ONLY"]);
+
+        let info = tree.get("LOG_INFO").unwrap();
+        assert_eq!(info.choice.as_deref(), Some("LOG_LEVEL"));
+        assert_eq!(info.menu, vec!["Log level"]);
+        assert_eq!(tree.search("radio").len(), 1);
+
+        let values = HashMap::from([
+            ("WIFI_ENABLED".to_string(), "y".to_string()),
+            ("WIFI_CHANNEL".to_string(), "6".to_string()),
+            ("LOG_NAME".to_string(), "main".to_string()),
+        ]);
+        let value = |name: &str| values.get(name).cloned();
+        assert!(evaluate("WIFI_ENABLED && !BT_ONLY", &value));
+        assert!(evaluate(
+            "(BT_ONLY || WIFI_CHANNEL >= 0x5) && LOG_NAME = \"main\"",
+            &value
+        ));
+        assert!(!evaluate("WIFI_CHANNEL != 6 || BT_ONLY", &value));
+
+        assert_eq!(channel.parse_value("11", &value).unwrap(), "11");
+        assert!(channel.parse_value("14", &value).is_err());
+        assert!(wifi.parse_value("yes", &value).is_err());
+    }
+}
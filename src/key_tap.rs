@@ -0,0 +1,234 @@
+//! Shortcuts of idf-rs in an interactive tool: the terminal is read by idf-rs and
+//! passed on to the tool through a pseudo terminal, taking out the Ctrl+T <key>
+//! shortcuts idf-rs handles itself. The tool still sees a terminal on its stdin, so
+//! idf_monitor's own Ctrl+T menu keeps working.
+
+use tokio::sync::mpsc;
+
+/// idf_monitor's menu key
+const MENU_KEY: u8 = 0x14;
+
+/// Pass typed bytes on, taking out Ctrl+T `shortcut`. A Ctrl+T at the end of `bytes`
+/// is held back in `after_menu` until the next key shows what it starts.
+fn filter_keys(bytes: &[u8], after_menu: &mut bool, shortcut: u8) -> (Vec<u8>, usize) {
+    let mut forward = Vec::with_capacity(bytes.len());
+    let mut pressed = 0;
+    for &byte in bytes {
+        if std::mem::take(after_menu) {
+            if byte == shortcut {
+                pressed += 1;
+                continue;
+            }
+            forward.push(MENU_KEY);
+            forward.push(byte);
+        } else if byte == MENU_KEY {
+            *after_menu = true;
+        } else {
+            forward.push(byte);
+        }
+    }
+    (forward, pressed)
+}
+
+/// The terminal tapped for a tool, until dropped
+#[cfg_attr(not(unix), allow(dead_code))]
+pub struct KeyTap {
+    presses: mpsc::UnboundedReceiver<()>,
+    /// Stops reading and restores the terminal when dropped
+    #[cfg(unix)]
+    _reader: unix::Reader,
+}
+
+impl KeyTap {
+    /// Wait for the shortcut. Never returns once the terminal can't be read anymore.
+    pub async fn pressed(&mut self) {
+        if self.presses.recv().await.is_none() {
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+/// Wait for the shortcut of `tap`, forever without one
+pub async fn pressed(tap: &mut Option<KeyTap>) {
+    match tap {
+        Some(tap) => tap.pressed().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Tap the terminal for Ctrl+T `shortcut`, returning the stdin to give the tool. None
+/// when stdin isn't a terminal or no pseudo terminal can be opened, the tool then
+/// reads the terminal itself.
+#[cfg(unix)]
+pub fn start(shortcut: u8) -> Option<(KeyTap, std::process::Stdio)> {
+    let (presses_tx, presses) = mpsc::unbounded_channel();
+    let (reader, stdin) = unix::Reader::start(shortcut, presses_tx)?;
+    Some((
+        KeyTap {
+            presses,
+            _reader: reader,
+        },
+        stdin,
+    ))
+}
+
+#[cfg(not(unix))]
+pub fn start(_shortcut: u8) -> Option<(KeyTap, std::process::Stdio)> {
+    None
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::filter_keys;
+    use std::fs::File;
+    use std::io::{IsTerminal, Read, Write};
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread::JoinHandle;
+    use tokio::sync::mpsc;
+
+    /// How often the reader checks whether it should stop
+    const POLL_INTERVAL_MS: libc::c_int = 100;
+
+    /// Thread copying the terminal to the pseudo terminal, with the terminal settings
+    /// to restore
+    pub struct Reader {
+        stop: Arc<AtomicBool>,
+        thread: Option<JoinHandle<()>>,
+        saved: libc::termios,
+    }
+
+    fn set_cloexec(fd: RawFd) {
+        // SAFETY: fd is an open descriptor of this process
+        unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+    }
+
+    impl Reader {
+        pub fn start(
+            shortcut: u8,
+            presses: mpsc::UnboundedSender<()>,
+        ) -> Option<(Self, std::process::Stdio)> {
+            let stdin = std::io::stdin();
+            if !stdin.is_terminal() {
+                return None;
+            }
+            let fd = stdin.as_raw_fd();
+
+            let (mut master, mut slave) = (0, 0);
+            // SAFETY: the out pointers are valid, name, settings and size are optional
+            let opened = unsafe {
+                libc::openpty(
+                    &mut master,
+                    &mut slave,
+                    std::ptr::null_mut(),
+                    std::ptr::null(),
+                    std::ptr::null(),
+                )
+            };
+            if opened < 0 {
+                return None;
+            }
+            set_cloexec(master);
+            set_cloexec(slave);
+            // SAFETY: openpty returned both descriptors open and owned by nobody else
+            let (master, slave) =
+                unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) };
+
+            // Keys are passed on as typed, as idf_monitor sets up the terminal itself:
+            // no line editing, echo or signals
+            // SAFETY: termios is plain data, filled in by tcgetattr
+            let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+            if unsafe { libc::tcgetattr(fd, &mut saved) } < 0 {
+                return None;
+            }
+            let mut raw = saved;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            // SAFETY: fd is the terminal the settings were read from
+            unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) };
+
+            let stop = Arc::new(AtomicBool::new(false));
+            let thread = {
+                let stop = stop.clone();
+                let mut master = File::from(master);
+                std::thread::spawn(move || {
+                    let mut after_menu = false;
+                    let mut buffer = [0u8; 256];
+                    while !stop.load(Ordering::Relaxed) {
+                        let mut poll = libc::pollfd {
+                            fd,
+                            events: libc::POLLIN,
+                            revents: 0,
+                        };
+                        // SAFETY: one valid pollfd
+                        match unsafe { libc::poll(&mut poll, 1, POLL_INTERVAL_MS) } {
+                            0 => continue,
+                            ready if ready < 0 => break,
+                            _ => {}
+                        }
+                        let read = match std::io::stdin().lock().read(&mut buffer) {
+                            Ok(0) | Err(_) => break,
+                            Ok(read) => read,
+                        };
+                        let (forward, pressed) =
+                            filter_keys(&buffer[..read], &mut after_menu, shortcut);
+                        for _ in 0..pressed {
+                            let _ = presses.send(());
+                        }
+                        if master.write_all(&forward).is_err() {
+                            break;
+                        }
+                    }
+                })
+            };
+
+            Some((
+                Self {
+                    stop,
+                    thread: Some(thread),
+                    saved,
+                },
+                slave.into(),
+            ))
+        }
+    }
+
+    impl Drop for Reader {
+        fn drop(&mut self) {
+            self.stop.store(true, Ordering::Relaxed);
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+            // SAFETY: restoring the settings read from the same terminal
+            unsafe { libc::tcsetattr(std::io::stdin().as_raw_fd(), libc::TCSANOW, &self.saved) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_keys() {
+        let mut after_menu = false;
+        // Ctrl+T Ctrl+V is taken out, other Ctrl+T keys and plain keys pass
+        let (forward, pressed) = filter_keys(b"a\x14\x16b\x14\x12", &mut after_menu, 0x16);
+        assert_eq!(forward, b"ab\x14\x12");
+        assert_eq!(pressed, 1);
+        assert!(!after_menu);
+
+        // A Ctrl+T read on its own waits for the next key
+        let (forward, pressed) = filter_keys(b"x\x14", &mut after_menu, 0x16);
+        assert_eq!((forward.as_slice(), pressed), (&b"x"[..], 0));
+        assert!(after_menu);
+        let (forward, pressed) = filter_keys(b"\x16", &mut after_menu, 0x16);
+        assert_eq!((forward.as_slice(), pressed), (&b""[..], 1));
+
+        // Ctrl+T Ctrl+T, idf_monitor's way of sending Ctrl+T, is passed on
+        let (forward, _) = filter_keys(b"\x14\x14", &mut after_menu, 0x16);
+        assert_eq!(forward, b"\x14\x14");
+    }
+}
@@ -0,0 +1,130 @@
+//! idf-rs core: ESP-IDF project discovery, configuration, build orchestration,
+//! flashing and size analysis, for tools that drive ESP-IDF builds without shelling
+//! out to the idf-rs binary and scraping its output.
+//!
+//! The functions take a [`Cli`], the options of an idf-rs invocation:
+//!
+//! ```no_run
+//! # async fn example() -> anyhow::Result<()> {
+//! let cli = idf_rs_core::Cli::for_project("path/to/project")?;
+//! idf_rs_core::build(&cli).await?;
+//! if let Some(size) = idf_rs_core::get_size(&cli)? {
+//!     println!("{} uses {:.1}% of '{}'", size.binary, size.percent_used(), size.partition);
+//! }
+//! idf_rs_core::flash(&cli).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+mod aes;
+pub mod annotations;
+pub mod app_image;
+mod backtrace;
+pub mod build_systems;
+pub mod cli;
+pub mod commands;
+pub mod compile_commands;
+pub mod config;
+pub mod coredump;
+mod crc32;
+pub mod extensions;
+pub mod flash_image;
+mod fleet;
+mod heap;
+mod hints;
+pub mod idf_py;
+pub mod invocation;
+mod kconfig;
+mod key_tap;
+mod md5;
+mod nvs;
+mod openocd;
+mod otadata;
+pub mod output;
+pub mod partition_table;
+pub mod plugins;
+mod probe_rs;
+pub mod serial_ports;
+mod sha256;
+mod state;
+mod stats;
+mod toml;
+pub mod utils;
+mod version_spec;
+mod websocket;
+mod yaml;
+
+pub use cli::Cli;
+pub use commands::size::AppSize;
+
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// The ESP-IDF project containing `dir`: the closest directory up from it whose
+/// CMakeLists.txt includes ESP-IDF's project.cmake
+pub fn discover_project(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .find(|dir| {
+            std::fs::read_to_string(dir.join("CMakeLists.txt"))
+                .is_ok_and(|cmake| cmake.contains("tools/cmake/project.cmake"))
+        })
+        .map(Path::to_path_buf)
+}
+
+/// The options of `cli` completed as the command line's are (idf.toml, profile,
+/// remembered state, build directory pattern, --env), with prompts turned off: a
+/// library caller has no terminal to answer them, so what would be asked fails with
+/// an error instead
+fn resolve(cli: &Cli) -> Result<Cli> {
+    let mut cli = cli.clone();
+    cli.non_interactive = true;
+    cli::resolve_defaults(&mut cli)?;
+    Ok(cli)
+}
+
+/// Configure and build the project, like `idf-rs build --non-interactive`
+pub async fn build(cli: &Cli) -> Result<()> {
+    commands::build::execute(&resolve(cli)?, &[], false, None).await
+}
+
+/// Flash the built project, like `idf-rs flash --non-interactive`
+pub async fn flash(cli: &Cli) -> Result<()> {
+    commands::flash::execute(&resolve(cli)?, &[], None, false, false, false).await
+}
+
+/// Size of the built app against its smallest app partition, None when the project
+/// isn't built or has no app partition
+pub fn get_size(cli: &Cli) -> Result<Option<AppSize>> {
+    let cli = resolve(cli)?;
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+    commands::size::app_size(&build_dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_discover_project() {
+        let dir = std::env::temp_dir().join(format!("idf-rs-discover-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("main/include")).unwrap();
+        std::fs::write(
+            dir.join("CMakeLists.txt"),
+            "include($ENV{IDF_PATH}/tools/cmake/project.cmake)\nproject(app)\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("main/CMakeLists.txt"),
+            "idf_component_register()\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            discover_project(&dir.join("main/include")),
+            Some(dir.clone())
+        );
+        assert_eq!(discover_project(&dir), Some(dir.clone()));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,764 @@
+//! NVS partition images in the format of nvs_partition_gen.py (version 2, blobs split
+//! into chunks across pages), optionally encrypted with XTS-AES for nvs_flash_secure_init()
+
+use crate::aes::Xts;
+use crate::crc32::crc32_le;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::Path;
+
+pub const PAGE_SIZE: usize = 0x1000;
+const BITMAP_OFFSET: usize = 32;
+const FIRST_ENTRY_OFFSET: usize = 64;
+const ENTRY_SIZE: usize = 32;
+const ENTRIES_PER_PAGE: usize = 126;
+
+const PAGE_ACTIVE: u32 = 0xFFFF_FFFE;
+const PAGE_FULL: u32 = 0xFFFF_FFFC;
+const PAGE_UNINITIALIZED: u32 = 0xFFFF_FFFF;
+const PAGE_CORRUPT: u32 = 0;
+const VERSION2: u8 = 0xFE;
+
+/// Entry state in the page bitmap (two bits per entry)
+const ENTRY_WRITTEN: u8 = 0b10;
+
+/// Integer types; the low nibble is the size in bytes, 0x10 marks signed types
+const INT_TYPES: [(&str, u8); 8] = [
+    ("u8", 0x01),
+    ("i8", 0x11),
+    ("u16", 0x02),
+    ("i16", 0x12),
+    ("u32", 0x04),
+    ("i32", 0x14),
+    ("u64", 0x08),
+    ("i64", 0x18),
+];
+const TYPE_U8: u8 = 0x01;
+const TYPE_SZ: u8 = 0x21;
+/// Single-page blobs of version 1 images, only read
+const TYPE_BLOB: u8 = 0x41;
+const TYPE_BLOB_DATA: u8 = 0x42;
+const TYPE_BLOB_IDX: u8 = 0x48;
+const CHUNK_ANY: u8 = 0xFF;
+
+const MAX_KEY_LEN: usize = 15;
+/// Strings including their terminating zero
+const MAX_STRING_SIZE: usize = 4000;
+const MAX_BLOB_SIZE: usize = 508_000;
+/// Blob chunk indexes of a version are below 128
+const MAX_BLOB_CHUNKS: usize = 128;
+/// Two pages for data and one reserved for garbage collection
+const MIN_SIZE: usize = 3 * PAGE_SIZE;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// Integer with its type code
+    Int(u8, i128),
+    String(String),
+    Blob(Vec<u8>),
+}
+
+impl Value {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int(code, _) => int_type_name(*code).unwrap_or("u8"),
+            Value::String(_) => "string",
+            Value::Blob(_) => "hex2bin",
+        }
+    }
+}
+
+/// A row of the generator CSV: a namespace that the following entries are stored in, or
+/// an entry
+#[derive(Debug, Clone, PartialEq)]
+pub enum Row {
+    Namespace(String),
+    Entry { key: String, value: Value },
+}
+
+/// A key/value pair read from an NVS image
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    pub namespace: String,
+    pub key: String,
+    pub value: Value,
+}
+
+fn int_type(name: &str) -> Option<u8> {
+    INT_TYPES
+        .iter()
+        .find(|(type_name, _)| *type_name == name)
+        .map(|(_, code)| *code)
+}
+
+fn int_type_name(code: u8) -> Option<&'static str> {
+    INT_TYPES
+        .iter()
+        .find(|(_, type_code)| *type_code == code)
+        .map(|(name, _)| *name)
+}
+
+fn int_range(code: u8) -> (i128, i128) {
+    let bits = (code & 0x0F) as u32 * 8;
+    if code & 0x10 != 0 {
+        (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1)
+    } else {
+        (0, (1i128 << bits) - 1)
+    }
+}
+
+/// Integer in decimal or with a 0x, 0o or 0b prefix, like Python's int(value, 0)
+fn parse_int(text: &str) -> Option<i128> {
+    let text = text.trim();
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let lower = digits.to_ascii_lowercase();
+    let value = if let Some(hex) = lower.strip_prefix("0x") {
+        i128::from_str_radix(hex, 16).ok()?
+    } else if let Some(octal) = lower.strip_prefix("0o") {
+        i128::from_str_radix(octal, 8).ok()?
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        i128::from_str_radix(binary, 2).ok()?
+    } else {
+        lower.parse().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn decode_hex(text: &str) -> Result<Vec<u8>> {
+    let digits: Vec<u8> = text.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err(anyhow::anyhow!("Hex value has an odd number of digits"));
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| {
+                    anyhow::anyhow!("Invalid hex digits '{}'", String::from_utf8_lossy(pair))
+                })
+        })
+        .collect()
+}
+
+pub fn decode_base64(text: &str) -> Result<Vec<u8>> {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut output = Vec::new();
+    let mut bits = 0u32;
+    let mut count = 0;
+    for byte in text
+        .bytes()
+        .filter(|b| !b.is_ascii_whitespace() && *b != b'=')
+    {
+        let value = ALPHABET
+            .iter()
+            .position(|&c| c == byte)
+            .ok_or_else(|| anyhow::anyhow!("Invalid base64 character '{}'", byte as char))?;
+        bits = (bits << 6) | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            output.push((bits >> count) as u8);
+        }
+    }
+    Ok(output)
+}
+
+/// Records of a CSV file, with quoted fields that may hold commas, doubled quotes and
+/// line breaks
+fn parse_csv_records(content: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            ('"', true) => quoted = false,
+            ('"', false) if field.is_empty() => quoted = true,
+            (',', false) => record.push(std::mem::take(&mut field)),
+            ('\r', false) => {}
+            ('\n', false) => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            _ => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records
+}
+
+fn quote_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) || field.trim() != field {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn parse_value(encoding: &str, text: &str) -> Result<Value> {
+    if let Some(code) = int_type(encoding) {
+        let value = parse_int(text).ok_or_else(|| anyhow::anyhow!("Invalid integer '{}'", text))?;
+        let (min, max) = int_range(code);
+        if !(min..=max).contains(&value) {
+            return Err(anyhow::anyhow!(
+                "{} is out of range for {} ({} to {})",
+                value,
+                encoding,
+                min,
+                max
+            ));
+        }
+        return Ok(Value::Int(code, value));
+    }
+    match encoding {
+        "string" => Ok(Value::String(text.to_string())),
+        "hex2bin" => Ok(Value::Blob(decode_hex(text)?)),
+        "base64" => Ok(Value::Blob(decode_base64(text)?)),
+        _ => Err(anyhow::anyhow!("Unknown encoding '{}'", encoding)),
+    }
+}
+
+/// Parse a CSV in the nvs_partition_gen.py format (key,type,encoding,value). Paths of
+/// `file` entries are relative to `base_dir`.
+pub fn parse_csv(content: &str, base_dir: &Path) -> Result<Vec<Row>> {
+    let mut rows = Vec::new();
+    for (index, record) in parse_csv_records(content).into_iter().enumerate() {
+        let field = |i: usize| record.get(i).map(String::as_str).unwrap_or("");
+        let key = field(0).trim();
+        if key.is_empty() || key.starts_with('#') || (index == 0 && key == "key") {
+            continue;
+        }
+        let context = |e: anyhow::Error| anyhow::anyhow!("Row {} ({}): {}", index + 1, key, e);
+        if key.len() > MAX_KEY_LEN {
+            return Err(context(anyhow::anyhow!(
+                "Keys are at most {} characters",
+                MAX_KEY_LEN
+            )));
+        }
+
+        let (kind, encoding, value) = (field(1).trim(), field(2).trim(), field(3));
+        let row = match kind {
+            "namespace" => Row::Namespace(key.to_string()),
+            "data" => Row::Entry {
+                key: key.to_string(),
+                value: parse_value(encoding, value).map_err(context)?,
+            },
+            "file" => {
+                let path = base_dir.join(value.trim());
+                let data = std::fs::read(&path).map_err(|e| {
+                    context(anyhow::anyhow!("Failed to read {}: {}", path.display(), e))
+                })?;
+                let value = match encoding {
+                    "binary" => Value::Blob(data),
+                    "string" | "hex2bin" | "base64" => {
+                        parse_value(encoding, &String::from_utf8_lossy(&data)).map_err(context)?
+                    }
+                    _ => {
+                        return Err(context(anyhow::anyhow!(
+                            "Unknown file encoding '{}'",
+                            encoding
+                        )))
+                    }
+                };
+                Row::Entry {
+                    key: key.to_string(),
+                    value,
+                }
+            }
+            _ => return Err(context(anyhow::anyhow!("Unknown type '{}'", kind))),
+        };
+        if rows.is_empty() && !matches!(row, Row::Namespace(_)) {
+            return Err(context(anyhow::anyhow!(
+                "The first entry must be a namespace"
+            )));
+        }
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+/// Write items back as a CSV that `parse_csv` reads, with a namespace row whenever the
+/// namespace changes
+pub fn to_csv(items: &[Item]) -> String {
+    let mut csv = String::from("key,type,encoding,value\n");
+    let mut namespace = None;
+    for item in items {
+        if namespace != Some(&item.namespace) {
+            csv.push_str(&format!("{},namespace,,\n", quote_csv(&item.namespace)));
+            namespace = Some(&item.namespace);
+        }
+        let value = match &item.value {
+            Value::Int(_, value) => value.to_string(),
+            Value::String(text) => quote_csv(text),
+            Value::Blob(data) => crate::sha256::to_hex(data),
+        };
+        csv.push_str(&format!(
+            "{},data,{},{}\n",
+            quote_csv(&item.key),
+            item.value.type_name(),
+            value
+        ));
+    }
+    csv
+}
+
+/// 128-bit little endian XTS tweak of the entry at this offset in the partition
+fn tweak(address: usize) -> [u8; 16] {
+    let mut tweak = [0u8; 16];
+    tweak[..8].copy_from_slice(&(address as u64).to_le_bytes());
+    tweak
+}
+
+fn entry_header(
+    ns: u8,
+    kind: u8,
+    span: u8,
+    chunk: u8,
+    key: &str,
+    data: [u8; 8],
+) -> [u8; ENTRY_SIZE] {
+    let mut entry = [0xFF; ENTRY_SIZE];
+    entry[0] = ns;
+    entry[1] = kind;
+    entry[2] = span;
+    entry[3] = chunk;
+    entry[8..24].fill(0);
+    entry[8..8 + key.len()].copy_from_slice(key.as_bytes());
+    entry[24..32].copy_from_slice(&data);
+    let crc = entry_crc(&entry);
+    entry[4..8].copy_from_slice(&crc.to_le_bytes());
+    entry
+}
+
+/// CRC of an entry, over everything but the CRC field
+fn entry_crc(entry: &[u8]) -> u32 {
+    crc32_le(u32::MAX, &[&entry[0..4], &entry[8..32]].concat())
+}
+
+/// Data field of a string or blob chunk header: size, reserved and CRC of the data
+fn data_field(data: &[u8]) -> [u8; 8] {
+    let mut field = [0xFF; 8];
+    field[0..2].copy_from_slice(&(data.len() as u16).to_le_bytes());
+    field[4..8].copy_from_slice(&crc32_le(u32::MAX, data).to_le_bytes());
+    field
+}
+
+/// Lays out entries page by page like nvs_partition_gen.py
+struct Writer<'a> {
+    image: Vec<u8>,
+    page: usize,
+    /// Next free entry of the current page
+    entry: usize,
+    /// Pages available for data; the last page of the partition stays erased
+    data_pages: usize,
+    xts: Option<&'a Xts>,
+}
+
+impl Writer<'_> {
+    fn start_page(&mut self) {
+        let offset = self.page * PAGE_SIZE;
+        let header = &mut self.image[offset..offset + 32];
+        header[0..4].copy_from_slice(&PAGE_ACTIVE.to_le_bytes());
+        header[4..8].copy_from_slice(&(self.page as u32).to_le_bytes());
+        header[8] = VERSION2;
+        let crc = crc32_le(u32::MAX, &header[4..28]);
+        header[28..32].copy_from_slice(&crc.to_le_bytes());
+        self.entry = 0;
+    }
+
+    fn next_page(&mut self) -> Result<()> {
+        if self.page + 1 >= self.data_pages {
+            return Err(anyhow::anyhow!(
+                "The data does not fit in {} bytes, increase the size",
+                self.image.len()
+            ));
+        }
+        let offset = self.page * PAGE_SIZE;
+        self.image[offset..offset + 4].copy_from_slice(&PAGE_FULL.to_le_bytes());
+        self.page += 1;
+        self.start_page();
+        Ok(())
+    }
+
+    /// Write `count` entries holding `data`, padded with 0xFF
+    fn write(&mut self, data: &[u8], count: usize) {
+        for index in 0..count {
+            let mut entry = [0xFF; ENTRY_SIZE];
+            let chunk = data
+                .get(index * ENTRY_SIZE..)
+                .map(|rest| &rest[..rest.len().min(ENTRY_SIZE)])
+                .unwrap_or_default();
+            entry[..chunk.len()].copy_from_slice(chunk);
+
+            let address = self.page * PAGE_SIZE + FIRST_ENTRY_OFFSET + self.entry * ENTRY_SIZE;
+            if let Some(xts) = self.xts {
+                xts.encrypt(tweak(address), &mut entry);
+            }
+            self.image[address..address + ENTRY_SIZE].copy_from_slice(&entry);
+
+            let bitmap = self.page * PAGE_SIZE + BITMAP_OFFSET + self.entry / 4;
+            self.image[bitmap] &= !(1 << ((self.entry % 4) * 2));
+            self.entry += 1;
+        }
+    }
+
+    fn write_int(&mut self, ns: u8, key: &str, code: u8, value: i128) -> Result<()> {
+        if self.entry >= ENTRIES_PER_PAGE {
+            self.next_page()?;
+        }
+        let mut data = [0xFF; 8];
+        let size = (code & 0x0F) as usize;
+        data[..size].copy_from_slice(&value.to_le_bytes()[..size]);
+        self.write(&entry_header(ns, code, 1, CHUNK_ANY, key, data), 1);
+        Ok(())
+    }
+
+    fn write_string(&mut self, ns: u8, key: &str, text: &str) -> Result<()> {
+        let mut data = text.as_bytes().to_vec();
+        data.push(0);
+        if data.len() > MAX_STRING_SIZE {
+            return Err(anyhow::anyhow!(
+                "String of {} bytes exceeds the maximum of {}",
+                data.len(),
+                MAX_STRING_SIZE
+            ));
+        }
+        let count = data.len().div_ceil(ENTRY_SIZE);
+        if self.entry + count + 1 >= ENTRIES_PER_PAGE {
+            self.next_page()?;
+        }
+        let header = entry_header(
+            ns,
+            TYPE_SZ,
+            (count + 1) as u8,
+            CHUNK_ANY,
+            key,
+            data_field(&data),
+        );
+        self.write(&header, 1);
+        self.write(&data, count);
+        Ok(())
+    }
+
+    /// Split the blob into chunks filling the rest of each page, followed by the index
+    fn write_blob(&mut self, ns: u8, key: &str, data: &[u8]) -> Result<()> {
+        if data.len() > MAX_BLOB_SIZE {
+            return Err(anyhow::anyhow!(
+                "Blob of {} bytes exceeds the maximum of {}",
+                data.len(),
+                MAX_BLOB_SIZE
+            ));
+        }
+        if self.entry >= ENTRIES_PER_PAGE {
+            self.next_page()?;
+        }
+
+        let mut chunks = 0;
+        let mut offset = 0;
+        loop {
+            let tailroom = (ENTRIES_PER_PAGE - self.entry - 1) * ENTRY_SIZE;
+            let chunk = &data[offset..offset + (data.len() - offset).min(tailroom)];
+            if chunks == MAX_BLOB_CHUNKS {
+                return Err(anyhow::anyhow!(
+                    "Blob needs more than {} chunks",
+                    MAX_BLOB_CHUNKS
+                ));
+            }
+            let count = chunk.len().div_ceil(ENTRY_SIZE);
+            let header = entry_header(
+                ns,
+                TYPE_BLOB_DATA,
+                (count + 1) as u8,
+                chunks as u8,
+                key,
+                data_field(chunk),
+            );
+            self.write(&header, 1);
+            self.write(chunk, count);
+            chunks += 1;
+            offset += chunk.len();
+
+            let done = offset == data.len();
+            if !done || tailroom - chunk.len() < ENTRY_SIZE {
+                self.next_page()?;
+            }
+            if done {
+                break;
+            }
+        }
+
+        let mut index = [0xFF; 8];
+        index[0..4].copy_from_slice(&(data.len() as u32).to_le_bytes());
+        index[4] = chunks as u8;
+        index[5] = 0;
+        self.write(
+            &entry_header(ns, TYPE_BLOB_IDX, 1, CHUNK_ANY, key, index),
+            1,
+        );
+        Ok(())
+    }
+}
+
+/// Generate an NVS partition image of `size` bytes, encrypted when a key is given
+pub fn generate(rows: &[Row], size: usize, xts: Option<&Xts>) -> Result<Vec<u8>> {
+    if !size.is_multiple_of(PAGE_SIZE) || size < MIN_SIZE {
+        return Err(anyhow::anyhow!(
+            "The NVS partition size must be a multiple of 0x{:x} and at least 0x{:x}",
+            PAGE_SIZE,
+            MIN_SIZE
+        ));
+    }
+    let mut writer = Writer {
+        image: vec![0xFF; size],
+        page: 0,
+        entry: 0,
+        data_pages: size / PAGE_SIZE - 1,
+        xts,
+    };
+    writer.start_page();
+
+    let mut namespaces = 0u8;
+    for row in rows {
+        match row {
+            Row::Namespace(name) => {
+                namespaces = namespaces
+                    .checked_add(1)
+                    .filter(|&index| index < 0xFF)
+                    .ok_or_else(|| anyhow::anyhow!("Too many namespaces"))?;
+                writer.write_int(0, name, TYPE_U8, namespaces as i128)?;
+            }
+            Row::Entry { key, value } => match value {
+                Value::Int(code, value) => writer.write_int(namespaces, key, *code, *value)?,
+                Value::String(text) => writer.write_string(namespaces, key, text)?,
+                Value::Blob(data) => writer.write_blob(namespaces, key, data)?,
+            },
+        }
+    }
+
+    // Like nvs_partition_gen.py, initialize the remaining data pages
+    while writer.page + 1 < writer.data_pages {
+        writer.next_page()?;
+    }
+    Ok(writer.image)
+}
+
+/// A written entry with the data of the entries it spans
+struct RawEntry {
+    ns: u8,
+    kind: u8,
+    chunk: u8,
+    key: String,
+    data: [u8; 8],
+    payload: Vec<u8>,
+}
+
+fn read_entry(image: &[u8], address: usize, xts: Option<&Xts>) -> [u8; ENTRY_SIZE] {
+    let mut entry = [0u8; ENTRY_SIZE];
+    entry.copy_from_slice(&image[address..address + ENTRY_SIZE]);
+    if let Some(xts) = xts {
+        xts.decrypt(tweak(address), &mut entry);
+    }
+    entry
+}
+
+fn read_entries(image: &[u8], xts: Option<&Xts>) -> Vec<RawEntry> {
+    let mut entries = Vec::new();
+    for page in (0..image.len() / PAGE_SIZE).map(|index| index * PAGE_SIZE) {
+        let state = u32::from_le_bytes(image[page..page + 4].try_into().unwrap());
+        if state == PAGE_UNINITIALIZED || state == PAGE_CORRUPT {
+            continue;
+        }
+        let written = |index: usize| {
+            (image[page + BITMAP_OFFSET + index / 4] >> ((index % 4) * 2)) & 0b11 == ENTRY_WRITTEN
+        };
+        let address = |index: usize| page + FIRST_ENTRY_OFFSET + index * ENTRY_SIZE;
+
+        let mut index = 0;
+        while index < ENTRIES_PER_PAGE {
+            if !written(index) {
+                index += 1;
+                continue;
+            }
+            let entry = read_entry(image, address(index), xts);
+            let span = entry[2] as usize;
+            let crc = u32::from_le_bytes(entry[4..8].try_into().unwrap());
+            if crc != entry_crc(&entry) || span == 0 || index + span > ENTRIES_PER_PAGE {
+                index += 1;
+                continue;
+            }
+
+            let kind = entry[1];
+            let mut payload = Vec::new();
+            if matches!(kind, TYPE_SZ | TYPE_BLOB | TYPE_BLOB_DATA) {
+                for data_index in index + 1..index + span {
+                    payload.extend_from_slice(&read_entry(image, address(data_index), xts));
+                }
+                payload.truncate(u16::from_le_bytes([entry[24], entry[25]]) as usize);
+                let data_crc = u32::from_le_bytes(entry[28..32].try_into().unwrap());
+                if crc32_le(u32::MAX, &payload) != data_crc {
+                    index += span;
+                    continue;
+                }
+            }
+
+            let key_len = entry[8..24].iter().position(|&b| b == 0).unwrap_or(16);
+            entries.push(RawEntry {
+                ns: entry[0],
+                kind,
+                chunk: entry[3],
+                key: String::from_utf8_lossy(&entry[8..8 + key_len]).to_string(),
+                data: entry[24..32].try_into().unwrap(),
+                payload,
+            });
+            index += span;
+        }
+    }
+    entries
+}
+
+/// Decode the key/value pairs of an NVS partition image, decrypting it when a key is given.
+/// Entries that fail their CRC check are left out.
+pub fn dump(image: &[u8], xts: Option<&Xts>) -> Result<Vec<Item>> {
+    if !image.len().is_multiple_of(PAGE_SIZE) {
+        return Err(anyhow::anyhow!(
+            "The image size is not a multiple of the 0x{:x} byte page size",
+            PAGE_SIZE
+        ));
+    }
+    let entries = read_entries(image, xts);
+
+    let namespaces: HashMap<u8, &str> = entries
+        .iter()
+        .filter(|entry| entry.ns == 0 && entry.kind == TYPE_U8)
+        .map(|entry| (entry.data[0], entry.key.as_str()))
+        .collect();
+
+    let mut items = Vec::new();
+    for entry in entries.iter().filter(|entry| entry.ns != 0) {
+        let value = match entry.kind {
+            TYPE_SZ => {
+                let text = entry.payload.strip_suffix(&[0]).unwrap_or(&entry.payload);
+                Value::String(String::from_utf8_lossy(text).to_string())
+            }
+            TYPE_BLOB => Value::Blob(entry.payload.clone()),
+            TYPE_BLOB_IDX => {
+                let size = u32::from_le_bytes(entry.data[0..4].try_into().unwrap()) as usize;
+                let (count, start) = (entry.data[4], entry.data[5]);
+                let mut data = Vec::new();
+                for chunk in (start..start.saturating_add(count)).map(|chunk| {
+                    entries.iter().find(|other| {
+                        other.kind == TYPE_BLOB_DATA
+                            && other.ns == entry.ns
+                            && other.key == entry.key
+                            && other.chunk == chunk
+                    })
+                }) {
+                    match chunk {
+                        Some(chunk) => data.extend_from_slice(&chunk.payload),
+                        None => break,
+                    }
+                }
+                if data.len() != size {
+                    continue;
+                }
+                Value::Blob(data)
+            }
+            code => match int_type_name(code) {
+                Some(_) => {
+                    let size = (code & 0x0F) as usize;
+                    let mut bytes = [0u8; 16];
+                    bytes[..size].copy_from_slice(&entry.data[..size]);
+                    let mut value = i128::from_le_bytes(bytes);
+                    if code & 0x10 != 0 && value >> (size * 8 - 1) & 1 != 0 {
+                        value -= 1i128 << (size * 8);
+                    }
+                    Value::Int(code, value)
+                }
+                None => continue,
+            },
+        };
+        let Some(namespace) = namespaces.get(&entry.ns) else {
+            continue;
+        };
+        items.push(Item {
+            namespace: namespace.to_string(),
+            key: entry.key.clone(),
+            value,
+        });
+    }
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_and_dump() {
+        let csv = "key,type,encoding,value\n\
+                   storage,namespace,,\n\
+                   count,data,u8,0x2a\n\
+                   offset,data,i32,-5\n\
+                   big,data,u64,18446744073709551615\n\
+                   name,data,string,\"a, \"\"quoted\"\" name\"\n\
+                   cert,data,base64,SGVsbG8=\n\
+                   wifi,namespace,,\n\
+                   blob,data,hex2bin,0102ff\n";
+        let mut rows = parse_csv(csv, Path::new(".")).unwrap();
+        assert_eq!(rows.len(), 8);
+        assert_eq!(
+            rows[4],
+            Row::Entry {
+                key: "name".to_string(),
+                value: Value::String("a, \"quoted\" name".to_string())
+            }
+        );
+        assert_eq!(
+            rows[5],
+            Row::Entry {
+                key: "cert".to_string(),
+                value: Value::Blob(b"Hello".to_vec())
+            }
+        );
+        assert!(parse_csv("count,data,u8,1\n", Path::new(".")).is_err());
+        assert!(parse_csv("ns,namespace,,\ncount,data,u8,256\n", Path::new(".")).is_err());
+
+        // A blob spanning pages and a long string
+        rows.push(Row::Entry {
+            key: "large".to_string(),
+            value: Value::Blob((0..9000).map(|i| i as u8).collect()),
+        });
+        rows.push(Row::Entry {
+            key: "text".to_string(),
+            value: Value::String("x".repeat(3000)),
+        });
+
+        let key: Vec<u8> = (0..64).collect();
+        let xts = Xts::new(key.as_slice().try_into().unwrap());
+        for xts in [None, Some(&xts)] {
+            let image = generate(&rows, 0x6000, xts).unwrap();
+            assert_eq!(image.len(), 0x6000);
+            assert!(image[0x5000..].iter().all(|&b| b == 0xFF));
+
+            let items = dump(&image, xts).unwrap();
+            let csv = to_csv(&items);
+            assert_eq!(parse_csv(&csv, Path::new(".")).unwrap(), rows);
+        }
+
+        assert!(generate(&rows, 0x3000, None).is_err());
+        assert!(generate(&rows, 0x2000, None).is_err());
+    }
+}
@@ -0,0 +1,104 @@
+use crate::{build_systems, config, utils};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Default OpenOCD board configuration per target, matching `idf.py openocd`
+pub fn default_board_config(target: &str) -> Option<&'static str> {
+    match target {
+        "esp32" => Some("board/esp32-wrover-kit-3.3v.cfg"),
+        "esp32s2" => Some("board/esp32s2-kaluga-1.cfg"),
+        "esp32s3" => Some("board/esp32s3-builtin.cfg"),
+        "esp32c2" => Some("board/esp32c2-ftdi.cfg"),
+        "esp32c3" => Some("board/esp32c3-builtin.cfg"),
+        "esp32c6" => Some("board/esp32c6-builtin.cfg"),
+        "esp32h2" => Some("board/esp32h2-builtin.cfg"),
+        "esp32p4" => Some("board/esp32p4-builtin.cfg"),
+        _ => None,
+    }
+}
+
+/// Whether the target has Xtensa cores (and thus TRAX trace memory)
+pub fn is_xtensa(target: &str) -> bool {
+    matches!(target, "esp32" | "esp32s2" | "esp32s3")
+}
+
+/// Target of the build directory, falling back to sdkconfig and the default target
+pub fn resolve_target(project_dir: &Path, build_dir: &Path) -> String {
+    build_systems::get_cmake_cache_entry(build_dir, "IDF_TARGET")
+        .or_else(|| {
+            config::load_project_config(project_dir)
+                .ok()
+                .and_then(|sdk_config| sdk_config.get_target().cloned())
+        })
+        .unwrap_or_else(|| utils::DEFAULT_TARGET.to_string())
+}
+
+/// Symbols OpenOCD needs to list FreeRTOS tasks. uxTopUsedPriority is only kept
+/// with CONFIG_FREERTOS_DEBUG_OCDAWARE, the current TCB was renamed in IDF 5.2.
+pub fn missing_freertos_symbols(symbols: &HashMap<String, String>) -> Vec<&'static str> {
+    let mut missing = Vec::new();
+    for symbol in ["uxTopUsedPriority", "pxReadyTasksLists"] {
+        if !symbols.contains_key(symbol) {
+            missing.push(symbol);
+        }
+    }
+    if !symbols.contains_key("pxCurrentTCB") && !symbols.contains_key("pxCurrentTCBs") {
+        missing.push("pxCurrentTCBs");
+    }
+    missing
+}
+
+/// Arguments selecting the RTOS OpenOCD reports threads for, set before the board config is loaded
+pub fn rtos_args(freertos: bool) -> Vec<String> {
+    let rtos = if freertos { "FreeRTOS" } else { "none" };
+    vec!["-c".to_string(), format!("set ESP_RTOS {}", rtos)]
+}
+
+/// Prefix of the target's toolchain executables, e.g. "xtensa-esp32s3-elf-"
+pub fn toolchain_prefix(target: &str) -> String {
+    if is_xtensa(target) {
+        format!("xtensa-{}-elf-", target)
+    } else {
+        "riscv32-esp-elf-".to_string()
+    }
+}
+
+/// GDB executable of the target's toolchain
+pub fn gdb_executable(target: &str) -> String {
+    format!("{}gdb", toolchain_prefix(target))
+}
+
+/// OpenOCD configuration arguments: $OPENOCD_COMMANDS like idf.py, or the target's default board
+pub fn config_args(target: &str) -> Result<Vec<String>> {
+    if let Some(commands) = utils::env_var("OPENOCD_COMMANDS") {
+        return Ok(crate::compile_commands::split_command_line(&commands));
+    }
+
+    let board = default_board_config(target).ok_or_else(|| {
+        anyhow::anyhow!(
+            "No default OpenOCD configuration for {}, set OPENOCD_COMMANDS (e.g. \"-f board/<board>.cfg\")",
+            target
+        )
+    })?;
+
+    Ok(vec!["-f".to_string(), board.to_string()])
+}
+
+/// Run OpenOCD with the given commands, then shut it down
+pub async fn run_commands(
+    target: &str,
+    commands: &[String],
+    project_dir: &Path,
+    verbose: bool,
+) -> Result<()> {
+    let mut script = commands.to_vec();
+    script.push("shutdown".to_string());
+
+    let mut args = config_args(target)?;
+    args.push("-c".to_string());
+    args.push(script.join("; "));
+
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    utils::run_command("openocd", &args, Some(project_dir), verbose).await
+}
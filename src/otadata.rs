@@ -0,0 +1,157 @@
+//! The otadata partition: two copies of esp_ota_select_entry_t, one per flash sector.
+//! The valid entry with the highest sequence number selects the OTA app slot to boot.
+
+use crate::crc32::crc32_le;
+use anyhow::Result;
+
+pub const SECTOR_SIZE: usize = 0x1000;
+/// ota_seq, seq_label[20], ota_state and crc
+const ENTRY_SIZE: usize = 32;
+
+/// esp_ota_img_states_t
+const STATES: [(&str, u32); 6] = [
+    ("new", 0),
+    ("pending_verify", 1),
+    ("valid", 2),
+    ("invalid", 3),
+    ("aborted", 4),
+    ("undefined", 0xFFFF_FFFF),
+];
+const STATE_UNDEFINED: u32 = 0xFFFF_FFFF;
+
+/// One of the two otadata entries
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OtaEntry {
+    pub seq: u32,
+    pub state: u32,
+    pub crc: u32,
+}
+
+impl OtaEntry {
+    /// Entry selecting the boot slot with this sequence number, as otatool.py writes it
+    pub fn new(seq: u32) -> Self {
+        Self {
+            seq,
+            state: STATE_UNDEFINED,
+            crc: crc32_le(u32::MAX, &seq.to_le_bytes()),
+        }
+    }
+
+    /// Whether the bootloader takes this entry into account
+    pub fn is_valid(&self) -> bool {
+        self.seq != u32::MAX && self.crc == crc32_le(u32::MAX, &self.seq.to_le_bytes())
+    }
+
+    pub fn state_name(&self) -> String {
+        STATES
+            .iter()
+            .find(|(_, value)| *value == self.state)
+            .map(|(name, _)| name.to_string())
+            .unwrap_or_else(|| format!("0x{:08x}", self.state))
+    }
+
+    /// Index of the OTA slot (ota_0, ota_1, ...) this entry selects
+    pub fn slot(&self, slots: usize) -> usize {
+        (self.seq.wrapping_sub(1) as usize) % slots
+    }
+
+    fn to_bytes(self) -> [u8; ENTRY_SIZE] {
+        let mut bytes = [0xFF; ENTRY_SIZE];
+        bytes[0..4].copy_from_slice(&self.seq.to_le_bytes());
+        bytes[24..28].copy_from_slice(&self.state.to_le_bytes());
+        bytes[28..32].copy_from_slice(&self.crc.to_le_bytes());
+        bytes
+    }
+}
+
+fn word(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+/// Parse the two entries of the otadata partition contents
+pub fn parse(data: &[u8]) -> Result<[OtaEntry; 2]> {
+    if data.len() < SECTOR_SIZE + ENTRY_SIZE {
+        return Err(anyhow::anyhow!(
+            "otadata is {} bytes, expected two {} byte sectors",
+            data.len(),
+            SECTOR_SIZE
+        ));
+    }
+    Ok([0, SECTOR_SIZE].map(|offset| OtaEntry {
+        seq: word(data, offset),
+        state: word(data, offset + 24),
+        crc: word(data, offset + 28),
+    }))
+}
+
+/// Index of the entry the bootloader uses: the valid one with the highest sequence number.
+/// Without one it boots the factory app (or ota_0 if there is none).
+pub fn active_entry(entries: &[OtaEntry; 2]) -> Option<usize> {
+    (0..entries.len())
+        .filter(|&index| entries[index].is_valid())
+        .max_by_key(|&index| entries[index].seq)
+}
+
+/// Rewrite the otadata contents so the bootloader starts OTA slot `slot` of `slots`. The
+/// new entry replaces the inactive one, like esp_ota_set_boot_partition() does.
+pub fn select_slot(data: &mut [u8], slot: usize, slots: usize) -> Result<()> {
+    let entries = parse(data)?;
+    let target = slot as u32 + 1;
+    let (seq, sector) = match active_entry(&entries) {
+        Some(active) => {
+            // Lowest sequence number for the slot that isn't older than the active entry
+            let base = entries[active].seq;
+            let slots = slots as u32;
+            let rounds = base.saturating_sub(target).div_ceil(slots);
+            (target + rounds * slots, 1 - active)
+        }
+        None => (target, 0),
+    };
+
+    let offset = sector * SECTOR_SIZE;
+    data[offset..offset + SECTOR_SIZE].fill(0xFF);
+    data[offset..offset + ENTRY_SIZE].copy_from_slice(&OtaEntry::new(seq).to_bytes());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_slot() {
+        // Same CRC as binascii.crc32(struct.pack("<I", 1), 0xFFFFFFFF) in otatool.py
+        assert_eq!(OtaEntry::new(1).crc, 0x4743_989a);
+        assert!(OtaEntry::new(1).is_valid());
+
+        let mut data = vec![0xFF; 2 * SECTOR_SIZE];
+        assert_eq!(active_entry(&parse(&data).unwrap()), None);
+
+        select_slot(&mut data, 1, 2).unwrap();
+        let entries = parse(&data).unwrap();
+        assert_eq!(active_entry(&entries), Some(0));
+        assert_eq!(entries[0].seq, 2);
+        assert_eq!(entries[0].slot(2), 1);
+        assert_eq!(entries[0].state_name(), "undefined");
+
+        select_slot(&mut data, 0, 2).unwrap();
+        let entries = parse(&data).unwrap();
+        assert_eq!(active_entry(&entries), Some(1));
+        assert_eq!(entries[1].seq, 3);
+        assert_eq!(entries[1].slot(2), 0);
+
+        // Selecting the active slot again keeps its sequence number
+        select_slot(&mut data, 0, 2).unwrap();
+        let entries = parse(&data).unwrap();
+        assert_eq!(entries[0].seq, 3);
+        assert_eq!(entries[active_entry(&entries).unwrap()].slot(2), 0);
+
+        data[SECTOR_SIZE + 28] ^= 1;
+        assert!(!parse(&data).unwrap()[1].is_valid());
+    }
+}
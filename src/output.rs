@@ -0,0 +1,182 @@
+//! Machine-readable output for CI (`--output-format json`): events as JSON lines on
+//! stdout. The human-readable output, of idf-rs and of the tools it runs, moves to
+//! stderr so that stdout carries nothing but the events.
+
+use serde::Serialize;
+use std::io::Write;
+use std::sync::{Mutex, OnceLock};
+use tokio::sync::mpsc::UnboundedSender;
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable text
+    #[default]
+    Text,
+    /// JSON lines with the events of the run
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    CommandStarted {
+        command: String,
+    },
+    CommandFinished {
+        command: String,
+        duration_ms: u64,
+        success: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    /// An external tool exited, exit_code is None when it was killed by a signal
+    ToolFinished {
+        program: String,
+        exit_code: Option<i32>,
+        duration_ms: u64,
+    },
+    Warning {
+        message: String,
+    },
+    /// A line of tool output, only passed to the sink (the daemon's client)
+    Output {
+        stream: String,
+        line: String,
+    },
+    /// Build progress, emitted when the percentage changes. Make only reports the
+    /// percentage, Ninja also the finished and total targets
+    BuildProgress {
+        percent: u32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        finished: Option<u32>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        total: Option<u32>,
+    },
+    AppSize {
+        binary: String,
+        size: u64,
+        partition: String,
+        partition_size: u64,
+        percent_used: f64,
+    },
+}
+
+/// Where the events go, the original stdout, once JSON output is enabled
+static EVENTS: OnceLock<Option<Mutex<std::fs::File>>> = OnceLock::new();
+
+/// Receives the events as well, e.g. to forward them to a daemon client
+static SINK: Mutex<Option<UnboundedSender<Event>>> = Mutex::new(None);
+
+/// Select the output format for the rest of the run, the first call wins
+pub fn init(format: OutputFormat) -> anyhow::Result<()> {
+    let mut result = Ok(());
+    EVENTS.get_or_init(|| match format {
+        OutputFormat::Text => None,
+        OutputFormat::Json => match events_file() {
+            Ok(file) => Some(Mutex::new(file)),
+            Err(e) => {
+                result = Err(e);
+                None
+            }
+        },
+    });
+    result
+}
+
+pub fn is_json() -> bool {
+    matches!(EVENTS.get(), Some(Some(_)))
+}
+
+/// Keep a handle on stdout for the events and point the process's stdout (and with it
+/// the inherited stdout of child processes) at stderr
+#[cfg(unix)]
+fn events_file() -> anyhow::Result<std::fs::File> {
+    use std::os::fd::{AsFd, AsRawFd};
+
+    let stdout = std::io::stdout();
+    let events = stdout.as_fd().try_clone_to_owned()?;
+    stdout.lock().flush()?;
+    // SAFETY: both descriptors are open for the lifetime of the process
+    if unsafe { libc::dup2(std::io::stderr().as_raw_fd(), stdout.as_raw_fd()) } < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(events.into())
+}
+
+/// The tools' output can't be moved off stdout here yet, and interleaved with it the
+/// events wouldn't be parseable
+#[cfg(not(unix))]
+fn events_file() -> anyhow::Result<std::fs::File> {
+    Err(anyhow::anyhow!(
+        "--output-format json is not supported on this platform yet"
+    ))
+}
+
+/// Send the events to `sink` too, or stop with None
+pub fn set_sink(sink: Option<UnboundedSender<Event>>) {
+    *SINK.lock().unwrap() = sink;
+}
+
+/// Pass a line of tool output to the sink, the JSON output leaves it on stderr
+pub fn tool_output(stream: &str, line: &str) {
+    if let Some(sink) = SINK.lock().unwrap().as_ref() {
+        let _ = sink.send(Event::Output {
+            stream: stream.to_string(),
+            line: line.to_string(),
+        });
+    }
+}
+
+/// Emit an event, to stdout for JSON output and to the sink
+pub fn emit(event: &Event) {
+    if let Some(sink) = SINK.lock().unwrap().as_ref() {
+        let _ = sink.send(event.clone());
+    }
+    let Some(Some(events)) = EVENTS.get() else {
+        return;
+    };
+    if let Ok(line) = serde_json::to_string(event) {
+        let mut events = events.lock().unwrap();
+        let _ = writeln!(events, "{}", line);
+        let _ = events.flush();
+    }
+}
+
+/// Report a warning, printed to stderr or emitted as an event
+pub fn warning(message: impl Into<String>) {
+    let message = message.into();
+    if !is_json() {
+        eprintln!("Warning: {}", message);
+    }
+    emit(&Event::Warning { message });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_json() {
+        let finished = Event::CommandFinished {
+            command: "build".to_string(),
+            duration_ms: 1200,
+            success: false,
+            error: Some("Command failed with exit code: Some(2)".to_string()),
+        };
+        assert_eq!(
+            serde_json::to_string(&finished).unwrap(),
+            r#"{"event":"command_finished","command":"build","duration_ms":1200,"success":false,"error":"Command failed with exit code: Some(2)"}"#
+        );
+
+        let tool = Event::ToolFinished {
+            program: "ninja".to_string(),
+            exit_code: None,
+            duration_ms: 5,
+        };
+        assert_eq!(
+            serde_json::to_string(&tool).unwrap(),
+            r#"{"event":"tool_finished","program":"ninja","exit_code":null,"duration_ms":5}"#
+        );
+        assert!(!is_json());
+    }
+}
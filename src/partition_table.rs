@@ -0,0 +1,711 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// Magic bytes that start every partition table entry
+const ENTRY_MAGIC: [u8; 2] = [0xAA, 0x50];
+/// Magic bytes of the optional MD5 checksum entry that ends the table
+const MD5_MAGIC: [u8; 2] = [0xEB, 0xEB];
+/// Size of a single binary partition table entry
+const ENTRY_SIZE: usize = 32;
+
+pub const TYPE_APP: u8 = 0x00;
+pub const TYPE_DATA: u8 = 0x01;
+
+/// Partition type names used in CSVs, as gen_esp32part.py knows them
+const TYPES: [(&str, u8); 4] = [
+    ("app", TYPE_APP),
+    ("data", TYPE_DATA),
+    ("bootloader", 0x02),
+    ("partition_table", 0x03),
+];
+
+/// Subtype names per type; app subtypes ota_0..ota_15 are 0x10..0x1f
+const SUBTYPES: [(u8, &str, u8); 14] = [
+    (TYPE_APP, "factory", 0x00),
+    (TYPE_APP, "test", 0x20),
+    (TYPE_DATA, "ota", 0x00),
+    (TYPE_DATA, "phy", 0x01),
+    (TYPE_DATA, "nvs", 0x02),
+    (TYPE_DATA, "coredump", 0x03),
+    (TYPE_DATA, "nvs_keys", 0x04),
+    (TYPE_DATA, "efuse", 0x05),
+    (TYPE_DATA, "undefined", 0x06),
+    (TYPE_DATA, "esphttpd", 0x80),
+    (TYPE_DATA, "fat", 0x81),
+    (TYPE_DATA, "spiffs", 0x82),
+    (TYPE_DATA, "littlefs", 0x83),
+    (0x02, "primary", 0x00),
+];
+const OTA_SUBTYPE_MIN: u8 = 0x10;
+const OTA_SLOTS: u8 = 16;
+
+/// Entry flags
+const FLAGS: [(&str, u32); 2] = [("encrypted", 1 << 0), ("readonly", 1 << 1)];
+
+pub fn type_name(ptype: u8) -> String {
+    TYPES
+        .iter()
+        .find(|(_, value)| *value == ptype)
+        .map(|(name, _)| name.to_string())
+        .unwrap_or_else(|| format!("0x{:02x}", ptype))
+}
+
+pub fn subtype_name(ptype: u8, subtype: u8) -> String {
+    if ptype == TYPE_APP && (OTA_SUBTYPE_MIN..OTA_SUBTYPE_MIN + OTA_SLOTS).contains(&subtype) {
+        return format!("ota_{}", subtype - OTA_SUBTYPE_MIN);
+    }
+    SUBTYPES
+        .iter()
+        .find(|(kind, _, value)| *kind == ptype && *value == subtype)
+        .map(|(_, name, _)| name.to_string())
+        .unwrap_or_else(|| format!("0x{:02x}", subtype))
+}
+
+fn parse_type(text: &str) -> Option<u8> {
+    TYPES
+        .iter()
+        .find(|(name, _)| *name == text)
+        .map(|(_, value)| *value)
+        .or_else(|| parse_size(text).and_then(|value| u8::try_from(value).ok()))
+}
+
+fn parse_subtype(ptype: u8, text: &str) -> Option<u8> {
+    if text.is_empty() {
+        return Some(0);
+    }
+    if let Some(slot) = text
+        .strip_prefix("ota_")
+        .and_then(|slot| slot.parse::<u8>().ok())
+    {
+        if ptype == TYPE_APP && slot < OTA_SLOTS {
+            return Some(OTA_SUBTYPE_MIN + slot);
+        }
+    }
+    SUBTYPES
+        .iter()
+        .find(|(kind, name, _)| *kind == ptype && *name == text)
+        .map(|(_, _, value)| *value)
+        .or_else(|| parse_size(text).and_then(|value| u8::try_from(value).ok()))
+}
+
+/// A single entry of the partition table
+#[derive(Debug, Clone, PartialEq)]
+pub struct Partition {
+    pub label: String,
+    pub ptype: u8,
+    pub subtype: u8,
+    pub offset: u32,
+    pub size: u32,
+    pub flags: u32,
+}
+
+impl Partition {
+    pub fn is_app(&self) -> bool {
+        self.ptype == TYPE_APP
+    }
+
+    /// Slot number of an OTA app partition (ota_0 is 0)
+    pub fn ota_slot(&self) -> Option<u8> {
+        (self.is_app() && (OTA_SUBTYPE_MIN..OTA_SUBTYPE_MIN + OTA_SLOTS).contains(&self.subtype))
+            .then(|| self.subtype - OTA_SUBTYPE_MIN)
+    }
+
+    /// Whether this is the otadata partition selecting the OTA slot to boot
+    pub fn is_otadata(&self) -> bool {
+        self.ptype == TYPE_DATA && self.subtype == 0x00
+    }
+
+    /// Flag names, e.g. "encrypted"
+    pub fn flag_names(&self) -> Vec<&'static str> {
+        FLAGS
+            .iter()
+            .filter(|(_, bit)| self.flags & bit != 0)
+            .map(|(name, _)| *name)
+            .collect()
+    }
+}
+
+/// Partition with the given label
+pub fn find<'a>(partitions: &'a [Partition], label: &str) -> Result<&'a Partition> {
+    partitions
+        .iter()
+        .find(|partition| partition.label == label)
+        .ok_or_else(|| {
+            let labels: Vec<&str> = partitions.iter().map(|p| p.label.as_str()).collect();
+            anyhow::anyhow!(
+                "No partition named '{}' (partitions: {})",
+                label,
+                labels.join(", ")
+            )
+        })
+}
+
+/// Location of the partition table binary generated by the build
+pub fn get_partition_table_bin_path(build_dir: &Path) -> PathBuf {
+    build_dir
+        .join("partition_table")
+        .join("partition-table.bin")
+}
+
+/// Parse a binary partition table (the format written by gen_esp32part.py)
+pub fn parse_binary(data: &[u8]) -> Result<Vec<Partition>> {
+    let mut partitions = Vec::new();
+
+    for entry in data.chunks(ENTRY_SIZE) {
+        if entry.len() < ENTRY_SIZE || entry.iter().all(|&b| b == 0xFF) {
+            break;
+        }
+
+        if entry[0..2] == MD5_MAGIC {
+            break;
+        }
+
+        if entry[0..2] != ENTRY_MAGIC {
+            anyhow::bail!(
+                "Invalid partition table entry magic {:02x}{:02x}",
+                entry[0],
+                entry[1]
+            );
+        }
+
+        let read_u32 = |start: usize| {
+            u32::from_le_bytes([
+                entry[start],
+                entry[start + 1],
+                entry[start + 2],
+                entry[start + 3],
+            ])
+        };
+
+        let label_bytes = &entry[12..28];
+        let label_len = label_bytes
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(label_bytes.len());
+
+        partitions.push(Partition {
+            label: String::from_utf8_lossy(&label_bytes[..label_len]).to_string(),
+            ptype: entry[2],
+            subtype: entry[3],
+            offset: read_u32(4),
+            size: read_u32(8),
+            flags: read_u32(28),
+        });
+    }
+
+    Ok(partitions)
+}
+
+/// Space reserved for the table entries, the rest of the table sector is unused
+const MAX_TABLE_LEN: usize = 0xC00;
+
+/// Generate the binary partition table, as gen_esp32part.py writes it: the entries,
+/// optionally an MD5 entry over them, padded with 0xFF
+pub fn generate_binary(partitions: &[Partition], md5: bool) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    for partition in partitions {
+        if partition.label.len() > MAX_LABEL_LEN {
+            anyhow::bail!("Partition label '{}' is too long", partition.label);
+        }
+        data.extend_from_slice(&ENTRY_MAGIC);
+        data.push(partition.ptype);
+        data.push(partition.subtype);
+        data.extend_from_slice(&partition.offset.to_le_bytes());
+        data.extend_from_slice(&partition.size.to_le_bytes());
+        let mut label = [0u8; MAX_LABEL_LEN];
+        label[..partition.label.len()].copy_from_slice(partition.label.as_bytes());
+        data.extend_from_slice(&label);
+        data.extend_from_slice(&partition.flags.to_le_bytes());
+    }
+
+    if md5 {
+        let checksum = crate::md5::digest(&data);
+        data.extend_from_slice(&MD5_MAGIC);
+        data.extend_from_slice(&[0xFF; 14]);
+        data.extend_from_slice(&checksum);
+    }
+
+    if data.len() > MAX_TABLE_LEN {
+        anyhow::bail!("Partition table has too many entries");
+    }
+    data.resize(MAX_TABLE_LEN, 0xFF);
+    Ok(data)
+}
+
+/// Load the generated partition table from the build directory, if present
+pub fn load_from_build_dir(build_dir: &Path) -> Result<Option<Vec<Partition>>> {
+    let path = get_partition_table_bin_path(build_dir);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let data = std::fs::read(&path)?;
+    Ok(Some(parse_binary(&data)?))
+}
+
+/// Find the smallest app partition, which is the one that limits the app size
+/// (for OTA layouts every slot must be able to hold the image)
+pub fn smallest_app_partition(partitions: &[Partition]) -> Option<&Partition> {
+    partitions
+        .iter()
+        .filter(|p| p.is_app())
+        .min_by_key(|p| p.size)
+}
+
+/// Offset of the partition table when CONFIG_PARTITION_TABLE_OFFSET is not set
+pub const DEFAULT_TABLE_OFFSET: u32 = 0x8000;
+/// Flash space reserved for the partition table
+const TABLE_SIZE: u32 = 0x1000;
+const APP_ALIGNMENT: u32 = 0x10000;
+const DATA_ALIGNMENT: u32 = 0x1000;
+/// Longest label that fits the binary entry
+const MAX_LABEL_LEN: usize = 16;
+
+/// Parse a number as written in partition CSVs: decimal or hex, with an optional K or M suffix
+pub fn parse_size(text: &str) -> Option<u32> {
+    let text = text.trim();
+    let (number, multiplier) = match text.chars().last()? {
+        'k' | 'K' => (&text[..text.len() - 1], 1024),
+        'm' | 'M' => (&text[..text.len() - 1], 1024 * 1024),
+        _ => (text, 1),
+    };
+
+    let value = match number
+        .strip_prefix("0x")
+        .or_else(|| number.strip_prefix("0X"))
+    {
+        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+        None => number.parse().ok()?,
+    };
+    value.checked_mul(multiplier)
+}
+
+/// Format a size the way partition CSVs usually spell it
+pub fn format_size(size: u32) -> String {
+    if size > 0 && size.is_multiple_of(1024 * 1024) {
+        format!("{}M", size / (1024 * 1024))
+    } else if size > 0 && size.is_multiple_of(1024) {
+        format!("{}K", size / 1024)
+    } else {
+        format!("0x{:x}", size)
+    }
+}
+
+fn align_up(value: u32, alignment: u32) -> u32 {
+    value.div_ceil(alignment) * alignment
+}
+
+/// A partition as written in the partition table CSV
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvEntry {
+    pub label: String,
+    pub ptype: String,
+    pub subtype: String,
+    /// Explicit offset, None when gen_esp32part.py places the partition
+    pub offset: Option<u32>,
+    pub size: u32,
+    pub flags: String,
+}
+
+impl CsvEntry {
+    /// Parse a CSV row: `label, type, subtype, offset, size[, flags]`. The short
+    /// form `label, type, subtype, size` leaves the offset to be calculated.
+    pub fn parse(line: &str) -> Result<Self> {
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let (offset, size, flags) = match fields.len() {
+            4 => ("", fields[3], ""),
+            5 => (fields[3], fields[4], ""),
+            6 => (fields[3], fields[4], fields[5]),
+            _ => anyhow::bail!(
+                "expected 'label, type, subtype, offset, size[, flags]', got '{}'",
+                line.trim()
+            ),
+        };
+
+        let offset = match offset {
+            "" => None,
+            offset => Some(
+                parse_size(offset).ok_or_else(|| anyhow::anyhow!("invalid offset '{}'", offset))?,
+            ),
+        };
+        let size = parse_size(size).ok_or_else(|| anyhow::anyhow!("invalid size '{}'", size))?;
+
+        Ok(Self {
+            label: fields[0].to_string(),
+            ptype: fields[1].to_string(),
+            subtype: fields[2].to_string(),
+            offset,
+            size,
+            flags: flags.to_string(),
+        })
+    }
+
+    pub fn is_app(&self) -> bool {
+        self.ptype == "app" || parse_size(&self.ptype) == Some(TYPE_APP as u32)
+    }
+
+    /// The binary entry, placed at `offset`
+    pub fn to_partition(&self, offset: u32) -> Result<Partition> {
+        let ptype = parse_type(&self.ptype).ok_or_else(|| {
+            anyhow::anyhow!("{}: unknown partition type '{}'", self.label, self.ptype)
+        })?;
+        let subtype = parse_subtype(ptype, &self.subtype)
+            .ok_or_else(|| anyhow::anyhow!("{}: unknown subtype '{}'", self.label, self.subtype))?;
+
+        let mut flags = 0;
+        for name in self
+            .flags
+            .split(':')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            let (_, bit) = FLAGS
+                .iter()
+                .find(|(flag, _)| *flag == name)
+                .ok_or_else(|| anyhow::anyhow!("{}: unknown flag '{}'", self.label, name))?;
+            flags |= bit;
+        }
+
+        Ok(Partition {
+            label: self.label.clone(),
+            ptype,
+            subtype,
+            offset,
+            size: self.size,
+            flags,
+        })
+    }
+
+    fn alignment(&self) -> u32 {
+        if self.is_app() {
+            APP_ALIGNMENT
+        } else {
+            DATA_ALIGNMENT
+        }
+    }
+
+    fn to_csv(&self) -> String {
+        let offset = self
+            .offset
+            .map(|offset| format!("0x{:x}", offset))
+            .unwrap_or_default();
+        format!(
+            "{}, {}, {}, {}, {}, {}",
+            self.label,
+            self.ptype,
+            self.subtype,
+            offset,
+            format_size(self.size),
+            self.flags
+        )
+        .trim_end()
+        .to_string()
+    }
+}
+
+#[derive(Debug, Clone)]
+enum CsvLine {
+    /// Comments and blank lines, kept as they are
+    Text(String),
+    /// An entry with its original text, None once edited
+    Entry(CsvEntry, Option<String>),
+}
+
+/// A partition table CSV that can be edited while keeping comments and untouched rows
+#[derive(Debug, Clone)]
+pub struct PartitionCsv {
+    lines: Vec<CsvLine>,
+    table_offset: u32,
+}
+
+impl PartitionCsv {
+    pub fn parse(content: &str, table_offset: u32) -> Result<Self> {
+        let lines = content
+            .lines()
+            .enumerate()
+            .map(|(index, line)| {
+                let trimmed = line.trim();
+                if trimmed.is_empty() || trimmed.starts_with('#') {
+                    return Ok(CsvLine::Text(line.to_string()));
+                }
+                let entry = CsvEntry::parse(line)
+                    .map_err(|e| anyhow::anyhow!("line {}: {}", index + 1, e))?;
+                Ok(CsvLine::Entry(entry, Some(line.to_string())))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            lines,
+            table_offset,
+        })
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &CsvEntry> {
+        self.lines.iter().filter_map(|line| match line {
+            CsvLine::Entry(entry, _) => Some(entry),
+            CsvLine::Text(_) => None,
+        })
+    }
+
+    /// Offsets of all entries, placing the ones without an offset like gen_esp32part.py
+    pub fn layout(&self) -> Vec<(&CsvEntry, u32)> {
+        let mut next = self.table_offset + TABLE_SIZE;
+        self.entries()
+            .map(|entry| {
+                let offset = entry
+                    .offset
+                    .unwrap_or_else(|| align_up(next, entry.alignment()));
+                next = offset.saturating_add(entry.size);
+                (entry, offset)
+            })
+            .collect()
+    }
+
+    /// The binary entries of the table
+    pub fn partitions(&self) -> Result<Vec<Partition>> {
+        self.layout()
+            .into_iter()
+            .map(|(entry, offset)| entry.to_partition(offset))
+            .collect()
+    }
+
+    fn position(&self, label: &str) -> Option<usize> {
+        self.lines
+            .iter()
+            .position(|line| matches!(line, CsvLine::Entry(entry, _) if entry.label == label))
+    }
+
+    /// Move every explicitly placed entry from line `start` on right after its predecessor
+    fn relayout_from(&mut self, start: usize) {
+        let mut next = self.table_offset + TABLE_SIZE;
+        for (index, line) in self.lines.iter_mut().enumerate() {
+            let CsvLine::Entry(entry, raw) = line else {
+                continue;
+            };
+
+            let offset = match entry.offset {
+                Some(offset) if index < start => offset,
+                Some(offset) => {
+                    let aligned = align_up(next, entry.alignment());
+                    if aligned != offset {
+                        entry.offset = Some(aligned);
+                        *raw = None;
+                    }
+                    aligned
+                }
+                None => align_up(next, entry.alignment()),
+            };
+            next = offset.saturating_add(entry.size);
+        }
+    }
+
+    /// Change the size of a partition, moving the partitions after it
+    pub fn resize(&mut self, label: &str, size: u32) -> Result<()> {
+        let index = self
+            .position(label)
+            .ok_or_else(|| anyhow::anyhow!("No partition labelled '{}'", label))?;
+        if let CsvLine::Entry(entry, raw) = &mut self.lines[index] {
+            entry.size = size;
+            *raw = None;
+        }
+        self.relayout_from(index + 1);
+        Ok(())
+    }
+
+    /// Insert a partition after `after` (or at the end), moving the partitions after it
+    pub fn add(&mut self, mut entry: CsvEntry, after: Option<&str>) -> Result<()> {
+        if self.position(&entry.label).is_some() {
+            anyhow::bail!("A partition labelled '{}' already exists", entry.label);
+        }
+
+        let index = match after {
+            Some(after) => {
+                self.position(after)
+                    .ok_or_else(|| anyhow::anyhow!("No partition labelled '{}'", after))?
+                    + 1
+            }
+            None => self
+                .lines
+                .iter()
+                .rposition(|line| matches!(line, CsvLine::Entry(..)))
+                .map_or(self.lines.len(), |index| index + 1),
+        };
+
+        // Follow the table's style: place the new entry explicitly if the others are
+        let explicit = self.entries().any(|entry| entry.offset.is_some());
+        if explicit && entry.offset.is_none() {
+            entry.offset = Some(0);
+        }
+
+        self.lines.insert(index, CsvLine::Entry(entry, None));
+        self.relayout_from(index);
+        Ok(())
+    }
+
+    /// Problems gen_esp32part.py or the device would reject
+    pub fn validate(&self, flash_size: Option<u32>) -> Vec<String> {
+        let mut errors = Vec::new();
+        let table_end = self.table_offset + TABLE_SIZE;
+        let layout = self.layout();
+
+        for (index, (entry, offset)) in layout.iter().enumerate() {
+            if entry.label.len() > MAX_LABEL_LEN {
+                errors.push(format!(
+                    "{}: label is longer than {} characters",
+                    entry.label, MAX_LABEL_LEN
+                ));
+            }
+            if layout[..index]
+                .iter()
+                .any(|(other, _)| other.label == entry.label)
+            {
+                errors.push(format!("{}: duplicate label", entry.label));
+            }
+            if entry.size == 0 {
+                errors.push(format!("{}: size is zero", entry.label));
+            }
+            if offset % entry.alignment() != 0 {
+                errors.push(format!(
+                    "{}: offset 0x{:x} is not aligned to 0x{:x}",
+                    entry.label,
+                    offset,
+                    entry.alignment()
+                ));
+            }
+            if *offset < table_end {
+                errors.push(format!(
+                    "{}: offset 0x{:x} overlaps the partition table (ends at 0x{:x})",
+                    entry.label, offset, table_end
+                ));
+            }
+
+            let end = *offset as u64 + entry.size as u64;
+            if let Some((previous, previous_offset)) = index.checked_sub(1).map(|i| layout[i]) {
+                if (previous_offset as u64 + previous.size as u64) > *offset as u64 {
+                    errors.push(format!("{}: overlaps {}", entry.label, previous.label));
+                }
+            }
+            if let Some(flash_size) = flash_size {
+                if end > flash_size as u64 {
+                    errors.push(format!(
+                        "{}: ends at 0x{:x}, beyond the 0x{:x} bytes of flash",
+                        entry.label, end, flash_size
+                    ));
+                }
+            }
+        }
+
+        errors
+    }
+}
+
+impl std::fmt::Display for PartitionCsv {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for line in &self.lines {
+            match line {
+                CsvLine::Text(text) | CsvLine::Entry(_, Some(text)) => writeln!(f, "{}", text)?,
+                CsvLine::Entry(entry, None) => writeln!(f, "{}", entry.to_csv())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(label: &str, ptype: u8, subtype: u8, offset: u32, size: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&ENTRY_MAGIC);
+        data.push(ptype);
+        data.push(subtype);
+        data.extend_from_slice(&offset.to_le_bytes());
+        data.extend_from_slice(&size.to_le_bytes());
+        let mut label_bytes = [0u8; 16];
+        label_bytes[..label.len()].copy_from_slice(label.as_bytes());
+        data.extend_from_slice(&label_bytes);
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn test_parse_binary() {
+        let mut data = Vec::new();
+        data.extend(entry("nvs", 0x01, 0x02, 0x9000, 0x6000));
+        data.extend(entry("ota_0", TYPE_APP, 0x10, 0x10000, 0x180000));
+        data.extend(entry("ota_1", TYPE_APP, 0x11, 0x190000, 0x100000));
+        data.extend_from_slice(&MD5_MAGIC);
+        data.resize(0xC00, 0xFF);
+
+        let partitions = parse_binary(&data).unwrap();
+        assert_eq!(partitions.len(), 3);
+        assert_eq!(partitions[0].label, "nvs");
+        assert_eq!(partitions[1].offset, 0x10000);
+
+        let smallest = smallest_app_partition(&partitions).unwrap();
+        assert_eq!(smallest.label, "ota_1");
+
+        assert_eq!(find(&partitions, "ota_0").unwrap().offset, 0x10000);
+        assert!(find(&partitions, "storage").is_err());
+    }
+
+    #[test]
+    fn test_parse_binary_invalid_magic() {
+        let data = vec![0x12; ENTRY_SIZE];
+        assert!(parse_binary(&data).is_err());
+    }
+
+    const CSV: &str = "# Name, Type, SubType, Offset, Size, Flags
+nvs,      data, nvs,     0x9000,  0x6000,
+phy_init, data, phy,     0xf000,  0x1000,
+factory,  app,  factory, 0x10000, 1M,
+storage,  data, spiffs,  0x110000, 512K,
+";
+
+    #[test]
+    fn test_edit_partition_csv() {
+        let mut csv = PartitionCsv::parse(CSV, DEFAULT_TABLE_OFFSET).unwrap();
+        assert!(csv.validate(Some(0x400000)).is_empty());
+
+        csv.resize("factory", 0x180000).unwrap();
+        let layout: Vec<_> = csv
+            .layout()
+            .iter()
+            .map(|(entry, offset)| (entry.label.clone(), *offset))
+            .collect();
+        assert_eq!(layout[3], ("storage".to_string(), 0x190000));
+
+        csv.add(
+            CsvEntry::parse("coredump, data, coredump, 64K").unwrap(),
+            Some("phy_init"),
+        )
+        .unwrap();
+        let layout = csv.layout();
+        assert_eq!(layout[2].0.label, "coredump");
+        assert_eq!(layout[2].1, 0x10000);
+        assert_eq!(layout[3].1, 0x20000);
+        assert!(csv.validate(Some(0x400000)).is_empty());
+
+        let written = csv.to_string();
+        assert!(written.starts_with("# Name, Type, SubType, Offset, Size, Flags\nnvs,      data"));
+        assert!(written.contains("factory, app, factory, 0x20000, 1536K,\n"));
+
+        let partitions = csv.partitions().unwrap();
+        assert_eq!(partitions[2].subtype, 0x03);
+        assert_eq!(
+            subtype_name(partitions[3].ptype, partitions[3].subtype),
+            "factory"
+        );
+        let binary = generate_binary(&partitions, true).unwrap();
+        assert_eq!(binary.len(), 0xC00);
+        assert_eq!(parse_binary(&binary).unwrap(), partitions);
+        assert_eq!(&binary[5 * ENTRY_SIZE..5 * ENTRY_SIZE + 2], &MD5_MAGIC);
+
+        csv.resize("storage", 0x300000).unwrap();
+        assert_eq!(csv.validate(Some(0x400000)).len(), 1);
+        assert!(csv
+            .add(CsvEntry::parse("nvs, data, nvs, 4K").unwrap(), None)
+            .is_err());
+    }
+}
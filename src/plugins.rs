@@ -0,0 +1,126 @@
+//! Plugins, cargo-style: an unknown command `foo` runs the `idf-rs-foo` executable from
+//! PATH with the rest of the arguments. The global options reach it resolved, as
+//! IDF_RS_* environment variables.
+
+use crate::{output, utils, Cli};
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+const PREFIX: &str = "idf-rs-";
+
+fn executable_name(command: &str) -> String {
+    format!("{}{}{}", PREFIX, command, std::env::consts::EXE_SUFFIX)
+}
+
+fn search_path() -> Vec<PathBuf> {
+    std::env::var_os("PATH")
+        .map(|path| std::env::split_paths(&path).collect())
+        .unwrap_or_default()
+}
+
+/// The plugin executable of a command
+pub fn find(command: &str) -> Option<PathBuf> {
+    let name = executable_name(command);
+    search_path()
+        .into_iter()
+        .map(|dir| dir.join(&name))
+        .find(|path| path.is_file())
+}
+
+/// The commands of the plugins on PATH, for --help
+pub fn list() -> Vec<String> {
+    let mut commands: Vec<String> = search_path()
+        .iter()
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let name = name.strip_suffix(std::env::consts::EXE_SUFFIX)?;
+            Some(name.strip_prefix(PREFIX)?.to_string())
+        })
+        .filter(|command| !command.is_empty())
+        .collect();
+    commands.sort();
+    commands.dedup();
+    commands
+}
+
+/// The global options as the plugin sees them
+pub fn env(cli: &Cli) -> Vec<(String, String)> {
+    let project_dir = utils::get_project_dir(cli.project_dir.as_deref());
+    let build_dir = utils::get_build_dir(cli.build_dir.as_deref(), &project_dir);
+    let mut vars = vec![
+        (
+            "IDF_RS_PROJECT_DIR".to_string(),
+            project_dir.to_string_lossy().to_string(),
+        ),
+        (
+            "IDF_RS_BUILD_DIR".to_string(),
+            build_dir.to_string_lossy().to_string(),
+        ),
+        (
+            "IDF_RS_VERBOSE".to_string(),
+            if cli.verbose { "1" } else { "0" }.to_string(),
+        ),
+        (
+            "IDF_RS_OUTPUT_FORMAT".to_string(),
+            if output::is_json() { "json" } else { "text" }.to_string(),
+        ),
+    ];
+    if let Ok(exe) = std::env::current_exe() {
+        vars.push(("IDF_RS".to_string(), exe.to_string_lossy().to_string()));
+    }
+    if let Some(port) = &cli.port {
+        vars.push(("IDF_RS_PORT".to_string(), port.clone()));
+    }
+    if let Some(baud) = cli.baud {
+        vars.push(("IDF_RS_BAUD".to_string(), baud.to_string()));
+    }
+    if let Some(generator) = &cli.generator {
+        vars.push(("IDF_RS_GENERATOR".to_string(), generator.clone()));
+    }
+    if let Some(jobs) = cli.jobs {
+        vars.push(("IDF_RS_JOBS".to_string(), jobs.to_string()));
+    }
+    if !cli.define_cache_entry.is_empty() {
+        // One entry per line, entries like SDKCONFIG_DEFAULTS contain `;` themselves
+        vars.push((
+            "IDF_RS_DEFINE_CACHE_ENTRY".to_string(),
+            cli.define_cache_entry.join("\n"),
+        ));
+    }
+    vars
+}
+
+/// Run a plugin, replacing idf-rs where the platform allows it. Returns the plugin's
+/// exit code otherwise.
+pub fn run(plugin: &Path, args: &[String], cli: &Cli) -> Result<i32> {
+    let mut command = std::process::Command::new(plugin);
+    command
+        .args(args)
+        .envs(env(cli))
+        .envs(utils::CommandEnv::new().vars());
+    if cli.verbose {
+        println!("Running plugin: {} {}", plugin.display(), args.join(" "));
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        let error = command.exec();
+        Err(anyhow::anyhow!(
+            "Failed to run {}: {}",
+            plugin.display(),
+            error
+        ))
+    }
+
+    #[cfg(not(unix))]
+    {
+        let status = command
+            .status()
+            .map_err(|e| anyhow::anyhow!("Failed to run {}: {}", plugin.display(), e))?;
+        Ok(status.code().unwrap_or(1))
+    }
+}
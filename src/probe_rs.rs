@@ -0,0 +1,78 @@
+use crate::commands::flash;
+use anyhow::Result;
+use std::path::Path;
+
+/// Targets probe-rs can program and debug over USB-JTAG or an external probe
+const SUPPORTED_TARGETS: [&str; 7] = [
+    "esp32", "esp32s2", "esp32s3", "esp32c2", "esp32c3", "esp32c6", "esp32h2",
+];
+
+/// probe-rs executable, $IDF_RS_PROBE_RS or probe-rs from PATH
+pub fn program() -> String {
+    std::env::var("IDF_RS_PROBE_RS").unwrap_or_else(|_| "probe-rs".to_string())
+}
+
+/// probe-rs uses the ESP-IDF target names as chip names
+pub fn chip(target: &str) -> Result<&str> {
+    if SUPPORTED_TARGETS.contains(&target) {
+        Ok(target)
+    } else {
+        Err(anyhow::anyhow!(
+            "probe-rs doesn't support {}, use OpenOCD and esptool instead",
+            target
+        ))
+    }
+}
+
+/// Arguments writing the app ELF as an ESP-IDF image, together with the project's
+/// bootloader and partition table from flasher_args.json
+pub fn download_args(target: &str, build_dir: &Path, elf: &Path) -> Result<Vec<String>> {
+    let mut args = vec![
+        "download".to_string(),
+        "--chip".to_string(),
+        chip(target)?.to_string(),
+        "--binary-format".to_string(),
+        "idf".to_string(),
+    ];
+    let flasher_args = flash::load_flasher_args(build_dir)?;
+    for (key, option) in [
+        ("bootloader", "--idf-bootloader"),
+        ("partition-table", "--idf-partition-table"),
+    ] {
+        if let Some(file) = flasher_args[key]["file"].as_str() {
+            args.push(option.to_string());
+            args.push(build_dir.join(file).to_string_lossy().to_string());
+        }
+    }
+    args.push(elf.to_string_lossy().to_string());
+    Ok(args)
+}
+
+pub fn reset_args(target: &str) -> Result<Vec<String>> {
+    Ok(vec![
+        "reset".to_string(),
+        "--chip".to_string(),
+        chip(target)?.to_string(),
+    ])
+}
+
+/// Arguments attaching to the running app and printing its RTT and semihosting output
+pub fn attach_args(target: &str, elf: &Path) -> Result<Vec<String>> {
+    Ok(vec![
+        "attach".to_string(),
+        "--chip".to_string(),
+        chip(target)?.to_string(),
+        elf.to_string_lossy().to_string(),
+    ])
+}
+
+/// Arguments running the probe-rs GDB server on the port OpenOCD would use
+pub fn gdb_server_args(target: &str, port: u16) -> Result<Vec<String>> {
+    Ok(vec![
+        "gdb".to_string(),
+        "--chip".to_string(),
+        chip(target)?.to_string(),
+        "--gdb-connection-string".to_string(),
+        format!("127.0.0.1:{}", port),
+    ])
+}
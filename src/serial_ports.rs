@@ -0,0 +1,709 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// USB VID:PIDs of Espressif's USB-Serial-JTAG/USB-OTG (any PID) and of the USB-UART
+/// bridges found on ESP development boards
+const ESP_USB_IDS: &[(u16, Option<u16>, &str)] = &[
+    (0x303a, None, "Espressif USB"),
+    (0x10c4, Some(0xea60), "CP210x"),
+    (0x10c4, Some(0xea70), "CP2105"),
+    (0x1a86, Some(0x7523), "CH340"),
+    (0x1a86, Some(0x55d3), "CH343"),
+    (0x1a86, Some(0x55d4), "CH9102"),
+    (0x0403, Some(0x6001), "FT232R"),
+    (0x0403, Some(0x6010), "FT2232H"),
+    (0x0403, Some(0x6011), "FT4232H"),
+    (0x0403, Some(0x6014), "FT232H"),
+    (0x0403, Some(0x6015), "FT231X"),
+];
+
+/// A USB serial port attached to the host
+#[derive(Debug, Clone, Default)]
+pub struct SerialPortInfo {
+    /// Device name used to open the port (e.g. /dev/ttyUSB0)
+    pub port: String,
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+    pub serial_number: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    /// Stable /dev/serial/by-id path pointing to the port
+    pub by_id: Option<PathBuf>,
+}
+
+impl SerialPortInfo {
+    /// Human readable description of the attached adapter
+    pub fn description(&self) -> String {
+        match (&self.manufacturer, &self.product) {
+            (Some(manufacturer), Some(product)) => format!("{} {}", manufacturer, product),
+            (Some(name), None) | (None, Some(name)) => name.clone(),
+            (None, None) => String::new(),
+        }
+    }
+
+    /// USB VID:PID of the adapter as hex, e.g. 10c4:ea60
+    pub fn vid_pid(&self) -> Option<String> {
+        Some(format!("{:04x}:{:04x}", self.vid?, self.pid?))
+    }
+
+    /// The ESP USB interface or USB-UART bridge behind the port, by its VID:PID
+    pub fn adapter(&self) -> Option<&'static str> {
+        let (vid, pid) = (self.vid?, self.pid?);
+        ESP_USB_IDS
+            .iter()
+            .find(|(known_vid, known_pid, _)| {
+                *known_vid == vid && known_pid.is_none_or(|known_pid| known_pid == pid)
+            })
+            .map(|(_, _, name)| *name)
+    }
+
+    /// Whether an ESP chip may be behind the port. Ports without USB descriptors (macOS)
+    /// are already limited to the USB-UART names.
+    pub fn is_esp_candidate(&self) -> bool {
+        self.vid.is_none() || self.adapter().is_some()
+    }
+
+    /// Check whether the given identifier (serial number or by-id path) refers to this port
+    pub fn matches_device_id(&self, id: &str) -> bool {
+        if self.serial_number.as_deref() == Some(id) {
+            return true;
+        }
+
+        match &self.by_id {
+            Some(by_id) => {
+                by_id == Path::new(id)
+                    || by_id
+                        .file_name()
+                        .map(|name| name.to_string_lossy() == id)
+                        .unwrap_or(false)
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn read_sysfs_attr(dir: &Path, name: &str) -> Option<String> {
+    std::fs::read_to_string(dir.join(name))
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// List USB serial ports using sysfs and the udev /dev/serial/by-id links
+#[cfg(target_os = "linux")]
+pub fn available_ports() -> Result<Vec<SerialPortInfo>> {
+    let by_id_links: Vec<(PathBuf, PathBuf)> = std::fs::read_dir("/dev/serial/by-id")
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let link = entry.path();
+            let target = link.canonicalize().ok()?;
+            Some((link, target))
+        })
+        .collect();
+
+    let mut ports = Vec::new();
+    for entry in std::fs::read_dir("/sys/class/tty")? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+
+        // Only devices backed by real hardware have a device link
+        let device = match entry.path().join("device").canonicalize() {
+            Ok(device) => device,
+            Err(_) => continue,
+        };
+
+        // Walk up to the USB device that carries the descriptor attributes
+        let usb_device = device
+            .ancestors()
+            .find(|dir| dir.join("idVendor").exists())
+            .map(|dir| dir.to_path_buf());
+        let usb_device = match usb_device {
+            Some(usb_device) => usb_device,
+            None => continue,
+        };
+
+        let port = format!("/dev/{}", name);
+        let by_id = by_id_links
+            .iter()
+            .find(|(_, target)| target == Path::new(&port))
+            .map(|(link, _)| link.clone());
+
+        let read_hex = |attr: &str| {
+            read_sysfs_attr(&usb_device, attr)
+                .and_then(|value| u16::from_str_radix(&value, 16).ok())
+        };
+
+        ports.push(SerialPortInfo {
+            port,
+            vid: read_hex("idVendor"),
+            pid: read_hex("idProduct"),
+            serial_number: read_sysfs_attr(&usb_device, "serial"),
+            manufacturer: read_sysfs_attr(&usb_device, "manufacturer"),
+            product: read_sysfs_attr(&usb_device, "product"),
+            by_id,
+        });
+    }
+
+    ports.sort_by(|a, b| a.port.cmp(&b.port));
+    Ok(ports)
+}
+
+/// List USB serial ports. macOS encodes the adapter serial number in the device name.
+#[cfg(target_os = "macos")]
+pub fn available_ports() -> Result<Vec<SerialPortInfo>> {
+    let mut ports: Vec<SerialPortInfo> = std::fs::read_dir("/dev")?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter_map(|name| {
+            let serial = name
+                .strip_prefix("cu.usbmodem")
+                .or_else(|| name.strip_prefix("cu.usbserial-"))
+                .or_else(|| name.strip_prefix("cu.wchusbserial"))
+                .or_else(|| name.strip_prefix("cu.SLAB_USBtoUART"))?;
+
+            Some(SerialPortInfo {
+                port: format!("/dev/{}", name),
+                serial_number: Some(serial.to_string()).filter(|s| !s.is_empty()),
+                ..Default::default()
+            })
+        })
+        .collect();
+
+    ports.sort_by(|a, b| a.port.cmp(&b.port));
+    Ok(ports)
+}
+
+/// Values named `value` under the keys of `reg query` output, as (key, data) pairs
+#[cfg(any(windows, test))]
+fn parse_reg_query(output: &str, value: &str) -> Vec<(String, String)> {
+    let mut values = Vec::new();
+    let mut key = "";
+    for line in output.lines() {
+        if line.starts_with("HKEY_") {
+            key = line.trim();
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        if fields.next() == Some(value)
+            && fields.next().is_some_and(|kind| kind.starts_with("REG_"))
+        {
+            values.push((key.to_string(), fields.collect::<Vec<_>>().join(" ")));
+        }
+    }
+    values
+}
+
+/// VID, PID and serial number of a USB device instance key, e.g.
+/// `...\Enum\USB\VID_10C4&PID_EA60\0001` or `...\Enum\FTDIBUS\VID_0403+PID_6001+A50285BIA\0000`.
+/// Interfaces of composite devices (`&MI_xx`) have a generated instance ID, not a serial.
+#[cfg(any(windows, test))]
+fn parse_usb_instance(key: &str) -> Option<(u16, u16, Option<String>)> {
+    let mut parts = key.rsplit('\\');
+    let instance = parts.next()?;
+    let device = parts.next()?;
+    let hex_after = |prefix: &str| {
+        let start = device.find(prefix)? + prefix.len();
+        u16::from_str_radix(device.get(start..start + 4)?, 16).ok()
+    };
+    let (vid, pid) = (hex_after("VID_")?, hex_after("PID_")?);
+
+    let serial = match device.split('+').nth(2) {
+        // FTDI's driver puts the serial number into the device ID
+        Some(serial) => Some(serial.to_string()),
+        None if !device.contains("&MI_") && !instance.contains('&') => Some(instance.to_string()),
+        None => None,
+    };
+    Some((vid, pid, serial.filter(|serial| !serial.is_empty())))
+}
+
+/// Run `reg query` and return the values named `value` below `key`
+#[cfg(windows)]
+fn reg_query(key: &str, value: &str) -> Vec<(String, String)> {
+    std::process::Command::new("reg")
+        .args(["query", key, "/s", "/v", value])
+        .output()
+        .map(|output| parse_reg_query(&String::from_utf8_lossy(&output.stdout), value))
+        .unwrap_or_default()
+}
+
+/// List the COM ports present in the registry's SERIALCOMM map, with the USB descriptors
+/// of the USB (and FTDI driver) device instances whose PortName they are
+#[cfg(windows)]
+pub fn available_ports() -> Result<Vec<SerialPortInfo>> {
+    let output = std::process::Command::new("reg")
+        .args(["query", r"HKLM\HARDWARE\DEVICEMAP\SERIALCOMM"])
+        .output()
+        .map_err(|e| anyhow::anyhow!("Failed to run reg query: {}", e))?;
+    let serialcomm = String::from_utf8_lossy(&output.stdout);
+
+    let mut port_names = Vec::new();
+    let mut friendly_names = Vec::new();
+    for bus in ["USB", "FTDIBUS"] {
+        let key = format!(r"HKLM\SYSTEM\CurrentControlSet\Enum\{}", bus);
+        port_names.extend(reg_query(&key, "PortName"));
+        friendly_names.extend(reg_query(&key, "FriendlyName"));
+    }
+
+    let mut ports = Vec::new();
+    for line in serialcomm.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        // Built-in UARTs (\Device\Serial0) can't have an ESP chip behind them
+        let [device, kind, port] = fields[..] else {
+            continue;
+        };
+        if !kind.starts_with("REG_") || device.starts_with(r"\Device\Serial") {
+            continue;
+        }
+
+        // PortName lives in the instance's "Device Parameters" subkey
+        let instance = port_names
+            .iter()
+            .find(|(_, name)| name.eq_ignore_ascii_case(port))
+            .map(|(key, _)| key.trim_end_matches(r"\Device Parameters"));
+        let usb = instance.and_then(parse_usb_instance);
+        let product = instance.and_then(|instance| {
+            friendly_names
+                .iter()
+                .find(|(key, _)| key == instance)
+                .map(|(_, name)| name.trim_end_matches(&format!(" ({})", port)).to_string())
+        });
+
+        ports.push(SerialPortInfo {
+            port: port.to_string(),
+            vid: usb.as_ref().map(|usb| usb.0),
+            pid: usb.as_ref().map(|usb| usb.1),
+            serial_number: usb.and_then(|usb| usb.2),
+            product,
+            ..Default::default()
+        });
+    }
+
+    ports.sort_by_key(|port| {
+        let number = port.port.trim_start_matches("COM").parse::<u32>().ok();
+        (number, port.port.clone())
+    });
+    Ok(ports)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn available_ports() -> Result<Vec<SerialPortInfo>> {
+    Err(anyhow::anyhow!(
+        "Serial port enumeration is not supported on this platform yet"
+    ))
+}
+
+/// The ports of `ports` an ESP chip may be connected to. Ports without USB descriptors
+/// (e.g. legacy COM ports) only count when no port has a known ESP VID:PID.
+fn esp_candidates(ports: Vec<SerialPortInfo>) -> Vec<SerialPortInfo> {
+    let (known, unknown): (Vec<_>, Vec<_>) = ports
+        .into_iter()
+        .filter(SerialPortInfo::is_esp_candidate)
+        .partition(|port| port.adapter().is_some());
+    if known.is_empty() {
+        unknown
+    } else {
+        known
+    }
+}
+
+/// The ports an ESP chip may be connected to
+pub fn candidate_ports() -> Result<Vec<SerialPortInfo>> {
+    Ok(esp_candidates(available_ports()?))
+}
+
+/// Resolve a USB serial number or by-id path to the port device name
+pub fn resolve_device_id(id: &str) -> Result<String> {
+    let ports = available_ports()?;
+    let matching: Vec<_> = ports
+        .iter()
+        .filter(|port| port.matches_device_id(id))
+        .collect();
+
+    match matching.as_slice() {
+        [port] => Ok(port.port.clone()),
+        [] => Err(anyhow::anyhow!(
+            "No serial port with device id '{}' found. Run 'idf-rs list-ports' to see connected devices.",
+            id
+        )),
+        _ => Err(anyhow::anyhow!(
+            "Device id '{}' matches multiple ports: {}",
+            id,
+            matching
+                .iter()
+                .map(|port| port.port.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+/// How long to wait for a remote serial server to accept a connection
+const REMOTE_CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(3);
+
+/// The connection to a device's serial port. esptool and idf_monitor open the port
+/// themselves (pyserial takes device names and socket:// or rfc2217:// URLs alike);
+/// this tells idf-rs what the connection can do.
+pub trait SerialTransport {
+    /// The port as given to the tools
+    fn name(&self) -> &str;
+    /// Whether the port can be opened now
+    fn is_available(&self) -> bool;
+    /// Whether the baud rate of the device side can be changed through the connection
+    fn supports_baud_change(&self) -> bool;
+    /// Whether the port may come back after it goes away, so the monitor can wait for it
+    fn can_reconnect(&self) -> bool;
+}
+
+/// A serial port of this host, e.g. /dev/ttyUSB0 or COM3
+pub struct LocalPort {
+    name: String,
+}
+
+impl SerialTransport for LocalPort {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn is_available(&self) -> bool {
+        let path = Path::new(&self.name);
+        !path.is_absolute() || path.exists()
+    }
+
+    fn supports_baud_change(&self) -> bool {
+        true
+    }
+
+    fn can_reconnect(&self) -> bool {
+        // Only ports with a device node can be watched for re-enumeration
+        Path::new(&self.name).is_absolute()
+    }
+}
+
+/// A serial port shared over TCP by another machine, e.g. with ser2net: raw
+/// ("socket://host:port") or with RFC 2217 control ("rfc2217://host:port"), which also
+/// carries baud rate changes and the DTR/RTS lines resetting the chip
+pub struct RemotePort {
+    name: String,
+    rfc2217: bool,
+    address: String,
+}
+
+impl SerialTransport for RemotePort {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn is_available(&self) -> bool {
+        use std::net::ToSocketAddrs;
+        self.address
+            .to_socket_addrs()
+            .into_iter()
+            .flatten()
+            .any(|address| {
+                std::net::TcpStream::connect_timeout(&address, REMOTE_CONNECT_TIMEOUT).is_ok()
+            })
+    }
+
+    fn supports_baud_change(&self) -> bool {
+        self.rfc2217
+    }
+
+    fn can_reconnect(&self) -> bool {
+        true
+    }
+}
+
+/// The transport of a port given with --port
+pub fn transport(port: &str) -> Result<Box<dyn SerialTransport>> {
+    let remote = [("socket://", false), ("rfc2217://", true)]
+        .into_iter()
+        .find_map(|(scheme, rfc2217)| Some((port.strip_prefix(scheme)?, rfc2217)));
+    let Some((rest, rfc2217)) = remote else {
+        return Ok(Box::new(LocalPort {
+            name: port.to_string(),
+        }));
+    };
+
+    // pyserial URLs may carry options after the address, e.g. "?logging=debug"
+    let address = rest.split(['?', '/']).next().unwrap_or_default();
+    let valid = address
+        .rsplit_once(':')
+        .is_some_and(|(host, tcp_port)| !host.is_empty() && tcp_port.parse::<u16>().is_ok());
+    if !valid {
+        return Err(anyhow::anyhow!(
+            "Invalid remote port '{}', expected socket://host:port or rfc2217://host:port",
+            port
+        ));
+    }
+    Ok(Box::new(RemotePort {
+        name: port.to_string(),
+        rfc2217,
+        address: address.to_string(),
+    }))
+}
+
+/// Lock files of the ports this process holds, open and locked, with the number of
+/// holders of each
+static HELD_LOCKS: Mutex<Vec<(PathBuf, std::fs::File, usize)>> = Mutex::new(Vec::new());
+
+/// Locks kept until the end of a command chain, None outside of one
+static CHAIN_LOCKS: Mutex<Option<Vec<PortLock>>> = Mutex::new(None);
+
+/// Lock file of a port: locks/<port>.lock in the user's runtime directory, the same for
+/// a /dev/serial/by-id link and the device it points to
+fn lock_path(port: &str) -> Result<PathBuf> {
+    let port = Path::new(port)
+        .canonicalize()
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or_else(|_| port.to_string());
+    let name: String = port
+        .trim_start_matches('/')
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let dir = crate::utils::user_runtime_dir()?.join("locks");
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir.join(format!("{}.lock", name)))
+}
+
+fn open_lock_file(path: &Path) -> std::io::Result<std::fs::File> {
+    let mut options = std::fs::OpenOptions::new();
+    options.read(true).write(true).create(true).truncate(false);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)
+}
+
+/// Whether another process holds the lock of the port
+pub fn locked_elsewhere(port: &str) -> bool {
+    let Ok(path) = lock_path(port) else {
+        return false;
+    };
+    if HELD_LOCKS
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|(held, ..)| *held == path)
+    {
+        return false;
+    }
+    // Taking the lock for a moment tells whether somebody else has it
+    open_lock_file(&path)
+        .is_ok_and(|file| matches!(file.try_lock(), Err(std::fs::TryLockError::WouldBlock)))
+}
+
+/// Advisory lock of a serial port, so two idf-rs processes don't use the same device
+/// at once: an flock (LockFileEx on Windows) on the port's lock file, which the system
+/// releases when the holder exits. The file names the PID and command of the holder.
+/// Released when dropped.
+#[derive(Debug)]
+pub struct PortLock {
+    path: PathBuf,
+}
+
+impl PortLock {
+    /// Lock `port` for `command` (e.g. "flash"), named to other processes wanting it
+    pub fn acquire(port: &str, command: &str) -> Result<Self> {
+        let path = lock_path(port)?;
+        let mut held = HELD_LOCKS.lock().unwrap();
+        if let Some((.., holders)) = held.iter_mut().find(|(held, ..)| *held == path) {
+            *holders += 1;
+            return Ok(Self { path });
+        }
+
+        let mut file = open_lock_file(&path).map_err(|e| {
+            anyhow::anyhow!("Failed to open the lock file {}: {}", path.display(), e)
+        })?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(std::fs::TryLockError::WouldBlock) => {
+                let content = std::fs::read_to_string(&path).unwrap_or_default();
+                let mut lines = content.lines();
+                return Err(anyhow::anyhow!(
+                    "Serial port {} is in use by another idf-rs (PID {}: {}). Wait for it to finish or stop it",
+                    port,
+                    lines.next().unwrap_or("unknown"),
+                    lines.next().unwrap_or("unknown command"),
+                ));
+            }
+            Err(std::fs::TryLockError::Error(e)) => {
+                return Err(anyhow::anyhow!("Failed to lock {}: {}", path.display(), e))
+            }
+        }
+
+        {
+            use std::io::Write;
+            file.set_len(0)?;
+            write!(file, "{}\n{}\n", std::process::id(), command)?;
+        }
+        held.push((path.clone(), file, 1));
+        Ok(Self { path })
+    }
+
+    /// Keep the lock until the end of the running command chain, if there is one
+    pub fn keep_for_chain(self) {
+        if let Some(locks) = CHAIN_LOCKS.lock().unwrap().as_mut() {
+            locks.push(self);
+        }
+    }
+}
+
+impl Drop for PortLock {
+    fn drop(&mut self) {
+        let mut held = HELD_LOCKS.lock().unwrap();
+        let Some(index) = held.iter().position(|(path, ..)| *path == self.path) else {
+            return;
+        };
+        held[index].2 -= 1;
+        if held[index].2 == 0 {
+            // The file stays, closing it releases the lock. Removing it could split the
+            // lock between processes holding the old file and one creating a new one.
+            held.remove(index);
+        }
+    }
+}
+
+/// Command chain (e.g. "flash monitor") keeping the ports its commands locked until it
+/// ends, so no other idf-rs takes a port between two of the commands
+pub struct LockChain;
+
+impl LockChain {
+    pub fn start() -> Self {
+        *CHAIN_LOCKS.lock().unwrap() = Some(Vec::new());
+        Self
+    }
+}
+
+impl Drop for LockChain {
+    fn drop(&mut self) {
+        let locks = CHAIN_LOCKS.lock().unwrap().take();
+        drop(locks);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_windows_registry_ports() {
+        let output = "\r\nHKEY_LOCAL_MACHINE\\SYSTEM\\CurrentControlSet\\Enum\\USB\\VID_10C4&PID_EA60\\0001\\Device Parameters\r\n    PortName    REG_SZ    COM3\r\n\r\n\
+            HKEY_LOCAL_MACHINE\\SYSTEM\\CurrentControlSet\\Enum\\USB\\VID_303A&PID_1001&MI_00\\6&2c4c1b8e&0&0000\\Device Parameters\r\n    PortName    REG_SZ    COM5\r\n\r\n\
+            End of search: 2 match(es) found.\r\n";
+        let values = parse_reg_query(output, "PortName");
+        assert_eq!(values.len(), 2);
+        assert_eq!(values[1].1, "COM5");
+        assert!(values[0]
+            .0
+            .ends_with(r"VID_10C4&PID_EA60\0001\Device Parameters"));
+
+        let instance = values[0].0.trim_end_matches(r"\Device Parameters");
+        assert_eq!(
+            parse_usb_instance(instance),
+            Some((0x10c4, 0xea60, Some("0001".to_string())))
+        );
+        let composite = values[1].0.trim_end_matches(r"\Device Parameters");
+        assert_eq!(parse_usb_instance(composite), Some((0x303a, 0x1001, None)));
+        assert_eq!(
+            parse_usb_instance(
+                r"HKEY_LOCAL_MACHINE\SYSTEM\CurrentControlSet\Enum\FTDIBUS\VID_0403+PID_6001+A50285BIA\0000"
+            ),
+            Some((0x0403, 0x6001, Some("A50285BIA".to_string())))
+        );
+    }
+
+    #[test]
+    fn test_adapter() {
+        let port = |vid, pid| SerialPortInfo {
+            port: "/dev/ttyUSB0".to_string(),
+            vid: Some(vid),
+            pid: Some(pid),
+            ..Default::default()
+        };
+        assert_eq!(port(0x303a, 0x1001).adapter(), Some("Espressif USB"));
+        assert_eq!(port(0x10c4, 0xea60).adapter(), Some("CP210x"));
+        assert_eq!(port(0x10c4, 0xea60).vid_pid().as_deref(), Some("10c4:ea60"));
+        assert!(!port(0x2341, 0x0043).is_esp_candidate());
+        assert!(SerialPortInfo::default().is_esp_candidate());
+
+        let com = |name: &str| SerialPortInfo {
+            port: name.to_string(),
+            ..Default::default()
+        };
+        let ports = |ports: Vec<SerialPortInfo>| {
+            esp_candidates(ports)
+                .into_iter()
+                .map(|port| port.port)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            ports(vec![
+                com("COM1"),
+                port(0x10c4, 0xea60),
+                port(0x2341, 0x0043)
+            ]),
+            vec!["/dev/ttyUSB0"]
+        );
+        assert_eq!(ports(vec![com("COM1"), com("COM2")]), vec!["COM1", "COM2"]);
+    }
+
+    #[test]
+    fn test_transport() {
+        let local = transport("/dev/ttyUSB0").unwrap();
+        assert!(local.supports_baud_change());
+        assert!(local.can_reconnect());
+        assert!(!transport("COM3").unwrap().can_reconnect());
+
+        let raw = transport("socket://lab-pi:4000").unwrap();
+        assert_eq!(raw.name(), "socket://lab-pi:4000");
+        assert!(!raw.supports_baud_change());
+        assert!(raw.can_reconnect());
+        assert!(transport("rfc2217://192.168.1.20:4001?ign_set_control")
+            .unwrap()
+            .supports_baud_change());
+
+        assert!(transport("socket://lab-pi").is_err());
+        assert!(transport("rfc2217://:4000").is_err());
+    }
+
+    #[test]
+    fn test_port_lock() {
+        let port = format!("/dev/ttyIDFRSTEST{}", std::process::id());
+        let path = lock_path(&port).unwrap();
+        assert!(path.ends_with(format!("dev_ttyIDFRSTEST{}.lock", std::process::id())));
+
+        // This process may lock the port again, until the last holder drops it
+        let lock = PortLock::acquire(&port, "flash").unwrap();
+        let again = PortLock::acquire(&port, "monitor").unwrap();
+        assert!(!locked_elsewhere(&port));
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            format!("{}\nflash\n", std::process::id())
+        );
+        drop(lock);
+        drop(again);
+        assert!(!HELD_LOCKS
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|(held, ..)| *held == path));
+
+        // Another holder of the file lock, as another process would be
+        let other = open_lock_file(&path).unwrap();
+        other.try_lock().unwrap();
+        std::fs::write(&path, "1\nmonitor\n").unwrap();
+        assert!(locked_elsewhere(&port));
+        let error = PortLock::acquire(&port, "flash").unwrap_err().to_string();
+        assert!(error.contains("PID 1: monitor"), "{}", error);
+
+        // Its lock goes with it, whatever the file says
+        drop(other);
+        assert!(!locked_elsewhere(&port));
+        drop(PortLock::acquire(&port, "flash").unwrap());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,85 @@
+use crate::output;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Per-project state remembered between invocations, stored in the build directory
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ProjectState {
+    /// Serial port of the last successful flash or monitor
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<String>,
+    /// Baud rate explicitly used for the last successful flash, for the esptool commands
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flash_baud: Option<u32>,
+    /// Console baud rate of the last successful monitor session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monitor_baud: Option<u32>,
+    /// CMake generator of the last successful configure step, kept when CMakeCache.txt
+    /// is deleted by `reconfigure`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generator: Option<String>,
+    /// Hash of the inputs of the last successful CMake configure step
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub configure_fingerprint: Option<String>,
+    /// `-D` cache entries of earlier configure steps, applied again like CMakeCache.txt does
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cache_entries: Vec<String>,
+    /// Compiler launcher idf-rs set in CMakeCache.txt (sccache or --compiler-launcher)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compiler_launcher: Option<String>,
+}
+
+pub fn get_state_path(build_dir: &Path) -> PathBuf {
+    build_dir.join("idf-rs-state.json")
+}
+
+impl ProjectState {
+    /// Load the state, falling back to defaults when it is missing or unreadable
+    pub fn load(build_dir: &Path) -> Self {
+        std::fs::read_to_string(get_state_path(build_dir))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, build_dir: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        std::fs::write(get_state_path(build_dir), content)?;
+        Ok(())
+    }
+}
+
+/// Baud rate of a serial session, flashing and the console running at their own rates
+#[derive(Debug, Clone, Copy)]
+pub enum SessionBaud {
+    Flash(Option<u32>),
+    Monitor(Option<u32>),
+}
+
+/// Remember the serial settings of a successful flash or monitor session
+pub fn record_serial_settings(build_dir: &Path, port: Option<&str>, baud: SessionBaud) {
+    let (flash_baud, monitor_baud) = match baud {
+        SessionBaud::Flash(baud) => (baud, None),
+        SessionBaud::Monitor(baud) => (None, baud),
+    };
+    // Don't create a build directory just to store the state
+    if !build_dir.exists() || (port.is_none() && flash_baud.is_none() && monitor_baud.is_none()) {
+        return;
+    }
+
+    let mut state = ProjectState::load(build_dir);
+    if let Some(port) = port {
+        state.port = Some(port.to_string());
+    }
+    if let Some(baud) = flash_baud {
+        state.flash_baud = Some(baud);
+    }
+    if let Some(baud) = monitor_baud {
+        state.monitor_baud = Some(baud);
+    }
+
+    if let Err(e) = state.save(build_dir) {
+        output::warning(format!("failed to save project state: {}", e));
+    }
+}
@@ -0,0 +1,164 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+pub const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// One recorded idf-rs invocation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Invocation {
+    /// Unix time the command finished
+    pub time: u64,
+    pub project: String,
+    pub command: String,
+    pub duration_ms: u64,
+    pub success: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure: Option<String>,
+    /// Compiler cache hits and misses during a build
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_hits: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_misses: Option<u64>,
+}
+
+/// The metrics store, a JSON line per invocation next to the global settings.
+/// It never leaves the machine.
+pub fn get_stats_path() -> Option<PathBuf> {
+    crate::config::get_global_settings_path()
+        .and_then(|path| path.parent().map(|dir| dir.join("stats.jsonl")))
+}
+
+pub fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+pub fn append(path: &Path, invocation: &Invocation) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    writeln!(file, "{}", serde_json::to_string(invocation)?)?;
+    Ok(())
+}
+
+/// Load the recorded invocations, skipping lines that can't be parsed
+pub fn load(path: &Path) -> Result<Vec<Invocation>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    Ok(std::fs::read_to_string(path)?
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Bucket a failed command by its error message
+pub fn failure_category(message: &str) -> &'static str {
+    let message = message.to_lowercase();
+    let contains_any = |words: &[&str]| words.iter().any(|word| message.contains(word));
+
+    if contains_any(&["serial", "port", "connect", "timed out", "no such device"]) {
+        "connection"
+    } else if contains_any(&["cmake", "sdkconfig", "kconfig", "configure"]) {
+        "configuration"
+    } else if contains_any(&["idf_path", "not found in path", "python", "toolchain"]) {
+        "environment"
+    } else if contains_any(&["command failed", "exit code"]) {
+        "tool"
+    } else {
+        "other"
+    }
+}
+
+/// Year, month and day of a day count since the Unix epoch
+pub fn civil_date(days: u64) -> (i64, u32, u32) {
+    // Howard Hinnant's days_from_civil inverse
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Summary of the invocations of one command
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CommandSummary {
+    pub runs: usize,
+    pub failures: usize,
+    pub total_ms: u64,
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+}
+
+impl CommandSummary {
+    pub fn add(&mut self, invocation: &Invocation) {
+        self.runs += 1;
+        if !invocation.success {
+            self.failures += 1;
+        }
+        self.total_ms += invocation.duration_ms;
+        self.cache_hits += invocation.cache_hits.unwrap_or(0);
+        self.cache_misses += invocation.cache_misses.unwrap_or(0);
+    }
+
+    pub fn average_ms(&self) -> u64 {
+        self.total_ms / self.runs.max(1) as u64
+    }
+
+    /// Cache hit rate in percent, if any compilations were recorded
+    pub fn cache_hit_rate(&self) -> Option<f64> {
+        let total = self.cache_hits + self.cache_misses;
+        (total > 0).then(|| self.cache_hits as f64 * 100.0 / total as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let invocation = |success, duration_ms, hits| Invocation {
+            time: 0,
+            project: "/p".to_string(),
+            command: "build".to_string(),
+            duration_ms,
+            success,
+            failure: None,
+            cache_hits: hits,
+            cache_misses: hits.map(|_| 10),
+        };
+
+        let mut summary = CommandSummary::default();
+        summary.add(&invocation(true, 1000, Some(30)));
+        summary.add(&invocation(false, 3000, None));
+        assert_eq!(summary.runs, 2);
+        assert_eq!(summary.failures, 1);
+        assert_eq!(summary.average_ms(), 2000);
+        assert_eq!(summary.cache_hit_rate(), Some(75.0));
+
+        assert_eq!(
+            failure_category("Failed to connect to ESP32: No serial data received"),
+            "connection"
+        );
+        assert_eq!(
+            failure_category("Command failed with exit code: Some(1)"),
+            "tool"
+        );
+        assert_eq!(civil_date(0), (1970, 1, 1));
+        assert_eq!(civil_date(20376), (2025, 10, 15));
+    }
+}
@@ -0,0 +1,456 @@
+use anyhow::Result;
+use serde_json::{Map, Number, Value};
+
+struct Cursor<'a> {
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+    text: &'a str,
+    line: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(text: &'a str) -> Self {
+        Cursor {
+            chars: text.char_indices().peekable(),
+            text,
+            line: 1,
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|(_, c)| *c)
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next().map(|(_, c)| c);
+        if c == Some('\n') {
+            self.line += 1;
+        }
+        c
+    }
+
+    fn rest_starts_with(&mut self, prefix: &str) -> bool {
+        match self.chars.peek() {
+            Some((index, _)) => self.text[*index..].starts_with(prefix),
+            None => false,
+        }
+    }
+
+    fn error(&self, message: &str) -> anyhow::Error {
+        anyhow::anyhow!("line {}: {}", self.line, message)
+    }
+
+    /// Skip spaces and tabs on the current line
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ') | Some('\t')) {
+            self.next();
+        }
+    }
+
+    /// Skip whitespace, newlines and comments (inside arrays)
+    fn skip_whitespace_and_comments(&mut self) {
+        loop {
+            match self.peek() {
+                Some(c) if c.is_whitespace() => {
+                    self.next();
+                }
+                Some('#') => self.skip_comment(),
+                _ => break,
+            }
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == '\n' {
+                break;
+            }
+            self.next();
+        }
+    }
+
+    /// Expect the end of a line, allowing a trailing comment
+    fn expect_line_end(&mut self) -> Result<()> {
+        self.skip_spaces();
+        match self.peek() {
+            Some('#') => self.skip_comment(),
+            Some('\r') | Some('\n') | None => {}
+            Some(c) => return Err(self.error(&format!("unexpected character '{}'", c))),
+        }
+        Ok(())
+    }
+
+    fn parse_basic_string(&mut self) -> Result<String> {
+        let multiline = self.rest_starts_with("\"\"\"");
+        let quotes = if multiline { 3 } else { 1 };
+        for _ in 0..quotes {
+            self.next();
+        }
+        // A newline right after the opening delimiter is trimmed
+        if multiline && self.peek() == Some('\n') {
+            self.next();
+        }
+
+        let mut value = String::new();
+        loop {
+            if multiline && self.rest_starts_with("\"\"\"") {
+                for _ in 0..3 {
+                    self.next();
+                }
+                return Ok(value);
+            }
+
+            match self.next() {
+                Some('"') if !multiline => return Ok(value),
+                Some('\\') => match self.next() {
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some('r') => value.push('\r'),
+                    Some('"') => value.push('"'),
+                    Some('\\') => value.push('\\'),
+                    Some('u') => {
+                        let code: String = (0..4).filter_map(|_| self.next()).collect();
+                        let c = u32::from_str_radix(&code, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| self.error("invalid unicode escape"))?;
+                        value.push(c);
+                    }
+                    Some('\n') if multiline => {
+                        // Line ending backslash trims the following whitespace
+                        while matches!(self.peek(), Some(c) if c.is_whitespace()) {
+                            self.next();
+                        }
+                    }
+                    _ => return Err(self.error("invalid escape sequence")),
+                },
+                Some('\n') if !multiline => return Err(self.error("unterminated string")),
+                Some(c) => value.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn parse_literal_string(&mut self) -> Result<String> {
+        let multiline = self.rest_starts_with("'''");
+        let quotes = if multiline { 3 } else { 1 };
+        for _ in 0..quotes {
+            self.next();
+        }
+        if multiline && self.peek() == Some('\n') {
+            self.next();
+        }
+
+        let mut value = String::new();
+        loop {
+            if multiline && self.rest_starts_with("'''") {
+                for _ in 0..3 {
+                    self.next();
+                }
+                return Ok(value);
+            }
+
+            match self.next() {
+                Some('\'') if !multiline => return Ok(value),
+                Some('\n') if !multiline => return Err(self.error("unterminated string")),
+                Some(c) => value.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn parse_key_part(&mut self) -> Result<String> {
+        self.skip_spaces();
+        match self.peek() {
+            Some('"') => self.parse_basic_string(),
+            Some('\'') => self.parse_literal_string(),
+            _ => {
+                let mut key = String::new();
+                while let Some(c) = self.peek() {
+                    if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                        key.push(c);
+                        self.next();
+                    } else {
+                        break;
+                    }
+                }
+                if key.is_empty() {
+                    return Err(self.error("expected a key"));
+                }
+                Ok(key)
+            }
+        }
+    }
+
+    /// Parse a possibly dotted key such as `a.b."c d"`
+    fn parse_key(&mut self) -> Result<Vec<String>> {
+        let mut parts = vec![self.parse_key_part()?];
+        loop {
+            self.skip_spaces();
+            if self.peek() != Some('.') {
+                return Ok(parts);
+            }
+            self.next();
+            parts.push(self.parse_key_part()?);
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value> {
+        self.skip_spaces();
+        match self.peek() {
+            Some('"') => Ok(Value::String(self.parse_basic_string()?)),
+            Some('\'') => Ok(Value::String(self.parse_literal_string()?)),
+            Some('[') => self.parse_array(),
+            Some('{') => self.parse_inline_table(),
+            Some(_) => {
+                let mut token = String::new();
+                while let Some(c) = self.peek() {
+                    if c.is_whitespace() || matches!(c, ',' | ']' | '}' | '#') {
+                        break;
+                    }
+                    token.push(c);
+                    self.next();
+                }
+                self.parse_bare_value(&token)
+            }
+            None => Err(self.error("expected a value")),
+        }
+    }
+
+    fn parse_bare_value(&self, token: &str) -> Result<Value> {
+        match token {
+            "true" => return Ok(Value::Bool(true)),
+            "false" => return Ok(Value::Bool(false)),
+            _ => {}
+        }
+
+        let digits = token.replace('_', "");
+        let integer = if let Some(hex) = digits.strip_prefix("0x") {
+            i64::from_str_radix(hex, 16).ok()
+        } else if let Some(octal) = digits.strip_prefix("0o") {
+            i64::from_str_radix(octal, 8).ok()
+        } else if let Some(binary) = digits.strip_prefix("0b") {
+            i64::from_str_radix(binary, 2).ok()
+        } else {
+            digits.parse::<i64>().ok()
+        };
+        if let Some(integer) = integer {
+            return Ok(Value::Number(integer.into()));
+        }
+
+        if let Some(float) = digits.parse::<f64>().ok().and_then(Number::from_f64) {
+            return Ok(Value::Number(float));
+        }
+
+        // Dates and times are kept as strings
+        if token.starts_with(|c: char| c.is_ascii_digit()) && token.contains(['-', ':']) {
+            return Ok(Value::String(token.to_string()));
+        }
+
+        Err(self.error(&format!("invalid value '{}'", token)))
+    }
+
+    fn parse_array(&mut self) -> Result<Value> {
+        self.next();
+        let mut items = Vec::new();
+        loop {
+            self.skip_whitespace_and_comments();
+            if self.peek() == Some(']') {
+                self.next();
+                return Ok(Value::Array(items));
+            }
+
+            items.push(self.parse_value()?);
+
+            self.skip_whitespace_and_comments();
+            match self.next() {
+                Some(',') => {}
+                Some(']') => return Ok(Value::Array(items)),
+                _ => return Err(self.error("expected ',' or ']' in array")),
+            }
+        }
+    }
+
+    fn parse_inline_table(&mut self) -> Result<Value> {
+        self.next();
+        let mut table = Map::new();
+        self.skip_spaces();
+        if self.peek() == Some('}') {
+            self.next();
+            return Ok(Value::Object(table));
+        }
+
+        loop {
+            let key = self.parse_key()?;
+            self.skip_spaces();
+            if self.next() != Some('=') {
+                return Err(self.error("expected '=' after key"));
+            }
+            let value = self.parse_value()?;
+            insert(&mut table, &key, value).map_err(|e| self.error(&e))?;
+
+            self.skip_spaces();
+            match self.next() {
+                Some(',') => {}
+                Some('}') => return Ok(Value::Object(table)),
+                _ => return Err(self.error("expected ',' or '}' in inline table")),
+            }
+        }
+    }
+}
+
+/// Get (creating if needed) the table at `path`. For arrays of tables, the last element is used.
+fn table_at<'a>(
+    root: &'a mut Map<String, Value>,
+    path: &[String],
+) -> std::result::Result<&'a mut Map<String, Value>, String> {
+    let mut table = root;
+    for part in path {
+        let entry = table
+            .entry(part.clone())
+            .or_insert_with(|| Value::Object(Map::new()));
+        table = match entry {
+            Value::Object(map) => map,
+            Value::Array(items) => match items.last_mut() {
+                Some(Value::Object(map)) => map,
+                _ => return Err(format!("'{}' is not a table", part)),
+            },
+            _ => return Err(format!("'{}' is not a table", part)),
+        };
+    }
+    Ok(table)
+}
+
+fn insert(
+    table: &mut Map<String, Value>,
+    key: &[String],
+    value: Value,
+) -> std::result::Result<(), String> {
+    let (last, parents) = key.split_last().unwrap();
+    let table = table_at(table, parents)?;
+    if table.contains_key(last) {
+        return Err(format!("duplicate key '{}'", key.join(".")));
+    }
+    table.insert(last.clone(), value);
+    Ok(())
+}
+
+/// Parse a TOML document into a JSON value. Supports tables, arrays of tables,
+/// dotted keys, strings, numbers, booleans, arrays and inline tables; dates are
+/// kept as strings.
+pub fn parse(content: &str) -> Result<Value> {
+    let mut root = Map::new();
+    let mut current_path: Vec<String> = Vec::new();
+    let mut cursor = Cursor::new(content);
+
+    loop {
+        cursor.skip_whitespace_and_comments();
+        match cursor.peek() {
+            None => break,
+            Some('[') => {
+                cursor.next();
+                let array_of_tables = cursor.peek() == Some('[');
+                if array_of_tables {
+                    cursor.next();
+                }
+                let path = cursor.parse_key()?;
+                cursor.skip_spaces();
+                let closing = if array_of_tables { "]]" } else { "]" };
+                if !cursor.rest_starts_with(closing) {
+                    return Err(cursor.error(&format!("expected '{}'", closing)));
+                }
+                for _ in 0..closing.len() {
+                    cursor.next();
+                }
+                cursor.expect_line_end()?;
+
+                let (last, parents) = path.split_last().unwrap();
+                let parent = table_at(&mut root, parents).map_err(|e| cursor.error(&e))?;
+                if array_of_tables {
+                    let entry = parent
+                        .entry(last.clone())
+                        .or_insert_with(|| Value::Array(Vec::new()));
+                    match entry {
+                        Value::Array(items) => items.push(Value::Object(Map::new())),
+                        _ => return Err(cursor.error(&format!("'{}' is not an array", last))),
+                    }
+                } else {
+                    match parent.get(last) {
+                        Some(Value::Object(_)) | None => {
+                            parent
+                                .entry(last.clone())
+                                .or_insert_with(|| Value::Object(Map::new()));
+                        }
+                        Some(_) => return Err(cursor.error(&format!("'{}' is not a table", last))),
+                    }
+                }
+                current_path = path;
+            }
+            Some(_) => {
+                let key = cursor.parse_key()?;
+                cursor.skip_spaces();
+                if cursor.next() != Some('=') {
+                    return Err(cursor.error("expected '=' after key"));
+                }
+                let value = cursor.parse_value()?;
+                cursor.expect_line_end()?;
+
+                let table = table_at(&mut root, &current_path).map_err(|e| cursor.error(&e))?;
+                insert(table, &key, value).map_err(|e| cursor.error(&e))?;
+            }
+        }
+    }
+
+    Ok(Value::Object(root))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_document() {
+        let content = r#"
+# Shared settings
+target = "esp32s3"
+baud = 921_600
+defines = [
+    "FOO=1", # comment
+    'BAR=C:\path',
+]
+
+[cache]
+backend = "sccache"
+remote.url = "s3://bucket/prefix"
+inline = { enabled = true, ratio = 0.5 }
+
+[[device]]
+name = "bench-1"
+port = "/dev/ttyUSB0"
+
+[[device]]
+name = "bench-2"
+groups = ["bench", "ci"]
+"#;
+        let value = parse(content).unwrap();
+
+        assert_eq!(value["target"], json!("esp32s3"));
+        assert_eq!(value["baud"], json!(921600));
+        assert_eq!(value["defines"], json!(["FOO=1", "BAR=C:\\path"]));
+        assert_eq!(value["cache"]["remote"]["url"], json!("s3://bucket/prefix"));
+        assert_eq!(
+            value["cache"]["inline"],
+            json!({"enabled": true, "ratio": 0.5})
+        );
+        assert_eq!(value["device"][0]["name"], json!("bench-1"));
+        assert_eq!(value["device"][1]["groups"], json!(["bench", "ci"]));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("a = 1\na = 2\n").is_err());
+        assert!(parse("a = \"unterminated\n").is_err());
+        assert!(parse("a = 1 2\n").is_err());
+        assert!(parse("[table\n").is_err());
+    }
+}
@@ -1,15 +1,18 @@
 use anyhow::Result;
+use std::collections::HashMap;
 use std::env;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub const SUPPORTED_TARGETS: &[&str] = &[
+    "esp32", "esp32s2", "esp32s3", "esp32c2", "esp32c3", "esp32c6", "esp32h2", "esp32p4",
+];
 
 pub fn list_targets() {
     println!("Supported targets:");
-    let targets = [
-        "esp32", "esp32s2", "esp32s3", "esp32c2", "esp32c3", "esp32c6", "esp32h2", "esp32p4",
-    ];
-
-    for target in targets {
+    for target in SUPPORTED_TARGETS {
         println!("  {}", target);
     }
 }
@@ -20,6 +23,35 @@ pub fn get_idf_path() -> Result<PathBuf> {
         .map_err(|_| anyhow::anyhow!("IDF_PATH environment variable not set"))
 }
 
+/// Version of the ESP-IDF in $IDF_PATH, e.g. "v5.3.1"
+pub fn get_idf_version() -> Option<String> {
+    let idf_path = get_idf_path().ok()?;
+
+    // Release archives ship version.txt, git checkouts only have version.cmake
+    if let Ok(version) = std::fs::read_to_string(idf_path.join("version.txt")) {
+        return Some(version.trim().to_string());
+    }
+
+    let cmake = std::fs::read_to_string(idf_path.join("tools/cmake/version.cmake")).ok()?;
+    let component = |name: &str| {
+        cmake.lines().find_map(|line| {
+            line.trim()
+                .strip_prefix(&format!("set({} ", name))?
+                .trim_end_matches(')')
+                .trim()
+                .parse::<u32>()
+                .ok()
+        })
+    };
+
+    Some(format!(
+        "v{}.{}.{}",
+        component("IDF_VERSION_MAJOR")?,
+        component("IDF_VERSION_MINOR")?,
+        component("IDF_VERSION_PATCH")?
+    ))
+}
+
 pub fn get_project_dir(cli_project_dir: Option<&Path>) -> PathBuf {
     cli_project_dir
         .map(|p| p.to_path_buf())
@@ -32,11 +64,449 @@ pub fn get_build_dir(cli_build_dir: Option<&Path>, project_dir: &Path) -> PathBu
         .unwrap_or_else(|| project_dir.join("build"))
 }
 
+/// Directory of this user's idf-rs runtime files (locks, daemon token), readable by the
+/// user only: $XDG_RUNTIME_DIR/idf-rs, else idf-rs-<uid> in the temporary directory
+#[cfg(unix)]
+pub fn user_runtime_dir() -> Result<PathBuf> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+
+    // SAFETY: getuid has no preconditions
+    let uid = unsafe { libc::getuid() };
+    let dir = match env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        Some(runtime_dir) => PathBuf::from(runtime_dir).join("idf-rs"),
+        None => env::temp_dir().join(format!("idf-rs-{}", uid)),
+    };
+    match std::fs::DirBuilder::new().mode(0o700).create(&dir) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(anyhow::anyhow!("Failed to create {}: {}", dir.display(), e)),
+    }
+
+    // In the shared temporary directory another user could have made it first
+    let metadata = std::fs::symlink_metadata(&dir)?;
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.permissions().mode() & 0o077 != 0 {
+        return Err(anyhow::anyhow!(
+            "{} is not a directory of this user only, remove it or set XDG_RUNTIME_DIR",
+            dir.display()
+        ));
+    }
+    Ok(dir)
+}
+
+/// Directory of this user's idf-rs runtime files (locks, daemon token), in the user's
+/// own temporary directory
+#[cfg(not(unix))]
+pub fn user_runtime_dir() -> Result<PathBuf> {
+    let dir = env::temp_dir().join("idf-rs");
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", dir.display(), e))?;
+    Ok(dir)
+}
+
+/// Write a file only this user can read
+pub fn write_private_file(path: &Path, content: &str) -> Result<()> {
+    use std::io::Write;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options
+        .open(path)
+        .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))?;
+    file.write_all(content.as_bytes())?;
+    Ok(())
+}
+
+/// Target used by ESP-IDF when none is configured
+pub const DEFAULT_TARGET: &str = "esp32";
+
+/// Expand a build directory pattern such as `build.{target}` for the given target
+pub fn expand_build_dir_pattern(pattern: &str, project_dir: &Path, target: &str) -> PathBuf {
+    project_dir.join(pattern.replace("{target}", target))
+}
+
+/// Recursively collect files below `dir` matching `filter`, skipping hidden
+/// directories and the directories listed in `excluded_dirs`
+pub fn find_files(
+    dir: &Path,
+    excluded_dirs: &[&str],
+    filter: &dyn Fn(&Path) -> bool,
+    files: &mut Vec<PathBuf>,
+) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        if path.is_dir() {
+            if !name.starts_with('.') && !excluded_dirs.contains(&name.as_str()) {
+                find_files(&path, excluded_dirs, filter, files)?;
+            }
+        } else if filter(&path) {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Variables given with --env, applied to every external command
+static USER_ENV: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+/// Record the --env variables once the command line is parsed
+pub fn set_user_env(vars: &[(String, String)]) {
+    *USER_ENV.lock().unwrap() = vars.to_vec();
+}
+
+/// A variable of the external commands' environment: the --env value, else inherited
+pub fn env_var(name: &str) -> Option<String> {
+    USER_ENV
+        .lock()
+        .unwrap()
+        .iter()
+        .rev()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.clone())
+        .or_else(|| env::var(name).ok())
+}
+
+/// Environment of an external command: the inherited environment, the variables the
+/// command needs (e.g. ESPPORT for flashing) and finally the user's --env overrides.
+/// Commands get their own environment instead of idf-rs changing its own.
+#[derive(Debug, Clone, Default)]
+pub struct CommandEnv {
+    vars: Vec<(String, String)>,
+}
+
+impl CommandEnv {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.vars.push((key.into(), value.into()));
+    }
+
+    /// All variables to set, later entries win
+    pub fn vars(&self) -> Vec<(String, String)> {
+        let mut vars = self.vars.clone();
+        vars.extend(USER_ENV.lock().unwrap().iter().cloned());
+        vars
+    }
+
+    fn apply(&self, cmd: &mut Command, verbose: bool) {
+        for (key, value) in self.vars() {
+            if verbose {
+                println!("  with {}={}", key, display_env_value(&key, &value));
+            }
+            cmd.env(key, value);
+        }
+    }
+}
+
+/// Value of an environment variable as shown in verbose output, masked when the name
+/// suggests a credential (e.g. SCCACHE_REDIS_PASSWORD), since logs end up in CI output
+pub fn display_env_value<'a>(key: &str, value: &'a str) -> &'a str {
+    let key = key.to_uppercase();
+    if ["PASSWORD", "TOKEN", "KEY", "SECRET"]
+        .iter()
+        .any(|word| key.contains(word))
+    {
+        "***"
+    } else {
+        value
+    }
+}
+
+/// Log files of the tool output under <build>/log, like idf.py's
+pub const STDOUT_LOG: &str = "idf-rs_stdout.txt";
+pub const STDERR_LOG: &str = "idf-rs_stderr.txt";
+
+struct OutputLog {
+    dir: Option<PathBuf>,
+    /// Log files already started by this invocation, appended to by later commands
+    started: Vec<PathBuf>,
+}
+
+static OUTPUT_LOG: Mutex<OutputLog> = Mutex::new(OutputLog {
+    dir: None,
+    started: Vec::new(),
+});
+
+/// Tee the output of the external commands run from now on into `dir`, or let them
+/// write to the terminal directly (None, for interactive tools)
+pub fn set_output_log(dir: Option<&Path>) {
+    OUTPUT_LOG.lock().unwrap().dir = dir.map(Path::to_path_buf);
+}
+
+fn output_log_dir() -> Option<PathBuf> {
+    OUTPUT_LOG.lock().unwrap().dir.clone()
+}
+
+/// Open a log file, replacing the one of a previous invocation
+fn open_log(path: &Path) -> Result<std::fs::File> {
+    let mut log = OUTPUT_LOG.lock().unwrap();
+    let append = log.started.iter().any(|started| started == path);
+    if !append {
+        log.started.push(path.to_path_buf());
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    Ok(std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)?)
+}
+
+/// "[2024-05-01 12:00:00] " prefix of the logged lines
+fn log_timestamp() -> String {
+    let time = crate::stats::now();
+    let (year, month, day) = crate::stats::civil_date(time / crate::stats::SECONDS_PER_DAY);
+    let seconds = time % crate::stats::SECONDS_PER_DAY;
+    format!(
+        "[{}-{:02}-{:02} {:02}:{:02}:{:02}] ",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// How the status lines of a build tool's output are shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Progress {
+    /// Passed through like any other output
+    None,
+    /// Reported as build progress events
+    Events,
+    /// Reported as events and drawn as one progress bar on the terminal
+    Bar,
+}
+
+/// The progress bar replacing a status line, with the finished and total targets (or
+/// the percentage for Make), the elapsed time and the current file, fitted to the
+/// terminal width
+fn progress_bar(status: &crate::build_systems::BuildStatus, elapsed: Duration) -> String {
+    const BAR_WIDTH: usize = 30;
+    let width: usize = std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .unwrap_or(80);
+    let filled = BAR_WIDTH * status.percent as usize / 100;
+    let count = match status.targets {
+        Some((finished, total)) => format!("{}/{}", finished, total),
+        None => format!("{}%", status.percent),
+    };
+    let seconds = elapsed.as_secs();
+    let mut line = format!(
+        "[{}{}] {} {}:{:02} ",
+        "#".repeat(filled.min(BAR_WIDTH)),
+        " ".repeat(BAR_WIDTH.saturating_sub(filled)),
+        count,
+        seconds / 60,
+        seconds % 60
+    );
+    // The file being built rather than the action, e.g. "main.c.obj" of
+    // "Building C object esp-idf/main/CMakeFiles/__idf_main.dir/main.c.obj"
+    let description = status
+        .description
+        .rsplit_once(' ')
+        .map(|(_, last)| last.rsplit('/').next().unwrap_or(last))
+        .filter(|file| file.contains('.'))
+        .unwrap_or(status.description);
+    let room = width.saturating_sub(line.chars().count() + 1);
+    line.extend(description.chars().take(room));
+    line
+}
+
+/// Copy a stream of the child to the terminal as it comes and its lines to the log,
+/// annotating the compiler diagnostics for CI
+async fn tee(
+    stream: &str,
+    mut reader: impl tokio::io::AsyncRead + Unpin,
+    mut console: impl std::io::Write,
+    mut log: std::fs::File,
+    progress: Progress,
+) -> Result<()> {
+    use std::io::Write;
+    use tokio::io::AsyncReadExt;
+
+    let mut buffer = [0u8; 4096];
+    let mut pending = Vec::new();
+    let started = Instant::now();
+    let mut percent = None;
+    let mut bar_shown = false;
+    loop {
+        let read = reader.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        // With a progress bar the console gets complete lines, to tell status lines apart
+        if progress != Progress::Bar {
+            console.write_all(&buffer[..read])?;
+            console.flush()?;
+        }
+
+        pending.extend_from_slice(&buffer[..read]);
+        while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = pending.drain(..=end).collect();
+            log.write_all(log_timestamp().as_bytes())?;
+            log.write_all(&line)?;
+            let text = String::from_utf8_lossy(&line);
+            crate::output::tool_output(stream, text.trim_end());
+
+            let status = match progress {
+                Progress::None => None,
+                _ => crate::build_systems::parse_build_status(&text),
+            };
+            if let Some(status) = &status {
+                if percent != Some(status.percent) {
+                    percent = Some(status.percent);
+                    crate::output::emit(&crate::output::Event::BuildProgress {
+                        percent: status.percent,
+                        finished: status.targets.map(|(finished, _)| finished),
+                        total: status.targets.map(|(_, total)| total),
+                    });
+                }
+            }
+            if progress == Progress::Bar {
+                match status {
+                    Some(status) => {
+                        write!(
+                            console,
+                            "\r\x1b[K{}",
+                            progress_bar(&status, started.elapsed())
+                        )?;
+                        bar_shown = true;
+                    }
+                    None => {
+                        if bar_shown {
+                            console.write_all(b"\r\x1b[K")?;
+                            bar_shown = false;
+                        }
+                        console.write_all(&line)?;
+                    }
+                }
+                console.flush()?;
+            }
+
+            if let Some(annotation) = crate::annotations::annotate(&text) {
+                println!("{}", annotation);
+            }
+        }
+    }
+    if !pending.is_empty() {
+        log.write_all(log_timestamp().as_bytes())?;
+        log.write_all(&pending)?;
+        log.write_all(b"\n")?;
+        if progress == Progress::Bar {
+            console.write_all(&pending)?;
+        }
+    }
+    if bar_shown {
+        writeln!(console)?;
+    }
+    Ok(())
+}
+
+/// Run a command with its stdout and stderr streamed through idf-rs into the logs
+async fn run_logged(
+    cmd: Command,
+    program: &str,
+    args: &[&str],
+    log_dir: &Path,
+    progress: Progress,
+) -> Result<std::process::ExitStatus> {
+    use std::io::Write;
+
+    let mut stdout_log = open_log(&log_dir.join(STDOUT_LOG))?;
+    let stderr_log = open_log(&log_dir.join(STDERR_LOG))?;
+    writeln!(
+        stdout_log,
+        "{}$ {} {}",
+        log_timestamp(),
+        program,
+        args.join(" ")
+    )?;
+
+    let mut child = tokio::process::Command::from(cmd)
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Failed to execute {}: {}", program, e))?;
+
+    let stdout = child.stdout.take().unwrap();
+    let stderr = child.stderr.take().unwrap();
+    let (stdout_result, stderr_result) = tokio::join!(
+        tee("stdout", stdout, std::io::stdout(), stdout_log, progress),
+        tee(
+            "stderr",
+            stderr,
+            std::io::stderr(),
+            stderr_log,
+            Progress::None
+        )
+    );
+    let status = child.wait().await?;
+    stdout_result?;
+    stderr_result?;
+    Ok(status)
+}
+
 pub async fn run_command(
     program: &str,
     args: &[&str],
     current_dir: Option<&Path>,
     verbose: bool,
+) -> Result<()> {
+    run_command_with_env(program, args, current_dir, verbose, &CommandEnv::new()).await
+}
+
+pub async fn run_command_with_env(
+    program: &str,
+    args: &[&str],
+    current_dir: Option<&Path>,
+    verbose: bool,
+    env: &CommandEnv,
+) -> Result<()> {
+    run_tool(program, args, current_dir, verbose, env, Progress::None).await
+}
+
+/// Run a build tool like Ninja, showing its status lines as a progress bar on the
+/// terminal (unless verbose) and reporting them as build progress events
+pub async fn run_build_command(
+    program: &str,
+    args: &[&str],
+    current_dir: Option<&Path>,
+    verbose: bool,
+    env: &CommandEnv,
+) -> Result<()> {
+    use std::io::IsTerminal;
+
+    let progress = if !verbose && std::io::stdout().is_terminal() && !crate::output::is_json() {
+        Progress::Bar
+    } else {
+        Progress::Events
+    };
+    run_tool(program, args, current_dir, verbose, env, progress).await
+}
+
+async fn run_tool(
+    program: &str,
+    args: &[&str],
+    current_dir: Option<&Path>,
+    verbose: bool,
+    env: &CommandEnv,
+    progress: Progress,
 ) -> Result<()> {
     if verbose {
         println!("Running: {} {}", program, args.join(" "));
@@ -44,15 +514,25 @@ pub async fn run_command(
 
     let mut cmd = Command::new(program);
     cmd.args(args);
+    env.apply(&mut cmd, verbose);
 
     if let Some(dir) = current_dir {
         cmd.current_dir(dir);
     }
 
-    let status = cmd
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
-        .status()?;
+    let started = Instant::now();
+    let status = match output_log_dir() {
+        Some(log_dir) => run_logged(cmd, program, args, &log_dir, progress).await?,
+        None => cmd
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()?,
+    };
+    crate::output::emit(&crate::output::Event::ToolFinished {
+        program: program.to_string(),
+        exit_code: status.code(),
+        duration_ms: started.elapsed().as_millis() as u64,
+    });
 
     if status.success() {
         Ok(())
@@ -68,9 +548,19 @@ pub async fn run_command_with_output(
     program: &str,
     args: &[&str],
     current_dir: Option<&Path>,
+) -> Result<String> {
+    run_command_with_output_env(program, args, current_dir, &CommandEnv::new()).await
+}
+
+pub async fn run_command_with_output_env(
+    program: &str,
+    args: &[&str],
+    current_dir: Option<&Path>,
+    env: &CommandEnv,
 ) -> Result<String> {
     let mut cmd = Command::new(program);
     cmd.args(args);
+    env.apply(&mut cmd, false);
 
     if let Some(dir) = current_dir {
         cmd.current_dir(dir);
@@ -86,6 +576,19 @@ pub async fn run_command_with_output(
     }
 }
 
+/// Parse a `KEY=VALUE` command line assignment
+pub fn parse_env_assignment(text: &str) -> Result<(String, String), String> {
+    match text.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got '{}'", text)),
+    }
+}
+
+/// A script of the esptool shipped with ESP-IDF, e.g. "espefuse" for espefuse.py
+pub fn esptool_script(name: &str) -> Result<PathBuf> {
+    Ok(get_idf_path()?.join(format!("components/esptool_py/esptool/{}.py", name)))
+}
+
 pub fn get_python_executable() -> Result<String> {
     // Try to find the ESP-IDF Python environment
     if let Ok(idf_python_env) = env::var("IDF_PYTHON_ENV_PATH") {
@@ -109,3 +612,100 @@ pub fn setup_idf_environment() -> Result<()> {
 
     Ok(())
 }
+
+/// Load the project_description.json generated by the ESP-IDF build system
+pub fn load_project_description(build_dir: &Path) -> Result<serde_json::Value> {
+    let path = build_dir.join("project_description.json");
+    let content = std::fs::read_to_string(&path).map_err(|e| {
+        anyhow::anyhow!(
+            "Failed to read {}: {}. Run 'build' command first.",
+            path.display(),
+            e
+        )
+    })?;
+
+    Ok(serde_json::from_str(&content)?)
+}
+
+/// Get the path of the app binary produced by the build
+pub fn get_app_bin_path(build_dir: &Path) -> Result<PathBuf> {
+    let description = load_project_description(build_dir)?;
+    let app_bin = description["app_bin"]
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("app_bin not found in project_description.json"))?;
+
+    Ok(build_dir.join(app_bin))
+}
+
+/// Get the path of the app ELF file produced by the build
+pub fn get_app_elf_path(build_dir: &Path) -> Result<PathBuf> {
+    let description = load_project_description(build_dir)?;
+    let app_elf = description["app_elf"]
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("app_elf not found in project_description.json"))?;
+
+    Ok(build_dir.join(app_elf))
+}
+
+/// Symbols of the app ELF mapped to their addresses, read with the toolchain's nm
+pub async fn read_elf_symbols(build_dir: &Path) -> Result<HashMap<String, String>> {
+    let nm = crate::build_systems::get_cmake_cache_entry(build_dir, "CMAKE_NM")
+        .ok_or_else(|| anyhow::anyhow!("CMAKE_NM not found in CMakeCache.txt"))?;
+    let elf = get_app_elf_path(build_dir)?;
+    let output = run_command_with_output(&nm, &[elf.to_str().unwrap()], None).await?;
+
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let address = fields.next()?;
+            let _kind = fields.next()?;
+            Some((fields.next()?.to_string(), format!("0x{}", address)))
+        })
+        .collect())
+}
+
+/// Ask the user a yes/no question on the terminal
+pub fn confirm(prompt: &str, default: bool) -> Result<bool> {
+    use std::io::Write;
+
+    let hint = if default { "[Y/n]" } else { "[y/N]" };
+    print!("{} {} ", prompt, hint);
+    std::io::stdout().flush()?;
+
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+
+    Ok(match answer.trim().to_lowercase().as_str() {
+        "" => default,
+        "y" | "yes" => true,
+        _ => false,
+    })
+}
+
+/// Let the user pick one of `choices` by number on the terminal, None when they quit
+pub fn choose(prompt: &str, choices: &[String]) -> Result<Option<usize>> {
+    use std::io::Write;
+
+    for (i, choice) in choices.iter().enumerate() {
+        println!("  {}) {}", i + 1, choice);
+    }
+
+    loop {
+        print!("{} [1-{}, q to quit] ", prompt, choices.len());
+        std::io::stdout().flush()?;
+
+        let mut answer = String::new();
+        if std::io::stdin().read_line(&mut answer)? == 0 {
+            return Ok(None);
+        }
+
+        match answer.trim() {
+            "q" | "quit" => return Ok(None),
+            answer => match answer.parse::<usize>() {
+                Ok(n) if (1..=choices.len()).contains(&n) => return Ok(Some(n - 1)),
+                _ => println!("Enter a number between 1 and {}", choices.len()),
+            },
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use std::cmp::Ordering;
+
+/// A component version as used by the ESP-IDF component manager, e.g. `1.2.3`,
+/// `1.2.3-rc.1` or `1.2.3~1` (revision of a republished version)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    pub parts: [u64; 3],
+    pub prerelease: Option<String>,
+    pub revision: u64,
+}
+
+impl Version {
+    pub fn parse(text: &str) -> Option<Version> {
+        let text = text.trim().trim_start_matches('v');
+        let (text, revision) = match text.split_once('~') {
+            Some((text, revision)) => (text, revision.parse().ok()?),
+            None => (text, 0),
+        };
+        let text = text.split_once('+').map(|(text, _)| text).unwrap_or(text);
+        let (core, prerelease) = match text.split_once('-') {
+            Some((core, prerelease)) => (core, Some(prerelease.to_string())),
+            None => (text, None),
+        };
+
+        let mut parts = [0u64; 3];
+        for (index, part) in core.split('.').enumerate() {
+            *parts.get_mut(index)? = part.parse().ok()?;
+        }
+
+        Some(Version {
+            parts,
+            prerelease,
+            revision,
+        })
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.parts
+            .cmp(&other.parts)
+            .then_with(|| match (&self.prerelease, &other.prerelease) {
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(a), Some(b)) => a.cmp(b),
+            })
+            .then_with(|| self.revision.cmp(&other.revision))
+    }
+}
+
+/// Upper bound for `^` (same major) / `~` (same minor) style constraints,
+/// based on how many components were given
+fn bump(parts: &[u64; 3], index: usize) -> Version {
+    let mut upper = [0u64; 3];
+    upper[..index].copy_from_slice(&parts[..index]);
+    upper[index] = parts[index] + 1;
+    Version {
+        parts: upper,
+        prerelease: None,
+        revision: 0,
+    }
+}
+
+fn matches_clause(version: &Version, clause: &str) -> Option<bool> {
+    let clause = clause.trim();
+    if clause == "*" || clause.is_empty() {
+        return Some(true);
+    }
+
+    let (op, text) = ["==", ">=", "<=", "!=", "~=", ">", "<", "=", "^", "~"]
+        .iter()
+        .find_map(|op| clause.strip_prefix(op).map(|text| (*op, text.trim())))
+        .unwrap_or(("==", clause));
+
+    // Wildcards such as 1.2.* match any version with the same prefix
+    if let Some(prefix) = text.strip_suffix(".*") {
+        let prefix: Vec<u64> = prefix
+            .split('.')
+            .map(|part| part.parse().ok())
+            .collect::<Option<_>>()?;
+        let matches = version.parts[..prefix.len()] == prefix[..];
+        return match op {
+            "==" | "=" => Some(matches),
+            "!=" => Some(!matches),
+            _ => None,
+        };
+    }
+
+    let given_parts = text.split('.').count();
+    let bound = Version::parse(text)?;
+
+    Some(match op {
+        "==" | "=" => *version == bound,
+        "!=" => *version != bound,
+        ">=" => *version >= bound,
+        "<=" => *version <= bound,
+        ">" => *version > bound,
+        "<" => *version < bound,
+        "^" => {
+            let index = bound.parts.iter().position(|part| *part != 0).unwrap_or(2);
+            *version >= bound && *version < bump(&bound.parts, index.min(given_parts - 1))
+        }
+        "~" => {
+            let index = if given_parts > 1 { 1 } else { 0 };
+            *version >= bound && *version < bump(&bound.parts, index)
+        }
+        "~=" => {
+            let index = given_parts.saturating_sub(2);
+            *version >= bound && *version < bump(&bound.parts, index)
+        }
+        _ => return None,
+    })
+}
+
+/// Check whether `version` satisfies a comma separated constraint such as `>=1.0,<2.0`.
+/// Returns `None` when the version or constraint cannot be parsed.
+pub fn matches(version: &str, spec: &str) -> Option<bool> {
+    let version = Version::parse(version)?;
+    let mut result = true;
+    for clause in spec.split(',') {
+        result &= matches_clause(&version, clause)?;
+    }
+    Some(result)
+}
+
+/// Whether a constraint pins a single exact version
+pub fn is_exact(spec: &str) -> bool {
+    let spec = spec.trim();
+    let version = spec
+        .strip_prefix("==")
+        .or_else(|| spec.strip_prefix('='))
+        .unwrap_or(spec)
+        .trim();
+    !spec.contains(',') && version.split('.').count() == 3 && Version::parse(version).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_ordering() {
+        let parse = |text| Version::parse(text).unwrap();
+        assert!(parse("1.2.3") < parse("1.10.0"));
+        assert!(parse("1.2.3-rc.1") < parse("1.2.3"));
+        assert!(parse("1.2.3") < parse("1.2.3~1"));
+    }
+
+    #[test]
+    fn test_matches() {
+        assert_eq!(matches("1.2.3", ">=1.0,<2.0"), Some(true));
+        assert_eq!(matches("2.0.0", ">=1.0,<2.0"), Some(false));
+        assert_eq!(matches("3.4.1", "^3.1"), Some(true));
+        assert_eq!(matches("4.0.0", "^3.1"), Some(false));
+        assert_eq!(matches("1.2.9", "~1.2.0"), Some(true));
+        assert_eq!(matches("1.3.0", "~1.2.0"), Some(false));
+        assert_eq!(matches("1.2.5", "1.2.*"), Some(true));
+        assert_eq!(matches("1.2.3", "1.2.3"), Some(true));
+        assert_eq!(matches("1.2.3", "*"), Some(true));
+        assert_eq!(matches("1.2.3", ">=x"), None);
+    }
+
+    #[test]
+    fn test_is_exact() {
+        assert!(is_exact("1.2.3"));
+        assert!(is_exact("==1.2.3"));
+        assert!(!is_exact("^1.2.3"));
+        assert!(!is_exact(">=1.2.3,<2.0"));
+        assert!(!is_exact("1.2"));
+    }
+}
@@ -0,0 +1,305 @@
+//! Minimal WebSocket server (RFC 6455) for pushing text frames to browsers
+
+use anyhow::Result;
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, mpsc};
+
+/// GUID from RFC 6455 used to derive Sec-WebSocket-Accept
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+/// Page served to plain HTTP requests, showing the stream in the browser
+const VIEWER_PAGE: &str = r#"<!DOCTYPE html>
+<html><head><meta charset="utf-8"><title>idf-rs monitor</title>
+<style>body{background:#111;color:#ddd;font:13px monospace;margin:0;padding:8px}
+.E{color:#f66}.W{color:#fc6}.I{color:#6d6}.D,.V{color:#999}</style></head>
+<body><pre id="log"></pre><script>
+const log = document.getElementById('log');
+const socket = new WebSocket('ws://' + location.host + '/');
+socket.onmessage = (event) => {
+  const line = JSON.parse(event.data);
+  const span = document.createElement('span');
+  span.className = line.level || '';
+  span.textContent = line.line + '\n';
+  log.appendChild(span);
+  window.scrollTo(0, document.body.scrollHeight);
+};
+socket.onclose = () => log.appendChild(document.createTextNode('--- disconnected ---\n'));
+</script></body></html>
+"#;
+
+/// SHA-1, only needed for the WebSocket handshake
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        for (value, add) in h.iter_mut().zip([a, b, c, d, e]) {
+            *value = value.wrapping_add(add);
+        }
+    }
+
+    let mut output = [0u8; 20];
+    for (chunk, value) in output.chunks_mut(4).zip(h) {
+        chunk.copy_from_slice(&value.to_be_bytes());
+    }
+    output
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut output = String::new();
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let value = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        for index in 0..4 {
+            if index <= chunk.len() {
+                output.push(ALPHABET[(value >> (18 - index * 6)) as usize & 0x3f] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+    output
+}
+
+/// Sec-WebSocket-Accept value for a client's Sec-WebSocket-Key
+pub fn accept_key(key: &str) -> String {
+    base64(&sha1(
+        format!("{}{}", key.trim(), WEBSOCKET_GUID).as_bytes(),
+    ))
+}
+
+/// Encode an unmasked server frame
+pub fn encode_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Read one (masked) client frame, returning its opcode and payload
+async fn read_frame(reader: &mut (impl AsyncReadExt + Unpin)) -> Result<(u8, Vec<u8>)> {
+    let mut header = [0u8; 2];
+    reader.read_exact(&mut header).await?;
+    let opcode = header[0] & 0x0f;
+    let masked = header[1] & 0x80 != 0;
+
+    let len = match header[1] & 0x7f {
+        126 => reader.read_u16().await? as usize,
+        127 => reader.read_u64().await? as usize,
+        len => len as usize,
+    };
+    let mut mask = [0u8; 4];
+    if masked {
+        reader.read_exact(&mut mask).await?;
+    }
+
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload).await?;
+    if masked {
+        for (index, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[index % 4];
+        }
+    }
+
+    Ok((opcode, payload))
+}
+
+/// Read the HTTP request head of a new connection
+async fn read_request(stream: &mut TcpStream) -> Result<String> {
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 || request.len() > 16 * 1024 {
+            anyhow::bail!("incomplete request");
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+    Ok(String::from_utf8_lossy(&request).to_string())
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    peer: SocketAddr,
+    mut receiver: broadcast::Receiver<String>,
+) -> Result<()> {
+    let request = read_request(&mut stream).await?;
+    let key = request.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("sec-websocket-key")
+            .then(|| value.trim().to_string())
+    });
+
+    let key = match key {
+        Some(key) => key,
+        None => {
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                VIEWER_PAGE.len(),
+                VIEWER_PAGE
+            );
+            stream.write_all(response.as_bytes()).await?;
+            return Ok(());
+        }
+    };
+
+    let response = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(&key)
+    );
+    stream.write_all(response.as_bytes()).await?;
+    println!("--- WebSocket client {} connected ---", peer);
+
+    let (mut reader, mut writer) = stream.into_split();
+
+    // Control frames from the client are answered by the writer
+    let (control_tx, mut control_rx) = mpsc::channel::<(u8, Vec<u8>)>(8);
+    tokio::spawn(async move {
+        while let Ok((opcode, payload)) = read_frame(&mut reader).await {
+            let reply = match opcode {
+                OPCODE_PING => (OPCODE_PONG, payload),
+                OPCODE_CLOSE => (OPCODE_CLOSE, payload),
+                _ => continue,
+            };
+            let closing = reply.0 == OPCODE_CLOSE;
+            if control_tx.send(reply).await.is_err() || closing {
+                break;
+            }
+        }
+    });
+
+    loop {
+        let frame = tokio::select! {
+            message = receiver.recv() => match message {
+                Ok(message) => encode_frame(OPCODE_TEXT, message.as_bytes()),
+                // A slow client misses lines rather than holding up the monitor
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            control = control_rx.recv() => match control {
+                Some((OPCODE_CLOSE, payload)) => {
+                    let _ = writer.write_all(&encode_frame(OPCODE_CLOSE, &payload)).await;
+                    break;
+                }
+                Some((opcode, payload)) => encode_frame(opcode, &payload),
+                None => break,
+            },
+        };
+        if writer.write_all(&frame).await.is_err() {
+            break;
+        }
+    }
+
+    println!("--- WebSocket client {} disconnected ---", peer);
+    Ok(())
+}
+
+/// Accept WebSocket clients on `address` and send them every message broadcast on
+/// `messages` as a text frame. Plain HTTP requests get a log viewer page.
+pub async fn serve(address: &str, messages: broadcast::Sender<String>) -> Result<()> {
+    let listener = TcpListener::bind(address)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to listen on {}: {}", address, e))?;
+
+    // Stop listening once the sender side is gone, e.g. when the daemon stops a monitor
+    let mut closed = messages.subscribe();
+    let messages = messages.downgrade();
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                accepted = listener.accept() => {
+                    let (Ok((stream, peer)), Some(messages)) = (accepted, messages.upgrade()) else {
+                        break;
+                    };
+                    let receiver = messages.subscribe();
+                    tokio::spawn(async move {
+                        let _ = handle_connection(stream, peer, receiver).await;
+                    });
+                }
+                received = closed.recv() => {
+                    if let Err(broadcast::error::RecvError::Closed) = received {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accept_key() {
+        // Example handshake from RFC 6455
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+        assert_eq!(base64(b"ab"), "YWI=");
+
+        let frame = encode_frame(OPCODE_TEXT, &[b'x'; 200]);
+        assert_eq!(&frame[..4], &[0x81, 126, 0, 200]);
+    }
+}
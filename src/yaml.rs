@@ -0,0 +1,359 @@
+use anyhow::Result;
+use serde_json::{Map, Value};
+
+#[derive(Debug, Clone)]
+struct Line {
+    number: usize,
+    indent: usize,
+    text: String,
+}
+
+/// Remove a trailing comment that is not inside quotes
+fn strip_comment(line: &str) -> &str {
+    let mut in_single = false;
+    let mut in_double = false;
+    let mut previous = ' ';
+
+    for (index, c) in line.char_indices() {
+        match c {
+            '\'' if !in_double => in_single = !in_single,
+            '"' if !in_single && previous != '\\' => in_double = !in_double,
+            '#' if !in_single && !in_double && previous.is_whitespace() => {
+                return &line[..index];
+            }
+            _ => {}
+        }
+        previous = c;
+    }
+
+    line
+}
+
+fn tokenize(content: &str) -> Vec<Line> {
+    content
+        .lines()
+        .enumerate()
+        .filter_map(|(index, raw)| {
+            let text = strip_comment(raw).trim_end();
+            let trimmed = text.trim_start();
+            if trimmed.is_empty() || trimmed == "---" {
+                return None;
+            }
+
+            Some(Line {
+                number: index + 1,
+                indent: text.len() - trimmed.len(),
+                text: trimmed.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Find the `:` separating a mapping key from its value, outside of quotes and brackets
+fn find_key_separator(text: &str) -> Option<usize> {
+    let mut in_single = false;
+    let mut in_double = false;
+    let mut depth = 0i32;
+    let bytes = text.as_bytes();
+
+    for (index, c) in text.char_indices() {
+        match c {
+            '\'' if !in_double => in_single = !in_single,
+            '"' if !in_single => in_double = !in_double,
+            '[' | '{' if !in_single && !in_double => depth += 1,
+            ']' | '}' if !in_single && !in_double => depth -= 1,
+            ':' if !in_single
+                && !in_double
+                && depth == 0
+                && (index + 1 == text.len() || bytes[index + 1] == b' ') =>
+            {
+                return Some(index);
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+fn unquote(text: &str) -> String {
+    let text = text.trim();
+    if text.len() >= 2 && text.starts_with('"') && text.ends_with('"') {
+        text[1..text.len() - 1]
+            .replace("\\\"", "\"")
+            .replace("\\n", "\n")
+            .replace("\\\\", "\\")
+    } else if text.len() >= 2 && text.starts_with('\'') && text.ends_with('\'') {
+        text[1..text.len() - 1].replace("''", "'")
+    } else {
+        text.to_string()
+    }
+}
+
+/// Split the inside of a flow collection on top-level commas
+fn split_flow_items(inner: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut current = String::new();
+    let mut in_single = false;
+    let mut in_double = false;
+    let mut depth = 0i32;
+
+    for c in inner.chars() {
+        match c {
+            '\'' if !in_double => in_single = !in_single,
+            '"' if !in_single => in_double = !in_double,
+            '[' | '{' if !in_single && !in_double => depth += 1,
+            ']' | '}' if !in_single && !in_double => depth -= 1,
+            ',' if !in_single && !in_double && depth == 0 => {
+                items.push(std::mem::take(&mut current));
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+
+    if !current.trim().is_empty() {
+        items.push(current);
+    }
+
+    items
+}
+
+fn parse_scalar(text: &str) -> Value {
+    let text = text.trim();
+
+    if let Some(inner) = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+        return Value::Array(
+            split_flow_items(inner)
+                .iter()
+                .map(|item| parse_scalar(item))
+                .collect(),
+        );
+    }
+
+    if let Some(inner) = text.strip_prefix('{').and_then(|t| t.strip_suffix('}')) {
+        let mut map = Map::new();
+        for item in split_flow_items(inner) {
+            match find_key_separator(item.trim()) {
+                Some(index) => {
+                    let item = item.trim();
+                    map.insert(unquote(&item[..index]), parse_scalar(&item[index + 1..]));
+                }
+                None => {
+                    map.insert(unquote(&item), Value::Null);
+                }
+            }
+        }
+        return Value::Object(map);
+    }
+
+    if text.starts_with('"') || text.starts_with('\'') {
+        return Value::String(unquote(text));
+    }
+
+    match text {
+        "" | "~" | "null" | "Null" | "NULL" => Value::Null,
+        "true" | "True" | "TRUE" => Value::Bool(true),
+        "false" | "False" | "FALSE" => Value::Bool(false),
+        _ => Value::String(text.to_string()),
+    }
+}
+
+struct Parser {
+    lines: Vec<Line>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Line> {
+        self.lines.get(self.pos)
+    }
+
+    fn is_sequence_item(text: &str) -> bool {
+        text == "-" || text.starts_with("- ")
+    }
+
+    fn parse_block(&mut self, indent: usize) -> Result<Value> {
+        match self.peek() {
+            Some(line) if Self::is_sequence_item(&line.text) => self.parse_sequence(indent),
+            Some(_) => self.parse_mapping(indent),
+            None => Ok(Value::Null),
+        }
+    }
+
+    fn parse_sequence(&mut self, indent: usize) -> Result<Value> {
+        let mut items = Vec::new();
+
+        while let Some(line) = self.peek().cloned() {
+            if line.indent != indent || !Self::is_sequence_item(&line.text) {
+                break;
+            }
+
+            let rest = line.text[1..].trim_start();
+            if rest.is_empty() {
+                self.pos += 1;
+                match self.peek() {
+                    Some(next) if next.indent > indent => {
+                        let child_indent = next.indent;
+                        items.push(self.parse_block(child_indent)?);
+                    }
+                    _ => items.push(Value::Null),
+                }
+            } else if Self::is_sequence_item(rest) || find_key_separator(rest).is_some() {
+                // Inline collection ("- key: value"), continue it at the item's column
+                let child_indent = indent + (line.text.len() - rest.len());
+                self.lines[self.pos] = Line {
+                    number: line.number,
+                    indent: child_indent,
+                    text: rest.to_string(),
+                };
+                items.push(self.parse_block(child_indent)?);
+            } else {
+                self.pos += 1;
+                items.push(parse_scalar(rest));
+            }
+        }
+
+        Ok(Value::Array(items))
+    }
+
+    fn parse_block_scalar(&mut self, indent: usize, folded: bool) -> Value {
+        let mut parts = Vec::new();
+        let mut block_indent = None;
+
+        while let Some(line) = self.peek() {
+            if line.indent <= indent {
+                break;
+            }
+            let block_indent = *block_indent.get_or_insert(line.indent);
+            parts.push(format!(
+                "{}{}",
+                " ".repeat(line.indent.saturating_sub(block_indent)),
+                line.text
+            ));
+            self.pos += 1;
+        }
+
+        let separator = if folded { " " } else { "\n" };
+        let mut text = parts.join(separator);
+        text.push('\n');
+        Value::String(text)
+    }
+
+    fn parse_mapping(&mut self, indent: usize) -> Result<Value> {
+        let mut map = Map::new();
+
+        while let Some(line) = self.peek().cloned() {
+            if line.indent < indent {
+                break;
+            }
+            if line.indent > indent {
+                anyhow::bail!("line {}: unexpected indentation", line.number);
+            }
+            if Self::is_sequence_item(&line.text) {
+                break;
+            }
+
+            let separator = find_key_separator(&line.text)
+                .ok_or_else(|| anyhow::anyhow!("line {}: expected 'key: value'", line.number))?;
+            let key = unquote(&line.text[..separator]);
+            let value_text = line.text[separator + 1..].trim();
+            self.pos += 1;
+
+            let value = match value_text {
+                "" => match self.peek() {
+                    Some(next) if next.indent > indent => {
+                        let child_indent = next.indent;
+                        self.parse_block(child_indent)?
+                    }
+                    // Sequences may start at the same column as their key
+                    Some(next) if next.indent == indent && Self::is_sequence_item(&next.text) => {
+                        self.parse_sequence(indent)?
+                    }
+                    _ => Value::Null,
+                },
+                "|" | "|-" | "|+" => self.parse_block_scalar(indent, false),
+                ">" | ">-" | ">+" => self.parse_block_scalar(indent, true),
+                _ => parse_scalar(value_text),
+            };
+
+            if map.insert(key.clone(), value).is_some() {
+                anyhow::bail!("line {}: duplicate key '{}'", line.number, key);
+            }
+        }
+
+        Ok(Value::Object(map))
+    }
+}
+
+/// Parse a YAML document into a JSON value. Only the block-style subset used by
+/// ESP-IDF files (idf_component.yml, dependencies.lock, hints.yml) is supported.
+/// Plain scalars stay strings (except booleans and null) so that version numbers
+/// such as `1.10` keep their formatting.
+pub fn parse(content: &str) -> Result<Value> {
+    let lines = tokenize(content);
+    let indent = lines.first().map(|line| line.indent).unwrap_or(0);
+    let mut parser = Parser { lines, pos: 0 };
+    let value = parser.parse_block(indent)?;
+
+    if let Some(line) = parser.peek() {
+        anyhow::bail!("line {}: unexpected content '{}'", line.number, line.text);
+    }
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_manifest() {
+        let content = r#"
+version: "1.2.0"
+description: Test component # trailing comment
+targets:
+  - esp32
+  - esp32s3
+dependencies:
+  idf: ">=5.0"
+  espressif/button:
+    version: ~3.0
+    rules:
+      - if: "target in [esp32, esp32s3]"
+  local:
+    path: ../local
+tags: [sensor, "i2c"]
+"#;
+        let value = parse(content).unwrap();
+
+        assert_eq!(value["version"], json!("1.2.0"));
+        assert_eq!(value["description"], json!("Test component"));
+        assert_eq!(value["targets"], json!(["esp32", "esp32s3"]));
+        assert_eq!(value["dependencies"]["idf"], json!(">=5.0"));
+        assert_eq!(
+            value["dependencies"]["espressif/button"]["rules"][0]["if"],
+            json!("target in [esp32, esp32s3]")
+        );
+        assert_eq!(value["dependencies"]["local"]["path"], json!("../local"));
+        assert_eq!(value["tags"], json!(["sensor", "i2c"]));
+    }
+
+    #[test]
+    fn test_parse_sequence_of_mappings() {
+        let content = "-\n  re: \"error: (.+)\"\n  hint: |\n    Line one\n    Line two\n- re: other\n  match_to_output: true\n";
+        let value = parse(content).unwrap();
+
+        assert_eq!(value[0]["re"], json!("error: (.+)"));
+        assert_eq!(value[0]["hint"], json!("Line one\nLine two\n"));
+        assert_eq!(value[1]["match_to_output"], json!(true));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("a: 1\na: 2\n").is_err());
+        assert!(parse("a: 1\n    b: 2\n").is_err());
+    }
+}